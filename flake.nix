@@ -47,6 +47,9 @@
           # rodio dependencies
           alsa-lib
 
+          # Opus encoding for exports
+          libopus
+
           # fltk dependencies
           cmake
           gcc
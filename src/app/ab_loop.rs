@@ -0,0 +1,308 @@
+use std::time::{Duration, Instant};
+
+use rodio::mixer::Mixer;
+use rodio::source::SineWave;
+use rodio::{Sink, Source};
+use serde::{Deserialize, Serialize};
+
+/// A section of the audio, from point A to point B, that is played over and over.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AbLoop {
+    pub(crate) start: Duration,
+    pub(crate) end: Duration,
+
+    #[serde(default)]
+    pub(crate) practice: PracticeSettings,
+}
+
+/// Settings that help with practicing along to a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PracticeSettings {
+    /// How long to wait in silence before each repeat.
+    pub(crate) gap: Duration,
+
+    /// How many metronome clicks to play before each repeat. These are played after the gap.
+    pub(crate) count_in_beats: u32,
+
+    /// The tempo of the count-in, in beats per minute.
+    pub(crate) count_in_bpm: u32,
+
+    /// The speed of the first pass, where 1.0 is the original speed.
+    pub(crate) start_speed: f32,
+
+    /// How much faster each pass is than the last one.
+    pub(crate) speed_step: f32,
+
+    /// The speed that the passes stop speeding up at.
+    pub(crate) target_speed: f32,
+}
+
+impl Default for PracticeSettings {
+    fn default() -> PracticeSettings {
+        PracticeSettings {
+            gap: Duration::ZERO,
+            count_in_beats: 0,
+            count_in_bpm: 100,
+            start_speed: 1.0,
+            speed_step: 0.0,
+            target_speed: 1.0,
+        }
+    }
+}
+
+impl PracticeSettings {
+    /// Return the speed of a pass, where the first pass is pass 0.
+    pub(crate) fn speed_for_pass(&self, pass: u32) -> f32 {
+        let speed = self.start_speed + self.speed_step * pass as f32;
+
+        // Stop at the target speed, no matter which direction the speed is changing in
+        if self.speed_step >= 0.0 {
+            speed.min(self.target_speed)
+        } else {
+            speed.max(self.target_speed)
+        }
+    }
+
+    /// Return the time between two count-in clicks.
+    pub(crate) fn beat_interval(&self) -> Duration {
+        Duration::from_secs_f64(60.0 / self.count_in_bpm.max(1) as f64)
+    }
+
+    /// Return how long playback waits before each repeat, including the count-in.
+    pub(crate) fn pause_before_repeat(&self) -> Duration {
+        self.gap + self.beat_interval() * self.count_in_beats
+    }
+}
+
+impl AbLoop {
+    /// The largest jump in position between two ticks that still counts as normal playback rather than a seek.
+    const MAX_TICK_JUMP: Duration = Duration::from_secs(1);
+
+    /// Create a loop between two points, in whichever order they are given.
+    pub(crate) fn new(a: Duration, b: Duration, practice: PracticeSettings) -> AbLoop {
+        AbLoop {
+            start: a.min(b),
+            end: a.max(b),
+            practice,
+        }
+    }
+
+    /// Return whether playback has just reached the end of the loop.
+    ///
+    /// Seeking past the end of the loop jumps further than normal playback would, so it does not count.
+    /// This lets the user leave the loop by seeking out of it.
+    pub(crate) fn reached_end(&self, previous_pos: Duration, current_pos: Duration) -> bool {
+        previous_pos < self.end
+            && current_pos >= self.end
+            && current_pos - previous_pos <= Self::MAX_TICK_JUMP
+    }
+}
+
+/// Keeps track of the A–B loop on the audio thread, and jumps back to the start of the loop when needed.
+pub(crate) struct LoopPlayer {
+    ab_loop: Option<AbLoop>,
+
+    /// How many times the loop has been repeated so far
+    pass: u32,
+
+    /// The position of the audio at the last tick
+    previous_pos: Duration,
+
+    /// When to continue playing after the gap and count-in
+    resume_at: Option<Instant>,
+}
+
+impl LoopPlayer {
+    const CLICK_FREQUENCY: f32 = 1000.0;
+    const CLICK_LENGTH: Duration = Duration::from_millis(40);
+    const CLICK_VOLUME: f32 = 0.3;
+
+    pub(crate) fn new() -> LoopPlayer {
+        LoopPlayer {
+            ab_loop: None,
+            pass: 0,
+            previous_pos: Duration::ZERO,
+            resume_at: None,
+        }
+    }
+
+    /// Replace the current loop, starting again from the first pass.
+    pub(crate) fn set_loop(&mut self, ab_loop: Option<AbLoop>, sink: &Sink) {
+        self.ab_loop = ab_loop;
+        self.pass = 0;
+        self.previous_pos = sink.get_pos();
+
+        // If the loop was cleared during a gap, continue playing straight away
+        if self.resume_at.take().is_some() {
+            sink.play();
+        }
+
+        let speed = ab_loop.map_or(1.0, |ab_loop| ab_loop.practice.speed_for_pass(0));
+        sink.set_speed(speed);
+    }
+
+    /// Stop waiting for the gap to end, because the user played or paused the audio themselves.
+    pub(crate) fn cancel_gap(&mut self) {
+        self.resume_at = None;
+    }
+
+    /// Check the position of the audio, and jump back to the start of the loop once it reaches the end.
+    /// Returns the position that was jumped to, if any.
+    /// This function is intended to be called continuously on the audio thread.
+    pub(crate) fn tick(&mut self, sink: &Sink, mixer: &Mixer) -> Option<Duration> {
+        // Continue playing once the gap is over
+        if let Some(resume_at) = self.resume_at
+            && Instant::now() >= resume_at
+        {
+            self.resume_at = None;
+            sink.play();
+        }
+
+        let ab_loop = self.ab_loop?;
+        let current_pos = sink.get_pos();
+        let previous_pos = std::mem::replace(&mut self.previous_pos, current_pos);
+
+        if sink.is_paused() || !ab_loop.reached_end(previous_pos, current_pos) {
+            return None;
+        }
+
+        if let Err(e) = sink.try_seek(ab_loop.start) {
+            eprintln!("Unable to jump to the start of the loop: {:?}", e);
+            return None;
+        }
+        self.previous_pos = ab_loop.start;
+
+        self.pass += 1;
+        sink.set_speed(ab_loop.practice.speed_for_pass(self.pass));
+
+        let pause = ab_loop.practice.pause_before_repeat();
+        if !pause.is_zero() {
+            sink.pause();
+            self.resume_at = Some(Instant::now() + pause);
+            LoopPlayer::play_count_in(&ab_loop.practice, mixer);
+        }
+
+        Some(ab_loop.start)
+    }
+
+    /// Play the count-in clicks, starting once the gap is over.
+    fn play_count_in(practice: &PracticeSettings, mixer: &Mixer) {
+        for beat in 0..practice.count_in_beats {
+            let click = SineWave::new(Self::CLICK_FREQUENCY)
+                .take_duration(Self::CLICK_LENGTH)
+                .amplify(Self::CLICK_VOLUME)
+                .delay(practice.gap + practice.beat_interval() * beat);
+
+            mixer.add(click);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod speed_for_pass {
+        use super::*;
+
+        fn speeding_up() -> PracticeSettings {
+            PracticeSettings {
+                start_speed: 0.5,
+                speed_step: 0.1,
+                target_speed: 0.8,
+                ..PracticeSettings::default()
+            }
+        }
+
+        #[test]
+        fn test_first_pass() {
+            assert_eq!(speeding_up().speed_for_pass(0), 0.5);
+        }
+
+        #[test]
+        fn test_speeds_up() {
+            assert!((speeding_up().speed_for_pass(2) - 0.7).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_stops_at_target() {
+            assert_eq!(speeding_up().speed_for_pass(10), 0.8);
+        }
+
+        #[test]
+        fn test_slowing_down() {
+            let practice = PracticeSettings {
+                start_speed: 1.0,
+                speed_step: -0.25,
+                target_speed: 0.5,
+                ..PracticeSettings::default()
+            };
+
+            assert_eq!(practice.speed_for_pass(1), 0.75);
+            assert_eq!(practice.speed_for_pass(5), 0.5);
+        }
+    }
+
+    mod pause_before_repeat {
+        use super::*;
+
+        #[test]
+        fn test_gap_and_count_in() {
+            let practice = PracticeSettings {
+                gap: Duration::from_secs(1),
+                count_in_beats: 4,
+                count_in_bpm: 120,
+                ..PracticeSettings::default()
+            };
+
+            assert_eq!(practice.pause_before_repeat(), Duration::from_secs(3));
+        }
+
+        #[test]
+        fn test_no_pause_by_default() {
+            assert!(PracticeSettings::default().pause_before_repeat().is_zero());
+        }
+    }
+
+    mod reached_end {
+        use super::*;
+
+        fn ab_loop() -> AbLoop {
+            AbLoop::new(
+                Duration::from_secs(10),
+                Duration::from_secs(20),
+                PracticeSettings::default(),
+            )
+        }
+
+        #[test]
+        fn test_points_are_ordered() {
+            let ab_loop = AbLoop::new(
+                Duration::from_secs(20),
+                Duration::from_secs(10),
+                PracticeSettings::default(),
+            );
+
+            assert_eq!(ab_loop.start, Duration::from_secs(10));
+            assert_eq!(ab_loop.end, Duration::from_secs(20));
+        }
+
+        #[test]
+        fn test_playing_past_the_end() {
+            assert!(
+                ab_loop().reached_end(Duration::from_millis(19980), Duration::from_millis(20030))
+            );
+        }
+
+        #[test]
+        fn test_still_inside() {
+            assert!(!ab_loop().reached_end(Duration::from_secs(15), Duration::from_millis(15050)));
+        }
+
+        #[test]
+        fn test_seeking_past_the_end() {
+            assert!(!ab_loop().reached_end(Duration::from_secs(15), Duration::from_secs(25)));
+        }
+    }
+}
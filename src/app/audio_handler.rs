@@ -1,13 +1,29 @@
 use rodio::Sink;
-use rodio::{Decoder, OutputStream, Source};
+use rodio::cpal::{
+    self,
+    traits::{DeviceTrait, HostTrait},
+};
+use rodio::decoder::DecoderError;
+use rodio::{Decoder, OutputStream, OutputStreamBuilder, Source};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 use std::time::Duration;
 
 use crate::app::Message;
+use crate::app::ab_loop::LoopPlayer;
+use crate::app::config::AudioSettings;
+use crate::app::queue::Queue;
+use crate::app::queue_slots::QueueSlots;
+use crate::app::replay_gain::ReplayGain;
+use crate::app::sleep_timer::{SleepTimer, SleepTimerStatus};
+use crate::app::track_length::LengthScanner;
+use crate::app::track_loader::{self, Loaded, OpenedTrack, TrackLoader};
+use crate::app::visualizer::{SampleTap, Tapped};
 
 /// Store the functionality for playing audio and other functions.
 // Note that pub(crate) means that AudioHandler can only be used by files in `app/`
@@ -17,65 +33,102 @@ pub(crate) struct AudioHandler {
 
     /// The audio that is playing
     stream: Arc<Mutex<Option<OutputStream>>>,
+
+    /// A copy of the samples that are played, for the visualizer
+    tap: Arc<SampleTap>,
+
+    /// The output device and how it is fed, from the config file
+    settings: AudioSettings,
+
+    /// How the volume of the tracks is evened out when the audio thread starts
+    replay_gain: ReplayGain,
 }
 
 impl AudioHandler {
-    /// Return an empty instance of AudioPlayer.
-    pub(crate) fn new() -> AudioHandler {
+    /// How often the audio thread checks on the A–B loop, the sleep timer and the queue when no messages arrive.
+    const TICK_INTERVAL: Duration = Duration::from_millis(20);
+
+    /// Return an empty instance of AudioPlayer, which will play on the output device in `settings`.
+    pub(crate) fn new(settings: AudioSettings, replay_gain: ReplayGain) -> AudioHandler {
         // Use None for now; this will become populated in self.play_audio
         let sink = Arc::new(Mutex::new(None));
         let stream = Arc::new(Mutex::new(None));
 
-        AudioHandler { sink, stream }
+        AudioHandler {
+            sink,
+            stream,
+            tap: Arc::new(SampleTap::new()),
+            settings,
+            replay_gain,
+        }
+    }
+
+    /// Return the tap that every sample is copied into as it is played.
+    pub(crate) fn sample_tap(&self) -> Arc<SampleTap> {
+        Arc::clone(&self.tap)
     }
 
-    /// Load audio source from file, returning the Decoder and its total duration (if available).
-    pub(crate) fn load_audio(file_path: &str) -> (Decoder<BufReader<File>>, Option<Duration>) {
-        // Load sound file
-        let file = File::open(file_path).unwrap_or_else(|_| {
-            eprintln!("File path does not exist. Exiting...");
-            exit(1);
-        });
+    /// Load the first of `paths` that can be played, returning the source that plays it and its entry in the queue.
+    /// The paths before it are skipped, and the rest are left in `paths`. Returns `None` if none of them can be played.
+    pub(crate) fn load_audio(
+        paths: &mut impl Iterator<Item = PathBuf>,
+        replay_gain: ReplayGain,
+    ) -> Option<OpenedTrack> {
+        track_loader::open_first(paths, replay_gain)
+    }
 
+    /// Build a seekable Decoder from an opened audio file.
+    /// # Errors
+    /// - If the file format is not supported
+    pub(crate) fn build_decoder(file: File) -> Result<Decoder<BufReader<File>>, DecoderError> {
         let byte_len = file.metadata().map(|m| m.len()).unwrap_or(0);
         let file = BufReader::new(file);
 
-        let decoder = Decoder::builder()
+        Decoder::builder()
             .with_data(file)
             // Specify the length of the audio source for reliable seeking
             .with_byte_len(byte_len)
             // Essential to allow for seeking backwards
             .with_seekable(true)
             .build()
-            .unwrap_or_else(|_| {
-                eprintln!("File format not supported. Exiting...");
-                exit(1);
-            });
-
-        let duration = decoder.total_duration();
-
-        (decoder, duration)
     }
 
     /// Play audio and initialize self.sink and self.stream.
+    /// `queue` starts with the track that `source` plays, and the audio thread sends it back through `queue_sender` whenever it changes.
     pub(crate) fn play_audio(
         &self,
         receiver: Arc<Mutex<mpsc::Receiver<Message>>>,
         audio_pos_sender: mpsc::Sender<Duration>,
-        decoder: Decoder<BufReader<File>>,
+        sleep_timer_sender: mpsc::Sender<SleepTimerStatus>,
+        queue_sender: mpsc::Sender<Queue>,
+        source: Box<dyn Source + Send>,
+        mut queue: Queue,
     ) {
         let sink_ref = Arc::clone(&self.sink);
         let stream_ref = Arc::clone(&self.stream);
+        let tap = Arc::clone(&self.tap);
+        let settings = self.settings.clone();
+        let replay_gain = self.replay_gain;
 
         thread::spawn(move || {
-            // Get an output stream handle to the default physical sound device.
-            let stream_handle = AudioHandler::open_output_stream();
+            // Get an output stream handle to the sound device that the user picked, or else the default one.
+            let stream_handle = AudioHandler::open_output_stream(&settings);
 
             // Create a new audio sink, which will be used to control playback of audio
             let sink = AudioHandler::create_sink(&stream_handle);
 
             // Play the sound directly on the device
-            sink.append(decoder);
+            sink.append(Tapped::new(source, Arc::clone(&tap)));
+
+            // Keep a handle to the mixer so that the count-in clicks of a loop can be played alongside the audio
+            let mixer = stream_handle.mixer().clone();
+            let mut loop_player = LoopPlayer::new();
+            let mut sleep_timer = SleepTimer::new();
+            let mut length_scanner = LengthScanner::new();
+            let mut queue_slots = QueueSlots::new(Arc::clone(&tap));
+
+            // Tracks are opened on a thread of their own, so that the sink is only locked to add them
+            let track_loader = TrackLoader::start(replay_gain);
 
             // Add sink to self.sink so that it can be accessed by other methods
             *sink_ref.lock().unwrap() = Some(sink);
@@ -84,20 +137,112 @@ impl AudioHandler {
             *stream_ref.lock().unwrap() = Some(stream_handle);
 
             // Send the audio's current position to the progress bar
-            AudioHandler::send_audio_pos(audio_pos_sender.clone(), Arc::clone(&sink_ref));
+            AudioHandler::send_audio_pos(
+                audio_pos_sender.clone(),
+                Arc::clone(&sink_ref),
+                settings.position_interval(),
+            );
 
             // Continuously scan for new messages sent by the AudioApp
             loop {
-                let message = receiver.lock().unwrap().recv().unwrap();
-                AudioHandler::handle_messages(message, &sink_ref, &audio_pos_sender);
+                // Wake up regularly even without messages, so that the loop can jump back in time
+                let message = receiver
+                    .lock()
+                    .unwrap()
+                    .recv_timeout(AudioHandler::TICK_INTERVAL);
+
+                match message {
+                    Ok(message) => {
+                        let queue_changed = AudioHandler::handle_messages(
+                            message,
+                            &sink_ref,
+                            &audio_pos_sender,
+                            &mut loop_player,
+                            &mut sleep_timer,
+                            &mut queue,
+                            &mut queue_slots,
+                            &track_loader,
+                        );
+                        if queue_changed {
+                            AudioHandler::send_queue(&queue_sender, &queue);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let loaded = track_loader.loaded();
+                if !loaded.is_empty() {
+                    AudioHandler::with_sink(&sink_ref, |sink| {
+                        for loaded in loaded {
+                            match loaded {
+                                Loaded::Enqueue(opened) => AudioHandler::enqueue(
+                                    opened,
+                                    sink,
+                                    &mut queue,
+                                    &mut queue_slots,
+                                ),
+                                Loaded::Open(opened) => AudioHandler::open(
+                                    opened,
+                                    sink,
+                                    &mut loop_player,
+                                    &mut queue,
+                                    &mut queue_slots,
+                                ),
+                                Loaded::GoBack { previous, current } => AudioHandler::go_back(
+                                    *previous,
+                                    *current,
+                                    sink,
+                                    &mut loop_player,
+                                    &mut queue,
+                                    &mut queue_slots,
+                                ),
+                            }
+                        }
+                    });
+                    AudioHandler::send_queue(&queue_sender, &queue);
+                }
+
+                if length_scanner.tick(&mut queue) {
+                    AudioHandler::send_queue(&queue_sender, &queue);
+                }
+
+                AudioHandler::with_sink(&sink_ref, |sink| {
+                    if let Some(loop_start) = loop_player.tick(sink, &mixer)
+                        && let Err(e) = audio_pos_sender.send(loop_start)
+                    {
+                        eprintln!("Unable to send position to progress bar: {:?}", e)
+                    }
+
+                    let finished_tracks = queue.finished_tracks(queue_slots.sources_left(sink));
+                    if finished_tracks > 0 {
+                        queue.advance(finished_tracks);
+                        queue_slots.advance(finished_tracks);
+
+                        // The loop belonged to the track that finished
+                        loop_player.set_loop(None, sink);
+                        for _ in 0..finished_tracks {
+                            sleep_timer.track_finished();
+                        }
+
+                        AudioHandler::send_queue(&queue_sender, &queue);
+                    }
+
+                    if let Some(status) = sleep_timer.tick(sink, queue.current().length)
+                        && let Err(e) = sleep_timer_sender.send(status)
+                    {
+                        eprintln!("Unable to send sleep timer status: {:?}", e)
+                    }
+                });
             }
         });
     }
 
-    /// Create a new thread to send the audio's current position to the progress bar
+    /// Create a new thread to send the audio's current position to the progress bar every `interval`
     fn send_audio_pos(
         audio_pos_sender: mpsc::Sender<Duration>,
         new_sink_ref: Arc<Mutex<Option<Sink>>>,
+        interval: Duration,
     ) {
         thread::spawn(move || {
             loop {
@@ -112,8 +257,7 @@ impl AudioHandler {
                 }
 
                 // Sleep to prevent using too much cpu
-                let delay = Duration::from_millis(50);
-                thread::sleep(delay);
+                thread::sleep(interval);
             }
         });
     }
@@ -122,29 +266,243 @@ impl AudioHandler {
         rodio::Sink::connect_new(stream_handle.mixer())
     }
 
-    fn open_output_stream() -> OutputStream {
-        rodio::OutputStreamBuilder::open_default_stream().expect("open default audio stream")
+    /// Open the output device and buffer size in `settings`. If they can't be used, the default device is used instead,
+    /// so that the player still works.
+    fn open_output_stream(settings: &AudioSettings) -> OutputStream {
+        if settings.output_device.is_some() || settings.buffer_size.is_some() {
+            match AudioHandler::open_configured_stream(settings) {
+                Ok(stream) => return stream,
+                Err(e) => eprintln!("{}. Using the default output device instead", e),
+            }
+        }
+
+        OutputStreamBuilder::open_default_stream().expect("open default audio stream")
+    }
+
+    /// Open the output device and buffer size in `settings`.
+    /// # Errors
+    /// - If there is no output device with the name in `settings`
+    /// - If the device can't be opened, e.g. because it doesn't support the buffer size
+    fn open_configured_stream(settings: &AudioSettings) -> Result<OutputStream, String> {
+        let builder = match &settings.output_device {
+            Some(name) => {
+                let device = cpal::default_host()
+                    .output_devices()
+                    .map_err(|e| format!("Unable to list the output devices: {}", e))?
+                    .find(|device| device.name().is_ok_and(|device_name| device_name == *name))
+                    .ok_or_else(|| format!("There is no output device called \"{}\"", name))?;
+                OutputStreamBuilder::from_device(device)
+            }
+            None => OutputStreamBuilder::from_default_device(),
+        }
+        .map_err(|e| format!("Unable to open the output device: {}", e))?;
+
+        let builder = match settings.buffer_size {
+            Some(frames) => builder.with_buffer_size(cpal::BufferSize::Fixed(frames)),
+            None => builder,
+        };
+
+        builder
+            .open_stream()
+            .map_err(|e| format!("Unable to open the output device: {}", e))
     }
 
     /// A function that handles messages sent to the audio thread.
+    /// Returns whether the queue changed, so that the UI can be told about it.
+    #[allow(clippy::too_many_arguments)]
     fn handle_messages(
         message: Message,
         sink_ref: &Arc<Mutex<Option<Sink>>>,
         audio_pos_sender: &mpsc::Sender<Duration>,
-    ) {
+        loop_player: &mut LoopPlayer,
+        sleep_timer: &mut SleepTimer,
+        queue: &mut Queue,
+        queue_slots: &mut QueueSlots,
+        track_loader: &TrackLoader,
+    ) -> bool {
         match message {
             Message::Play => AudioHandler::with_sink(sink_ref, |sink| {
+                loop_player.cancel_gap();
                 sink.play();
             }),
             Message::Pause => AudioHandler::with_sink(sink_ref, |sink| {
+                loop_player.cancel_gap();
                 sink.pause();
             }),
+            // A stream can only be played forwards, so there is nothing to seek in
+            Message::FastForward(_) | Message::Rewind(_) | Message::Seek(_)
+                if queue.current().stream.is_some() => {}
             Message::FastForward(duration_secs) => AudioHandler::with_sink(sink_ref, |sink| {
                 AudioHandler::fast_forward(audio_pos_sender, duration_secs, sink);
             }),
             Message::Rewind(duration_secs) => AudioHandler::with_sink(sink_ref, |sink| {
                 AudioHandler::rewind(audio_pos_sender, duration_secs, sink);
             }),
+            Message::Seek(target_pos) => AudioHandler::with_sink(sink_ref, |sink| {
+                AudioHandler::seek(audio_pos_sender, sink, target_pos);
+            }),
+            Message::SetLoop(ab_loop) => AudioHandler::with_sink(sink_ref, |sink| {
+                loop_player.set_loop(ab_loop, sink);
+            }),
+            Message::SetSleepTimer(mode) => AudioHandler::with_sink(sink_ref, |sink| {
+                sleep_timer.set(mode, sink);
+            }),
+            Message::ExtendSleepTimer => AudioHandler::with_sink(sink_ref, |sink| {
+                sleep_timer.extend(sink);
+            }),
+            Message::SetVolume(volume) => AudioHandler::with_sink(sink_ref, |sink| {
+                // The sleep timer may be fading the volume out, so let it decide how loud the sink is
+                sleep_timer.set_volume(volume, sink);
+            }),
+            // The queue changes once the tracks are opened
+            Message::Enqueue(path) => track_loader.enqueue(path),
+            Message::Open(paths) => track_loader.open(paths),
+            Message::MoveTrack { from, to } => {
+                return AudioHandler::move_track(from, to, queue, queue_slots);
+            }
+            Message::RemoveTrack(index) => {
+                return AudioHandler::remove_track(index, queue, queue_slots);
+            }
+            // The tracks that are already in the sink keep their gain, and only the ones opened from now on change
+            Message::SetReplayGain(replay_gain) => track_loader.set_replay_gain(replay_gain),
+            // The tracks that were played are no longer in the sink, so only the queue forgets them
+            Message::ClearPlayed => return queue.clear_played(),
+            Message::Next => AudioHandler::with_sink(sink_ref, |sink| {
+                // The queue moves on once the audio thread notices that the track is gone
+                if !queue.upcoming().is_empty() {
+                    queue.skip_current(sink.get_pos());
+                    sink.skip_one();
+                }
+            }),
+            Message::Previous => {
+                // The tracks that were played are gone from the sink, so they are opened again
+                if let Some(previous) = queue.current_index().checked_sub(1) {
+                    let previous = queue.tracks()[previous].path.clone();
+                    track_loader.go_back(previous, queue.current().path.clone());
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Add a track that was opened to the end of the queue.
+    fn enqueue(
+        (source, track): OpenedTrack,
+        sink: &Sink,
+        queue: &mut Queue,
+        queue_slots: &mut QueueSlots,
+    ) {
+        // If everything in the queue has already played, the new track starts straight away
+        let ran_out = sink.empty();
+
+        queue_slots.push(source, sink);
+        queue.push(track);
+
+        if ran_out {
+            queue.advance(1);
+            queue_slots.advance(1);
+        }
+    }
+
+    /// Replace the queue with a track that was opened. The rest of the tracks that replace it are added as they are opened.
+    fn open(
+        (source, track): OpenedTrack,
+        sink: &Sink,
+        loop_player: &mut LoopPlayer,
+        queue: &mut Queue,
+        queue_slots: &mut QueueSlots,
+    ) {
+        // Clearing the sink pauses it, so only start the new track if the old one was playing
+        let paused = sink.is_paused();
+        loop_player.set_loop(None, sink);
+        sink.clear();
+
+        // The sink is empty, so the new track gets a slot of its own, which it starts straight away
+        queue_slots.push(source, sink);
+        queue_slots.advance(1);
+        queue.replace(track);
+
+        if !paused {
+            sink.play();
+        }
+    }
+
+    /// Go back to the track before the current one, playing it again along with the current one and the tracks after it.
+    /// Nothing happens if the queue moved on while they were being opened.
+    fn go_back(
+        (previous_source, previous): OpenedTrack,
+        (current_source, current): OpenedTrack,
+        sink: &Sink,
+        loop_player: &mut LoopPlayer,
+        queue: &mut Queue,
+        queue_slots: &mut QueueSlots,
+    ) {
+        let index = queue.current_index();
+        if index == 0
+            || queue.tracks()[index - 1].path != previous.path
+            || queue.current().path != current.path
+        {
+            return;
+        }
+
+        // The tracks after the current one are kept as they are, so only the two tracks before them are opened again
+        let Some(upcoming) = queue_slots.take_upcoming(sink) else {
+            return;
+        };
+
+        // Clearing the sink pauses it, so only carry on playing if the track was playing
+        let paused = sink.is_paused();
+        queue.skip_current(sink.get_pos());
+        loop_player.set_loop(None, sink);
+        sink.clear();
+
+        queue_slots.push(previous_source, sink);
+        queue_slots.advance(1);
+        queue_slots.push(current_source, sink);
+        for source in upcoming {
+            queue_slots.push(source, sink);
+        }
+        queue.go_back();
+
+        if !paused {
+            sink.play();
+        }
+    }
+
+    /// Move the track at `from` in the queue to `to`, in both the queue and the sink.
+    /// Returns `false` if the track can't be moved, such as when it has already started.
+    fn move_track(from: usize, to: usize, queue: &mut Queue, queue_slots: &mut QueueSlots) -> bool {
+        // The slots start with the track after the current one
+        let first_slot = queue.current_index() + 1;
+
+        if !queue.move_track(from, to) {
+            return false;
+        }
+        if !queue_slots.move_track(from - first_slot, to - first_slot) {
+            // The sink reached the track in the meantime, so put it back where it was
+            queue.move_track(to, from);
+            return false;
+        }
+
+        true
+    }
+
+    /// Remove the track at `index` from the queue, and from the sink if it hasn't been played yet.
+    /// Returns `false` if the track can't be removed, such as when it is playing.
+    fn remove_track(index: usize, queue: &mut Queue, queue_slots: &mut QueueSlots) -> bool {
+        let current = queue.current_index();
+
+        if index > current && !queue_slots.remove(index - current - 1) {
+            return false;
+        }
+
+        queue.remove(index)
+    }
+
+    fn send_queue(queue_sender: &mpsc::Sender<Queue>, queue: &Queue) {
+        if let Err(e) = queue_sender.send(queue.clone()) {
+            eprintln!("Unable to send the queue: {:?}", e)
         }
     }
 
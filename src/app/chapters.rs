@@ -0,0 +1,835 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use lofty::prelude::*;
+use lofty::tag::ItemKey;
+
+use crate::app::stream;
+use crate::cli::parse_timestamp;
+
+/// A chapter that is embedded in an audio file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chapter {
+    pub(crate) start: Duration,
+    pub(crate) title: String,
+}
+
+/// Read the chapters that are embedded in an audio file, sorted by their start.
+///
+/// Chapters are read from ID3 `CHAP` frames, MP4 chapters (both Nero `chpl` atoms and QuickTime chapter tracks),
+/// and `CHAPTERxx` Vorbis comments. Files without chapters, or whose chapters can't be read, return no chapters.
+pub(crate) fn read_chapters(path: &Path) -> Vec<Chapter> {
+    // Internet streams have no chapters, and can't be opened as files
+    if stream::is_stream(path) {
+        return Vec::new();
+    }
+
+    let mut chapters = match read_chapters_from_file(path) {
+        Ok(chapters) => chapters,
+        Err(e) => {
+            eprintln!("Unable to read chapters of {}: {}", path.display(), e);
+            Vec::new()
+        }
+    };
+
+    chapters.sort_by_key(|chapter| chapter.start);
+    chapters
+}
+
+fn read_chapters_from_file(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = File::open(path)?;
+
+    // Every format is recognized by its first few bytes
+    let mut magic = [0; 8];
+    let read = file.read(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if read >= 3 && &magic[..3] == b"ID3" {
+        return read_id3_chapters(&mut file);
+    }
+
+    if read == 8 && &magic[4..8] == b"ftyp" {
+        return mp4::read_chapters(&mut file);
+    }
+
+    Ok(read_vorbis_chapters(path))
+}
+
+/// Read the chapters from the `CHAPTERxx` and `CHAPTERxxNAME` comments of Ogg and FLAC files.
+fn read_vorbis_chapters(path: &Path) -> Vec<Chapter> {
+    // Files that lofty can't read simply have no chapters
+    let Ok(tagged_file) = lofty::read_from_path(path) else {
+        return Vec::new();
+    };
+
+    let comments = tagged_file.tags().iter().flat_map(|tag| {
+        tag.items()
+            .filter_map(|item| match (item.key(), item.value().text()) {
+                (ItemKey::Unknown(key), Some(value)) => Some((key.as_str(), value)),
+                _ => None,
+            })
+    });
+
+    parse_vorbis_chapters(comments)
+}
+
+/// Turn `CHAPTERxx=HH:MM:SS.mmm` and `CHAPTERxxNAME=Title` comments into chapters.
+fn parse_vorbis_chapters<'a>(comments: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<Chapter> {
+    const PREFIX: &str = "CHAPTER";
+    const NAME_SUFFIX: &str = "NAME";
+
+    // The start and title of each chapter, by its number
+    let mut starts = Vec::new();
+    let mut titles = Vec::new();
+
+    for (key, value) in comments {
+        let key = key.to_uppercase();
+        let Some(rest) = key.strip_prefix(PREFIX) else {
+            continue;
+        };
+
+        if let Some(number) = rest.strip_suffix(NAME_SUFFIX) {
+            titles.push((number.to_string(), value.to_string()));
+        } else if !rest.is_empty()
+            && rest.chars().all(|c| c.is_ascii_digit())
+            && let Ok(start) = parse_timestamp(value)
+        {
+            starts.push((rest.to_string(), start));
+        }
+    }
+
+    starts
+        .into_iter()
+        .map(|(number, start)| {
+            let title = titles
+                .iter()
+                .find(|(title_number, _)| *title_number == number)
+                .map(|(_, title)| title.clone())
+                .unwrap_or_else(|| format!("Chapter {}", number.trim_start_matches('0')));
+
+            Chapter { start, title }
+        })
+        .collect()
+}
+
+/// Read the chapters from the `CHAP` frames of a file's ID3v2 tag.
+fn read_id3_chapters(file: &mut File) -> io::Result<Vec<Chapter>> {
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+
+    let tag_size = syncsafe(&header[6..10]) as usize;
+    let mut tag = vec![0; tag_size];
+    file.read_exact(&mut tag)?;
+
+    Ok(parse_id3_chapters(&header, tag))
+}
+
+/// Parse the `CHAP` frames of an ID3v2.3 or ID3v2.4 tag, given its header and the rest of the tag.
+fn parse_id3_chapters(header: &[u8], mut tag: Vec<u8>) -> Vec<Chapter> {
+    const FLAG_UNSYNCHRONISATION: u8 = 0x80;
+    const FLAG_EXTENDED_HEADER: u8 = 0x40;
+
+    let version = header[3];
+    let flags = header[5];
+
+    // ID3v2.2 uses a different frame layout, and doesn't support chapters
+    if version != 3 && version != 4 {
+        return Vec::new();
+    }
+
+    if flags & FLAG_UNSYNCHRONISATION != 0 {
+        tag = remove_unsynchronisation(&tag);
+    }
+
+    let mut frames = &tag[..];
+    if flags & FLAG_EXTENDED_HEADER != 0 {
+        let Some(size) = frames.get(..4) else {
+            return Vec::new();
+        };
+
+        // The extended header's size includes itself in ID3v2.4, but not in ID3v2.3
+        let skip = match version {
+            4 => syncsafe(size) as usize,
+            _ => u32::from_be_bytes(size.try_into().unwrap()) as usize + 4,
+        };
+        frames = frames.get(skip..).unwrap_or_default();
+    }
+
+    id3_frames(frames, version)
+        .filter(|(id, _)| id == b"CHAP")
+        .filter_map(|(_, body)| parse_chap_frame(body, version))
+        .collect()
+}
+
+/// Parse the body of a single `CHAP` frame.
+fn parse_chap_frame(body: &[u8], version: u8) -> Option<Chapter> {
+    let element_id_len = body.iter().position(|&b| b == 0)?;
+    let element_id = String::from_utf8_lossy(&body[..element_id_len]).into_owned();
+
+    let times = body.get(element_id_len + 1..element_id_len + 17)?;
+    let start_ms = u32::from_be_bytes(times[..4].try_into().unwrap());
+
+    // The title is stored in an embedded TIT2 frame
+    let title = id3_frames(&body[element_id_len + 17..], version)
+        .find(|(id, _)| id == b"TIT2")
+        .map(|(_, body)| decode_id3_text(body))
+        .filter(|title| !title.is_empty())
+        .unwrap_or(element_id);
+
+    Some(Chapter {
+        start: Duration::from_millis(start_ms as u64),
+        title,
+    })
+}
+
+/// Iterate over the ID and body of each frame in an ID3v2 tag.
+fn id3_frames(mut frames: &[u8], version: u8) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let header = frames.get(..10)?;
+
+        // The frames are followed by padding
+        if header[0] == 0 {
+            return None;
+        }
+
+        let id: [u8; 4] = header[..4].try_into().unwrap();
+        let size = match version {
+            4 => syncsafe(&header[4..8]),
+            _ => u32::from_be_bytes(header[4..8].try_into().unwrap()),
+        } as usize;
+
+        let body = frames.get(10..10 + size)?;
+        frames = &frames[10 + size..];
+
+        Some((id, body))
+    })
+}
+
+/// Decode the body of an ID3v2 text frame, which starts with a byte that says how the text is encoded.
+fn decode_id3_text(body: &[u8]) -> String {
+    let Some((&encoding, text)) = body.split_first() else {
+        return String::new();
+    };
+
+    let text = match encoding {
+        // UTF-16 with a byte order mark
+        1 => match text {
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            _ => decode_utf16(text, u16::from_le_bytes),
+        },
+        // UTF-16BE
+        2 => decode_utf16(text, u16::from_be_bytes),
+        // UTF-8
+        3 => String::from_utf8_lossy(text).into_owned(),
+        // ISO-8859-1, whose characters all map directly to the same Unicode code points
+        _ => text.iter().map(|&b| b as char).collect(),
+    };
+
+    text.trim_end_matches('\0').to_string()
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+/// Decode a 28-bit "syncsafe" integer, where the highest bit of every byte is always 0.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .fold(0, |value, &b| (value << 7) | (b & 0x7F) as u32)
+}
+
+/// Undo the unsynchronisation scheme, which inserts a 0x00 after every 0xFF.
+fn remove_unsynchronisation(tag: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(tag.len());
+
+    for (i, &b) in tag.iter().enumerate() {
+        if b == 0 && i > 0 && tag[i - 1] == 0xFF {
+            continue;
+        }
+        result.push(b);
+    }
+
+    result
+}
+
+mod mp4 {
+    use super::*;
+
+    /// Read the chapters of an MP4 file, preferring a Nero `chpl` atom over a QuickTime chapter track.
+    pub(super) fn read_chapters(file: &mut File) -> io::Result<Vec<Chapter>> {
+        let Some(moov) = read_moov(file)? else {
+            return Ok(Vec::new());
+        };
+
+        if let Some(chpl) = child(&moov, b"udta").and_then(|udta| child(udta, b"chpl")) {
+            let chapters = parse_chpl(chpl);
+            if !chapters.is_empty() {
+                return Ok(chapters);
+            }
+        }
+
+        read_chapter_track(file, &moov)
+    }
+
+    /// Read the body of the `moov` atom, which holds all of the metadata of the file.
+    fn read_moov(file: &mut File) -> io::Result<Option<Vec<u8>>> {
+        let file_len = file.metadata()?.len();
+        let mut pos = 0;
+
+        while pos + 8 <= file_len {
+            file.seek(SeekFrom::Start(pos))?;
+
+            let mut header = [0; 8];
+            file.read_exact(&mut header)?;
+            let mut header_len = 8;
+
+            let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                // The atom goes on until the end of the file
+                0 => file_len - pos,
+                // The real size is stored in the next 8 bytes
+                1 => {
+                    let mut large_size = [0; 8];
+                    file.read_exact(&mut large_size)?;
+                    header_len = 16;
+                    u64::from_be_bytes(large_size)
+                }
+                size => size as u64,
+            };
+
+            if size < header_len {
+                break;
+            }
+
+            if &header[4..8] == b"moov" {
+                let mut moov = vec![0; (size - header_len) as usize];
+                file.read_exact(&mut moov)?;
+                return Ok(Some(moov));
+            }
+
+            pos += size;
+        }
+
+        Ok(None)
+    }
+
+    /// Iterate over the type and body of each atom in `data`.
+    fn atoms(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+        std::iter::from_fn(move || {
+            let header = data.get(..8)?;
+            let kind: [u8; 4] = header[4..8].try_into().unwrap();
+
+            let (size, header_len) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                0 => (data.len(), 8),
+                1 => (
+                    u64::from_be_bytes(data.get(8..16)?.try_into().unwrap()) as usize,
+                    16,
+                ),
+                size => (size as usize, 8),
+            };
+
+            let body = data.get(header_len..size)?;
+            data = &data[size..];
+
+            Some((kind, body))
+        })
+    }
+
+    /// Return the body of the first child atom of a type.
+    pub(super) fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+        atoms(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+    }
+
+    /// Parse a Nero `chpl` atom, whose chapter starts are stored in units of 100 nanoseconds.
+    pub(super) fn parse_chpl(chpl: &[u8]) -> Vec<Chapter> {
+        let Some(&version) = chpl.first() else {
+            return Vec::new();
+        };
+
+        // Version 1 has 4 more reserved bytes after the version and flags
+        let mut pos = if version == 1 { 8 } else { 4 };
+        let Some(&count) = chpl.get(pos) else {
+            return Vec::new();
+        };
+        pos += 1;
+
+        let mut chapters = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let Some(start) = chpl.get(pos..pos + 8) else {
+                break;
+            };
+            let start = u64::from_be_bytes(start.try_into().unwrap());
+
+            let Some(&title_len) = chpl.get(pos + 8) else {
+                break;
+            };
+            let Some(title) = chpl.get(pos + 9..pos + 9 + title_len as usize) else {
+                break;
+            };
+            pos += 9 + title_len as usize;
+
+            chapters.push(Chapter {
+                start: Duration::from_nanos(start * 100),
+                title: String::from_utf8_lossy(title).into_owned(),
+            });
+        }
+
+        chapters
+    }
+
+    /// Read the chapters from a QuickTime chapter track: a text track that another track points to through `tref/chap`.
+    fn read_chapter_track(file: &mut File, moov: &[u8]) -> io::Result<Vec<Chapter>> {
+        let traks: Vec<&[u8]> = atoms(moov)
+            .filter(|(kind, _)| kind == b"trak")
+            .map(|(_, body)| body)
+            .collect();
+
+        let chapter_track_id = traks.iter().find_map(|trak| {
+            let chap = child(child(trak, b"tref")?, b"chap")?;
+            Some(u32::from_be_bytes(chap.get(..4)?.try_into().unwrap()))
+        });
+
+        let Some(chapter_track_id) = chapter_track_id else {
+            return Ok(Vec::new());
+        };
+
+        let Some(table) = traks
+            .iter()
+            .find(|trak| track_id(trak) == Some(chapter_track_id))
+            .and_then(|trak| SampleTable::parse(trak))
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut chapters = Vec::new();
+        for (start, (offset, size)) in table
+            .sample_starts()
+            .into_iter()
+            .zip(table.sample_locations())
+        {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut sample = vec![0; size as usize];
+            file.read_exact(&mut sample)?;
+
+            chapters.push(Chapter {
+                start,
+                title: decode_text_sample(&sample),
+            });
+        }
+
+        Ok(chapters)
+    }
+
+    fn track_id(trak: &[u8]) -> Option<u32> {
+        let tkhd = child(trak, b"tkhd")?;
+
+        // Version 1 uses 64-bit creation and modification times
+        let offset = if *tkhd.first()? == 1 { 20 } else { 12 };
+        Some(u32::from_be_bytes(
+            tkhd.get(offset..offset + 4)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Decode a QuickTime text sample, which is a 16-bit length followed by the text.
+    fn decode_text_sample(sample: &[u8]) -> String {
+        let Some(len) = sample.get(..2) else {
+            return String::new();
+        };
+        let len = u16::from_be_bytes(len.try_into().unwrap()) as usize;
+        let text = sample.get(2..2 + len).unwrap_or(&sample[2..]);
+
+        match text {
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            _ => String::from_utf8_lossy(text).into_owned(),
+        }
+    }
+
+    /// The parts of a track's sample table that are needed to find out when each sample starts and where it's stored.
+    #[derive(Debug, Default)]
+    pub(super) struct SampleTable {
+        pub(super) timescale: u32,
+
+        /// How many samples in a row have the same duration, and that duration (`stts`)
+        pub(super) time_to_sample: Vec<(u32, u32)>,
+
+        /// From which chunk onwards each chunk has a certain number of samples (`stsc`)
+        pub(super) sample_to_chunk: Vec<(u32, u32)>,
+
+        /// The size of each sample (`stsz`)
+        pub(super) sample_sizes: Vec<u32>,
+
+        /// Where each chunk starts in the file (`stco` or `co64`)
+        pub(super) chunk_offsets: Vec<u64>,
+    }
+
+    impl SampleTable {
+        fn parse(trak: &[u8]) -> Option<SampleTable> {
+            let mdia = child(trak, b"mdia")?;
+            let mdhd = child(mdia, b"mdhd")?;
+            let stbl = child(child(mdia, b"minf")?, b"stbl")?;
+
+            let timescale_offset = if *mdhd.first()? == 1 { 20 } else { 12 };
+            let timescale = read_u32(mdhd, timescale_offset)?;
+
+            let time_to_sample = read_pairs(child(stbl, b"stts")?)?;
+            let sample_to_chunk = read_entries(child(stbl, b"stsc")?, 12)?
+                .map(|entry| (read_u32(entry, 0).unwrap(), read_u32(entry, 4).unwrap()))
+                .collect();
+
+            let stsz = child(stbl, b"stsz")?;
+            let constant_size = read_u32(stsz, 4)?;
+            let sample_count = read_u32(stsz, 8)?;
+            let sample_sizes = if constant_size != 0 {
+                vec![constant_size; sample_count as usize]
+            } else {
+                (0..sample_count as usize)
+                    .map(|i| read_u32(stsz, 12 + i * 4))
+                    .collect::<Option<_>>()?
+            };
+
+            let chunk_offsets = if let Some(stco) = child(stbl, b"stco") {
+                read_entries(stco, 4)?
+                    .map(|entry| read_u32(entry, 0).unwrap() as u64)
+                    .collect()
+            } else {
+                read_entries(child(stbl, b"co64")?, 8)?
+                    .map(|entry| u64::from_be_bytes(entry.try_into().unwrap()))
+                    .collect()
+            };
+
+            Some(SampleTable {
+                timescale,
+                time_to_sample,
+                sample_to_chunk,
+                sample_sizes,
+                chunk_offsets,
+            })
+        }
+
+        /// Return when each sample starts.
+        pub(super) fn sample_starts(&self) -> Vec<Duration> {
+            let mut starts = Vec::with_capacity(self.sample_sizes.len());
+            let mut time = 0_u64;
+
+            for &(count, delta) in &self.time_to_sample {
+                for _ in 0..count {
+                    starts.push(Duration::from_secs_f64(
+                        time as f64 / self.timescale.max(1) as f64,
+                    ));
+                    time += delta as u64;
+                }
+            }
+
+            starts
+        }
+
+        /// Return the offset in the file and size of each sample.
+        pub(super) fn sample_locations(&self) -> Vec<(u64, u32)> {
+            let mut locations = Vec::with_capacity(self.sample_sizes.len());
+            let mut sizes = self.sample_sizes.iter();
+
+            for (i, &chunk_offset) in self.chunk_offsets.iter().enumerate() {
+                let chunk = i as u32 + 1;
+
+                // The last entry that starts at or before this chunk says how many samples it has
+                let samples_in_chunk = self
+                    .sample_to_chunk
+                    .iter()
+                    .rev()
+                    .find(|(first_chunk, _)| *first_chunk <= chunk)
+                    .map_or(0, |(_, samples)| *samples);
+
+                let mut offset = chunk_offset;
+                for _ in 0..samples_in_chunk {
+                    let Some(&size) = sizes.next() else {
+                        return locations;
+                    };
+
+                    locations.push((offset, size));
+                    offset += size as u64;
+                }
+            }
+
+            locations
+        }
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(offset..offset + 4)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Iterate over the entries of a full atom whose body is a version, flags, entry count and then the entries.
+    fn read_entries(atom: &[u8], entry_len: usize) -> Option<impl Iterator<Item = &[u8]>> {
+        let count = read_u32(atom, 4)? as usize;
+        let entries = atom.get(8..8 + count * entry_len)?;
+
+        Some(entries.chunks_exact(entry_len))
+    }
+
+    fn read_pairs(atom: &[u8]) -> Option<Vec<(u32, u32)>> {
+        Some(
+            read_entries(atom, 8)?
+                .map(|entry| (read_u32(entry, 0).unwrap(), read_u32(entry, 4).unwrap()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod parse_vorbis_chapters {
+        use super::*;
+
+        #[test]
+        fn test_chapters_with_names() {
+            let comments = [
+                ("CHAPTER001", "00:00:00.000"),
+                ("CHAPTER001NAME", "Intro"),
+                ("CHAPTER002", "00:01:30.500"),
+                ("CHAPTER002NAME", "Verse"),
+                ("TITLE", "Song"),
+            ];
+
+            assert_eq!(
+                parse_vorbis_chapters(comments.into_iter()),
+                vec![
+                    Chapter {
+                        start: Duration::ZERO,
+                        title: "Intro".to_string(),
+                    },
+                    Chapter {
+                        start: Duration::from_millis(90500),
+                        title: "Verse".to_string(),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_chapter_without_name() {
+            let comments = [("chapter003", "00:02:00.000")];
+
+            assert_eq!(
+                parse_vorbis_chapters(comments.into_iter()),
+                vec![Chapter {
+                    start: Duration::from_secs(120),
+                    title: "Chapter 3".to_string(),
+                }]
+            );
+        }
+
+        #[test]
+        fn test_invalid_timestamp() {
+            let comments = [("CHAPTER001", "soon")];
+
+            assert!(parse_vorbis_chapters(comments.into_iter()).is_empty());
+        }
+    }
+
+    mod parse_id3_chapters {
+        use super::*;
+
+        fn frame(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+            let mut frame = id.to_vec();
+            frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&[0, 0]);
+            frame.extend_from_slice(body);
+            frame
+        }
+
+        fn chap(element_id: &str, start_ms: u32, title: Option<&[u8]>) -> Vec<u8> {
+            let mut body = element_id.as_bytes().to_vec();
+            body.push(0);
+            body.extend_from_slice(&start_ms.to_be_bytes());
+            body.extend_from_slice(&(start_ms + 1000).to_be_bytes());
+            body.extend_from_slice(&[0xFF; 8]);
+            if let Some(title) = title {
+                body.extend_from_slice(&frame(b"TIT2", title));
+            }
+            frame(b"CHAP", &body)
+        }
+
+        #[test]
+        fn test_chap_frames() {
+            let mut tag = frame(b"TIT2", b"\x03Song");
+            tag.extend_from_slice(&chap("ch0", 0, Some(b"\x03First")));
+            tag.extend_from_slice(&chap("ch1", 61000, Some(b"\x01\xFF\xFES\0e\0c\0o\0n\0d\0")));
+            tag.extend_from_slice(&chap("ch2", 120000, None));
+            tag.extend_from_slice(&[0; 16]);
+
+            let header = [b'I', b'D', b'3', 3, 0, 0, 0, 0, 0, 0];
+
+            assert_eq!(
+                parse_id3_chapters(&header, tag),
+                vec![
+                    Chapter {
+                        start: Duration::ZERO,
+                        title: "First".to_string(),
+                    },
+                    Chapter {
+                        start: Duration::from_secs(61),
+                        title: "Second".to_string(),
+                    },
+                    Chapter {
+                        start: Duration::from_secs(120),
+                        title: "ch2".to_string(),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_id3v22_is_ignored() {
+            let header = [b'I', b'D', b'3', 2, 0, 0, 0, 0, 0, 0];
+
+            assert!(parse_id3_chapters(&header, chap("ch0", 0, None)).is_empty());
+        }
+
+        #[test]
+        fn test_syncsafe() {
+            assert_eq!(syncsafe(&[0x00, 0x00, 0x02, 0x01]), 257);
+        }
+
+        #[test]
+        fn test_remove_unsynchronisation() {
+            assert_eq!(
+                remove_unsynchronisation(&[0xFF, 0x00, 0xE0, 0x00]),
+                vec![0xFF, 0xE0, 0x00]
+            );
+        }
+    }
+
+    mod mp4 {
+        use super::super::mp4::*;
+        use super::*;
+
+        #[test]
+        fn test_parse_chpl() {
+            let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, 2];
+            chpl.extend_from_slice(&0_u64.to_be_bytes());
+            chpl.push(5);
+            chpl.extend_from_slice(b"Intro");
+            chpl.extend_from_slice(&(15 * 10_000_000_u64).to_be_bytes());
+            chpl.push(3);
+            chpl.extend_from_slice(b"End");
+
+            assert_eq!(
+                parse_chpl(&chpl),
+                vec![
+                    Chapter {
+                        start: Duration::ZERO,
+                        title: "Intro".to_string(),
+                    },
+                    Chapter {
+                        start: Duration::from_secs(15),
+                        title: "End".to_string(),
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_child() {
+            let mut data = Vec::new();
+            data.extend_from_slice(&12_u32.to_be_bytes());
+            data.extend_from_slice(b"free");
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&10_u32.to_be_bytes());
+            data.extend_from_slice(b"udta");
+            data.extend_from_slice(&[1, 2]);
+
+            assert_eq!(child(&data, b"udta"), Some(&[1, 2][..]));
+            assert_eq!(child(&data, b"moov"), None);
+        }
+
+        #[test]
+        fn test_sample_table() {
+            let table = SampleTable {
+                timescale: 1000,
+                time_to_sample: vec![(2, 30000), (1, 5000)],
+                // Two samples in the first chunk, and one in every chunk after that
+                sample_to_chunk: vec![(1, 2), (2, 1)],
+                sample_sizes: vec![10, 20, 30],
+                chunk_offsets: vec![100, 500],
+            };
+
+            assert_eq!(
+                table.sample_starts(),
+                vec![
+                    Duration::ZERO,
+                    Duration::from_secs(30),
+                    Duration::from_secs(60)
+                ]
+            );
+            assert_eq!(
+                table.sample_locations(),
+                vec![(100, 10), (110, 20), (500, 30)]
+            );
+        }
+    }
+
+    mod read_chapters {
+        use std::fs;
+
+        use lofty::config::WriteOptions;
+        use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+
+        use super::*;
+
+        #[test]
+        fn test_vorbis_comments() {
+            let path = std::env::temp_dir()
+                .join(format!("audio_player_chapters_{}.ogg", std::process::id()));
+            fs::copy(
+                "./src/app/ui/tests/files/audio/without-metadata/test.ogg",
+                &path,
+            )
+            .unwrap();
+
+            // Chapter comments have no ItemKey of their own, so they have to be inserted unchecked
+            let mut tag = Tag::new(TagType::VorbisComments);
+            for (key, value) in [("CHAPTER001", "00:00:10.000"), ("CHAPTER001NAME", "Ten")] {
+                tag.insert_unchecked(TagItem::new(
+                    ItemKey::Unknown(key.to_string()),
+                    ItemValue::Text(value.to_string()),
+                ));
+            }
+            tag.save_to_path(&path, WriteOptions::default()).unwrap();
+
+            let chapters = read_chapters(&path);
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(
+                chapters,
+                vec![Chapter {
+                    start: Duration::from_secs(10),
+                    title: "Ten".to_string(),
+                }]
+            );
+        }
+
+        #[test]
+        fn test_no_chapters() {
+            assert!(
+                read_chapters(Path::new(
+                    "./src/app/ui/tests/files/audio/without-metadata/test.ogg"
+                ))
+                .is_empty()
+            );
+        }
+    }
+}
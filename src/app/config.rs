@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::app::http_api::HttpSettings;
+use crate::app::keybindings::{Keybindings, SeekSteps};
+use crate::app::replay_gain::{ReplayGain, ReplayGainMode};
+use crate::app::theme::ThemeSettings;
+
+/// The config file that is written when the user doesn't have one, which lists every setting.
+const DEFAULT_CONFIG: &str = include_str!("../../assets/config.toml");
+
+/// The `[playback]` table of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PlaybackSettings {
+    /// How far the seek buttons and the seek shortcuts jump, in seconds
+    pub(crate) seek_step: f64,
+
+    /// How far the large seek shortcuts jump, in seconds
+    pub(crate) large_seek_step: f64,
+
+    pub(crate) replay_gain: ReplayGainMode,
+
+    /// How many decibels are added on top of the ReplayGain
+    pub(crate) replay_gain_preamp: f32,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> PlaybackSettings {
+        let seek_steps = SeekSteps::default();
+
+        PlaybackSettings {
+            seek_step: seek_steps.step.as_secs_f64(),
+            large_seek_step: seek_steps.large_step.as_secs_f64(),
+            replay_gain: ReplayGainMode::default(),
+            replay_gain_preamp: 0.0,
+        }
+    }
+}
+
+impl PlaybackSettings {
+    const MAX_SEEK_STEP: f64 = 3600.0;
+    const MAX_PREAMP: f32 = 15.0;
+
+    pub(crate) fn seek_steps(&self) -> SeekSteps {
+        SeekSteps {
+            step: Duration::from_secs_f64(self.seek_step),
+            large_step: Duration::from_secs_f64(self.large_seek_step),
+        }
+    }
+
+    pub(crate) fn replay_gain(&self) -> ReplayGain {
+        ReplayGain {
+            mode: self.replay_gain,
+            preamp: self.replay_gain_preamp,
+        }
+    }
+}
+
+/// The `[audio]` table of the config file, which is only read when the player starts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AudioSettings {
+    /// The name of the output device. The system's default device is used if this is `None`
+    pub(crate) output_device: Option<String>,
+
+    /// The size of the output buffer, in frames. The device picks the size if this is `None`
+    pub(crate) buffer_size: Option<u32>,
+
+    /// How often the progress bar is told where the audio is, in milliseconds
+    pub(crate) position_interval: u64,
+}
+
+impl Default for AudioSettings {
+    fn default() -> AudioSettings {
+        AudioSettings {
+            output_device: None,
+            buffer_size: None,
+            position_interval: 50,
+        }
+    }
+}
+
+impl AudioSettings {
+    const BUFFER_SIZES: (u32, u32) = (64, 65536);
+    const POSITION_INTERVALS: (u64, u64) = (10, 1000);
+
+    pub(crate) fn position_interval(&self) -> Duration {
+        Duration::from_millis(self.position_interval)
+    }
+}
+
+/// The `[library]` table of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LibrarySettings {
+    /// The folders that the music is in. A leading `~` stands for the user's home folder
+    pub(crate) music_folders: Vec<PathBuf>,
+}
+
+impl LibrarySettings {
+    /// Return the folder that the open dialogs start in, and that the library search looks through.
+    pub(crate) fn main_folder(&self) -> Option<PathBuf> {
+        self.music_folders.first().cloned().or_else(dirs::audio_dir)
+    }
+}
+
+/// Every setting of the player, from the user's config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Config {
+    pub(crate) playback: PlaybackSettings,
+    pub(crate) audio: AudioSettings,
+    pub(crate) library: LibrarySettings,
+    pub(crate) theme: ThemeSettings,
+    pub(crate) keybindings: Keybindings,
+
+    /// The settings of the HTTP server, which only runs when they are there
+    pub(crate) http: Option<HttpSettings>,
+}
+
+/// The config file as it is written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    playback: PlaybackSettings,
+    #[serde(default)]
+    audio: AudioSettings,
+    #[serde(default)]
+    library: LibrarySettings,
+    #[serde(default)]
+    theme: ThemeSettings,
+    #[serde(default)]
+    keybindings: HashMap<String, Vec<String>>,
+    http: Option<HttpSettings>,
+}
+
+impl Config {
+    const FILE_NAME: &str = "config.toml";
+
+    /// Return where the config file is kept, which is `$XDG_CONFIG_HOME/audio_player/config.toml`.
+    /// Without `$XDG_CONFIG_HOME`, the platform's config directory is used, such as `~/.config`.
+    pub(crate) fn path() -> Option<PathBuf> {
+        Config::path_in(env::var_os("XDG_CONFIG_HOME"))
+    }
+
+    fn path_in(xdg_config_home: Option<OsString>) -> Option<PathBuf> {
+        // The spec says that a relative path is invalid, and should be ignored
+        let config_dir = xdg_config_home
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(dirs::config_dir)?;
+
+        Some(config_dir.join("audio_player").join(Config::FILE_NAME))
+    }
+
+    /// Load the user's config file, writing the default one if there isn't one yet.
+    /// If the file can't be read, the default settings are used so that the player still works.
+    pub(crate) fn load() -> Config {
+        let Some(path) = Config::path() else {
+            eprintln!("Unable to find the config directory, using the default settings");
+            return Config::default();
+        };
+
+        if !path.exists()
+            && let Err(e) = Config::write_default(&path)
+        {
+            eprintln!(
+                "Unable to write the default config to {}: {}",
+                path.display(),
+                e
+            );
+        }
+
+        Config::load_from(&path)
+    }
+
+    /// Load a specific config file. A missing file results in the default settings.
+    pub(crate) fn load_from(path: &Path) -> Config {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Config::default(),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path.display(), e);
+                return Config::default();
+            }
+        };
+
+        match Config::parse(&contents) {
+            Ok((config, warnings)) => {
+                for warning in warnings {
+                    eprintln!("{}: {}", path.display(), warning);
+                }
+                config
+            }
+            Err(e) => {
+                eprintln!("Unable to parse {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Parse the contents of a config file.
+    /// Settings that are out of range are replaced by their defaults, and returned as warnings.
+    /// # Errors
+    /// - If the file isn't valid TOML
+    /// - If a setting has the wrong type, or doesn't exist
+    pub(crate) fn parse(contents: &str) -> Result<(Config, Vec<String>), String> {
+        let file: ConfigFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+        let (keybindings, mut warnings) = Keybindings::from_config(&file.keybindings);
+        let mut config = Config {
+            playback: file.playback,
+            audio: file.audio,
+            library: file.library,
+            theme: file.theme,
+            keybindings,
+            http: file.http,
+        };
+        warnings.extend(config.validate());
+
+        Ok((config, warnings))
+    }
+
+    /// Replace the settings that are out of range with their defaults, and return what was wrong with them.
+    fn validate(&mut self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let defaults = Config::default();
+
+        let playback = &mut self.playback;
+        for (name, value, default) in [
+            (
+                "playback.seek_step",
+                &mut playback.seek_step,
+                defaults.playback.seek_step,
+            ),
+            (
+                "playback.large_seek_step",
+                &mut playback.large_seek_step,
+                defaults.playback.large_seek_step,
+            ),
+        ] {
+            // NaN isn't in any range, so it is replaced too
+            if !(*value > 0.0 && *value <= PlaybackSettings::MAX_SEEK_STEP) {
+                warnings.push(format!(
+                    "{} has to be more than 0 and at most {} seconds, but is {}. Using {} instead",
+                    name,
+                    PlaybackSettings::MAX_SEEK_STEP,
+                    value,
+                    default
+                ));
+                *value = default;
+            }
+        }
+
+        let preamp = playback.replay_gain_preamp;
+        let max = PlaybackSettings::MAX_PREAMP;
+        if !(-max..=max).contains(&preamp) {
+            warnings.push(format!(
+                "playback.replay_gain_preamp has to be between -{} and {} dB, but is {}. Using {} instead",
+                max, max, preamp, defaults.playback.replay_gain_preamp
+            ));
+            playback.replay_gain_preamp = defaults.playback.replay_gain_preamp;
+        }
+
+        let (min, max) = AudioSettings::BUFFER_SIZES;
+        if let Some(buffer_size) = self.audio.buffer_size
+            && !(min..=max).contains(&buffer_size)
+        {
+            warnings.push(format!(
+                "audio.buffer_size has to be between {} and {} frames, but is {}. Letting the device pick the size instead",
+                min, max, buffer_size
+            ));
+            self.audio.buffer_size = None;
+        }
+
+        let (min, max) = AudioSettings::POSITION_INTERVALS;
+        let position_interval = self.audio.position_interval;
+        if !(min..=max).contains(&position_interval) {
+            warnings.push(format!(
+                "audio.position_interval has to be between {} and {} milliseconds, but is {}. Using {} instead",
+                min, max, position_interval, defaults.audio.position_interval
+            ));
+            self.audio.position_interval = defaults.audio.position_interval;
+        }
+
+        for folder in self.library.music_folders.iter_mut() {
+            *folder = expand_home(folder);
+            if !folder.is_dir() {
+                warnings.push(format!(
+                    "library.music_folders has {}, which isn't a folder",
+                    folder.display()
+                ));
+            }
+        }
+
+        if let Some(http) = self.http.as_mut() {
+            if http.token.trim().is_empty() {
+                warnings.push(
+                    "http.token can't be empty, since it is what keeps others out. The HTTP server is off"
+                        .to_string(),
+                );
+                self.http = None;
+            } else if http.library.is_none() {
+                http.library = self.library.music_folders.first().cloned();
+            }
+        }
+
+        warnings
+    }
+
+    /// Write the default config to `path`, so that the user can see every setting.
+    /// # Errors
+    /// - If the directory of the file could not be created
+    /// - If the file could not be written
+    fn write_default(path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, DEFAULT_CONFIG)?;
+        println!("Wrote the default config to {}", path.display());
+
+        Ok(())
+    }
+}
+
+/// Replace a leading `~` in `path` with the user's home folder.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Watches the config file in the background, and loads it again whenever it changes.
+pub(crate) struct ConfigWatcher {
+    receiver: mpsc::Receiver<Result<(Config, Vec<String>), String>>,
+}
+
+impl ConfigWatcher {
+    /// How often the file is checked for changes.
+    const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Start watching the config file at `path`.
+    pub(crate) fn start(path: PathBuf) -> ConfigWatcher {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+            let mut last_modified: Option<SystemTime> = modified(&path);
+
+            loop {
+                thread::sleep(ConfigWatcher::POLL_INTERVAL);
+
+                let now_modified = modified(&path);
+                if now_modified == last_modified {
+                    continue;
+                }
+                last_modified = now_modified;
+
+                // A file that was deleted is written again with the defaults the next time the player starts
+                let Ok(contents) = fs::read_to_string(&path) else {
+                    continue;
+                };
+
+                // The player was closed
+                if sender.send(Config::parse(&contents)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        ConfigWatcher { receiver }
+    }
+
+    /// Return the config file as it was last loaded, if it changed since this was last called.
+    /// An error means that the file was changed, but can't be used.
+    pub(crate) fn changed(&self) -> Option<Result<(Config, Vec<String>), String>> {
+        self.receiver.try_iter().last()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn test_default_config() {
+            let (config, warnings) = Config::parse(DEFAULT_CONFIG).unwrap();
+
+            assert_eq!(config, Config::default());
+            assert!(warnings.is_empty());
+        }
+
+        #[test]
+        fn test_empty_file() {
+            assert_eq!(Config::parse(""), Ok((Config::default(), Vec::new())));
+        }
+
+        #[test]
+        fn test_settings() {
+            let (config, warnings) = Config::parse(
+                "[playback]\n\
+                 seek_step = 10\n\
+                 replay_gain = \"album\"\n\
+                 \n\
+                 [audio]\n\
+                 output_device = \"USB DAC\"\n\
+                 buffer_size = 4096\n",
+            )
+            .unwrap();
+
+            assert!(warnings.is_empty());
+            assert_eq!(config.playback.seek_steps().step, Duration::from_secs(10));
+            assert_eq!(config.playback.replay_gain, ReplayGainMode::Album);
+            assert_eq!(config.audio.output_device.as_deref(), Some("USB DAC"));
+            assert_eq!(config.audio.buffer_size, Some(4096));
+            // The settings that aren't there keep their defaults
+            assert_eq!(config.playback.large_seek_step, 30.0);
+        }
+
+        #[test]
+        fn test_unknown_setting() {
+            let e = Config::parse("[playback]\nseek_stpe = 10\n").unwrap_err();
+
+            assert!(e.contains("line 2"), "{}", e);
+            assert!(e.contains("seek_stpe"), "{}", e);
+            assert!(e.contains("seek_step"), "{}", e);
+        }
+
+        #[test]
+        fn test_unknown_table() {
+            let e = Config::parse("[playbak]\n").unwrap_err();
+            assert!(e.contains("playbak"), "{}", e);
+        }
+
+        #[test]
+        fn test_wrong_type() {
+            let e = Config::parse("[playback]\nreplay_gain = true\n").unwrap_err();
+            assert!(e.contains("line 2"), "{}", e);
+        }
+
+        #[test]
+        fn test_out_of_range() {
+            let (config, warnings) = Config::parse(
+                "[playback]\n\
+                 seek_step = 0\n\
+                 large_seek_step = 45\n\
+                 \n\
+                 [audio]\n\
+                 buffer_size = 1\n\
+                 position_interval = 0\n",
+            )
+            .unwrap();
+
+            assert_eq!(warnings.len(), 3);
+            assert!(warnings[0].contains("playback.seek_step"));
+            assert_eq!(config.playback.seek_step, 5.0);
+            // Only the settings that are out of range are replaced
+            assert_eq!(config.playback.large_seek_step, 45.0);
+            assert_eq!(config.audio, AudioSettings::default());
+        }
+
+        #[test]
+        fn test_empty_http_token() {
+            let (config, warnings) = Config::parse("[http]\ntoken = \" \"\n").unwrap();
+
+            assert_eq!(config.http, None);
+            assert_eq!(warnings.len(), 1);
+        }
+
+        #[test]
+        fn test_http_library_defaults_to_music_folder() {
+            let folder = env::temp_dir();
+            let (config, _) = Config::parse(&format!(
+                "[library]\nmusic_folders = [{:?}]\n\n[http]\ntoken = \"abc\"\n",
+                folder
+            ))
+            .unwrap();
+
+            assert_eq!(config.http.unwrap().library, Some(folder));
+        }
+    }
+
+    mod path_in {
+        use super::*;
+
+        #[test]
+        fn test_xdg_config_home() {
+            assert_eq!(
+                Config::path_in(Some(OsString::from("/xdg/config"))),
+                Some(PathBuf::from("/xdg/config/audio_player/config.toml"))
+            );
+        }
+
+        #[test]
+        fn test_relative_xdg_config_home_is_ignored() {
+            assert_eq!(
+                Config::path_in(Some(OsString::from("config"))),
+                Config::path_in(None)
+            );
+        }
+    }
+
+    mod expand_home {
+        use super::*;
+
+        #[test]
+        fn test_home() {
+            let home = dirs::home_dir().unwrap();
+            assert_eq!(expand_home(Path::new("~/Music")), home.join("Music"));
+        }
+
+        #[test]
+        fn test_other_paths() {
+            assert_eq!(expand_home(Path::new("/music")), PathBuf::from("/music"));
+            assert_eq!(expand_home(Path::new("~music")), PathBuf::from("~music"));
+        }
+    }
+
+    mod load_from {
+        use super::*;
+
+        #[test]
+        fn test_missing_file() {
+            assert_eq!(
+                Config::load_from(Path::new("./does/not/exist.toml")),
+                Config::default()
+            );
+        }
+
+        #[test]
+        fn test_invalid_file() {
+            let path =
+                env::temp_dir().join(format!("audio_player_config_{}.toml", std::process::id()));
+            fs::write(&path, "[playback\n").unwrap();
+
+            let config = Config::load_from(&path);
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(config, Config::default());
+        }
+    }
+}
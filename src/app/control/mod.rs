@@ -0,0 +1,147 @@
+#[cfg(unix)]
+mod socket;
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(unix)]
+pub(crate) use socket::{ControlServer, send};
+
+/// A command or query sent to the control socket, as one line of JSON such as `{"command":"seek","position":42.5}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub(crate) enum ControlRequest {
+    Play,
+    Pause,
+
+    /// Jump to a position in the current track, in seconds
+    Seek {
+        position: f64,
+    },
+
+    /// Change the volume, from 0.0 to 1.0
+    Volume {
+        volume: f32,
+    },
+
+    /// Add tracks to the end of the queue
+    Enqueue {
+        paths: Vec<PathBuf>,
+    },
+
+    /// Skip to the next track in the queue
+    Next,
+
+    Status,
+    NowPlaying,
+    Queue,
+}
+
+/// The answer to a request, sent back as one line of JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ControlResponse {
+    /// The command was carried out
+    Ok,
+
+    Error {
+        message: String,
+    },
+
+    /// The position and length are in seconds. Live streams have no length
+    Status {
+        playing: bool,
+        position: f64,
+        length: Option<f64>,
+        volume: f32,
+    },
+
+    NowPlaying {
+        title: String,
+        artist: String,
+        album: Option<String>,
+        path: PathBuf,
+    },
+
+    /// Every track in the queue, where `current` is the index of the one that is playing
+    Queue {
+        tracks: Vec<PathBuf>,
+        current: usize,
+    },
+}
+
+/// A request that is waiting for the UI to answer it.
+pub(crate) struct ControlCall {
+    pub(crate) request: ControlRequest,
+    reply_sender: mpsc::Sender<ControlResponse>,
+}
+
+impl ControlCall {
+    /// How long a request waits for the UI to answer it.
+    const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Hand a request to the UI and wait for its answer.
+    pub(crate) fn ask(
+        call_sender: &mpsc::Sender<ControlCall>,
+        request: ControlRequest,
+    ) -> ControlResponse {
+        let (reply_sender, reply_receiver) = mpsc::channel();
+
+        if call_sender
+            .send(ControlCall {
+                request,
+                reply_sender,
+            })
+            .is_err()
+        {
+            return ControlResponse::Error {
+                message: "The player is shutting down".to_string(),
+            };
+        }
+
+        reply_receiver
+            .recv_timeout(ControlCall::REPLY_TIMEOUT)
+            .unwrap_or_else(|_| ControlResponse::Error {
+                message: "The player did not answer in time".to_string(),
+            })
+    }
+
+    /// Send the answer back to whoever made the request.
+    pub(crate) fn reply(self, response: ControlResponse) {
+        // The client may have given up waiting, in which case there is nobody to answer
+        let _ = self.reply_sender.send(response);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod control_request {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            let request: ControlRequest =
+                serde_json::from_str(r#"{"command":"seek","position":42.5}"#).unwrap();
+
+            assert_eq!(request, ControlRequest::Seek { position: 42.5 });
+        }
+
+        #[test]
+        fn test_parse_query() {
+            let request: ControlRequest =
+                serde_json::from_str(r#"{"command":"now_playing"}"#).unwrap();
+
+            assert_eq!(request, ControlRequest::NowPlaying);
+        }
+
+        #[test]
+        fn test_unknown_command() {
+            assert!(serde_json::from_str::<ControlRequest>(r#"{"command":"dance"}"#).is_err());
+        }
+    }
+}
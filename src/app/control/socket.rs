@@ -0,0 +1,314 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::app::control::{ControlCall, ControlRequest, ControlResponse};
+
+/// Listens on a Unix socket for requests from other programs, such as `audio_player ctl`.
+/// The socket is removed again once the server is dropped.
+pub(crate) struct ControlServer {
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Start listening on the socket of the player. Each request is sent through `call_sender` for the UI to answer.
+    /// # Errors
+    /// - If another instance of the player is already listening on the socket
+    /// - If the socket could not be created
+    /// - If the folder of the socket can be used by other users
+    pub(crate) fn start(call_sender: mpsc::Sender<ControlCall>) -> io::Result<ControlServer> {
+        let dir = socket_dir();
+        create_private_dir(&dir)?;
+
+        ControlServer::start_at(dir.join(SOCKET_NAME), call_sender)
+    }
+
+    /// Start listening on the socket at `path`.
+    pub(crate) fn start_at(
+        path: PathBuf,
+        call_sender: mpsc::Sender<ControlCall>,
+    ) -> io::Result<ControlServer> {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "another instance is already listening on {}",
+                    path.display()
+                ),
+            ));
+        }
+
+        // Nothing answers on the socket, so it was left behind by an instance that didn't shut down cleanly
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(&path)?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let call_sender = call_sender.clone();
+                        thread::spawn(move || ControlServer::serve(stream, &call_sender));
+                    }
+                    Err(e) => eprintln!("Unable to accept a control connection: {}", e),
+                }
+            }
+        });
+
+        Ok(ControlServer { path })
+    }
+
+    /// Answer every request on a connection, one line at a time, until the client hangs up.
+    fn serve(stream: UnixStream, call_sender: &mpsc::Sender<ControlCall>) {
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(e) => {
+                eprintln!("Unable to read from a control connection: {}", e);
+                return;
+            }
+        };
+        let mut writer = stream;
+
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => ControlCall::ask(call_sender, request),
+                Err(e) => ControlResponse::Error {
+                    message: format!("Invalid request: {}", e),
+                },
+            };
+
+            if write_line(&mut writer, &response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            eprintln!("Unable to remove the control socket: {}", e);
+        }
+    }
+}
+
+const SOCKET_NAME: &str = "audio_player.sock";
+
+/// Return the folder that the control socket of the player is in, which is `$XDG_RUNTIME_DIR` when it is set.
+/// Otherwise it is a folder of the user's own in the temporary folder, since every user shares that one.
+fn socket_dir() -> PathBuf {
+    dirs::runtime_dir().unwrap_or_else(|| {
+        let user = env::var("USER")
+            .or_else(|_| env::var("LOGNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        env::temp_dir().join(format!("audio_player-{}", user))
+    })
+}
+
+/// Create `dir` if it is missing, so that only the user can use it.
+/// # Errors
+/// - If the folder could not be created
+/// - If other users can use the folder, such as when another user created it first
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+
+    check_private_dir(dir)
+}
+
+/// Make sure that only the user can use `dir`, so that the socket in it can't be swapped for another user's.
+/// A folder of another user's that only they can use can't be opened at all.
+fn check_private_dir(dir: &Path) -> io::Result<()> {
+    let mode = fs::metadata(dir)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} can be used by other users", dir.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Send a request to the running player and return its answer.
+/// # Errors
+/// - If no player is running
+/// - If the player did not answer
+/// - If the folder of the socket can be used by other users, in which case the socket may not be the player's
+pub(crate) fn send(request: &ControlRequest) -> io::Result<ControlResponse> {
+    let dir = socket_dir();
+    check_private_dir(&dir)?;
+
+    send_to(&dir.join(SOCKET_NAME), request)
+}
+
+/// Send a request to the player listening on the socket at `path`.
+pub(crate) fn send_to(path: &Path, request: &ControlRequest) -> io::Result<ControlResponse> {
+    let mut stream = UnixStream::connect(path)?;
+
+    // Wait a little longer than the server does, so that its own timeout is reported instead
+    stream.set_read_timeout(Some(ControlCall::REPLY_TIMEOUT + Duration::from_secs(1)))?;
+
+    write_line(&mut stream, request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Write a value as one line of JSON.
+fn write_line<T: Serialize>(writer: &mut impl Write, value: &T) -> io::Result<()> {
+    let json = serde_json::to_string(value)?;
+    writeln!(writer, "{}", json)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Start a server on a socket of its own, answering every request with `answer`.
+    fn start_server(name: &str, answer: fn(ControlRequest) -> ControlResponse) -> ControlServer {
+        let path =
+            env::temp_dir().join(format!("audio_player_{}_{}.sock", name, std::process::id()));
+        let (call_sender, call_receiver) = mpsc::channel::<ControlCall>();
+
+        thread::spawn(move || {
+            for call in call_receiver {
+                let response = answer(call.request.clone());
+                call.reply(response);
+            }
+        });
+
+        ControlServer::start_at(path, call_sender).unwrap()
+    }
+
+    mod send_to {
+        use super::*;
+
+        #[test]
+        fn test_answer() {
+            let server = start_server("answer", |request| match request {
+                ControlRequest::Status => ControlResponse::Status {
+                    playing: true,
+                    position: 1.0,
+                    length: Some(2.0),
+                    volume: 0.5,
+                },
+                _ => ControlResponse::Ok,
+            });
+
+            let response = send_to(&server.path, &ControlRequest::Status).unwrap();
+
+            assert_eq!(
+                response,
+                ControlResponse::Status {
+                    playing: true,
+                    position: 1.0,
+                    length: Some(2.0),
+                    volume: 0.5,
+                }
+            );
+            assert_eq!(
+                send_to(&server.path, &ControlRequest::Play).unwrap(),
+                ControlResponse::Ok
+            );
+        }
+
+        #[test]
+        fn test_invalid_request() {
+            let server = start_server("invalid", |_| ControlResponse::Ok);
+
+            let mut stream = UnixStream::connect(&server.path).unwrap();
+            writeln!(stream, "not json").unwrap();
+
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+
+            assert!(matches!(
+                serde_json::from_str(&line).unwrap(),
+                ControlResponse::Error { .. }
+            ));
+        }
+
+        #[test]
+        fn test_no_server() {
+            let path = env::temp_dir().join("audio_player_missing.sock");
+
+            assert!(send_to(&path, &ControlRequest::Status).is_err());
+        }
+    }
+
+    mod create_private_dir {
+        use super::*;
+
+        #[test]
+        fn test_new_dir() {
+            let dir = env::temp_dir().join(format!("audio_player_private_{}", std::process::id()));
+            let _ = fs::remove_dir(&dir);
+
+            create_private_dir(&dir).unwrap();
+            let mode = fs::metadata(&dir).unwrap().permissions().mode();
+            fs::remove_dir(&dir).unwrap();
+
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        #[test]
+        fn test_shared_dir() {
+            let dir = env::temp_dir().join(format!("audio_player_shared_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+            let result = create_private_dir(&dir);
+            fs::remove_dir(&dir).unwrap();
+
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    mod start_at {
+        use super::*;
+
+        #[test]
+        fn test_already_running() {
+            let server = start_server("running", |_| ControlResponse::Ok);
+            let (call_sender, _call_receiver) = mpsc::channel();
+
+            let second = ControlServer::start_at(server.path.clone(), call_sender);
+
+            assert_eq!(second.err().unwrap().kind(), io::ErrorKind::AddrInUse);
+        }
+
+        #[test]
+        fn test_removed_on_drop() {
+            let server = start_server("dropped", |_| ControlResponse::Ok);
+            let path = server.path.clone();
+
+            drop(server);
+
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// The effects applied to the audio on top of plain decoding.
+/// There is no equalizer, since the player has none whose settings an export could carry over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DspSettings {
+    /// The playback speed, where 1.0 is the original speed. Changing the speed also changes the pitch.
+    pub(crate) speed: f32,
+
+    /// Whether the audio should be scaled so that its loudest sample peaks at `NORMALIZED_PEAK`.
+    pub(crate) normalize: bool,
+}
+
+impl DspSettings {
+    /// The peak amplitude that normalized audio is scaled to, roughly -1 dBFS.
+    pub(crate) const NORMALIZED_PEAK: f32 = 0.891;
+
+    /// Return the gain needed to bring audio with the given peak amplitude up (or down) to `NORMALIZED_PEAK`.
+    /// Silent audio is left untouched.
+    pub(crate) fn normalization_gain(peak: f32) -> f32 {
+        if peak <= f32::EPSILON {
+            return 1.0;
+        }
+
+        Self::NORMALIZED_PEAK / peak
+    }
+}
+
+impl Default for DspSettings {
+    fn default() -> DspSettings {
+        DspSettings {
+            speed: 1.0,
+            normalize: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod normalization_gain {
+        use super::*;
+
+        #[test]
+        fn test_quiet_audio_is_boosted() {
+            let gain = DspSettings::normalization_gain(0.5);
+            assert!((0.5 * gain - DspSettings::NORMALIZED_PEAK).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_loud_audio_is_attenuated() {
+            let gain = DspSettings::normalization_gain(1.0);
+            assert!(gain < 1.0);
+        }
+
+        #[test]
+        fn test_silence_is_untouched() {
+            assert_eq!(DspSettings::normalization_gain(0.0), 1.0);
+        }
+    }
+}
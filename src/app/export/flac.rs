@@ -0,0 +1,582 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use md5::{Digest, Md5};
+use rodio::{ChannelCount, SampleRate};
+
+use super::{ExportError, SampleWriter, quantize};
+
+/// Writes samples to a FLAC file.
+///
+/// Each channel is encoded with whichever fixed linear predictor (order 0 to 4) gives the smallest
+/// Rice-coded residual, falling back to storing the samples verbatim if prediction doesn't help.
+pub(super) struct FlacWriter {
+    writer: BufWriter<File>,
+    channels: usize,
+    sample_rate: SampleRate,
+    bit_depth: u16,
+
+    /// Interleaved samples that have not yet filled up a whole block
+    pending: Vec<i32>,
+
+    /// The MD5 signature of every sample that was encoded so far, which decoders use to check the audio
+    md5: Md5,
+
+    frame_number: u64,
+    total_frames: u64,
+    min_frame_size: usize,
+    max_frame_size: usize,
+}
+
+impl FlacWriter {
+    /// The number of samples per channel in every block except the last one.
+    const BLOCK_SIZE: usize = 4096;
+
+    const MAX_FIXED_ORDER: usize = 4;
+
+    /// The largest Rice parameter that can be stored in 4 bits. 15 is reserved as an escape code.
+    const MAX_RICE_PARAMETER: u32 = 14;
+
+    /// The size of the PADDING block, the same as the reference encoder uses.
+    const PADDING_SIZE: usize = 8192;
+
+    /// Where the STREAMINFO block starts: right after the "fLaC" marker and the metadata block header.
+    const STREAMINFO_OFFSET: u64 = 8;
+
+    pub(super) fn create(
+        path: &Path,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        bit_depth: u16,
+    ) -> Result<FlacWriter, ExportError> {
+        let mut writer = FlacWriter {
+            writer: BufWriter::new(File::create(path)?),
+            channels: channels as usize,
+            sample_rate,
+            bit_depth,
+            pending: Vec::with_capacity(Self::BLOCK_SIZE * channels as usize),
+            md5: Md5::new(),
+            frame_number: 0,
+            total_frames: 0,
+            min_frame_size: usize::MAX,
+            max_frame_size: 0,
+        };
+
+        writer.writer.write_all(b"fLaC")?;
+
+        // The contents of the STREAMINFO block are written again once the stream is finished and the totals are known
+        writer.writer.write_all(&[0x00, 0, 0, 34])?;
+        writer.writer.write_all(&writer.stream_info())?;
+
+        // Leave some padding as the last metadata block, so tags can be added later without rewriting the whole file
+        let padding_len = (Self::PADDING_SIZE as u32).to_be_bytes();
+        writer
+            .writer
+            .write_all(&[0x81, padding_len[1], padding_len[2], padding_len[3]])?;
+        writer.writer.write_all(&[0; Self::PADDING_SIZE])?;
+
+        Ok(writer)
+    }
+
+    /// Encode and write the pending samples as a single frame.
+    fn write_frame(&mut self) -> Result<(), ExportError> {
+        let frame = FlacWriter::encode_frame(
+            &self.pending,
+            self.channels,
+            self.bit_depth as u32,
+            self.frame_number,
+        );
+
+        self.writer.write_all(&frame)?;
+
+        // The signature is of the samples as little-endian numbers that are as many whole bytes as the bit depth needs
+        let sample_len = (self.bit_depth as usize).div_ceil(8);
+        for &sample in &self.pending {
+            self.md5.update(&sample.to_le_bytes()[..sample_len]);
+        }
+
+        self.min_frame_size = self.min_frame_size.min(frame.len());
+        self.max_frame_size = self.max_frame_size.max(frame.len());
+        self.total_frames += (self.pending.len() / self.channels) as u64;
+        self.frame_number += 1;
+        self.pending.clear();
+
+        Ok(())
+    }
+
+    /// Return the contents of the STREAMINFO metadata block.
+    fn stream_info(&self) -> Vec<u8> {
+        let mut bits = BitWriter::new();
+
+        bits.write(Self::BLOCK_SIZE as u64, 16);
+        bits.write(Self::BLOCK_SIZE as u64, 16);
+
+        // A frame size of 0 means that the size is unknown
+        let min_frame_size = if self.min_frame_size == usize::MAX {
+            0
+        } else {
+            self.min_frame_size
+        };
+        bits.write(min_frame_size as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(self.bit_depth as u64 - 1, 5);
+        bits.write(self.total_frames, 36);
+
+        let mut stream_info = bits.into_bytes();
+        stream_info.extend_from_slice(&self.md5.clone().finalize());
+        stream_info
+    }
+
+    fn encode_frame(
+        samples: &[i32],
+        channels: usize,
+        bit_depth: u32,
+        frame_number: u64,
+    ) -> Vec<u8> {
+        let block_size = samples.len() / channels;
+        let mut bits = BitWriter::new();
+
+        // Frame header
+        bits.write(0b11_1111_1111_1110, 14); // Sync code
+        bits.write(0, 1); // Reserved
+        bits.write(0, 1); // Fixed block size, so the header holds the frame number
+        bits.write(0b0111, 4); // The block size is stored as a 16 bit number at the end of the header
+        bits.write(0b0000, 4); // Use the sample rate from STREAMINFO
+        bits.write(channels as u64 - 1, 4); // Every channel is encoded independently
+        // Some decoders can't take the bit depth from STREAMINFO, so it goes in every frame like the reference encoder does
+        bits.write(bit_depth_code(bit_depth), 3);
+        bits.write(0, 1); // Reserved
+        bits.write_utf8(frame_number);
+        bits.write(block_size as u64 - 1, 16);
+
+        let header_crc = crc8(bits.bytes());
+        bits.write(header_crc as u64, 8);
+
+        for channel in 0..channels {
+            let channel_samples: Vec<i32> = samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect();
+
+            FlacWriter::encode_subframe(&mut bits, &channel_samples, bit_depth);
+        }
+
+        bits.align();
+
+        let frame_crc = crc16(bits.bytes());
+        bits.write(frame_crc as u64, 16);
+
+        bits.into_bytes()
+    }
+
+    fn encode_subframe(bits: &mut BitWriter, samples: &[i32], bit_depth: u32) {
+        let verbatim_size = samples.len() as u64 * bit_depth as u64;
+
+        // Find the predictor order that produces the smallest subframe
+        let best = (0..=Self::MAX_FIXED_ORDER.min(samples.len() - 1))
+            .map(|order| {
+                let residuals = fixed_residuals(samples, order);
+                let (parameter, residual_size) = best_rice_parameter(&residuals);
+
+                // Warm-up samples, then the residual coding method, partition order and Rice parameter
+                let size = order as u64 * bit_depth as u64 + 2 + 4 + 4 + residual_size;
+
+                (order, residuals, parameter, size)
+            })
+            .min_by_key(|(_, _, _, size)| *size);
+
+        match best {
+            Some((order, residuals, parameter, size)) if size < verbatim_size => {
+                // Subframe header: padding, FIXED subframe type with the predictor order, no wasted bits
+                bits.write(0, 1);
+                bits.write(0b001000 | order as u64, 6);
+                bits.write(0, 1);
+
+                for &sample in &samples[..order] {
+                    bits.write_signed(sample as i64, bit_depth);
+                }
+
+                bits.write(0b00, 2); // Rice coding with 4-bit parameters
+                bits.write(0, 4); // A single partition
+                bits.write(parameter as u64, 4);
+
+                for &residual in &residuals {
+                    bits.write_rice(residual, parameter);
+                }
+            }
+            _ => {
+                // Subframe header: padding, VERBATIM subframe type, no wasted bits
+                bits.write(0, 1);
+                bits.write(0b000001, 6);
+                bits.write(0, 1);
+
+                for &sample in samples {
+                    bits.write_signed(sample as i64, bit_depth);
+                }
+            }
+        }
+    }
+}
+
+impl SampleWriter for FlacWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), ExportError> {
+        for &sample in samples {
+            self.pending.push(quantize(sample, self.bit_depth));
+
+            if self.pending.len() == Self::BLOCK_SIZE * self.channels {
+                self.write_frame()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        // Drop any incomplete frame at the very end, since a block must hold every channel
+        let complete_len = self.pending.len() - self.pending.len() % self.channels;
+        self.pending.truncate(complete_len);
+
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+
+        // Now that the totals are known, go back and fill in the STREAMINFO block
+        let stream_info = self.stream_info();
+        self.writer.seek(SeekFrom::Start(Self::STREAMINFO_OFFSET))?;
+        self.writer.write_all(&stream_info)?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Return how a frame header stores `bit_depth`, falling back to the bit depth in STREAMINFO for the unusual ones.
+fn bit_depth_code(bit_depth: u32) -> u64 {
+    match bit_depth {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        32 => 0b111,
+        _ => 0b000,
+    }
+}
+
+/// Return the residuals left over after predicting each sample with a fixed polynomial predictor of the given order.
+/// The first `order` samples are used to warm up the predictor, so they have no residual.
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |offset: usize| samples[i - offset] as i64;
+
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                4 => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+                _ => unreachable!(),
+            }
+        })
+        .collect()
+}
+
+/// Return the Rice parameter that encodes `residuals` in the fewest bits, along with that number of bits.
+fn best_rice_parameter(residuals: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = residuals.iter().map(|&residual| fold(residual)).collect();
+
+    (0..=FlacWriter::MAX_RICE_PARAMETER)
+        .map(|parameter| {
+            let size = folded
+                .iter()
+                .map(|&value| (value >> parameter) + 1 + parameter as u64)
+                .sum();
+
+            (parameter, size)
+        })
+        .min_by_key(|(_, size)| *size)
+        .unwrap()
+}
+
+/// Map a signed number onto an unsigned one so that small negative numbers stay small: 0, -1, 1, -2, 2 -> 0, 1, 2, 3, 4.
+fn fold(value: i64) -> u64 {
+    if value >= 0 {
+        (value as u64) << 1
+    } else {
+        ((-value as u64) << 1) - 1
+    }
+}
+
+/// CRC-8 with the polynomial x^8 + x^2 + x^1 + x^0, used by FLAC frame headers.
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 with the polynomial x^16 + x^15 + x^2 + x^0, used by FLAC frame footers.
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Packs values into bytes, most significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+
+    /// Bits that haven't filled up a whole byte yet
+    buffer: u64,
+    buffer_len: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            buffer_len: 0,
+        }
+    }
+
+    /// Write the lowest `count` bits of `value`.
+    fn write(&mut self, value: u64, count: u32) {
+        // Split up large writes so the buffer never overflows
+        if count > 32 {
+            self.write(value >> 32, count - 32);
+            self.write(value, 32);
+            return;
+        }
+
+        let mask = (1_u64 << count) - 1;
+        self.buffer = (self.buffer << count) | (value & mask);
+        self.buffer_len += count;
+
+        while self.buffer_len >= 8 {
+            self.buffer_len -= 8;
+            self.bytes.push((self.buffer >> self.buffer_len) as u8);
+        }
+
+        self.buffer &= (1 << self.buffer_len) - 1;
+    }
+
+    /// Write a two's complement signed number in `count` bits.
+    fn write_signed(&mut self, value: i64, count: u32) {
+        self.write(value as u64, count);
+    }
+
+    /// Write a number with UTF-8 style variable length coding, as used for FLAC frame numbers.
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+
+        let significant_bits = 64 - value.leading_zeros();
+        let len = match significant_bits {
+            0..=11 => 2,
+            12..=16 => 3,
+            17..=21 => 4,
+            22..=26 => 5,
+            27..=31 => 6,
+            _ => 7,
+        };
+
+        // The first byte starts with `len` ones, followed by a zero and the highest bits of the value
+        let prefix = (0xFF00_u64 >> len) & 0xFF;
+        self.write(prefix | (value >> (6 * (len - 1))), 8);
+
+        for i in (0..len - 1).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    /// Write a residual with Rice coding: the high bits in unary, then the low `parameter` bits as-is.
+    fn write_rice(&mut self, value: i64, parameter: u32) {
+        let folded = fold(value);
+        let mut quotient = folded >> parameter;
+
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        self.write(1, quotient as u32 + 1);
+
+        if parameter > 0 {
+            self.write(folded, parameter);
+        }
+    }
+
+    /// Pad with zeroes up to the next byte boundary.
+    fn align(&mut self) {
+        if self.buffer_len > 0 {
+            self.write(0, 8 - self.buffer_len);
+        }
+    }
+
+    /// Return the whole bytes that have been written so far.
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+
+    /// Encode `samples` to a FLAC file, and decode it again with the reference decoder.
+    /// Returns the decoded samples, and whether the MD5 signature in STREAMINFO matches them.
+    fn round_trip(samples: &[f32], channels: ChannelCount, bit_depth: u16) -> (Vec<i32>, bool) {
+        let path = env::temp_dir().join(format!(
+            "audio_player_flac_{}_{}_{}_{}.flac",
+            std::process::id(),
+            samples.len(),
+            channels,
+            bit_depth
+        ));
+
+        let mut writer = Box::new(FlacWriter::create(&path, channels, 44100, bit_depth).unwrap());
+        writer.write_samples(samples).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = claxon::FlacReader::open(&path).unwrap();
+        let stream_info = reader.streaminfo();
+        let decoded: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+
+        let sample_len = (bit_depth as usize).div_ceil(8);
+        let mut md5 = Md5::new();
+        for sample in &decoded {
+            md5.update(&sample.to_le_bytes()[..sample_len]);
+        }
+        let md5_matches = md5.finalize()[..] == stream_info.md5sum;
+
+        assert_eq!(stream_info.channels, channels as u32);
+        assert_eq!(stream_info.bits_per_sample, bit_depth as u32);
+        assert_eq!(
+            stream_info.samples,
+            Some((decoded.len() / channels as usize) as u64)
+        );
+
+        (decoded, md5_matches)
+    }
+
+    /// A mix of a tone, noise and full-scale peaks, so that every kind of subframe gets used.
+    fn test_signal(len: usize) -> Vec<f32> {
+        let mut noise = 1_u32;
+        (0..len)
+            .map(|i| {
+                noise = noise.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                match i % 3000 {
+                    0..1000 => (i as f32 * 0.05).sin() * 0.8,
+                    1000..2000 => (noise >> 8) as f32 / (1 << 23) as f32 - 1.0,
+                    _ if i % 2 == 0 => 1.0,
+                    _ => -1.0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Lengths that leave the last block empty, a single sample long, or anything in between
+        let lengths = [
+            1,
+            FlacWriter::BLOCK_SIZE - 1,
+            FlacWriter::BLOCK_SIZE,
+            FlacWriter::BLOCK_SIZE * 2 + 17,
+        ];
+
+        for channels in [1, 2] {
+            for bit_depth in [16, 24] {
+                for len in lengths {
+                    let samples = test_signal(len * channels as usize);
+                    let expected: Vec<i32> = samples
+                        .iter()
+                        .map(|&sample| quantize(sample, bit_depth))
+                        .collect();
+
+                    let (decoded, md5_matches) = round_trip(&samples, channels, bit_depth);
+
+                    assert!(
+                        decoded == expected,
+                        "{} channels, {}-bit, {} samples",
+                        channels,
+                        bit_depth,
+                        len
+                    );
+                    assert!(md5_matches);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_incomplete_frame_dropped() {
+        let (decoded, md5_matches) = round_trip(&[0.5, -0.5, 0.25], 2, 16);
+
+        assert_eq!(decoded, vec![quantize(0.5, 16), quantize(-0.5, 16)]);
+        assert!(md5_matches);
+    }
+
+    #[test]
+    fn test_fold() {
+        let folded: Vec<u64> = [0, -1, 1, -2, 2].into_iter().map(fold).collect();
+        assert_eq!(folded, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_fixed_residuals_of_a_line() {
+        // A straight line is predicted perfectly by a second order predictor
+        let samples = [10, 20, 30, 40, 50];
+        assert_eq!(fixed_residuals(&samples, 2), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_utf8_frame_number() {
+        let mut bits = BitWriter::new();
+        bits.write_utf8(0x7F);
+        bits.write_utf8(0x80);
+
+        assert_eq!(bits.into_bytes(), vec![0x7F, 0xC2, 0x80]);
+    }
+
+    #[test]
+    fn test_crc8() {
+        // The check value of CRC-8/SMBUS
+        assert_eq!(crc8(b"123456789"), 0xF4);
+    }
+
+    #[test]
+    fn test_crc16() {
+        // The check value of CRC-16/UMTS
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+}
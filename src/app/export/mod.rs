@@ -0,0 +1,707 @@
+mod flac;
+mod opus;
+mod wav;
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use lofty::config::WriteOptions;
+use lofty::error::LoftyError;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::read_from_path;
+use lofty::tag::TagType;
+use rodio::decoder::DecoderError;
+use rodio::source::UniformSourceIterator;
+use rodio::{ChannelCount, SampleRate, Source};
+
+use crate::app::audio_handler::AudioHandler;
+use crate::app::dsp::DspSettings;
+
+use flac::FlacWriter;
+use opus::OpusWriter;
+use wav::WavWriter;
+
+/// The file formats that audio can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ExportFormat {
+    Wav,
+    Flac,
+    Opus,
+}
+
+impl ExportFormat {
+    /// Every format, in the order they are shown to the user.
+    pub(crate) const ALL: [ExportFormat; 3] =
+        [ExportFormat::Wav, ExportFormat::Flac, ExportFormat::Opus];
+
+    /// Return the format with the given name, ignoring case. The name is the same as the format's file extension.
+    pub(crate) fn from_name(name: &str) -> Option<ExportFormat> {
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(name))
+    }
+
+    /// Guess the format from the extension of a file path.
+    pub(crate) fn from_path(path: &Path) -> Option<ExportFormat> {
+        let extension = path.extension()?.to_str()?;
+
+        // Ogg Opus files are sometimes saved with the .ogg extension
+        if extension.eq_ignore_ascii_case("ogg") {
+            return Some(ExportFormat::Opus);
+        }
+
+        ExportFormat::from_name(extension)
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "wav",
+            ExportFormat::Flac => "flac",
+            ExportFormat::Opus => "opus",
+        }
+    }
+
+    /// Return the bit depths that the format can be written with. Opus has no fixed bit depth, so it returns an empty slice.
+    pub(crate) fn supported_bit_depths(&self) -> &'static [u16] {
+        match self {
+            // 32-bit WAV files are written as floating point
+            ExportFormat::Wav => &[8, 16, 24, 32],
+            ExportFormat::Flac => &[16, 24],
+            ExportFormat::Opus => &[],
+        }
+    }
+}
+
+/// Settings that control how audio is exported.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExportOptions {
+    pub(crate) format: ExportFormat,
+
+    /// The sample rate of the exported audio. If this is `None`, the sample rate of the original audio is kept.
+    pub(crate) sample_rate: Option<SampleRate>,
+
+    /// The bit depth of the exported audio. Ignored for formats that have no fixed bit depth.
+    pub(crate) bit_depth: u16,
+
+    /// Where the exported selection starts. If this is `None`, the selection starts at the beginning of the audio.
+    pub(crate) start: Option<Duration>,
+
+    /// Where the exported selection ends. If this is `None`, the selection ends at the end of the audio.
+    pub(crate) end: Option<Duration>,
+
+    /// The effects to apply to the exported audio. If this is `None`, the audio is exported as-is.
+    pub(crate) dsp: Option<DspSettings>,
+
+    /// Whether the tags and cover art of the original file should be copied over.
+    pub(crate) copy_tags: bool,
+}
+
+impl ExportOptions {
+    /// Return the default options for a format.
+    pub(crate) fn new(format: ExportFormat) -> ExportOptions {
+        ExportOptions {
+            format,
+            sample_rate: None,
+            bit_depth: 16,
+            start: None,
+            end: None,
+            dsp: None,
+            copy_tags: true,
+        }
+    }
+
+    /// Check that the options make sense for the chosen format.
+    /// # Errors
+    /// - If the bit depth is not supported by the format
+    /// - If the sample rate is 0, or is not 48 kHz for Opus
+    /// - If the selection ends before it starts
+    /// - If the speed isn't a number greater than 0
+    pub(crate) fn validate(&self) -> Result<(), ExportError> {
+        let bit_depths = self.format.supported_bit_depths();
+        if !bit_depths.is_empty() && !bit_depths.contains(&self.bit_depth) {
+            return Err(ExportError::InvalidOptions(format!(
+                "{} does not support a bit depth of {} (supported: {:?})",
+                self.format.extension(),
+                self.bit_depth,
+                bit_depths
+            )));
+        }
+
+        match (self.format, self.sample_rate) {
+            (_, Some(0)) => {
+                return Err(ExportError::InvalidOptions(
+                    "The sample rate must be greater than 0".to_string(),
+                ));
+            }
+            (ExportFormat::Opus, Some(rate)) if rate != OpusWriter::SAMPLE_RATE => {
+                return Err(ExportError::InvalidOptions(format!(
+                    "Opus is always encoded at {} Hz",
+                    OpusWriter::SAMPLE_RATE
+                )));
+            }
+            _ => (),
+        }
+
+        if let (Some(start), Some(end)) = (self.start, self.end)
+            && end <= start
+        {
+            return Err(ExportError::InvalidOptions(
+                "The selection must end after it starts".to_string(),
+            ));
+        }
+
+        if let Some(dsp) = self.dsp
+            && !(dsp.speed.is_finite() && dsp.speed > 0.0)
+        {
+            return Err(ExportError::InvalidOptions(format!(
+                "The speed must be greater than 0, but is {}",
+                dsp.speed
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// A message sent from the export thread to whoever started the export.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ExportProgress {
+    /// How far along the export is, from 0.0 to 1.0.
+    Progress(f64),
+    Finished,
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub(crate) enum ExportError {
+    Io(io::Error),
+    Decode(DecoderError),
+    Seek(String),
+    Encode(String),
+    Tag(LoftyError),
+    InvalidOptions(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "I/O error: {}", e),
+            ExportError::Decode(e) => write!(f, "Unable to decode audio: {}", e),
+            ExportError::Seek(e) => write!(f, "Unable to seek to the selection: {}", e),
+            ExportError::Encode(e) => write!(f, "Unable to encode audio: {}", e),
+            ExportError::Tag(e) => write!(f, "Unable to copy tags: {}", e),
+            ExportError::InvalidOptions(e) => write!(f, "Invalid export options: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> ExportError {
+        ExportError::Io(e)
+    }
+}
+
+impl From<DecoderError> for ExportError {
+    fn from(e: DecoderError) -> ExportError {
+        ExportError::Decode(e)
+    }
+}
+
+impl From<LoftyError> for ExportError {
+    fn from(e: LoftyError) -> ExportError {
+        ExportError::Tag(e)
+    }
+}
+
+impl From<hound::Error> for ExportError {
+    fn from(e: hound::Error) -> ExportError {
+        ExportError::Encode(e.to_string())
+    }
+}
+
+/// Something that interleaved samples can be written to, such as a WAV or FLAC file.
+trait SampleWriter {
+    /// Write interleaved samples. The number of samples does not have to be a multiple of the channel count.
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), ExportError>;
+
+    /// Flush any buffered samples and finish writing the file.
+    fn finish(self: Box<Self>) -> Result<(), ExportError>;
+}
+
+/// Decodes audio with the same pipeline used for playback and writes it to a new file.
+pub(crate) struct Exporter {}
+
+impl Exporter {
+    /// The number of frames that are decoded before they are handed to the writer.
+    const CHUNK_FRAMES: usize = 4096;
+
+    /// Export audio on a new thread, sending progress updates through `progress_sender`.
+    pub(crate) fn spawn(
+        input: PathBuf,
+        output: PathBuf,
+        options: ExportOptions,
+        progress_sender: mpsc::Sender<ExportProgress>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            // Only send an update once the progress has increased by at least a percent, so the receiver isn't flooded
+            let mut last_sent = 0.0;
+            let result = Exporter::export(&input, &output, &options, |progress| {
+                if progress - last_sent >= 0.01 {
+                    last_sent = progress;
+
+                    // The receiver may have been dropped if the dialog was closed, but the export should still finish
+                    let _ = progress_sender.send(ExportProgress::Progress(progress));
+                }
+            });
+
+            let message = match result {
+                Ok(()) => ExportProgress::Finished,
+                Err(e) => ExportProgress::Failed(e.to_string()),
+            };
+
+            let _ = progress_sender.send(message);
+        })
+    }
+
+    /// Export `input` to `output`, calling `on_progress` with a value from 0.0 to 1.0 as the export progresses.
+    /// # Errors
+    /// - If the options are invalid
+    /// - If `input` cannot be opened or decoded
+    /// - If `output` cannot be written
+    /// - If the tags cannot be copied
+    pub(crate) fn export<F>(
+        input: &Path,
+        output: &Path,
+        options: &ExportOptions,
+        mut on_progress: F,
+    ) -> Result<(), ExportError>
+    where
+        F: FnMut(f64),
+    {
+        options.validate()?;
+
+        let dsp = options.dsp.unwrap_or_default();
+
+        // Normalizing needs to know the peak of the whole selection, so decode it once beforehand
+        let gain = if dsp.normalize {
+            let peak = Exporter::peak_amplitude(Exporter::open_selection(input, options)?);
+            DspSettings::normalization_gain(peak)
+        } else {
+            1.0
+        };
+
+        let source = Exporter::open_selection(input, options)?;
+        let selection_length = source.total_duration();
+
+        let channels = match options.format {
+            // Opus files without a channel mapping table can only hold mono or stereo audio
+            ExportFormat::Opus => source.channels().min(2),
+            _ => source.channels(),
+        };
+        let sample_rate = match options.format {
+            ExportFormat::Opus => OpusWriter::SAMPLE_RATE,
+            _ => options.sample_rate.unwrap_or(source.sample_rate()),
+        };
+
+        let source = SingleSpan(source.speed(dsp.speed).amplify(gain));
+        let mut samples = UniformSourceIterator::new(source, channels, sample_rate);
+
+        let expected_samples = selection_length.map(|length| {
+            Exporter::expected_sample_count(length, dsp.speed, sample_rate, channels)
+        });
+
+        let mut writer = Exporter::create_writer(output, options, channels, sample_rate)?;
+
+        let chunk_size = Exporter::CHUNK_FRAMES * channels as usize;
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let mut written = 0;
+
+        loop {
+            chunk.clear();
+            chunk.extend(samples.by_ref().take(chunk_size));
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            writer.write_samples(&chunk)?;
+            written += chunk.len();
+
+            if let Some(expected_samples) = expected_samples {
+                on_progress((written as f64 / expected_samples as f64).min(1.0));
+            }
+        }
+
+        writer.finish()?;
+
+        if options.copy_tags {
+            Exporter::copy_tags(input, output, options.format)?;
+        }
+
+        on_progress(1.0);
+
+        Ok(())
+    }
+
+    /// Open `input` and trim it down to the selection in `options`.
+    fn open_selection(
+        input: &Path,
+        options: &ExportOptions,
+    ) -> Result<Box<dyn Source + Send>, ExportError> {
+        let mut decoder = AudioHandler::build_decoder(File::open(input)?)?;
+
+        let start = options.start.unwrap_or(Duration::ZERO);
+        if !start.is_zero() {
+            decoder
+                .try_seek(start)
+                .map_err(|e| ExportError::Seek(e.to_string()))?;
+        }
+
+        match options.end {
+            Some(end) => Ok(Box::new(decoder.take_duration(end - start))),
+            None => Ok(Box::new(decoder)),
+        }
+    }
+
+    fn create_writer(
+        output: &Path,
+        options: &ExportOptions,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+    ) -> Result<Box<dyn SampleWriter>, ExportError> {
+        Ok(match options.format {
+            ExportFormat::Wav => Box::new(WavWriter::create(
+                output,
+                channels,
+                sample_rate,
+                options.bit_depth,
+            )?),
+            ExportFormat::Flac => Box::new(FlacWriter::create(
+                output,
+                channels,
+                sample_rate,
+                options.bit_depth,
+            )?),
+            ExportFormat::Opus => Box::new(OpusWriter::create(output, channels)?),
+        })
+    }
+
+    /// Copy the tags and pictures of `input` over to `output`.
+    /// Files without any tags are skipped.
+    fn copy_tags(input: &Path, output: &Path, format: ExportFormat) -> Result<(), ExportError> {
+        let input_file = read_from_path(input)?;
+
+        let Some(tag) = input_file.primary_tag().or_else(|| input_file.first_tag()) else {
+            return Ok(());
+        };
+
+        let mut output_file = read_from_path(output)?;
+
+        let tag_type = match format {
+            // RIFF INFO chunks cannot hold pictures, so use ID3v2 for WAV files instead
+            ExportFormat::Wav => TagType::Id3v2,
+            _ => output_file.primary_tag_type(),
+        };
+
+        let mut tag = tag.clone();
+        tag.re_map(tag_type);
+        output_file.insert_tag(tag);
+
+        output_file.save_to_path(output, WriteOptions::default())?;
+
+        Ok(())
+    }
+
+    /// Return the loudest absolute sample value.
+    fn peak_amplitude(samples: impl Iterator<Item = f32>) -> f32 {
+        samples.fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    /// Return roughly how many samples will be written for audio of the given length.
+    fn expected_sample_count(
+        length: Duration,
+        speed: f32,
+        sample_rate: SampleRate,
+        channels: ChannelCount,
+    ) -> usize {
+        let seconds = length.as_secs_f64() / speed as f64;
+        (seconds * sample_rate as f64) as usize * channels as usize
+    }
+}
+
+/// Wraps a decoded file so that it reports a single span covering the whole file.
+///
+/// Decoders can report a span length of 0 before the first packet has been decoded, which makes
+/// `UniformSourceIterator` stop before it has produced a single sample. A file keeps the same
+/// channel count and sample rate from start to finish, so it is safe to treat it as one span.
+struct SingleSpan<S>(S);
+
+impl<S: Source> Iterator for SingleSpan<S> {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<S: Source> Source for SingleSpan<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.0.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.0.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.0.total_duration()
+    }
+}
+
+/// Convert a sample from the -1.0 to 1.0 range to a signed integer with the given bit depth.
+fn quantize(sample: f32, bit_depth: u16) -> i32 {
+    let max = ((1_i64 << (bit_depth - 1)) - 1) as f64;
+
+    (sample.clamp(-1.0, 1.0) as f64 * max).round() as i32
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    const TEST_FILES: &str = "./src/app/ui/tests/files";
+
+    mod export_format {
+        use super::*;
+
+        #[test]
+        fn test_from_name() {
+            assert_eq!(ExportFormat::from_name("flac"), Some(ExportFormat::Flac));
+            assert_eq!(ExportFormat::from_name("WAV"), Some(ExportFormat::Wav));
+            assert_eq!(ExportFormat::from_name("mp3"), None);
+        }
+
+        #[test]
+        fn test_from_path() {
+            assert_eq!(
+                ExportFormat::from_path(Path::new("clip.opus")),
+                Some(ExportFormat::Opus)
+            );
+            assert_eq!(
+                ExportFormat::from_path(Path::new("clip.ogg")),
+                Some(ExportFormat::Opus)
+            );
+            assert_eq!(ExportFormat::from_path(Path::new("clip")), None);
+        }
+    }
+
+    mod validate {
+        use super::*;
+
+        #[test]
+        fn test_default_options_are_valid() {
+            for format in ExportFormat::ALL {
+                assert!(ExportOptions::new(format).validate().is_ok());
+            }
+        }
+
+        #[test]
+        fn test_unsupported_bit_depth() {
+            let mut options = ExportOptions::new(ExportFormat::Flac);
+            options.bit_depth = 32;
+
+            assert!(options.validate().is_err());
+        }
+
+        #[test]
+        fn test_opus_sample_rate() {
+            let mut options = ExportOptions::new(ExportFormat::Opus);
+            options.sample_rate = Some(44100);
+
+            assert!(options.validate().is_err());
+        }
+
+        #[test]
+        fn test_selection_ends_before_start() {
+            let mut options = ExportOptions::new(ExportFormat::Wav);
+            options.start = Some(Duration::from_secs(10));
+            options.end = Some(Duration::from_secs(5));
+
+            assert!(options.validate().is_err());
+        }
+
+        #[test]
+        fn test_invalid_speed() {
+            for speed in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+                let mut options = ExportOptions::new(ExportFormat::Wav);
+                options.dsp = Some(DspSettings {
+                    speed,
+                    ..DspSettings::default()
+                });
+
+                assert!(
+                    matches!(options.validate(), Err(ExportError::InvalidOptions(_))),
+                    "{}",
+                    speed
+                );
+            }
+        }
+    }
+
+    mod quantize {
+        use super::*;
+
+        #[test]
+        fn test_16_bit() {
+            assert_eq!(quantize(1.0, 16), i16::MAX as i32);
+            assert_eq!(quantize(-1.0, 16), -(i16::MAX as i32));
+            assert_eq!(quantize(0.0, 16), 0);
+        }
+
+        #[test]
+        fn test_clipping() {
+            assert_eq!(quantize(2.0, 24), (1 << 23) - 1);
+        }
+    }
+
+    mod peak_amplitude {
+        use super::*;
+
+        #[test]
+        fn test_negative_peak() {
+            let samples = vec![0.1, -0.7, 0.5];
+            assert_eq!(Exporter::peak_amplitude(samples.into_iter()), 0.7);
+        }
+    }
+
+    mod export {
+        use lofty::picture::{MimeType, Picture, PictureType};
+        use lofty::tag::{Accessor, Tag};
+
+        use super::*;
+
+        fn test_input() -> PathBuf {
+            Path::new(TEST_FILES).join("audio/without-metadata/test.ogg")
+        }
+
+        fn temp_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!(
+                "audio_player_export_{}_{}",
+                std::process::id(),
+                name
+            ))
+        }
+
+        fn assert_decodes_to(path: &Path, sample_rate: SampleRate, length: Duration) {
+            let decoder = AudioHandler::build_decoder(File::open(path).unwrap()).unwrap();
+            assert_eq!(decoder.sample_rate(), sample_rate);
+
+            let channels = decoder.channels() as f64;
+            let samples = decoder.count() as f64;
+            let expected = length.as_secs_f64() * sample_rate as f64 * channels;
+
+            // Allow for a little bit of padding from resampling and block sizes
+            assert!((samples - expected).abs() / expected < 0.05);
+        }
+
+        #[test]
+        fn test_wav_selection() {
+            let output = temp_path("selection.wav");
+
+            let mut options = ExportOptions::new(ExportFormat::Wav);
+            options.sample_rate = Some(22050);
+            options.start = Some(Duration::from_secs(1));
+            options.end = Some(Duration::from_secs(2));
+
+            Exporter::export(&test_input(), &output, &options, |_| {}).unwrap();
+            assert_decodes_to(&output, 22050, Duration::from_secs(1));
+
+            fs::remove_file(output).unwrap();
+        }
+
+        #[test]
+        fn test_flac_round_trip() {
+            let output = temp_path("round_trip.flac");
+
+            let mut options = ExportOptions::new(ExportFormat::Flac);
+            options.bit_depth = 24;
+            options.sample_rate = Some(48000);
+            options.end = Some(Duration::from_secs(2));
+
+            Exporter::export(&test_input(), &output, &options, |_| {}).unwrap();
+            assert_decodes_to(&output, 48000, Duration::from_secs(2));
+
+            fs::remove_file(output).unwrap();
+        }
+
+        #[test]
+        fn test_speed_shortens_audio() {
+            let output = temp_path("speed.wav");
+
+            let mut options = ExportOptions::new(ExportFormat::Wav);
+            options.sample_rate = Some(44100);
+            options.end = Some(Duration::from_secs(2));
+            options.dsp = Some(DspSettings {
+                speed: 2.0,
+                normalize: true,
+            });
+
+            Exporter::export(&test_input(), &output, &options, |_| {}).unwrap();
+            assert_decodes_to(&output, 44100, Duration::from_secs(1));
+
+            fs::remove_file(output).unwrap();
+        }
+
+        #[test]
+        fn test_tags_are_copied() {
+            // Tag a copy of the test file, so the original is left untouched
+            let input = temp_path("tagged.ogg");
+            fs::copy(test_input(), &input).unwrap();
+
+            let mut tag = Tag::new(TagType::VorbisComments);
+            tag.set_title("less than lovers".to_string());
+
+            let cover =
+                fs::read(Path::new(TEST_FILES).join("images/covers/test_cover.png")).unwrap();
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::CoverFront,
+                Some(MimeType::Png),
+                None,
+                cover,
+            ));
+
+            let mut tagged_file = read_from_path(&input).unwrap();
+            tagged_file.insert_tag(tag);
+            tagged_file
+                .save_to_path(&input, WriteOptions::default())
+                .unwrap();
+
+            let output = temp_path("tagged.flac");
+
+            let mut options = ExportOptions::new(ExportFormat::Flac);
+            options.end = Some(Duration::from_secs(1));
+
+            Exporter::export(&input, &output, &options, |_| {}).unwrap();
+
+            let exported_file = read_from_path(&output).unwrap();
+            let exported_tag = exported_file.primary_tag().unwrap();
+
+            assert_eq!(exported_tag.title().unwrap(), "less than lovers");
+            assert_eq!(exported_tag.picture_count(), 1);
+
+            fs::remove_file(input).unwrap();
+            fs::remove_file(output).unwrap();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use audiopus::coder::Encoder;
+use audiopus::{Application, Channels};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use rodio::{ChannelCount, SampleRate};
+
+use super::{ExportError, SampleWriter};
+
+/// Writes samples to an Ogg Opus file.
+pub(super) struct OpusWriter {
+    encoder: Encoder,
+    packet_writer: PacketWriter<BufWriter<File>>,
+    channels: usize,
+
+    /// Interleaved samples that have not yet filled up a whole Opus frame
+    pending: Vec<f32>,
+
+    /// The granule position of the last encoded packet, which is the number of samples per channel including the pre-skip
+    granule_position: u64,
+}
+
+impl OpusWriter {
+    /// Opus always decodes at 48 kHz, so encoding at any other rate would only add an extra resampling step.
+    pub(super) const SAMPLE_RATE: SampleRate = 48000;
+
+    /// 20 ms frames, the size recommended for general purpose audio.
+    const FRAME_SIZE: usize = 960;
+
+    /// The largest packet that Opus can produce.
+    const MAX_PACKET_SIZE: usize = 4000;
+
+    const SERIAL: u32 = 1;
+
+    pub(super) fn create(path: &Path, channels: ChannelCount) -> Result<OpusWriter, ExportError> {
+        let opus_channels = match channels {
+            1 => Channels::Mono,
+            _ => Channels::Stereo,
+        };
+
+        let encoder = Encoder::new(
+            audiopus::SampleRate::Hz48000,
+            opus_channels,
+            Application::Audio,
+        )
+        .map_err(|e| ExportError::Encode(e.to_string()))?;
+
+        // The decoder throws away this many samples at the start to make up for the encoder's delay
+        let pre_skip = encoder
+            .lookahead()
+            .map_err(|e| ExportError::Encode(e.to_string()))? as u16;
+
+        let mut packet_writer = PacketWriter::new(BufWriter::new(File::create(path)?));
+
+        // Both header packets have to be on their own pages
+        packet_writer.write_packet(
+            OpusWriter::id_header(channels as u8, pre_skip),
+            Self::SERIAL,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )?;
+        packet_writer.write_packet(
+            OpusWriter::comment_header(),
+            Self::SERIAL,
+            PacketWriteEndInfo::EndPage,
+            0,
+        )?;
+
+        Ok(OpusWriter {
+            encoder,
+            packet_writer,
+            channels: channels as usize,
+            pending: Vec::with_capacity(Self::FRAME_SIZE * channels as usize),
+            granule_position: pre_skip as u64,
+        })
+    }
+
+    /// Return the identification header (RFC 7845, section 5.1).
+    fn id_header(channels: u8, pre_skip: u16) -> Box<[u8]> {
+        let mut header = Vec::with_capacity(19);
+
+        header.extend_from_slice(b"OpusHead");
+        header.push(1); // Version
+        header.push(channels);
+        header.extend_from_slice(&pre_skip.to_le_bytes());
+        header.extend_from_slice(&Self::SAMPLE_RATE.to_le_bytes());
+        header.extend_from_slice(&0_i16.to_le_bytes()); // Output gain
+        header.push(0); // Mono or stereo, without a channel mapping table
+
+        header.into_boxed_slice()
+    }
+
+    /// Return the comment header (RFC 7845, section 5.2). Tags are filled in later when they are copied over.
+    fn comment_header() -> Box<[u8]> {
+        let vendor = env!("CARGO_PKG_NAME").as_bytes();
+        let mut header = Vec::with_capacity(16 + vendor.len());
+
+        header.extend_from_slice(b"OpusTags");
+        header.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        header.extend_from_slice(vendor);
+        header.extend_from_slice(&0_u32.to_le_bytes()); // No user comments
+
+        header.into_boxed_slice()
+    }
+
+    /// Encode the pending samples as a single packet.
+    fn write_packet(&mut self, end_info: PacketWriteEndInfo) -> Result<(), ExportError> {
+        let frames = self.pending.len() / self.channels;
+
+        // Opus only accepts whole frames, so pad the last one with silence
+        self.pending.resize(Self::FRAME_SIZE * self.channels, 0.0);
+
+        let mut packet = vec![0; Self::MAX_PACKET_SIZE];
+        let len = self
+            .encoder
+            .encode_float(&self.pending, &mut packet)
+            .map_err(|e| ExportError::Encode(e.to_string()))?;
+        packet.truncate(len);
+
+        // The granule position of the last packet marks where the real audio ends, so the padding gets trimmed
+        self.granule_position += frames as u64;
+
+        self.packet_writer.write_packet(
+            packet.into_boxed_slice(),
+            Self::SERIAL,
+            end_info,
+            self.granule_position,
+        )?;
+
+        self.pending.clear();
+
+        Ok(())
+    }
+}
+
+impl SampleWriter for OpusWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), ExportError> {
+        for &sample in samples {
+            self.pending.push(sample);
+
+            if self.pending.len() == Self::FRAME_SIZE * self.channels {
+                self.write_packet(PacketWriteEndInfo::NormalPacket)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        // Always write a final packet, even if it's only silence, so that the stream is properly ended
+        self.write_packet(PacketWriteEndInfo::EndStream)?;
+        self.packet_writer.inner_mut().flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use hound::{SampleFormat, WavSpec};
+use rodio::{ChannelCount, SampleRate};
+
+use super::{ExportError, SampleWriter, quantize};
+
+/// Writes samples to a WAV file. 32-bit files are written as floating point, every other bit depth as integers.
+pub(super) struct WavWriter {
+    writer: hound::WavWriter<BufWriter<File>>,
+    channels: usize,
+    bit_depth: u16,
+    samples_written: usize,
+}
+
+impl WavWriter {
+    pub(super) fn create(
+        path: &Path,
+        channels: ChannelCount,
+        sample_rate: SampleRate,
+        bit_depth: u16,
+    ) -> Result<WavWriter, ExportError> {
+        let sample_format = if bit_depth == 32 {
+            SampleFormat::Float
+        } else {
+            SampleFormat::Int
+        };
+
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: bit_depth,
+            sample_format,
+        };
+
+        let writer = hound::WavWriter::create(path, spec)?;
+
+        Ok(WavWriter {
+            writer,
+            channels: channels as usize,
+            bit_depth,
+            samples_written: 0,
+        })
+    }
+}
+
+impl SampleWriter for WavWriter {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<(), ExportError> {
+        for &sample in samples {
+            match self.bit_depth {
+                32 => self.writer.write_sample(sample)?,
+                8 => self.writer.write_sample(quantize(sample, 8) as i8)?,
+                16 => self.writer.write_sample(quantize(sample, 16) as i16)?,
+                _ => self.writer.write_sample(quantize(sample, self.bit_depth))?,
+            }
+        }
+
+        self.samples_written += samples.len();
+
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ExportError> {
+        // Resampling can leave the last frame incomplete, so pad it with silence
+        let missing = (self.channels - self.samples_written % self.channels) % self.channels;
+        self.write_samples(&vec![0.0; missing])?;
+
+        self.writer.finalize()?;
+
+        Ok(())
+    }
+}
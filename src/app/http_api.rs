@@ -0,0 +1,817 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::app::control::{ControlCall, ControlRequest, ControlResponse};
+use crate::app::queue;
+use crate::app::ui::now_playing::NowPlaying;
+
+/// The page that is served at `/`, which controls the player from a browser.
+const REMOTE_PAGE: &str = include_str!("../../assets/remote.html");
+
+/// The cover that is served for tracks without one of their own.
+const DEFAULT_COVER: &[u8] = include_bytes!("../../assets/default.png");
+
+/// The `[http]` table of the config file. The HTTP server only runs when this table is there.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpSettings {
+    /// Where the server listens, which is only this machine by default
+    #[serde(default = "HttpSettings::default_address")]
+    pub(crate) address: SocketAddr,
+
+    /// The token that every request has to send, either as `Authorization: Bearer <token>` or as `?token=<token>`
+    pub(crate) token: String,
+
+    /// The folder that the library search looks through, which is the first of the music folders by default
+    #[serde(default)]
+    pub(crate) library: Option<PathBuf>,
+}
+
+impl HttpSettings {
+    fn default_address() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 8080))
+    }
+}
+
+/// The answer to a library search.
+#[derive(Debug, Serialize)]
+struct LibraryResults {
+    results: Vec<PathBuf>,
+}
+
+/// Serves a REST API, a stream of events and a remote control page, so that the player can be controlled from other machines.
+/// Every request is answered by the UI, in the same way as requests to the control socket.
+pub(crate) struct HttpServer {
+    server: Arc<Server>,
+}
+
+/// What every worker of the server shares.
+struct Shared {
+    settings: HttpSettings,
+    call_sender: mpsc::Sender<ControlCall>,
+
+    /// How many event streams are open, each of which keeps a worker busy until the client goes away
+    event_streams: AtomicUsize,
+}
+
+impl HttpServer {
+    /// How often the event stream checks whether anything changed.
+    const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// The most tracks that a library search returns.
+    const LIBRARY_RESULT_LIMIT: usize = 100;
+
+    /// How many requests are answered at once.
+    const WORKERS: usize = 8;
+
+    /// The most event streams that can be open at once, which leaves some workers for the other requests.
+    const MAX_EVENT_STREAMS: usize = 6;
+
+    /// Start listening on the address in `settings`.
+    /// # Errors
+    /// - If the address can't be listened on, e.g. because it is already in use
+    pub(crate) fn start(
+        settings: HttpSettings,
+        call_sender: mpsc::Sender<ControlCall>,
+    ) -> Result<HttpServer, String> {
+        let server = Server::http(settings.address)
+            .map(Arc::new)
+            .map_err(|e| format!("Unable to listen on {}: {}", settings.address, e))?;
+
+        let shared = Arc::new(Shared {
+            settings,
+            call_sender,
+            event_streams: AtomicUsize::new(0),
+        });
+        for _ in 0..HttpServer::WORKERS {
+            let incoming = Arc::clone(&server);
+            let shared = Arc::clone(&shared);
+
+            // Each worker answers one request at a time, until the server is dropped
+            thread::spawn(move || {
+                for request in incoming.incoming_requests() {
+                    HttpServer::handle(request, &shared);
+                }
+            });
+        }
+
+        Ok(HttpServer { server })
+    }
+
+    /// Return the address that the server is listening on.
+    pub(crate) fn address(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    fn handle(request: Request, shared: &Shared) {
+        let (settings, call_sender) = (&shared.settings, &shared.call_sender);
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        // The page asks for the token itself, so it is the only thing that can be fetched without it
+        if *request.method() == Method::Get && path == "/" {
+            let response = Response::from_string(REMOTE_PAGE)
+                .with_header(HttpServer::content_type("text/html; charset=utf-8"));
+            HttpServer::respond(request, response);
+            return;
+        }
+
+        if !HttpServer::is_authorized(&request, query, &settings.token) {
+            HttpServer::respond(request, HttpServer::error(401, "Missing or wrong token"));
+            return;
+        }
+
+        let command = match (request.method(), path) {
+            (Method::Get, "/api/events") => {
+                // The stream stays open, so too many of them would leave no worker for anything else
+                if shared.event_streams.fetch_add(1, Ordering::SeqCst)
+                    >= HttpServer::MAX_EVENT_STREAMS
+                {
+                    HttpServer::respond(
+                        request,
+                        HttpServer::error(503, "Too many event streams are open"),
+                    );
+                } else {
+                    HttpServer::stream_events(request, call_sender);
+                }
+                shared.event_streams.fetch_sub(1, Ordering::SeqCst);
+                return;
+            }
+            (Method::Get, "/api/cover") => {
+                let response = HttpServer::cover(call_sender);
+                HttpServer::respond(request, response);
+                return;
+            }
+            (Method::Get, "/api/library") => {
+                let query = query_param(query, "q").unwrap_or_default();
+                let response = HttpServer::search(settings, &query);
+                HttpServer::respond(request, response);
+                return;
+            }
+            (Method::Get, "/api/status") => "status",
+            (Method::Get, "/api/now-playing") => "now_playing",
+            (Method::Get, "/api/queue") => "queue",
+            (Method::Post, "/api/queue") => "enqueue",
+            (Method::Post, "/api/play") => "play",
+            (Method::Post, "/api/pause") => "pause",
+            (Method::Post, "/api/next") => "next",
+            (Method::Post, "/api/seek") => "seek",
+            (Method::Post, "/api/volume") => "volume",
+            _ => {
+                HttpServer::respond(request, HttpServer::error(404, "Not found"));
+                return;
+            }
+        };
+
+        let mut request = request;
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            HttpServer::respond(request, HttpServer::error(400, &e.to_string()));
+            return;
+        }
+
+        let response = match control_request(command, &body) {
+            Ok(control_request) => {
+                HttpServer::control_response(&ControlCall::ask(call_sender, control_request))
+            }
+            Err(e) => HttpServer::error(400, &e),
+        };
+
+        HttpServer::respond(request, response);
+    }
+
+    /// Check the token of a request, from its `Authorization` header or its query.
+    fn is_authorized(request: &Request, query: &str, token: &str) -> bool {
+        let bearer = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+            .map(str::to_string);
+
+        bearer
+            .or_else(|| query_param(query, "token"))
+            .is_some_and(|given| tokens_match(&given, token))
+    }
+
+    /// Send the player's status, track and queue whenever they change, as server-sent events.
+    fn stream_events(request: Request, call_sender: &mpsc::Sender<ControlCall>) {
+        const HEAD: &str =
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n";
+
+        let mut writer = request.into_writer();
+        if writer
+            .write_all(HEAD.as_bytes())
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            return;
+        }
+
+        let events = [
+            ("status", ControlRequest::Status),
+            ("now_playing", ControlRequest::NowPlaying),
+            ("queue", ControlRequest::Queue),
+        ];
+        let mut last_responses: [Option<ControlResponse>; 3] = Default::default();
+
+        loop {
+            for ((name, request), last_response) in events.iter().zip(&mut last_responses) {
+                let response = ControlCall::ask(call_sender, request.clone());
+
+                if let ControlResponse::Error { .. } = response {
+                    // The player is shutting down
+                    return;
+                }
+                if last_response.as_ref() == Some(&response) {
+                    continue;
+                }
+
+                let Ok(json) = serde_json::to_string(&response) else {
+                    continue;
+                };
+                if write!(writer, "event: {}\ndata: {}\n\n", name, json)
+                    .and_then(|_| writer.flush())
+                    .is_err()
+                {
+                    // The client went away
+                    return;
+                }
+
+                *last_response = Some(response);
+            }
+
+            thread::sleep(HttpServer::EVENT_INTERVAL);
+        }
+    }
+
+    /// Return the front cover of the current track, or the default cover if it has none.
+    fn cover(call_sender: &mpsc::Sender<ControlCall>) -> Response<io::Cursor<Vec<u8>>> {
+        let ControlResponse::NowPlaying { path, .. } =
+            ControlCall::ask(call_sender, ControlRequest::NowPlaying)
+        else {
+            return HttpServer::error(503, "Nothing is playing");
+        };
+
+        if let Some(cover) = NowPlaying::read_cover(&path.to_string_lossy()) {
+            let mime_type = cover
+                .mime_type()
+                .map_or("application/octet-stream", |mime_type| mime_type.as_str());
+
+            return Response::from_data(cover.data().to_vec())
+                .with_header(HttpServer::content_type(mime_type));
+        }
+
+        Response::from_data(DEFAULT_COVER).with_header(HttpServer::content_type("image/png"))
+    }
+
+    fn search(settings: &HttpSettings, query: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let Some(library) = settings.library.clone().or_else(dirs::audio_dir) else {
+            return HttpServer::error(404, "There is no library folder to search");
+        };
+
+        let results = search_library(&library, query, HttpServer::LIBRARY_RESULT_LIMIT);
+        HttpServer::json_response(&LibraryResults { results }, 200)
+    }
+
+    /// Send the UI's answer back, where an error means that the request couldn't be carried out.
+    fn control_response(response: &ControlResponse) -> Response<io::Cursor<Vec<u8>>> {
+        let status = match response {
+            ControlResponse::Error { .. } => 400,
+            _ => 200,
+        };
+
+        HttpServer::json_response(response, status)
+    }
+
+    fn json_response<T: Serialize>(value: &T, status: u16) -> Response<io::Cursor<Vec<u8>>> {
+        match serde_json::to_string(value) {
+            Ok(json) => Response::from_string(json)
+                .with_status_code(status)
+                .with_header(HttpServer::content_type("application/json")),
+            Err(e) => HttpServer::error(500, &e.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response<io::Cursor<Vec<u8>>> {
+        let body = ControlResponse::Error {
+            message: message.to_string(),
+        };
+
+        Response::from_string(serde_json::to_string(&body).unwrap_or_default())
+            .with_status_code(status)
+            .with_header(HttpServer::content_type("application/json"))
+    }
+
+    fn content_type(value: &str) -> Header {
+        Header::from_bytes("Content-Type", value).expect("content type is a valid header")
+    }
+
+    fn respond<R: Read>(request: Request, response: Response<R>) {
+        if let Err(e) = request.respond(response) {
+            eprintln!("Unable to answer an HTTP request: {}", e);
+        }
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        // Each call stops one worker
+        for _ in 0..HttpServer::WORKERS {
+            self.server.unblock();
+        }
+    }
+}
+
+/// Turn an API call into a request for the UI. The body is a JSON object with the arguments of the command, if it has any.
+fn control_request(command: &str, body: &str) -> Result<ControlRequest, String> {
+    let mut fields = if body.trim().is_empty() {
+        Map::new()
+    } else {
+        serde_json::from_str::<Map<String, Value>>(body)
+            .map_err(|e| format!("Invalid body: {}", e))?
+    };
+    fields.insert("command".to_string(), Value::from(command));
+
+    serde_json::from_value(Value::Object(fields)).map_err(|e| format!("Invalid body: {}", e))
+}
+
+/// Return the value of a parameter in a query string such as `q=abc&token=123`.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Decode a value from a query string, where `+` is a space and `%XX` is an encoded byte.
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let decoded = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+                match decoded {
+                    Some(decoded) => {
+                        bytes.push(decoded);
+                        rest = &rest[2..];
+                    }
+                    None => bytes.push(b'%'),
+                }
+            }
+            byte => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Compare a token with the expected one.
+fn tokens_match(given: &str, token: &str) -> bool {
+    // Look at every byte, so that how long the comparison takes doesn't give away how much of the token was right
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Find the audio files under `root` whose path contains every word of `query`, ignoring case.
+/// The results are sorted by path, and there are at most `limit` of them.
+fn search_library(root: &Path, query: &str, limit: usize) -> Vec<PathBuf> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+    // The entries that are left to look at, with the next one last. Each folder is searched by name, and before the
+    // entries after it, so the results come out sorted and the search can stop as soon as there are enough of them
+    let mut results = Vec::new();
+    let mut entries = visible_entries(root);
+
+    while results.len() < limit
+        && let Some((path, file_type)) = entries.pop()
+    {
+        // Links to folders aren't followed, since they can lead back up to a folder that is already being searched
+        if file_type.is_dir() {
+            entries.extend(visible_entries(&path));
+            continue;
+        }
+
+        let is_audio = queue::is_audio_file(&path);
+        let relative_path = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .to_lowercase();
+
+        if is_audio && words.iter().all(|word| relative_path.contains(word)) {
+            results.push(path);
+        }
+    }
+
+    results
+}
+
+/// Return the entries of `folder` that aren't hidden, along with what kind of entry each one is, in reverse order.
+fn visible_entries(folder: &Path) -> Vec<(PathBuf, fs::FileType)> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+
+    let mut entries: Vec<_> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?)))
+        .collect();
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpStream;
+
+    const TOKEN: &str = "secret";
+
+    /// Start a server on a free port of this machine, answering every request for the UI with `answer`.
+    fn start_server(answer: fn(ControlRequest) -> ControlResponse) -> HttpServer {
+        let (call_sender, call_receiver) = mpsc::channel::<ControlCall>();
+
+        thread::spawn(move || {
+            for call in call_receiver {
+                let response = answer(call.request.clone());
+                call.reply(response);
+            }
+        });
+
+        let settings = HttpSettings {
+            address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            token: TOKEN.to_string(),
+            library: None,
+        };
+
+        HttpServer::start(settings, call_sender).unwrap()
+    }
+
+    /// Send a request over loopback, and return the status code and body of the response.
+    fn send(server: &HttpServer, method: &str, target: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(server.address().unwrap()).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            target,
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+        (status, body.to_string())
+    }
+
+    fn status_response(_: ControlRequest) -> ControlResponse {
+        ControlResponse::Status {
+            playing: true,
+            position: 1.0,
+            length: Some(2.0),
+            volume: 0.5,
+        }
+    }
+
+    mod http_server {
+        use super::*;
+
+        #[test]
+        fn test_missing_token() {
+            let server = start_server(status_response);
+
+            let (status, _) = send(&server, "GET", "/api/status", "");
+
+            assert_eq!(status, 401);
+        }
+
+        #[test]
+        fn test_wrong_token() {
+            let server = start_server(status_response);
+
+            let (status, _) = send(&server, "GET", "/api/status?token=guess", "");
+
+            assert_eq!(status, 401);
+        }
+
+        #[test]
+        fn test_status() {
+            let server = start_server(status_response);
+
+            let (status, body) = send(&server, "GET", "/api/status?token=secret", "");
+
+            assert_eq!(status, 200);
+            assert_eq!(
+                serde_json::from_str::<ControlResponse>(&body).unwrap(),
+                status_response(ControlRequest::Status)
+            );
+        }
+
+        #[test]
+        fn test_bearer_token() {
+            let server = start_server(status_response);
+
+            let mut stream = TcpStream::connect(server.address().unwrap()).unwrap();
+            write!(
+                stream,
+                "GET /api/status HTTP/1.1\r\nAuthorization: Bearer secret\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+
+            assert!(response.starts_with("HTTP/1.1 200"));
+        }
+
+        #[test]
+        fn test_seek() {
+            // Answer with an error that names the request, so that the test can see what the UI was asked
+            let server = start_server(|request| ControlResponse::Error {
+                message: format!("{:?}", request),
+            });
+
+            let (status, body) = send(
+                &server,
+                "POST",
+                "/api/seek?token=secret",
+                r#"{"position":12.5}"#,
+            );
+
+            assert_eq!(status, 400);
+            assert!(body.contains("Seek { position: 12.5 }"));
+        }
+
+        #[test]
+        fn test_invalid_body() {
+            let server = start_server(|_| ControlResponse::Ok);
+
+            let (status, _) = send(&server, "POST", "/api/volume?token=secret", "{}");
+
+            assert_eq!(status, 400);
+        }
+
+        #[test]
+        fn test_page_without_token() {
+            let server = start_server(|_| ControlResponse::Ok);
+
+            let (status, body) = send(&server, "GET", "/", "");
+
+            assert_eq!(status, 200);
+            assert!(body.contains("<html"));
+        }
+
+        #[test]
+        fn test_unknown_endpoint() {
+            let server = start_server(|_| ControlResponse::Ok);
+
+            let (status, _) = send(&server, "GET", "/api/dance?token=secret", "");
+
+            assert_eq!(status, 404);
+        }
+
+        #[test]
+        fn test_events() {
+            let server = start_server(status_response);
+
+            let mut stream = TcpStream::connect(server.address().unwrap()).unwrap();
+            write!(
+                stream,
+                "GET /api/events?token=secret HTTP/1.1\r\nHost: localhost\r\n\r\n"
+            )
+            .unwrap();
+
+            // Read up to the end of the first event
+            let mut reader = BufReader::new(stream);
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.starts_with("data:") {
+                    lines.push(line);
+                    break;
+                }
+                lines.push(line);
+            }
+
+            assert!(lines[0].starts_with("HTTP/1.1 200"));
+            assert!(lines.contains(&"event: status\n".to_string()));
+            assert!(lines.last().unwrap().contains(r#""type":"status""#));
+        }
+
+        #[test]
+        fn test_too_many_events() {
+            let server = start_server(status_response);
+
+            // Keep the most event streams open that are allowed
+            let streams: Vec<_> = (0..HttpServer::MAX_EVENT_STREAMS)
+                .map(|_| {
+                    let mut stream = TcpStream::connect(server.address().unwrap()).unwrap();
+                    write!(
+                        stream,
+                        "GET /api/events?token=secret HTTP/1.1\r\nHost: localhost\r\n\r\n"
+                    )
+                    .unwrap();
+
+                    let mut reader = BufReader::new(stream);
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    assert!(line.starts_with("HTTP/1.1 200"));
+                    reader
+                })
+                .collect();
+
+            let (status, _) = send(&server, "GET", "/api/events?token=secret", "");
+            assert_eq!(status, 503);
+
+            // The other requests are still answered
+            let (status, _) = send(&server, "GET", "/api/status?token=secret", "");
+            assert_eq!(status, 200);
+
+            drop(streams);
+        }
+    }
+
+    mod control_request {
+        use super::*;
+
+        #[test]
+        fn test_without_body() {
+            assert_eq!(control_request("play", ""), Ok(ControlRequest::Play));
+        }
+
+        #[test]
+        fn test_with_body() {
+            assert_eq!(
+                control_request("enqueue", r#"{"paths":["/music/a.mp3"]}"#),
+                Ok(ControlRequest::Enqueue {
+                    paths: vec![PathBuf::from("/music/a.mp3")],
+                })
+            );
+        }
+    }
+
+    mod query_param {
+        use super::*;
+
+        #[test]
+        fn test_decoded() {
+            assert_eq!(
+                query_param("token=abc&q=daft+punk%21", "q"),
+                Some("daft punk!".to_string())
+            );
+        }
+
+        #[test]
+        fn test_missing() {
+            assert_eq!(query_param("token=abc", "q"), None);
+        }
+    }
+
+    mod tokens_match {
+        use super::*;
+
+        #[test]
+        fn test_match() {
+            assert!(tokens_match("secret", "secret"));
+            assert!(!tokens_match("secreT", "secret"));
+            assert!(!tokens_match("secret2", "secret"));
+            assert!(!tokens_match("", "secret"));
+        }
+    }
+
+    mod search_library {
+        use super::*;
+
+        #[test]
+        fn test_search() {
+            let root =
+                std::env::temp_dir().join(format!("audio_player_library_{}", std::process::id()));
+            fs::create_dir_all(root.join("Daft Punk")).unwrap();
+            fs::create_dir_all(root.join(".hidden")).unwrap();
+            for file in [
+                "Daft Punk/One More Time.mp3",
+                "Daft Punk/cover.jpg",
+                "Other/Something Else.flac",
+                ".hidden/Daft Punk Demo.mp3",
+            ] {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+
+            let results = search_library(&root, "daft TIME", 10);
+            fs::remove_dir_all(&root).unwrap();
+
+            assert_eq!(results, vec![root.join("Daft Punk/One More Time.mp3")]);
+        }
+
+        #[test]
+        fn test_limit() {
+            let root = std::env::temp_dir()
+                .join(format!("audio_player_library_limit_{}", std::process::id()));
+            for file in ["b/2.mp3", "a b/1.mp3", "a/2.mp3", "a/1.mp3", "c.mp3"] {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+
+            let results = search_library(&root, "", 3);
+            fs::remove_dir_all(&root).unwrap();
+
+            assert_eq!(
+                results,
+                vec![
+                    root.join("a/1.mp3"),
+                    root.join("a/2.mp3"),
+                    root.join("a b/1.mp3")
+                ]
+            );
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_linked_folder() {
+            let root = std::env::temp_dir()
+                .join(format!("audio_player_library_link_{}", std::process::id()));
+            fs::create_dir_all(root.join("album")).unwrap();
+            fs::write(root.join("album/track.mp3"), b"").unwrap();
+
+            // A link back up to the library, which would otherwise be searched forever
+            std::os::unix::fs::symlink(&root, root.join("album/library")).unwrap();
+
+            let results = search_library(&root, "track", 10);
+            fs::remove_dir_all(&root).unwrap();
+
+            assert_eq!(results, vec![root.join("album/track.mp3")]);
+        }
+    }
+
+    mod http_settings {
+        use super::*;
+
+        use crate::app::config::Config;
+
+        #[test]
+        fn test_load() {
+            let path =
+                std::env::temp_dir().join(format!("audio_player_http_{}.toml", std::process::id()));
+            fs::write(&path, "[http]\ntoken = \"abc\"\n").unwrap();
+
+            let settings = Config::load_from(&path).http;
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(
+                settings,
+                Some(HttpSettings {
+                    address: HttpSettings::default_address(),
+                    token: "abc".to_string(),
+                    library: None,
+                })
+            );
+        }
+
+        #[test]
+        fn test_off_without_table() {
+            let path = std::env::temp_dir()
+                .join(format!("audio_player_no_http_{}.toml", std::process::id()));
+            fs::write(&path, "[keybindings]\n").unwrap();
+
+            let settings = Config::load_from(&path).http;
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(settings, None);
+        }
+    }
+}
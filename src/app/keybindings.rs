@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Something that a keyboard shortcut can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Action {
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+    SeekForwardLarge,
+    SeekBackwardLarge,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+
+    /// Jump to the next chapter, or the next bookmark if the track has no chapters
+    Next,
+
+    /// Jump to the previous chapter, or the previous bookmark if the track has no chapters
+    Previous,
+
+    /// Jump to a part of the track, in tenths of its length
+    JumpTo(u8),
+
+    /// Show or hide the list of shortcuts
+    Help,
+}
+
+/// How far the seek actions jump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SeekSteps {
+    pub(crate) step: Duration,
+    pub(crate) large_step: Duration,
+}
+
+impl Default for SeekSteps {
+    fn default() -> SeekSteps {
+        SeekSteps {
+            step: Duration::from_secs(5),
+            large_step: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Action {
+    /// How much the volume actions change the volume, from 0.0 to 1.0.
+    pub(crate) const VOLUME_STEP: f32 = 0.05;
+
+    /// Return every action, in the order they are listed to the user.
+    pub(crate) fn all() -> Vec<Action> {
+        let mut actions = vec![
+            Action::PlayPause,
+            Action::SeekForward,
+            Action::SeekBackward,
+            Action::SeekForwardLarge,
+            Action::SeekBackwardLarge,
+            Action::VolumeUp,
+            Action::VolumeDown,
+            Action::Mute,
+            Action::Next,
+            Action::Previous,
+        ];
+        actions.extend((0..10).map(Action::JumpTo));
+        actions.push(Action::Help);
+
+        actions
+    }
+
+    /// Return the name of the action in the config file.
+    pub(crate) fn name(self) -> String {
+        match self {
+            Action::PlayPause => "play_pause".to_string(),
+            Action::SeekForward => "seek_forward".to_string(),
+            Action::SeekBackward => "seek_backward".to_string(),
+            Action::SeekForwardLarge => "seek_forward_large".to_string(),
+            Action::SeekBackwardLarge => "seek_backward_large".to_string(),
+            Action::VolumeUp => "volume_up".to_string(),
+            Action::VolumeDown => "volume_down".to_string(),
+            Action::Mute => "mute".to_string(),
+            Action::Next => "next".to_string(),
+            Action::Previous => "previous".to_string(),
+            Action::JumpTo(tenths) => format!("jump_to_{}", u32::from(tenths) * 10),
+            Action::Help => "help".to_string(),
+        }
+    }
+
+    /// Find an action by its name in the config file.
+    pub(crate) fn from_name(name: &str) -> Option<Action> {
+        Action::all()
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+
+    /// Return what the action does, as shown to the user.
+    pub(crate) fn description(self, seek_steps: SeekSteps) -> String {
+        let step = seek_steps.step.as_secs_f64();
+        let large_step = seek_steps.large_step.as_secs_f64();
+
+        match self {
+            Action::PlayPause => "Play/pause".to_string(),
+            Action::SeekForward => format!("Forward {} seconds", step),
+            Action::SeekBackward => format!("Back {} seconds", step),
+            Action::SeekForwardLarge => format!("Forward {} seconds", large_step),
+            Action::SeekBackwardLarge => format!("Back {} seconds", large_step),
+            Action::VolumeUp => "Volume up".to_string(),
+            Action::VolumeDown => "Volume down".to_string(),
+            Action::Mute => "Mute/unmute".to_string(),
+            Action::Next => "Next chapter".to_string(),
+            Action::Previous => "Previous chapter".to_string(),
+            Action::JumpTo(tenths) => format!("Jump to {}%", u32::from(tenths) * 10),
+            Action::Help => "Show/hide shortcuts".to_string(),
+        }
+    }
+
+    /// Return the keys that the action is bound to when the config file doesn't say otherwise.
+    fn default_keys(self) -> Vec<&'static str> {
+        const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+        match self {
+            Action::PlayPause => vec!["Space"],
+            Action::SeekForward => vec!["Right"],
+            Action::SeekBackward => vec!["Left"],
+            Action::SeekForwardLarge => vec!["Shift+Right", "Ctrl+Right"],
+            Action::SeekBackwardLarge => vec!["Shift+Left", "Ctrl+Left"],
+            Action::VolumeUp => vec!["Up"],
+            Action::VolumeDown => vec!["Down"],
+            Action::Mute => vec!["M"],
+            Action::Next => vec!["N"],
+            Action::Previous => vec!["P"],
+            Action::JumpTo(tenths) => vec![DIGITS[usize::from(tenths) % 10]],
+            Action::Help => vec!["?", "F1"],
+        }
+    }
+}
+
+/// A key that can be bound to an action, without its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Key {
+    /// A key that types a character. Letters are always stored in lowercase.
+    Char(char),
+    Space,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Delete,
+
+    /// A function key, such as F1
+    F(u8),
+}
+
+impl Key {
+    /// The names of the keys that aren't characters, both for parsing and for showing them to the user.
+    const NAMED_KEYS: [(Key, &str); 14] = [
+        (Key::Space, "Space"),
+        (Key::Left, "Left"),
+        (Key::Right, "Right"),
+        (Key::Up, "Up"),
+        (Key::Down, "Down"),
+        (Key::Home, "Home"),
+        (Key::End, "End"),
+        (Key::PageUp, "PageUp"),
+        (Key::PageDown, "PageDown"),
+        (Key::Enter, "Enter"),
+        (Key::Escape, "Escape"),
+        (Key::Tab, "Tab"),
+        (Key::Backspace, "Backspace"),
+        (Key::Delete, "Delete"),
+    ];
+
+    /// Parse the name of a key, such as "Space", "F5" or "m". Names are not case-sensitive.
+    fn parse(name: &str) -> Option<Key> {
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Some(Key::Char(c.to_ascii_lowercase()));
+        }
+
+        if let Some((key, _)) = Key::NAMED_KEYS
+            .iter()
+            .find(|(_, key_name)| key_name.eq_ignore_ascii_case(name))
+        {
+            return Some(*key);
+        }
+
+        match name.to_ascii_lowercase().as_str() {
+            "esc" => Some(Key::Escape),
+            "return" => Some(Key::Enter),
+            name => name
+                .strip_prefix('f')
+                .and_then(|number| number.parse().ok())
+                .filter(|number| (1..=24).contains(number))
+                .map(Key::F),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            Key::F(number) => write!(f, "F{}", number),
+            key => {
+                let (_, name) = Key::NAMED_KEYS
+                    .iter()
+                    .find(|(named_key, _)| named_key == key)
+                    .expect("Every key that isn't a character or function key has a name");
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+/// A key along with the modifiers that have to be held down with it, such as Ctrl+Right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct KeyCombo {
+    pub(crate) key: Key,
+    pub(crate) ctrl: bool,
+    pub(crate) shift: bool,
+    pub(crate) alt: bool,
+}
+
+impl KeyCombo {
+    /// A key without any modifiers.
+    pub(crate) fn new(key: Key) -> KeyCombo {
+        KeyCombo {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    /// Parse a key combo such as "Ctrl+Shift+Left". "+" on its own is the plus key.
+    /// # Errors
+    /// - If a modifier or the key isn't known
+    pub(crate) fn parse(combo: &str) -> Result<KeyCombo, String> {
+        let combo = combo.trim();
+
+        // The key comes last, and may itself be "+"
+        let (modifiers, key) = match combo.strip_suffix('+') {
+            Some(modifiers) if modifiers.is_empty() || modifiers.ends_with('+') => {
+                (modifiers.trim_end_matches('+'), "+")
+            }
+            _ => combo.rsplit_once('+').unwrap_or(("", combo)),
+        };
+
+        let key = Key::parse(key.trim()).ok_or_else(|| format!("Unknown key \"{}\"", key))?;
+        let mut key_combo = KeyCombo::new(key);
+
+        for modifier in modifiers.split('+').filter(|modifier| !modifier.is_empty()) {
+            match modifier.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => key_combo.ctrl = true,
+                "shift" => key_combo.shift = true,
+                "alt" => key_combo.alt = true,
+                _ => return Err(format!("Unknown modifier \"{}\"", modifier)),
+            }
+        }
+
+        Ok(key_combo)
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+
+        write!(f, "{}", self.key)
+    }
+}
+
+/// Which keys trigger which actions.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Keybindings {
+    /// The keys of every action, in the order they are listed to the user
+    bindings: Vec<(Action, Vec<KeyCombo>)>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Keybindings {
+        let bindings = Action::all()
+            .into_iter()
+            .map(|action| {
+                let keys = action
+                    .default_keys()
+                    .into_iter()
+                    .map(|key| KeyCombo::parse(key).expect("The default keybindings are valid"))
+                    .collect();
+                (action, keys)
+            })
+            .collect();
+
+        Keybindings { bindings }
+    }
+}
+
+impl Keybindings {
+    /// Start from the default keybindings and replace the keys of every action in `config`,
+    /// which holds the keys of each action by the name of the action.
+    /// Invalid entries are skipped, and returned as warnings.
+    pub(crate) fn from_config(config: &HashMap<String, Vec<String>>) -> (Keybindings, Vec<String>) {
+        let mut keybindings = Keybindings::default();
+        let mut warnings = Vec::new();
+
+        // Go through the actions in a fixed order, so that conflicts are always resolved the same way
+        let mut entries: Vec<_> = config.iter().collect();
+        entries.sort();
+
+        for (name, keys) in entries {
+            let Some(action) = Action::from_name(name) else {
+                warnings.push(format!("Unknown action \"{}\" in the keybindings", name));
+                continue;
+            };
+
+            let keys = keys
+                .iter()
+                .filter_map(|key| {
+                    KeyCombo::parse(key)
+                        .inspect_err(|e| {
+                            warnings.push(format!("Invalid keybinding for \"{}\": {}", name, e))
+                        })
+                        .ok()
+                })
+                .collect();
+
+            keybindings.bind(action, keys);
+        }
+
+        (keybindings, warnings)
+    }
+
+    /// Replace the keys of an action. A key can only do one thing, so the keys are taken away from any other action.
+    fn bind(&mut self, action: Action, keys: Vec<KeyCombo>) {
+        for (other_action, other_keys) in self.bindings.iter_mut() {
+            if *other_action == action {
+                *other_keys = keys.clone();
+            } else {
+                other_keys.retain(|key| !keys.contains(key));
+            }
+        }
+    }
+
+    /// Return the action that a key combo triggers, if there is one.
+    pub(crate) fn action_for(&self, combo: KeyCombo) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&combo))
+            .map(|(action, _)| *action)
+    }
+
+    /// Return every action along with its keys, in the order they are listed to the user.
+    pub(crate) fn bindings(&self) -> &[(Action, Vec<KeyCombo>)] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod action {
+        use super::*;
+
+        #[test]
+        fn test_names_round_trip() {
+            for action in Action::all() {
+                assert_eq!(Action::from_name(&action.name()), Some(action));
+            }
+        }
+
+        #[test]
+        fn test_jump_name() {
+            assert_eq!(Action::from_name("jump_to_70"), Some(Action::JumpTo(7)));
+        }
+
+        #[test]
+        fn test_unknown_name() {
+            assert_eq!(Action::from_name("explode"), None);
+        }
+    }
+
+    mod key_combo {
+        use super::*;
+
+        #[test]
+        fn test_plain_key() {
+            assert_eq!(KeyCombo::parse("Space"), Ok(KeyCombo::new(Key::Space)));
+        }
+
+        #[test]
+        fn test_letters_are_lowercased() {
+            assert_eq!(KeyCombo::parse("M"), Ok(KeyCombo::new(Key::Char('m'))));
+        }
+
+        #[test]
+        fn test_modifiers() {
+            let combo = KeyCombo::parse("ctrl+Shift+right").unwrap();
+
+            assert_eq!(combo.key, Key::Right);
+            assert!(combo.ctrl);
+            assert!(combo.shift);
+            assert!(!combo.alt);
+        }
+
+        #[test]
+        fn test_plus_key() {
+            assert_eq!(KeyCombo::parse("+"), Ok(KeyCombo::new(Key::Char('+'))));
+
+            let combo = KeyCombo::parse("Ctrl++").unwrap();
+            assert_eq!(combo.key, Key::Char('+'));
+            assert!(combo.ctrl);
+        }
+
+        #[test]
+        fn test_function_key() {
+            assert_eq!(KeyCombo::parse("F12"), Ok(KeyCombo::new(Key::F(12))));
+        }
+
+        #[test]
+        fn test_unknown_key() {
+            assert!(KeyCombo::parse("Hyper").is_err());
+        }
+
+        #[test]
+        fn test_unknown_modifier() {
+            assert!(KeyCombo::parse("Super+Left").is_err());
+        }
+
+        #[test]
+        fn test_display_round_trip() {
+            for combo in ["Ctrl+Shift+Left", "Space", "M", "F1", "?", "Alt+PageUp"] {
+                assert_eq!(KeyCombo::parse(combo).unwrap().to_string(), combo);
+            }
+        }
+    }
+
+    mod keybindings {
+        use super::*;
+
+        use std::fs;
+        use std::path::Path;
+
+        use crate::app::config::Config;
+
+        fn config(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+            entries
+                .iter()
+                .map(|(name, keys)| {
+                    let keys = keys.iter().map(|key| key.to_string()).collect();
+                    (name.to_string(), keys)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn test_defaults() {
+            let keybindings = Keybindings::default();
+
+            assert_eq!(
+                keybindings.action_for(KeyCombo::new(Key::Space)),
+                Some(Action::PlayPause)
+            );
+            assert_eq!(
+                keybindings.action_for(KeyCombo::parse("Shift+Left").unwrap()),
+                Some(Action::SeekBackwardLarge)
+            );
+            assert_eq!(
+                keybindings.action_for(KeyCombo::new(Key::Char('3'))),
+                Some(Action::JumpTo(3))
+            );
+        }
+
+        #[test]
+        fn test_override() {
+            let (keybindings, _) = Keybindings::from_config(&config(&[("play_pause", &["K"])]));
+
+            assert_eq!(
+                keybindings.action_for(KeyCombo::new(Key::Char('k'))),
+                Some(Action::PlayPause)
+            );
+            assert_eq!(keybindings.action_for(KeyCombo::new(Key::Space)), None);
+        }
+
+        #[test]
+        fn test_override_takes_key_from_other_action() {
+            let (keybindings, _) = Keybindings::from_config(&config(&[("mute", &["Space"])]));
+
+            assert_eq!(
+                keybindings.action_for(KeyCombo::new(Key::Space)),
+                Some(Action::Mute)
+            );
+        }
+
+        #[test]
+        fn test_invalid_entries_are_skipped() {
+            let (keybindings, warnings) = Keybindings::from_config(&config(&[
+                ("explode", &["X"]),
+                ("mute", &["Hyper", "X"]),
+            ]));
+
+            assert_eq!(warnings.len(), 2);
+
+            assert_eq!(
+                keybindings.action_for(KeyCombo::new(Key::Char('x'))),
+                Some(Action::Mute)
+            );
+            assert_eq!(keybindings.action_for(KeyCombo::new(Key::Char('m'))), None);
+        }
+
+        #[test]
+        fn test_load_missing_file() {
+            let keybindings = Config::load_from(Path::new("./does/not/exist.toml")).keybindings;
+
+            assert_eq!(keybindings, Keybindings::default());
+        }
+
+        #[test]
+        fn test_load_from_file() {
+            let path = std::env::temp_dir().join("audio_player_test_keybindings.toml");
+            fs::write(&path, "[keybindings]\nnext = [\"Ctrl+N\"]\n").unwrap();
+
+            let keybindings = Config::load_from(&path).keybindings;
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(
+                keybindings.action_for(KeyCombo::parse("Ctrl+N").unwrap()),
+                Some(Action::Next)
+            );
+        }
+    }
+}
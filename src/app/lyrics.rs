@@ -0,0 +1,502 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::Duration;
+
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::id3::v2::{
+    Frame, FrameFlags, Id3v2Tag, SyncTextContentType, SynchronizedTextFrame, TimestampFormat,
+};
+use lofty::mpeg::{Layer, MpegFile, MpegVersion};
+use lofty::read_from_path;
+use lofty::tag::ItemKey;
+
+use crate::cli::parse_timestamp;
+
+/// A word of a line with word-level timing.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LyricWord {
+    /// When the word starts being sung
+    pub(crate) time: Duration,
+
+    /// The text of the word, including any space that follows it
+    pub(crate) text: String,
+}
+
+/// A line of lyrics.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LyricLine {
+    /// When the line starts being sung, which unsynced lyrics don't say
+    pub(crate) time: Option<Duration>,
+
+    pub(crate) text: String,
+
+    /// When each word of the line starts, for lyrics with word-level timing. Empty otherwise
+    pub(crate) words: Vec<LyricWord>,
+}
+
+impl LyricLine {
+    /// Return how many bytes of the text have been sung at `position`.
+    /// Lines without word-level timing are sung all at once.
+    pub(crate) fn sung_len(&self, position: Duration) -> usize {
+        if self.words.is_empty() {
+            return if self.time.is_some_and(|time| time <= position) {
+                self.text.len()
+            } else {
+                0
+            };
+        }
+
+        // Any text before the first word is sung along with the start of the line
+        let lead_in = self.text.len() - self.words.iter().map(|w| w.text.len()).sum::<usize>();
+
+        lead_in
+            + self
+                .words
+                .iter()
+                .take_while(|word| word.time <= position)
+                .map(|word| word.text.len())
+                .sum::<usize>()
+    }
+}
+
+/// The lyrics of a track, which are either synced to the audio or just a list of lines.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Lyrics {
+    /// The lines in the order they are sung. Synced lines are sorted by their time
+    lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    /// Load the lyrics of the track at `path`, if it has any.
+    /// An `.lrc` file next to the track is preferred, since it is usually the one that was put there on purpose.
+    /// Otherwise, the synced lyrics of an ID3v2 tag come before the plain lyrics of any tag.
+    pub(crate) fn load(path: &Path) -> Option<Lyrics> {
+        let lyrics = Lyrics::read_sidecar(path)
+            .or_else(|| Lyrics::read_synced_frame(path))
+            .or_else(|| Lyrics::read_tag(path))?;
+
+        (!lyrics.lines.is_empty()).then_some(lyrics)
+    }
+
+    /// Parse LRC lyrics, such as "[00:12.50]A line". Lines without a timestamp are only kept if no line has one.
+    /// The `[offset:]` tag and enhanced LRC word timings, such as "<00:12.80>word", are applied.
+    pub(crate) fn parse_lrc(text: &str) -> Lyrics {
+        let mut offset_ms = 0;
+        let mut synced = Vec::new();
+        let mut unsynced = Vec::new();
+
+        for line in text.lines() {
+            let (times, rest) = Lyrics::split_tags(line.trim(), &mut offset_ms);
+
+            if times.is_empty() {
+                // Lines that only held tags, such as the artist, aren't lyrics
+                if rest.len() == line.trim().len() {
+                    unsynced.push(LyricLine {
+                        time: None,
+                        text: rest.to_string(),
+                        words: Vec::new(),
+                    });
+                }
+                continue;
+            }
+
+            // A line that is repeated, such as a chorus, can have a timestamp for every time it is sung
+            let (text, words) = Lyrics::parse_words(rest);
+            for time in times {
+                synced.push(LyricLine {
+                    time: Some(time),
+                    text: text.clone(),
+                    words: words.clone(),
+                });
+            }
+        }
+
+        if synced.is_empty() {
+            return Lyrics {
+                lines: Lyrics::trim_blank_lines(unsynced),
+            };
+        }
+
+        // A positive offset makes the lyrics come sooner
+        let shift = |time: Duration| {
+            let ms = time.as_millis() as i64 - offset_ms;
+            Duration::from_millis(ms.max(0) as u64)
+        };
+        for line in &mut synced {
+            line.time = line.time.map(shift);
+            for word in &mut line.words {
+                word.time = shift(word.time);
+            }
+        }
+
+        synced.sort_by_key(|line| line.time);
+        Lyrics { lines: synced }
+    }
+
+    pub(crate) fn lines(&self) -> &[LyricLine] {
+        &self.lines
+    }
+
+    /// Return whether the lines say when they are sung.
+    pub(crate) fn is_synced(&self) -> bool {
+        self.lines.iter().any(|line| line.time.is_some())
+    }
+
+    /// Return the index of the line that is being sung at `position`, which is `None` before the first line.
+    pub(crate) fn line_at(&self, position: Duration) -> Option<usize> {
+        let sung = self
+            .lines
+            .partition_point(|line| line.time.is_some_and(|time| time <= position));
+
+        sung.checked_sub(1)
+    }
+
+    /// Split the leading `[...]` tags off of an LRC line, returning its timestamps and the text after them.
+    /// An `[offset:]` tag is stored in `offset_ms`, and other tags like `[ar:]` are ignored.
+    fn split_tags<'a>(line: &'a str, offset_ms: &mut i64) -> (Vec<Duration>, &'a str) {
+        let mut times = Vec::new();
+        let mut rest = line;
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(end) = tag.find(']') else {
+                break;
+            };
+            let content = &tag[..end];
+
+            if let Ok(time) = parse_timestamp(content) {
+                times.push(time);
+            } else if let Some((key, value)) = content.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("offset") {
+                    *offset_ms = value.trim().parse().unwrap_or(0);
+                }
+            } else {
+                // Not a tag, so the brackets are part of the lyrics
+                break;
+            }
+
+            rest = &tag[end + 1..];
+        }
+
+        (times, rest)
+    }
+
+    /// Take the `<mm:ss.xx>` word timings out of the text of a line, returning the text and its words.
+    fn parse_words(text: &str) -> (String, Vec<LyricWord>) {
+        let mut plain = String::new();
+        let mut words: Vec<LyricWord> = Vec::new();
+        let mut rest = text;
+
+        while let Some(tag_start) = rest.find('<') {
+            let Some(tag_len) = rest[tag_start..].find('>') else {
+                break;
+            };
+            let Ok(time) = parse_timestamp(&rest[tag_start + 1..tag_start + tag_len]) else {
+                // Not a timing, so keep it as text
+                Lyrics::push_text(&mut plain, &mut words, &rest[..=tag_start]);
+                rest = &rest[tag_start + 1..];
+                continue;
+            };
+
+            Lyrics::push_text(&mut plain, &mut words, &rest[..tag_start]);
+            words.push(LyricWord {
+                time,
+                text: String::new(),
+            });
+            rest = &rest[tag_start + tag_len + 1..];
+        }
+        Lyrics::push_text(&mut plain, &mut words, rest);
+
+        // A timing at the very end only says when the last word stops
+        words.retain(|word| !word.text.is_empty());
+
+        (plain, words)
+    }
+
+    /// Add text to a line, and to the word that is being read if there is one.
+    fn push_text(plain: &mut String, words: &mut [LyricWord], text: &str) {
+        plain.push_str(text);
+        if let Some(word) = words.last_mut() {
+            word.text.push_str(text);
+        }
+    }
+
+    fn trim_blank_lines(mut lines: Vec<LyricLine>) -> Vec<LyricLine> {
+        while lines.last().is_some_and(|line| line.text.is_empty()) {
+            lines.pop();
+        }
+        let first = lines.iter().position(|line| !line.text.is_empty());
+
+        lines.split_off(first.unwrap_or(lines.len()))
+    }
+
+    /// Read the `.lrc` file with the same name as the track.
+    fn read_sidecar(path: &Path) -> Option<Lyrics> {
+        let text = fs::read_to_string(path.with_extension("lrc")).ok()?;
+
+        Some(Lyrics::parse_lrc(&text))
+    }
+
+    /// Read the synced lyrics from the SYLT frame of an MP3's ID3v2 tag.
+    fn read_synced_frame(path: &Path) -> Option<Lyrics> {
+        let mut file = File::open(path).ok()?;
+        let mpeg_file = MpegFile::read_from(&mut file, ParseOptions::new()).ok()?;
+        let tag: &Id3v2Tag = mpeg_file.id3v2()?;
+
+        let frame = tag.into_iter().find_map(|frame| match frame {
+            Frame::Binary(binary) if frame.id_str() == "SYLT" => {
+                SynchronizedTextFrame::parse(&binary.data, FrameFlags::default())
+                    .ok()
+                    .filter(|sylt| sylt.content_type == SyncTextContentType::Lyrics)
+            }
+            _ => None,
+        })?;
+
+        // Timestamps can count MPEG frames instead of milliseconds
+        let properties = mpeg_file.properties();
+        let frame_duration = match frame.timestamp_format {
+            TimestampFormat::MS => Duration::from_millis(1),
+            TimestampFormat::MPEG => {
+                let samples = match (properties.layer(), properties.version()) {
+                    (Layer::Layer1, _) => 384,
+                    (Layer::Layer3, MpegVersion::V2 | MpegVersion::V2_5) => 576,
+                    _ => 1152,
+                };
+                Duration::from_secs_f64(samples as f64 / properties.sample_rate().max(1) as f64)
+            }
+        };
+
+        Some(Lyrics::from_synced_text(&frame.content, frame_duration))
+    }
+
+    /// Turn the text of a SYLT frame into lines. Each piece of text starts a new line if it begins with a line break,
+    /// otherwise it is the next word of the current line.
+    fn from_synced_text(content: &[(u32, String)], unit: Duration) -> Lyrics {
+        let mut lines: Vec<LyricLine> = Vec::new();
+
+        for (timestamp, text) in content {
+            let time = unit * *timestamp;
+            let new_line = text.starts_with(['\n', '\r']) || lines.is_empty();
+            let text = text.trim_start_matches(['\n', '\r']);
+
+            if new_line {
+                lines.push(LyricLine {
+                    time: Some(time),
+                    text: String::new(),
+                    words: Vec::new(),
+                });
+            }
+
+            let line = lines.last_mut().expect("a line was just added");
+            line.text.push_str(text);
+            line.words.push(LyricWord {
+                time,
+                text: text.to_string(),
+            });
+        }
+
+        // A line that was given all at once has no word-level timing
+        for line in &mut lines {
+            if line.words.len() == 1 {
+                line.words.clear();
+            }
+        }
+
+        lines.sort_by_key(|line| line.time);
+        Lyrics { lines }
+    }
+
+    /// Read the plain lyrics of a track, such as an ID3v2 USLT frame or a Vorbis LYRICS comment.
+    /// These may be LRC lyrics that were pasted into the tag, so they are parsed as such.
+    fn read_tag(path: &Path) -> Option<Lyrics> {
+        let tagged_file = read_from_path(path).ok()?;
+
+        let text = tagged_file
+            .tags()
+            .iter()
+            .find_map(|tag| tag.get_string(&ItemKey::Lyrics))?;
+
+        Some(Lyrics::parse_lrc(text))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    fn texts(lyrics: &Lyrics) -> Vec<&str> {
+        lyrics
+            .lines()
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect()
+    }
+
+    mod parse_lrc {
+        use super::*;
+
+        #[test]
+        fn test_synced() {
+            let lyrics = Lyrics::parse_lrc("[ar:Someone]\n[00:01.50]First\n[00:03.00]Second\n");
+
+            assert!(lyrics.is_synced());
+            assert_eq!(texts(&lyrics), ["First", "Second"]);
+            assert_eq!(lyrics.lines()[0].time, Some(secs(1.5)));
+            assert_eq!(lyrics.lines()[1].time, Some(secs(3.0)));
+        }
+
+        #[test]
+        fn test_repeated_line() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00][00:05.00]Chorus\n[00:03.00]Verse");
+
+            assert_eq!(texts(&lyrics), ["Chorus", "Verse", "Chorus"]);
+        }
+
+        #[test]
+        fn test_offset() {
+            let lyrics = Lyrics::parse_lrc("[offset:+500]\n[00:00.25]First\n[00:02.00]Second");
+
+            assert_eq!(lyrics.lines()[0].time, Some(Duration::ZERO));
+            assert_eq!(lyrics.lines()[1].time, Some(secs(1.5)));
+        }
+
+        #[test]
+        fn test_negative_offset() {
+            let lyrics = Lyrics::parse_lrc("[offset:-250]\n[00:01.00]First");
+
+            assert_eq!(lyrics.lines()[0].time, Some(secs(1.25)));
+        }
+
+        #[test]
+        fn test_word_timing() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]<00:01.00>Hello <00:01.50>there<00:02.00>");
+            let line = &lyrics.lines()[0];
+
+            assert_eq!(line.text, "Hello there");
+            assert_eq!(
+                line.words,
+                [
+                    LyricWord {
+                        time: secs(1.0),
+                        text: "Hello ".to_string()
+                    },
+                    LyricWord {
+                        time: secs(1.5),
+                        text: "there".to_string()
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn test_unsynced() {
+            let lyrics = Lyrics::parse_lrc("\nFirst line\n\nSecond verse\n\n");
+
+            assert!(!lyrics.is_synced());
+            assert_eq!(texts(&lyrics), ["First line", "", "Second verse"]);
+        }
+
+        #[test]
+        fn test_brackets_in_text() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]Hey [whispered] <laughs>");
+
+            assert_eq!(texts(&lyrics), ["Hey [whispered] <laughs>"]);
+        }
+    }
+
+    mod line_at {
+        use super::*;
+
+        #[test]
+        fn test_before_first_line() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]First\n[00:03.00]Second");
+
+            assert_eq!(lyrics.line_at(secs(0.5)), None);
+        }
+
+        #[test]
+        fn test_current_line() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]First\n[00:03.00]Second");
+
+            assert_eq!(lyrics.line_at(secs(1.0)), Some(0));
+            assert_eq!(lyrics.line_at(secs(2.9)), Some(0));
+            assert_eq!(lyrics.line_at(secs(60.0)), Some(1));
+        }
+
+        #[test]
+        fn test_unsynced() {
+            let lyrics = Lyrics::parse_lrc("First\nSecond");
+
+            assert_eq!(lyrics.line_at(secs(60.0)), None);
+        }
+    }
+
+    mod sung_len {
+        use super::*;
+
+        #[test]
+        fn test_words() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]<00:01.00>Hello <00:01.50>there");
+            let line = &lyrics.lines()[0];
+
+            assert_eq!(line.sung_len(secs(0.5)), 0);
+            assert_eq!(line.sung_len(secs(1.2)), "Hello ".len());
+            assert_eq!(line.sung_len(secs(1.5)), "Hello there".len());
+        }
+
+        #[test]
+        fn test_whole_line() {
+            let lyrics = Lyrics::parse_lrc("[00:01.00]Hello there");
+            let line = &lyrics.lines()[0];
+
+            assert_eq!(line.sung_len(secs(0.5)), 0);
+            assert_eq!(line.sung_len(secs(1.0)), "Hello there".len());
+        }
+    }
+
+    mod from_synced_text {
+        use super::*;
+
+        #[test]
+        fn test_lines_and_words() {
+            let content = vec![
+                (1000, "First".to_string()),
+                (2000, "\nSecond ".to_string()),
+                (2500, "line".to_string()),
+            ];
+
+            let lyrics = Lyrics::from_synced_text(&content, Duration::from_millis(1));
+
+            assert_eq!(texts(&lyrics), ["First", "Second line"]);
+            assert!(lyrics.lines()[0].words.is_empty());
+            assert_eq!(lyrics.lines()[1].time, Some(secs(2.0)));
+            assert_eq!(lyrics.lines()[1].words[1].time, secs(2.5));
+        }
+    }
+
+    mod load {
+        use std::env;
+
+        use super::*;
+
+        #[test]
+        fn test_sidecar() {
+            let dir = env::temp_dir().join(format!("audio_player_lyrics_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("song.lrc"), "[00:01.00]From the file").unwrap();
+
+            let lyrics = Lyrics::load(&dir.join("song.mp3")).unwrap();
+
+            assert_eq!(texts(&lyrics), ["From the file"]);
+        }
+
+        #[test]
+        fn test_no_lyrics() {
+            assert_eq!(Lyrics::load(Path::new("does_not_exist.mp3")), None);
+        }
+    }
+}
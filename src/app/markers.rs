@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::chapters::Chapter;
+
+/// A named position in a track that the user saved to come back to later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Bookmark {
+    pub(crate) position: Duration,
+    pub(crate) name: String,
+}
+
+/// How far past a marker playback has to be before "previous" goes back to that marker rather than the one before it.
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// How far ahead of the current position a marker has to be for "next" to jump to it.
+/// Without this, "next" could jump to the marker that playback has only just been moved to.
+const NEXT_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// Return the first marker after `current_pos`, if there is one.
+/// `markers` has to be sorted.
+pub(crate) fn next_marker(markers: &[Duration], current_pos: Duration) -> Option<Duration> {
+    markers
+        .iter()
+        .copied()
+        .find(|&marker| marker > current_pos + NEXT_THRESHOLD)
+}
+
+/// Return the marker to go back to from `current_pos`.
+/// This is the start of the current section, unless playback has only just started it, in which case it's the start of the one before.
+/// If there is no marker before `current_pos`, this is the start of the track.
+/// `markers` has to be sorted.
+pub(crate) fn previous_marker(markers: &[Duration], current_pos: Duration) -> Duration {
+    let threshold = current_pos.saturating_sub(RESTART_THRESHOLD);
+
+    markers
+        .iter()
+        .copied()
+        .rev()
+        .find(|&marker| marker < threshold)
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Return the name of the marker at `pos`, to show where a click would land.
+/// A bookmark within `tolerance` of `pos` is preferred, with the closest one winning. Otherwise, this is the title of the chapter that `pos` is in.
+/// `bookmarks` and `chapters` have to be sorted.
+pub(crate) fn label_at(
+    bookmarks: &[Bookmark],
+    chapters: &[Chapter],
+    pos: Duration,
+    tolerance: Duration,
+) -> Option<String> {
+    let bookmark = bookmarks
+        .iter()
+        .filter(|bookmark| bookmark.position.abs_diff(pos) <= tolerance)
+        .min_by_key(|bookmark| bookmark.position.abs_diff(pos));
+    if let Some(bookmark) = bookmark {
+        return Some(bookmark.name.clone());
+    }
+
+    chapters
+        .iter()
+        .rev()
+        .find(|chapter| chapter.start <= pos)
+        .map(|chapter| chapter.title.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn markers() -> Vec<Duration> {
+        vec![
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+            Duration::from_secs(120),
+        ]
+    }
+
+    mod next_marker {
+        use super::*;
+
+        #[test]
+        fn test_between_markers() {
+            assert_eq!(
+                next_marker(&markers(), Duration::from_secs(30)),
+                Some(Duration::from_secs(60))
+            );
+        }
+
+        #[test]
+        fn test_just_jumped_to_marker() {
+            assert_eq!(
+                next_marker(&markers(), Duration::from_millis(60100)),
+                Some(Duration::from_secs(120))
+            );
+        }
+
+        #[test]
+        fn test_after_last_marker() {
+            assert_eq!(next_marker(&markers(), Duration::from_secs(130)), None);
+        }
+    }
+
+    mod previous_marker {
+        use super::*;
+
+        #[test]
+        fn test_restart_section() {
+            assert_eq!(
+                previous_marker(&markers(), Duration::from_secs(90)),
+                Duration::from_secs(60)
+            );
+        }
+
+        #[test]
+        fn test_just_started_section() {
+            assert_eq!(
+                previous_marker(&markers(), Duration::from_secs(61)),
+                Duration::from_secs(10)
+            );
+        }
+
+        #[test]
+        fn test_before_first_marker() {
+            assert_eq!(
+                previous_marker(&markers(), Duration::from_secs(5)),
+                Duration::ZERO
+            );
+        }
+    }
+
+    mod label_at {
+        use super::*;
+
+        fn bookmarks() -> Vec<Bookmark> {
+            vec![
+                Bookmark {
+                    position: Duration::from_secs(30),
+                    name: "Solo".to_string(),
+                },
+                Bookmark {
+                    position: Duration::from_secs(32),
+                    name: "Bridge".to_string(),
+                },
+            ]
+        }
+
+        fn chapters() -> Vec<Chapter> {
+            vec![
+                Chapter {
+                    start: Duration::ZERO,
+                    title: "Intro".to_string(),
+                },
+                Chapter {
+                    start: Duration::from_secs(20),
+                    title: "Verse".to_string(),
+                },
+            ]
+        }
+
+        #[test]
+        fn test_closest_bookmark() {
+            assert_eq!(
+                label_at(
+                    &bookmarks(),
+                    &chapters(),
+                    Duration::from_millis(31500),
+                    Duration::from_secs(2)
+                ),
+                Some("Bridge".to_string())
+            );
+        }
+
+        #[test]
+        fn test_chapter() {
+            assert_eq!(
+                label_at(
+                    &bookmarks(),
+                    &chapters(),
+                    Duration::from_secs(25),
+                    Duration::from_secs(2)
+                ),
+                Some("Verse".to_string())
+            );
+            assert_eq!(
+                label_at(
+                    &bookmarks(),
+                    &chapters(),
+                    Duration::from_secs(5),
+                    Duration::from_secs(2)
+                ),
+                Some("Intro".to_string())
+            );
+        }
+
+        #[test]
+        fn test_nothing_there() {
+            assert_eq!(
+                label_at(
+                    &bookmarks(),
+                    &[],
+                    Duration::from_secs(10),
+                    Duration::from_secs(2)
+                ),
+                None
+            );
+        }
+    }
+}
@@ -1,17 +1,87 @@
+pub(crate) mod ab_loop;
 mod audio_handler;
+pub(crate) mod chapters;
+pub(crate) mod config;
+pub(crate) mod control;
+pub(crate) mod dsp;
+pub(crate) mod export;
+pub(crate) mod http_api;
+pub(crate) mod keybindings;
+pub(crate) mod lyrics;
+pub(crate) mod markers;
+#[cfg(target_os = "linux")]
+pub(crate) mod mpris;
+pub(crate) mod playlist;
+pub(crate) mod podcasts;
+pub(crate) mod queue;
+mod queue_slots;
+pub(crate) mod recent;
+pub(crate) mod replay_gain;
+pub(crate) mod sleep_timer;
+pub(crate) mod stable_hash;
+pub(crate) mod stream;
+pub(crate) mod tag_editor;
+pub(crate) mod theme;
+pub(crate) mod track_length;
+mod track_loader;
+pub(crate) mod track_state;
+pub(crate) mod visualizer;
+pub(crate) mod waveform;
 mod ui;
 
-use fltk::{app, enums::Color, prelude::*, window};
+use fltk::{
+    app,
+    button::Button,
+    dialog,
+    enums::{Color, Event},
+    frame::Frame,
+    prelude::*,
+    window,
+};
 
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
 use std::process::exit;
+use std::rc::Rc;
+use std::slice;
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 use std::time::Duration;
 
+use ab_loop::AbLoop;
 use audio_handler::AudioHandler;
+use config::{Config, ConfigWatcher};
+use lyrics::Lyrics;
+use podcasts::PodcastLibrary;
+use queue::{Queue, QueuedTrack};
+use recent::RecentFiles;
+use replay_gain::ReplayGain;
+use sleep_timer::{SleepTimerMode, SleepTimerStatus};
+use theme::Theme;
+use track_loader::OpenedTrack;
+use track_state::TrackStateStore;
+use ui::bookmarks_dialog::BookmarksDialog;
+use ui::export_dialog::ExportDialog;
+use ui::file_drop::FileDrop;
+use ui::layout::{Bounds, Layout};
+use ui::loop_controls::{LoopControls, LoopRegion};
+use ui::lyrics_view::LyricsView;
+use ui::markers::Markers;
+#[cfg(target_os = "linux")]
+use ui::media_controls::MediaControls;
+use ui::menu_bar::{MenuBar, OpenKind};
 use ui::playback_buttons::PlaybackButtons;
+use ui::podcasts_dialog::PodcastsDialog;
+use ui::queue_panel::QueuePanel;
+use ui::remote_control::RemoteControl;
+use ui::shortcuts::{Shortcuts, Volume};
+use ui::sleep_timer_dialog::SleepTimerDialog;
+use ui::tag_editor_dialog::TagEditorDialog;
+use ui::track_info_dialog::TrackInfoDialog;
+use ui::visualizer::Visualizer;
+use visualizer::VisualizerMode;
 
-use crate::app::ui::progress_bar::ProgressBar;
+use crate::app::ui::progress_bar::{ProgressBar, TimeDisplay};
 
 use crate::app::ui::now_playing::NowPlaying;
 
@@ -22,6 +92,55 @@ enum Message {
     Pause,
     FastForward(Duration),
     Rewind(Duration),
+
+    /// Jump straight to a position in the audio
+    Seek(Duration),
+
+    /// Replace the A–B loop, or clear it with `None`
+    SetLoop(Option<AbLoop>),
+
+    /// Start the sleep timer, or cancel it with `None`
+    SetSleepTimer(Option<SleepTimerMode>),
+
+    /// Give the running sleep timer more time
+    ExtendSleepTimer,
+
+    /// Change the volume, from 0.0 to 1.0
+    SetVolume(f32),
+
+    /// Add a track to the end of the queue
+    Enqueue(PathBuf),
+
+    /// Skip to the next track in the queue
+    Next,
+
+    /// Go back to the track before the current one in the queue, which plays again along with the ones after it
+    Previous,
+
+    /// Replace the queue with these tracks, starting with the first one that can be played
+    Open(Vec<PathBuf>),
+
+    /// Even out the volume of the tracks that are opened from now on differently
+    SetReplayGain(ReplayGain),
+
+    /// Move a track that hasn't been played yet to a different place in the queue, by their indices in the queue
+    MoveTrack {
+        from: usize,
+        to: usize,
+    },
+
+    /// Take a track that isn't playing out of the queue, by its index in the queue
+    RemoveTrack(usize),
+
+    /// Take the tracks that were already played out of the queue
+    ClearPlayed,
+}
+
+/// The ends of the channels between the UI and the audio thread, which the components of every track share.
+struct Channels {
+    audio_sender: mpsc::Sender<Message>,
+    audio_pos_receiver: Rc<mpsc::Receiver<Duration>>,
+    sleep_timer_receiver: Rc<mpsc::Receiver<SleepTimerStatus>>,
 }
 
 /// Stores the components of the GUI.
@@ -29,6 +148,18 @@ pub struct AudioApp {
     app: app::App,
     window: window::DoubleWindow,
 
+    /// The colors of the player, which follow the desktop and the cover of the track if the user wants
+    theme: Theme,
+
+    /// The layout that the user picked, which the window switches to in the main loop
+    layout: Rc<Cell<Layout>>,
+
+    /// The layout that the window is in
+    shown_layout: Layout,
+
+    /// The menu bar that opens files, folders and playlists, which only the full window has
+    menu_bar: Option<MenuBar>,
+
     /// Buttons to control playback. These are the pause, rewind, and fast-forward buttons.
     playback_buttons: Option<PlaybackButtons>,
 
@@ -39,36 +170,140 @@ pub struct AudioApp {
     audio_handler: AudioHandler,
 
     /// The section that shows the user what is currently playing
-    _now_playing: NowPlaying,
+    now_playing: Option<NowPlaying>,
+
+    /// Whether the line with the codec, sample rate and bitrate is shown under the artist
+    show_technical: Rc<Cell<bool>>,
+
+    /// How the timestamps next to the progress bar are shown
+    time_display: Rc<Cell<TimeDisplay>>,
+
+    /// Whether the waveform of the track is drawn in place of the progress bar
+    show_waveform: Rc<Cell<bool>>,
+
+    /// The lyrics of the current track, which follow the audio if they are synced
+    lyrics_view: Option<LyricsView>,
+
+    /// The dialog that lets the user export the current track to a different format
+    export_dialog: Option<ExportDialog>,
+
+    /// Buttons to mark an A–B loop and change how it is practiced
+    loop_controls: Option<LoopControls>,
+
+    /// The dialog that controls the sleep timer, along with its countdown
+    sleep_timer_dialog: Option<SleepTimerDialog>,
+
+    /// The dialog that lists the podcasts and their episodes
+    podcasts_dialog: Option<PodcastsDialog>,
+
+    /// The dialog that edits the tags of the current track, which streams don't have
+    tag_editor_dialog: Option<TagEditorDialog>,
+
+    /// The list of every track in the queue, which stays open while the queue moves from track to track
+    queue_panel: Option<QueuePanel>,
+
+    /// The spectrum, oscilloscope or level meter of what is playing
+    visualizer: Option<Visualizer>,
+
+    /// What the visualizer shows, which carries over from one track to the next
+    visualizer_mode: Rc<Cell<VisualizerMode>>,
+
+    /// The dialogs of the current track, which are deleted once the queue moves on to another track
+    dialogs: Vec<window::Window>,
+
+    /// The settings from the config file, such as the keyboard shortcuts, which are shared by every track
+    config: Rc<Config>,
+
+    /// Loads the config file again whenever the user changes it, if it could be found
+    config_watcher: Option<ConfigWatcher>,
+
+    /// The volume, which carries over from one track to the next
+    volume: Rc<Cell<Volume>>,
+
+    /// The desktop's media controls, if they could be started
+    #[cfg(target_os = "linux")]
+    media_controls: Option<MediaControls>,
+
+    /// The control socket that `audio_player ctl` talks to and the HTTP server, if either could be started
+    remote_control: Option<RemoteControl>,
+
+    /// Everything that is remembered about each track between runs, such as its loop
+    track_state: Rc<RefCell<TrackStateStore>>,
+
+    /// The podcasts that the user is subscribed to, which keep downloading while the queue moves from track to track
+    podcasts: Rc<RefCell<PodcastLibrary>>,
+
+    /// The files, folders and playlists that were opened last
+    recent: Rc<RefCell<RecentFiles>>,
 }
 
 impl AudioApp {
     const WIN_WIDTH: i32 = 400;
-    const WIN_HEIGHT: i32 = 300;
+    const WIN_HEIGHT: i32 = 499;
+
+    /// The size of the buttons in the top corners, and their distance from the edges of the window
+    const CORNER_BTN_SIZE: i32 = 30;
+    const CORNER_BTN_MARGIN: i32 = 10;
+
+    /// The top of the buttons in the corners, which sit under the menu bar
+    const CORNER_BTN_Y: i32 = MenuBar::HEIGHT + AudioApp::CORNER_BTN_MARGIN;
+
+    /// The size of the mini player, which is a strip with a thumbnail of the cover, the title, the main buttons and a thin progress bar
+    const MINI_WIN_WIDTH: i32 = 360;
+    const MINI_WIN_HEIGHT: i32 = 64;
+
+    /// The button that switches between the full window and the mini player
+    const FULL_PLAYER_BTN_LABEL: &str = "\u{f05af}";
+    const MINI_PLAYER_BTN_LABEL: &str = "\u{f05b0}";
 
     /// Create the new App.
     pub fn new() -> AudioApp {
         let app = app::App::default().with_scheme(app::Scheme::Gtk);
-        let audio_handler = AudioHandler::new();
+        let config = Config::load();
+        let audio_handler = AudioHandler::new(config.audio.clone(), config.playback.replay_gain());
 
         // Create a new window
         let window = AudioApp::create_window();
 
-        let now_playing =
-            NowPlaying::new("/home/dastarruer/Documents/coding/rust/audio_player/test.mp3");
-
         AudioApp {
             app,
             window,
+            theme: Theme::new(config.theme.clone()),
+            layout: Rc::new(Cell::new(Layout::default())),
+            shown_layout: Layout::default(),
+            menu_bar: None,
             playback_buttons: None,
             progress_bar: None,
             audio_handler,
-            _now_playing: now_playing,
+            now_playing: None,
+            show_technical: Rc::new(Cell::new(true)),
+            time_display: Rc::new(Cell::new(TimeDisplay::default())),
+            show_waveform: Rc::new(Cell::new(false)),
+            lyrics_view: None,
+            export_dialog: None,
+            loop_controls: None,
+            sleep_timer_dialog: None,
+            podcasts_dialog: None,
+            tag_editor_dialog: None,
+            queue_panel: None,
+            visualizer: None,
+            visualizer_mode: Rc::new(Cell::new(VisualizerMode::default())),
+            dialogs: Vec::new(),
+            config: Rc::new(config),
+            config_watcher: Config::path().map(ConfigWatcher::start),
+            volume: Rc::new(Cell::new(Volume::default())),
+            #[cfg(target_os = "linux")]
+            media_controls: None,
+            remote_control: None,
+            track_state: Rc::new(RefCell::new(TrackStateStore::load())),
+            podcasts: Rc::new(RefCell::new(PodcastLibrary::load())),
+            recent: Rc::new(RefCell::new(RecentFiles::load())),
         }
     }
 
-    /// Run the app.
-    pub fn run(&mut self) {
+    /// Run the app, playing `files` one after the other.
+    /// Without any files, the player carries on with what was opened last, or asks the user what to open.
+    pub fn run(&mut self, files: Vec<PathBuf>) {
         // Create a channel to send messages to the audio thread, allowing ui elements to do things such as pause, play, rewind, etc.
         let (sender, recevier) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(recevier));
@@ -76,52 +311,796 @@ impl AudioApp {
         // Create the channel for the progress bar and audio sink to communicate the audio position to each other
         let (audio_pos_sender, audio_pos_receiver) = mpsc::channel::<Duration>();
 
+        // Create the channel for the audio thread to tell the countdown how much longer the sleep timer has left
+        let (sleep_timer_sender, sleep_timer_receiver) = mpsc::channel::<SleepTimerStatus>();
+
+        // Create the channel for the audio thread to tell the UI when the queue changes
+        let (queue_sender, queue_receiver) = mpsc::channel::<Queue>();
+
         // Load the audio
-        let (decoder, audio_length) = AudioHandler::load_audio(
-            "/home/dastarruer/Documents/coding/rust/audio_player/test.mp3",
-        );
-        let audio_length = audio_length.unwrap_or_else(|| {
-            eprintln!("Duration could not be determined. Exiting...");
-            exit(1);
-        });
+        let ((source, track), tracks) = self.open_starting_tracks(files);
+
+        let channels = Channels {
+            audio_sender: sender.clone(),
+            audio_pos_receiver: Rc::new(audio_pos_receiver),
+            sleep_timer_receiver: Rc::new(sleep_timer_receiver),
+        };
+
+        let queue = Rc::new(RefCell::new(Queue::new(track.clone())));
+        self.queue_panel = Some(QueuePanel::new(Rc::clone(&queue), sender.clone()));
 
         // Create the components
-        self.create_app_components(sender, audio_length, audio_pos_receiver);
+        let shortcuts = self.load_track(&track, &channels);
 
         // Show the window
-        self.window.end();
         self.window.show();
 
         // Play the audio
-        self.audio_handler
-            .play_audio(Arc::clone(&receiver), audio_pos_sender, decoder);
+        self.audio_handler.play_audio(
+            Arc::clone(&receiver),
+            audio_pos_sender,
+            sleep_timer_sender,
+            queue_sender,
+            source,
+            queue.borrow().clone(),
+        );
+
+        // The rest of the tracks play after the first one
+        for path in tracks {
+            if let Err(e) = sender.send(Message::Enqueue(path)) {
+                eprintln!("Unable to queue track: {:?}", e);
+            }
+        }
+
+        self.resume_episode(&track, &sender);
+
+        self.start_controls(&track, shortcuts, sender, Rc::clone(&queue));
 
         // Run the app
         while self.app.wait() {
-            // Sleep thread so that fltk updates even when idling
-            thread::sleep(Duration::from_millis(50));
+            // Sleep thread so that fltk updates even when idling, waking up often enough for the visualizer's frames
+            thread::sleep(Visualizer::FRAME_INTERVAL);
+
+            // Switch to the next track once the audio thread has moved on to it
+            if let Some(new_queue) = queue_receiver.try_iter().last() {
+                // Removing tracks from the queue moves the current one without changing it, so count how far the queue moved on
+                let advanced_by = new_queue.advanced() - queue.borrow().advanced();
+                let length_found = new_queue.current().length != queue.borrow().current().length;
+
+                // The new queue knows where the previous track was skipped, unless it was removed or replaced in the
+                // meantime, in which case it stopped wherever the progress bar last was
+                let previous_index = if new_queue.went_back() {
+                    Some(new_queue.current_index() + 1)
+                } else {
+                    new_queue.current_index().checked_sub(advanced_by)
+                };
+                let previous_track =
+                    match previous_index.and_then(|index| new_queue.tracks().get(index)) {
+                        Some(track) => track.clone(),
+                        None => self.stopped_track(queue.borrow().current()),
+                    };
+                *queue.borrow_mut() = new_queue;
+
+                let track = queue.borrow().current().clone();
+                if advanced_by > 0 {
+                    // Something opened from the menu is only remembered once it plays
+                    self.recent.borrow_mut().started_playing(&track.path);
+                    self.record_episode_progress(&previous_track);
+                    self.switch_track(&track, &channels);
+                    self.resume_episode(&track, &channels.audio_sender);
+                } else if length_found {
+                    self.show_length(&track);
+                }
+            }
+
+            // List the recent items again once something else is opened
+            if let Some(menu_bar) = self.menu_bar.as_ref() {
+                menu_bar.update();
+            }
+
+            // List the queue again once it changes
+            if let Some(queue_panel) = self.queue_panel.as_ref() {
+                queue_panel.update();
+            }
+
+            // Show the song that a stream is playing, once its station moves on to the next one
+            let track = queue.borrow().current().clone();
+            self.update_stream_title(&track);
+
+            // Switch between the full window and the mini player once the user asks to
+            if self.layout.get() != self.shown_layout {
+                self.switch_layout(&track, &channels);
+            }
+
+            // Show the new tags of the current track once they are saved
+            let saved = self
+                .tag_editor_dialog
+                .as_ref()
+                .map(TagEditorDialog::take_saved)
+                .unwrap_or_default();
+            if saved.contains(&track.path) {
+                self.switch_track(&track, &channels);
+            }
 
             // Update progress bar
             if let Some(pb) = self.progress_bar.as_mut() {
                 pb.update();
             }
+
+            // Switch between light and dark along with the desktop
+            self.theme.update();
+
+            // Use the settings in the config file once the user changes it
+            if let Some(changed) = self
+                .config_watcher
+                .as_ref()
+                .and_then(ConfigWatcher::changed)
+            {
+                self.reload_config(changed, &track, &channels);
+            }
+
+            // Scroll the title, artist and details if they don't fit
+            if let Some(now_playing) = self.now_playing.as_mut() {
+                now_playing.update();
+            }
+
+            // Draw the next frame of the visualizer
+            if let Some(visualizer) = self.visualizer.as_mut() {
+                visualizer.update();
+            }
+
+            // Highlight the lyric that is being sung at the new position
+            if let Some(lyrics_view) = self.lyrics_view.as_mut() {
+                lyrics_view.update();
+            }
+
+            // Update the progress of any running export
+            if let Some(export_dialog) = self.export_dialog.as_mut() {
+                export_dialog.update();
+            }
+
+            // Show the feeds and downloads that finished in the background
+            if let Some(podcasts_dialog) = self.podcasts_dialog.as_ref() {
+                podcasts_dialog.update();
+            }
+
+            // Update the sleep timer's countdown, and show that playback stopped once it runs out
+            if let Some(sleep_timer_dialog) = self.sleep_timer_dialog.as_mut()
+                && sleep_timer_dialog.update()
+                && let Some(playback_buttons) = self.playback_buttons.as_mut()
+            {
+                playback_buttons.show_paused();
+            }
+
+            // Take commands from the desktop's media controls, and tell them about any changes
+            #[cfg(target_os = "linux")]
+            if let Some(media_controls) = self.media_controls.as_mut() {
+                media_controls.update();
+            }
+
+            // Answer the requests that arrived on the control socket or over HTTP
+            if let Some(remote_control) = self.remote_control.as_mut() {
+                remote_control.update();
+            }
+        }
+
+        // Remember where a podcast episode was stopped, so that it carries on from there next time
+        let track = queue.borrow().current().clone();
+        if let Some(pb) = self.progress_bar.as_ref() {
+            let position = *pb.current_audio_pos().borrow();
+            self.podcasts
+                .borrow_mut()
+                .record_progress(&track.path, position, track.length);
         }
     }
 
-    /// Create all the necessary app components, such as the playback buttons, etc.
-    fn create_app_components(
+    /// Open the first track to play, returning it along with the tracks that play after it: the ones in `files`, or else
+    /// the ones in the most recent item that can still be played, or else the ones in what the user picks.
+    /// Exits if there is nothing to play.
+    fn open_starting_tracks(&self, files: Vec<PathBuf>) -> (OpenedTrack, Vec<PathBuf>) {
+        let opened = if files.is_empty() {
+            self.open_recent().or_else(|| self.open_chosen())
+        } else {
+            self.open_items(&files)
+        };
+
+        opened.unwrap_or_else(|| {
+            eprintln!("There is nothing to play. Exiting...");
+            exit(1);
+        })
+    }
+
+    /// Open the most recent item that can be played, forgetting the ones that no longer exist along the way.
+    fn open_recent(&self) -> Option<(OpenedTrack, Vec<PathBuf>)> {
+        let items = self.recent.borrow().items().to_vec();
+        items.into_iter().find_map(|item| {
+            if !stream::is_stream(&item) && !item.exists() {
+                eprintln!("{} no longer exists. Skipping...", item.display());
+                self.recent.borrow_mut().remove(&item);
+                return None;
+            }
+
+            self.open_items(slice::from_ref(&item))
+        })
+    }
+
+    /// Ask the user what to open until they pick something that can be played, or give up.
+    fn open_chosen(&self) -> Option<(OpenedTrack, Vec<PathBuf>)> {
+        let music_folder = self.config.library.main_folder();
+        while let Some(item) = MenuBar::choose(OpenKind::File, music_folder.as_deref()) {
+            if let Some(opened) = self.open_items(slice::from_ref(&item)) {
+                return Some(opened);
+            }
+
+            dialog::alert_default(&format!("There is nothing to play in {}", item.display()));
+        }
+
+        None
+    }
+
+    /// Open the first track in `items` that can be played, returning it along with the tracks after it.
+    /// The items are remembered as the most recent ones once it opens. Returns `None` if none of their tracks can be played.
+    fn open_items(&self, items: &[PathBuf]) -> Option<(OpenedTrack, Vec<PathBuf>)> {
+        let mut tracks = items.iter().flat_map(|item| queue::tracks_to_open(item));
+        let opened = AudioHandler::load_audio(&mut tracks, self.config.playback.replay_gain())?;
+        let tracks = tracks.collect();
+
+        let mut recent = self.recent.borrow_mut();
+        for item in items {
+            recent.add(item);
+        }
+
+        Some((opened, tracks))
+    }
+
+    /// Start the ways of controlling the player from outside of its window.
+    fn start_controls(
         &mut self,
+        track: &QueuedTrack,
+        shortcuts: Shortcuts,
         sender: mpsc::Sender<Message>,
-        audio_length: Duration,
-        audio_pos_receiver: mpsc::Receiver<Duration>,
+        queue: Rc<RefCell<Queue>>,
     ) {
-        self.playback_buttons = Some(PlaybackButtons::new(AudioApp::WIN_WIDTH, sender.clone()));
-        self.progress_bar = Some(ProgressBar::new(
+        let Some(now_playing) = self.now_playing.as_ref() else {
+            return;
+        };
+
+        self.remote_control = RemoteControl::new(
+            now_playing,
+            &track.path,
+            sender.clone(),
+            shortcuts.clone(),
+            Rc::clone(&queue),
+            self.config.http.clone(),
+        );
+
+        #[cfg(target_os = "linux")]
+        {
+            self.media_controls =
+                MediaControls::new(now_playing, &track.path, shortcuts, sender, queue);
+        }
+    }
+
+    /// Show a different track, once the audio thread has moved on to it.
+    fn switch_track(&mut self, track: &QueuedTrack, channels: &Channels) {
+        let was_playing = self
+            .playback_buttons
+            .as_ref()
+            .is_none_or(PlaybackButtons::is_playing);
+
+        let shortcuts = self.load_track(track, channels);
+
+        // The new buttons start out playing, so keep showing that playback is paused
+        if !was_playing && let Some(playback_buttons) = self.playback_buttons.as_mut() {
+            playback_buttons.show_paused();
+        }
+
+        let Some(now_playing) = self.now_playing.as_ref() else {
+            return;
+        };
+
+        if let Some(remote_control) = self.remote_control.as_mut() {
+            remote_control.set_track(now_playing, &track.path, shortcuts.clone());
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(media_controls) = self.media_controls.as_mut() {
+            media_controls.set_track(now_playing, &track.path, shortcuts);
+        }
+    }
+
+    /// Switch to the settings of the config file that the user changed, rebuilding the window with them.
+    /// If the file can't be used, the settings are kept as they were. Settings that were replaced by their
+    /// defaults are shown to the user, since they would otherwise not know why their change did nothing.
+    fn reload_config(
+        &mut self,
+        changed: Result<(Config, Vec<String>), String>,
+        track: &QueuedTrack,
+        channels: &Channels,
+    ) {
+        let (config, warnings) = match changed {
+            Ok(changed) => changed,
+            Err(e) => {
+                dialog::alert_default(&format!(
+                    "The config file wasn't reloaded, since it can't be used:\n{}",
+                    e
+                ));
+                return;
+            }
+        };
+
+        if !warnings.is_empty() {
+            dialog::alert_default(&format!(
+                "Some settings in the config file were replaced by their defaults:\n{}",
+                warnings.join("\n")
+            ));
+        }
+
+        if config == *self.config {
+            return;
+        }
+        if config.audio != self.config.audio || config.http != self.config.http {
+            eprintln!(
+                "The [audio] and [http] tables of the config file are only read when the player starts"
+            );
+        }
+
+        self.theme.set_settings(config.theme.clone());
+
+        let replay_gain = config.playback.replay_gain();
+        if replay_gain != self.config.playback.replay_gain()
+            && let Err(e) = channels
+                .audio_sender
+                .send(Message::SetReplayGain(replay_gain))
+        {
+            eprintln!("Unable to change the ReplayGain: {:?}", e);
+        }
+
+        // The shortcuts, buttons and menu bar only read the settings when they are created
+        self.config = Rc::new(config);
+        self.switch_track(track, channels);
+    }
+
+    /// Rebuild the window in the layout that the user picked.
+    /// The mini player has no window decorations, and stays on top of other windows.
+    fn switch_layout(&mut self, track: &QueuedTrack, channels: &Channels) {
+        self.shown_layout = self.layout.get();
+        let (width, height) = match self.shown_layout {
+            Layout::Full => (AudioApp::WIN_WIDTH, AudioApp::WIN_HEIGHT),
+            Layout::Mini => (AudioApp::MINI_WIN_WIDTH, AudioApp::MINI_WIN_HEIGHT),
+        };
+
+        // The decorations can only be changed while the window is hidden
+        self.window.hide();
+        self.window.set_border(self.shown_layout == Layout::Full);
+        let (x, y) = (self.window.x(), self.window.y());
+        self.window.resize(x, y, width, height);
+
+        self.switch_track(track, channels);
+
+        // Showing the window again also takes it off the top in the full layout
+        self.window.show();
+        if self.shown_layout == Layout::Mini {
+            self.window.set_on_top();
+        }
+    }
+
+    /// Return where the cover, title and artist go in `layout`.
+    fn now_playing_bounds(layout: Layout) -> Bounds {
+        match layout {
+            Layout::Full => Bounds::new(0, 65, AudioApp::WIN_WIDTH, 160),
+            Layout::Mini => Bounds::new(8, 6, 236, 44),
+        }
+    }
+
+    /// Return where the progress bar and its timestamps go in `layout`. The mini player only has room for a thin bar.
+    fn progress_bar_bounds(layout: Layout) -> Bounds {
+        match layout {
+            Layout::Full => Bounds::new(3, 299, AudioApp::WIN_WIDTH - 6, 20),
+            Layout::Mini => Bounds::new(0, 52, AudioApp::MINI_WIN_WIDTH, 10),
+        }
+    }
+
+    /// Return where the playback buttons go in `layout`.
+    fn playback_buttons_bounds(layout: Layout) -> Bounds {
+        match layout {
+            Layout::Full => Bounds::new(0, 319, AudioApp::WIN_WIDTH, 30),
+            Layout::Mini => Bounds::new(244, 13, 72, 30),
+        }
+    }
+
+    /// Return `track` as if it was skipped where the progress bar last was, unless it is known where it was skipped.
+    fn stopped_track(&self, track: &QueuedTrack) -> QueuedTrack {
+        let mut track = track.clone();
+        if let Some(pb) = self.progress_bar.as_ref() {
+            track
+                .skipped_at
+                .get_or_insert(*pb.current_audio_pos().borrow());
+        }
+        track
+    }
+
+    /// Remember how far into `track` playback got, once the queue has moved on from it, in case it is a podcast episode.
+    fn record_episode_progress(&self, track: &QueuedTrack) {
+        let mut podcasts = self.podcasts.borrow_mut();
+        match track.skipped_at {
+            Some(position) => podcasts.record_progress(&track.path, position, track.length),
+            None => podcasts.finished(&track.path),
+        }
+    }
+
+    /// Carry on playing `track` from where it was stopped last time, if it is a podcast episode.
+    fn resume_episode(&self, track: &QueuedTrack, sender: &mpsc::Sender<Message>) {
+        let Some(position) = self.podcasts.borrow().resume_position(&track.path) else {
+            return;
+        };
+
+        if let Err(e) = sender.send(Message::Seek(position)) {
+            eprintln!("Unable to resume the episode: {:?}", e);
+        }
+    }
+
+    /// Show the length of `track`, once the audio thread has worked it out.
+    fn show_length(&mut self, track: &QueuedTrack) {
+        let Some(length) = track.length else {
+            return;
+        };
+
+        if let Some(progress_bar) = self.progress_bar.as_mut() {
+            progress_bar.set_audio_length(length);
+        }
+
+        // The remote control asks the shortcuts for the length, but the desktop has to be told about it
+        #[cfg(target_os = "linux")]
+        if let (Some(media_controls), Some(now_playing)) =
+            (self.media_controls.as_mut(), self.now_playing.as_ref())
+        {
+            media_controls.set_now_playing(now_playing, &track.path);
+        }
+    }
+
+    /// Show the title that the station of `track` last announced, if it is a stream and the title changed.
+    fn update_stream_title(&mut self, track: &QueuedTrack) {
+        let (Some(stream_info), Some(now_playing)) = (&track.stream, self.now_playing.as_mut())
+        else {
+            return;
+        };
+
+        let title = NowPlaying::stream_title(&stream_info.details());
+        if title == now_playing.title() {
+            return;
+        }
+        now_playing.set_title(&title);
+        self.window.set_label(&now_playing.window_title());
+
+        if let Some(remote_control) = self.remote_control.as_mut() {
+            remote_control.set_now_playing(now_playing, &track.path);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(media_controls) = self.media_controls.as_mut() {
+            media_controls.set_now_playing(now_playing, &track.path);
+        }
+    }
+
+    /// Replace the components in the window with ones for `track`, and return its shortcuts.
+    fn load_track(&mut self, track: &QueuedTrack, channels: &Channels) -> Shortcuts {
+        // Throw away the widgets and dialogs of the previous track
+        self.window.clear();
+        for dialog in self.dialogs.drain(..) {
+            window::Window::delete(dialog);
+        }
+
+        self.window.begin();
+
+        // The mini player has no title bar, so it is dragged by its background, which has to be behind everything else
+        if self.shown_layout == Layout::Mini {
+            AudioApp::create_drag_area(&self.window);
+        }
+
+        let path = track.path.to_string_lossy();
+        let bounds = AudioApp::now_playing_bounds(self.shown_layout);
+        self.now_playing = Some(match &track.stream {
+            Some(stream_info) => {
+                NowPlaying::for_stream(&path, &stream_info.details(), bounds, self.shown_layout)
+            }
+            None => NowPlaying::new(&path, self.show_technical.get(), bounds, self.shown_layout),
+        });
+        if let Some(now_playing) = self.now_playing.as_ref() {
+            self.window.set_label(&now_playing.window_title());
+            self.theme.set_cover(now_playing.cover());
+        }
+        let shortcuts = self.create_app_components(track, channels);
+        self.window.end();
+        self.window.redraw();
+
+        shortcuts
+    }
+
+    /// Create all the necessary app components, such as the playback buttons, etc.
+    /// The mini player only has the progress bar and the play and next track buttons, besides what is playing.
+    fn create_app_components(&mut self, track: &QueuedTrack, channels: &Channels) -> Shortcuts {
+        let sender = &channels.audio_sender;
+        let layout = self.shown_layout;
+
+        // Load the loop, bookmarks and chapters of this track
+        let loop_region = LoopRegion::new(
+            track.path.clone(),
+            Rc::clone(&self.track_state),
+            sender.clone(),
+        );
+        let markers = Markers::new(
+            track.path.clone(),
+            Rc::clone(&self.track_state),
+            sender.clone(),
+        );
+
+        let mut progress_bar = ProgressBar::new(
+            AudioApp::progress_bar_bounds(layout),
+            track.length,
+            track.stream.is_some(),
+            Rc::clone(&channels.audio_pos_receiver),
+            sender.clone(),
+            loop_region.clone(),
+            markers.clone(),
+        );
+        progress_bar.share_time_display(Rc::clone(&self.time_display));
+        // Streams have no end, so there is no waveform to draw
+        if track.stream.is_none() {
+            progress_bar.enable_waveform(track.path.clone(), Rc::clone(&self.show_waveform));
+        }
+        let current_audio_pos = progress_bar.current_audio_pos();
+        self.dialogs.push(progress_bar.seek_preview_window());
+
+        let playback_buttons = PlaybackButtons::new(
+            AudioApp::playback_buttons_bounds(layout),
+            layout,
+            self.config.playback.seek_steps().step,
+            sender.clone(),
+            markers.clone(),
+            Rc::clone(&current_audio_pos),
+        );
+        self.playback_buttons = Some(playback_buttons.clone());
+
+        match layout {
+            Layout::Full => self.create_full_components(
+                track,
+                channels,
+                &markers,
+                loop_region,
+                current_audio_pos,
+            ),
+            Layout::Mini => self.create_mini_components(),
+        }
+
+        // The shortcuts come last, since the overlay that lists them has to be drawn on top of everything else
+        let shortcuts = Shortcuts::new(
+            &self.window,
+            Rc::clone(&self.config),
+            Rc::clone(&self.volume),
+            sender.clone(),
+            playback_buttons,
+            markers,
+            &progress_bar,
+        );
+
+        // Files that are dropped onto the window are queued, and every other event is left to the shortcuts
+        let file_drop = FileDrop::new(sender.clone());
+        let handler = shortcuts.clone();
+        self.window
+            .handle(move |_, event| file_drop.handle(event) || handler.handle(event));
+        self.progress_bar = Some(progress_bar);
+
+        shortcuts
+    }
+
+    /// Create the components that only the full window has room for, such as the menu bar, the lyrics and the dialogs.
+    fn create_full_components(
+        &mut self,
+        track: &QueuedTrack,
+        channels: &Channels,
+        markers: &Markers,
+        loop_region: LoopRegion,
+        current_audio_pos: Rc<RefCell<Duration>>,
+    ) {
+        let sender = &channels.audio_sender;
+
+        self.menu_bar = Some(MenuBar::new(
             AudioApp::WIN_WIDTH,
-            audio_length,
-            audio_pos_receiver,
-            sender,
+            self.config.library.main_folder(),
+            Rc::clone(&self.recent),
+            sender.clone(),
         ));
+
+        // Clicking the cover lists everything that is known about the track, which can be edited from there.
+        // Streams have nothing to list
+        self.tag_editor_dialog = None;
+        if track.stream.is_none()
+            && let Some(now_playing) = self.now_playing.as_mut()
+        {
+            let tag_editor_dialog = TagEditorDialog::new(&track.path);
+            self.dialogs.push(tag_editor_dialog.window());
+
+            let track_info_dialog = TrackInfoDialog::new(
+                &track.path,
+                Rc::clone(&self.show_technical),
+                now_playing.technical_widget(),
+                tag_editor_dialog.window(),
+            );
+            self.dialogs.push(track_info_dialog.window());
+            now_playing.show_on_cover_click(track_info_dialog.window(), "Track info");
+
+            self.tag_editor_dialog = Some(tag_editor_dialog);
+        }
+
+        // Streams have no lyrics, since what they play changes
+        let lyrics = match track.stream {
+            Some(_) => None,
+            None => Lyrics::load(&track.path),
+        };
+        self.lyrics_view = Some(LyricsView::new(
+            AudioApp::WIN_WIDTH,
+            lyrics,
+            Rc::clone(&current_audio_pos),
+            sender.clone(),
+        ));
+
+        let loop_controls = LoopControls::new(
+            AudioApp::WIN_WIDTH,
+            loop_region,
+            Rc::clone(&current_audio_pos),
+        );
+        self.dialogs.push(loop_controls.practice_window());
+        self.loop_controls = Some(loop_controls);
+
+        self.visualizer = Some(Visualizer::new(
+            AudioApp::WIN_WIDTH,
+            self.audio_handler.sample_tap(),
+            Rc::clone(&self.visualizer_mode),
+        ));
+
+        let export_dialog = ExportDialog::new(&track.path.to_string_lossy());
+        self.create_dialog_button(
+            AudioApp::WIN_WIDTH - AudioApp::CORNER_BTN_SIZE - AudioApp::CORNER_BTN_MARGIN,
+            "\u{f0207}",
+            "Export",
+            export_dialog.window(),
+        );
+        self.export_dialog = Some(export_dialog);
+
+        let bookmarks_dialog = BookmarksDialog::new(markers.clone(), current_audio_pos);
+        self.create_dialog_button(
+            AudioApp::CORNER_BTN_MARGIN,
+            "\u{f00c0}",
+            "Bookmarks",
+            bookmarks_dialog.window(),
+        );
+
+        let sleep_timer_dialog = SleepTimerDialog::new(
+            AudioApp::WIN_WIDTH,
+            sender.clone(),
+            Rc::clone(&channels.sleep_timer_receiver),
+        );
+        self.create_dialog_button(
+            AudioApp::CORNER_BTN_MARGIN * 2 + AudioApp::CORNER_BTN_SIZE,
+            "\u{f04b2}",
+            "Sleep timer",
+            sleep_timer_dialog.window(),
+        );
+        self.sleep_timer_dialog = Some(sleep_timer_dialog);
+
+        let podcasts_dialog = PodcastsDialog::new(Rc::clone(&self.podcasts), sender.clone());
+        self.create_dialog_button(
+            AudioApp::WIN_WIDTH - 2 * (AudioApp::CORNER_BTN_SIZE + AudioApp::CORNER_BTN_MARGIN),
+            "\u{f0994}",
+            "Podcasts",
+            podcasts_dialog.window(),
+        );
+        self.podcasts_dialog = Some(podcasts_dialog);
+
+        // The queue panel outlives the track, so it isn't deleted along with the other dialogs
+        if let Some(queue_panel) = self.queue_panel.as_ref() {
+            AudioApp::create_window_button(
+                AudioApp::WIN_WIDTH - 3 * (AudioApp::CORNER_BTN_SIZE + AudioApp::CORNER_BTN_MARGIN),
+                "\u{f0cb8}",
+                "Queue",
+                queue_panel.window(),
+            );
+        }
+
+        self.create_layout_button(
+            AudioApp::CORNER_BTN_MARGIN * 3 + AudioApp::CORNER_BTN_SIZE * 2,
+            AudioApp::CORNER_BTN_Y,
+            AudioApp::MINI_PLAYER_BTN_LABEL,
+            "Mini player",
+        );
+    }
+
+    /// Drop the components that the mini player has no room for, and add the button that goes back to the full window.
+    fn create_mini_components(&mut self) {
+        self.menu_bar = None;
+        self.tag_editor_dialog = None;
+        self.lyrics_view = None;
+        self.loop_controls = None;
+        self.visualizer = None;
+        self.export_dialog = None;
+        self.sleep_timer_dialog = None;
+        self.podcasts_dialog = None;
+
+        let btn_y = (AudioApp::MINI_WIN_HEIGHT - AudioApp::CORNER_BTN_SIZE) / 2 - 4;
+        self.create_layout_button(
+            AudioApp::MINI_WIN_WIDTH - AudioApp::CORNER_BTN_SIZE - 6,
+            btn_y,
+            AudioApp::FULL_PLAYER_BTN_LABEL,
+            "Full player",
+        );
+    }
+
+    /// Create a button in one of the top corners that opens a dialog.
+    fn create_dialog_button(
+        &mut self,
+        btn_x: i32,
+        label: &str,
+        tooltip: &str,
+        dialog: window::Window,
+    ) {
+        self.dialogs.push(dialog.clone());
+        AudioApp::create_window_button(btn_x, label, tooltip, dialog);
+    }
+
+    /// Create a button in one of the top corners that shows `window`, which is kept when the track changes.
+    fn create_window_button(btn_x: i32, label: &str, tooltip: &str, mut window: window::Window) {
+        let mut btn = Button::default()
+            .with_size(AudioApp::CORNER_BTN_SIZE, AudioApp::CORNER_BTN_SIZE)
+            .with_pos(btn_x, AudioApp::CORNER_BTN_Y)
+            .with_label(label);
+        btn.set_tooltip(tooltip);
+        btn.clear_visible_focus();
+        btn.set_frame(fltk::enums::FrameType::NoBox);
+
+        btn.set_callback(move |_| window.show());
+    }
+
+    /// Create the button that switches between the full window and the mini player.
+    fn create_layout_button(&self, btn_x: i32, btn_y: i32, label: &str, tooltip: &str) {
+        let mut btn = Button::default()
+            .with_size(AudioApp::CORNER_BTN_SIZE, AudioApp::CORNER_BTN_SIZE)
+            .with_pos(btn_x, btn_y)
+            .with_label(label);
+        btn.set_tooltip(tooltip);
+        btn.clear_visible_focus();
+        btn.set_frame(fltk::enums::FrameType::NoBox);
+
+        let layout = Rc::clone(&self.layout);
+        btn.set_callback(move |_| layout.set(layout.get().toggled()));
+    }
+
+    /// Cover `window` with an area that moves it around when it is dragged.
+    fn create_drag_area(window: &window::DoubleWindow) {
+        let mut drag_area = Frame::new(0, 0, window.w(), window.h(), None);
+
+        // Where the mouse grabbed the window, relative to its top left corner
+        let grab_offset = Rc::new(Cell::new((0, 0)));
+
+        let mut window = window.clone();
+        drag_area.handle(move |_, event| match event {
+            Event::Push => {
+                grab_offset.set((
+                    app::event_x_root() - window.x(),
+                    app::event_y_root() - window.y(),
+                ));
+                true
+            }
+            Event::Drag => {
+                let (offset_x, offset_y) = grab_offset.get();
+                window.set_pos(
+                    app::event_x_root() - offset_x,
+                    app::event_y_root() - offset_y,
+                );
+                true
+            }
+            _ => false,
+        });
     }
 
     /// Create the window and theme it.
@@ -129,7 +1108,8 @@ impl AudioApp {
         let mut win = window::Window::default()
             .with_size(AudioApp::WIN_WIDTH, AudioApp::WIN_HEIGHT)
             .with_label("My window");
-        win.set_color(Color::White);
+        // The background of the theme, so that the window follows it
+        win.set_color(Color::Background2);
         win
     }
 }
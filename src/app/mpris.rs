@@ -0,0 +1,836 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+use zbus::blocking::{Connection, connection};
+use zbus::interface;
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+/// Something that a desktop widget, media key or `playerctl` asked the player to do.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MprisCommand {
+    Play,
+    Pause,
+    PlayPause,
+
+    /// Pause and go back to the start of the track
+    Stop,
+
+    Next,
+    Previous,
+
+    /// Jump to a position in the track
+    SetPosition(Duration),
+
+    /// Change the volume, from 0.0 to 1.0
+    SetVolume(f64),
+
+    /// Play a file, folder, playlist or stream in place of the queue, given as a `file://` URL or the URL of the stream
+    OpenUri(String),
+}
+
+/// What is known about the track that is playing, as published to the desktop.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MprisMetadata {
+    /// Tells this play of a track apart from the ones before it, even of the same file.
+    /// It is how far the queue has advanced, which changes whenever a different track starts
+    pub(crate) track_number: usize,
+
+    pub(crate) title: String,
+    pub(crate) artist: String,
+    pub(crate) album: Option<String>,
+
+    /// A `file://` URL of the cover
+    pub(crate) art_url: Option<String>,
+
+    /// How long the track is, which isn't known for live streams
+    pub(crate) length: Option<Duration>,
+
+    /// A `file://` URL of the track, or the URL of a stream
+    pub(crate) url: Option<String>,
+}
+
+/// Everything that the desktop can see about the player.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MprisState {
+    pub(crate) playing: bool,
+    pub(crate) position: Duration,
+
+    /// The volume, from 0.0 to 1.0
+    pub(crate) volume: f64,
+
+    /// Whether there are tracks after the current one in the queue
+    pub(crate) can_go_next: bool,
+
+    /// Whether there are tracks before the current one in the queue
+    pub(crate) can_go_previous: bool,
+
+    pub(crate) metadata: MprisMetadata,
+}
+
+/// Exposes the player on the D-Bus session bus through MPRIS, which is what media keys, desktop widgets and `playerctl` talk to.
+/// Commands from the desktop are sent to the app, and the app tells the server whenever its state changes.
+pub(crate) struct MprisServer {
+    connection: Connection,
+    state: Arc<Mutex<MprisState>>,
+}
+
+impl MprisServer {
+    const BUS_NAME: &str = "org.mpris.MediaPlayer2.audio_player";
+    const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+    const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+    /// The start of the ID of each track, which ends with its `track_number`
+    const TRACK_ID_PREFIX: &str = "/audio_player/track/";
+
+    /// Connect to the session bus and start serving MPRIS.
+    /// # Errors
+    /// - If there is no session bus
+    /// - If the player couldn't be registered on the bus
+    pub(crate) fn start(
+        state: MprisState,
+        command_sender: mpsc::Sender<MprisCommand>,
+    ) -> zbus::Result<MprisServer> {
+        MprisServer::start_on(connection::Builder::session()?, state, command_sender)
+    }
+
+    /// Start serving MPRIS on the bus that `builder` connects to.
+    fn start_on(
+        builder: connection::Builder,
+        state: MprisState,
+        command_sender: mpsc::Sender<MprisCommand>,
+    ) -> zbus::Result<MprisServer> {
+        let state = Arc::new(Mutex::new(state));
+
+        let connection = builder
+            .serve_at(MprisServer::OBJECT_PATH, Root)?
+            .serve_at(
+                MprisServer::OBJECT_PATH,
+                Player {
+                    state: Arc::clone(&state),
+                    command_sender,
+                },
+            )?
+            .build()?;
+
+        // Another instance of the player may already have the name, in which case this one gets a name of its own
+        if connection.request_name(MprisServer::BUS_NAME).is_err() {
+            connection.request_name(format!(
+                "{}.instance{}",
+                MprisServer::BUS_NAME,
+                process::id()
+            ))?;
+        }
+
+        Ok(MprisServer { connection, state })
+    }
+
+    /// Replace the state of the player, and tell the desktop about any properties that changed.
+    /// The position is not announced, since the desktop works it out from the playback status; use [`MprisServer::seeked`] for jumps.
+    pub(crate) fn update(&self, new_state: MprisState) {
+        let changed = {
+            let mut state = self.state.lock().unwrap();
+            let changed = MprisServer::changed_properties(&state, &new_state);
+            *state = new_state;
+            changed
+        };
+
+        if changed.is_empty() {
+            return;
+        }
+
+        let invalidated: Vec<&str> = Vec::new();
+        if let Err(e) = self.connection.emit_signal(
+            None::<&str>,
+            MprisServer::OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(MprisServer::PLAYER_INTERFACE, changed, invalidated),
+        ) {
+            eprintln!("Unable to announce MPRIS property changes: {}", e);
+        }
+    }
+
+    /// Tell the desktop that playback jumped to a new position.
+    pub(crate) fn seeked(&self, position: Duration) {
+        if let Err(e) = self.connection.emit_signal(
+            None::<&str>,
+            MprisServer::OBJECT_PATH,
+            MprisServer::PLAYER_INTERFACE,
+            "Seeked",
+            &(microseconds(position),),
+        ) {
+            eprintln!("Unable to announce MPRIS seek: {}", e);
+        }
+    }
+
+    /// Return the properties of the Player interface that differ between two states, along with their new values.
+    fn changed_properties(old: &MprisState, new: &MprisState) -> HashMap<&'static str, OwnedValue> {
+        let mut changed = HashMap::new();
+
+        if old.playing != new.playing {
+            changed.insert("PlaybackStatus", owned_value(playback_status(new.playing)));
+        }
+        if old.volume != new.volume {
+            changed.insert("Volume", owned_value(new.volume));
+        }
+        if old.can_go_next != new.can_go_next {
+            changed.insert("CanGoNext", owned_value(new.can_go_next));
+        }
+        if old.can_go_previous != new.can_go_previous {
+            changed.insert("CanGoPrevious", owned_value(new.can_go_previous));
+        }
+        if old.metadata != new.metadata {
+            changed.insert("Metadata", owned_value(metadata_map(&new.metadata)));
+        }
+
+        changed
+    }
+}
+
+/// The `org.mpris.MediaPlayer2` interface, which describes the player itself.
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    /// The player can't bring its window to the front
+    fn raise(&self) {}
+
+    /// The player can't be closed from the desktop
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "Audio Player".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["file".to_string(), "http".to_string(), "https".to_string()]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, which controls playback.
+struct Player {
+    state: Arc<Mutex<MprisState>>,
+    command_sender: mpsc::Sender<MprisCommand>,
+}
+
+impl Player {
+    fn send(&self, command: MprisCommand) {
+        if let Err(e) = self.command_sender.send(command) {
+            eprintln!("Unable to send MPRIS command: {:?}", e);
+        }
+    }
+
+    fn state(&self) -> MprisState {
+        self.state.lock().unwrap().clone()
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(MprisCommand::Next);
+    }
+
+    fn previous(&self) {
+        self.send(MprisCommand::Previous);
+    }
+
+    fn pause(&self) {
+        self.send(MprisCommand::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(MprisCommand::PlayPause);
+    }
+
+    fn stop(&self) {
+        self.send(MprisCommand::Stop);
+    }
+
+    fn play(&self) {
+        self.send(MprisCommand::Play);
+    }
+
+    /// Seek forwards by `offset` microseconds, or backwards if it is negative.
+    /// Seeking past the end goes on to the next track, as the MPRIS specification asks
+    fn seek(&self, offset: i64) {
+        let state = self.state();
+        let Some(length) = state.metadata.length else {
+            return;
+        };
+
+        match seek_target(state.position, offset, length) {
+            Some(position) => self.send(MprisCommand::SetPosition(position)),
+            None => self.send(MprisCommand::Next),
+        }
+    }
+
+    /// Jump to `position` microseconds, as long as `track_id` is still the track that is playing
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let Some(length) = self.state().metadata.length else {
+            return;
+        };
+
+        // Positions outside of the track are ignored, as the MPRIS specification asks
+        if track_id.as_str() != track_id_of(self.state().metadata.track_number) || position < 0 {
+            return;
+        }
+
+        let position = Duration::from_micros(position.unsigned_abs());
+        if position <= length {
+            self.send(MprisCommand::SetPosition(position));
+        }
+    }
+
+    /// Play `uri` in place of the queue
+    fn open_uri(&self, uri: &str) {
+        self.send(MprisCommand::OpenUri(uri.to_string()));
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        playback_status(self.state().playing).to_string()
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        metadata_map(&self.state().metadata)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.state().volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, volume: f64) {
+        self.send(MprisCommand::SetVolume(volume.clamp(0.0, 1.0)));
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        microseconds(self.state().position)
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.state().can_go_next
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.state().can_go_previous
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        // Only tracks with a known length can be seeked, which rules out live streams
+        self.state().metadata.length.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Return where a seek by `offset` microseconds lands. Seeking before the start goes to the start.
+/// Returns `None` if the seek goes past the end of the track.
+fn seek_target(position: Duration, offset: i64, length: Duration) -> Option<Duration> {
+    let offset_duration = Duration::from_micros(offset.unsigned_abs());
+
+    let target = if offset < 0 {
+        position.saturating_sub(offset_duration)
+    } else {
+        position + offset_duration
+    };
+
+    (target <= length).then_some(target)
+}
+
+/// Return whether the position moved in a way that playback alone can't explain, such as after a seek or a loop.
+/// `elapsed` is how much time passed between the two positions.
+pub(crate) fn is_jump(
+    previous: Duration,
+    current: Duration,
+    elapsed: Duration,
+    playing: bool,
+) -> bool {
+    /// How far off the expected position can be before it counts as a jump
+    const TOLERANCE: Duration = Duration::from_secs(1);
+
+    let expected = if playing {
+        previous + elapsed
+    } else {
+        previous
+    };
+
+    current.abs_diff(expected) > TOLERANCE
+}
+
+/// Return the `file://` URL of a path, escaping anything that isn't allowed in a URL.
+pub(crate) fn file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(char::from(byte))
+            }
+            byte => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    url
+}
+
+fn playback_status(playing: bool) -> &'static str {
+    if playing { "Playing" } else { "Paused" }
+}
+
+/// Return the MPRIS ID of the track with `track_number`.
+fn track_id_of(track_number: usize) -> String {
+    format!("{}{}", MprisServer::TRACK_ID_PREFIX, track_number)
+}
+
+/// Turn a position into the microseconds that MPRIS uses.
+fn microseconds(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+/// Turn the metadata of a track into the map that MPRIS uses.
+fn metadata_map(metadata: &MprisMetadata) -> HashMap<&'static str, OwnedValue> {
+    let mut map = HashMap::new();
+
+    let track_id = ObjectPath::try_from(track_id_of(metadata.track_number))
+        .expect("Track IDs are made of valid path elements");
+    map.insert("mpris:trackid", owned_value(track_id));
+    map.insert("xesam:title", owned_value(metadata.title.as_str()));
+    map.insert("xesam:artist", owned_value(vec![metadata.artist.as_str()]));
+
+    // Live streams have no length
+    if let Some(length) = metadata.length {
+        map.insert("mpris:length", owned_value(microseconds(length)));
+    }
+    if let Some(album) = &metadata.album {
+        map.insert("xesam:album", owned_value(album.as_str()));
+    }
+    if let Some(art_url) = &metadata.art_url {
+        map.insert("mpris:artUrl", owned_value(art_url.as_str()));
+    }
+    if let Some(url) = &metadata.url {
+        map.insert("xesam:url", owned_value(url.as_str()));
+    }
+
+    map
+}
+
+fn owned_value<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    value
+        .into()
+        .try_to_owned()
+        .expect("Values without file descriptors can always be owned")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod seek_target {
+        use super::*;
+
+        const LENGTH: Duration = Duration::from_secs(60);
+
+        #[test]
+        fn test_forwards() {
+            assert_eq!(
+                seek_target(Duration::from_secs(10), 5_000_000, LENGTH),
+                Some(Duration::from_secs(15))
+            );
+        }
+
+        #[test]
+        fn test_backwards_past_start() {
+            assert_eq!(
+                seek_target(Duration::from_secs(3), -5_000_000, LENGTH),
+                Some(Duration::ZERO)
+            );
+        }
+
+        #[test]
+        fn test_past_end() {
+            assert_eq!(
+                seek_target(Duration::from_secs(58), 5_000_000, LENGTH),
+                None
+            );
+        }
+    }
+
+    mod is_jump {
+        use super::*;
+
+        #[test]
+        fn test_playing_normally() {
+            assert!(!is_jump(
+                Duration::from_secs(10),
+                Duration::from_millis(10_050),
+                Duration::from_millis(50),
+                true
+            ));
+        }
+
+        #[test]
+        fn test_seek() {
+            assert!(is_jump(
+                Duration::from_secs(10),
+                Duration::from_secs(30),
+                Duration::from_millis(50),
+                true
+            ));
+        }
+
+        #[test]
+        fn test_paused() {
+            assert!(!is_jump(
+                Duration::from_secs(10),
+                Duration::from_secs(10),
+                Duration::from_secs(5),
+                false
+            ));
+        }
+    }
+
+    mod file_url {
+        use super::*;
+
+        #[test]
+        fn test_plain_path() {
+            assert_eq!(
+                file_url(Path::new("/music/track.mp3")),
+                "file:///music/track.mp3"
+            );
+        }
+
+        #[test]
+        fn test_escaped_path() {
+            assert_eq!(
+                file_url(Path::new("/music/my track#1.mp3")),
+                "file:///music/my%20track%231.mp3"
+            );
+        }
+    }
+
+    mod server {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Child, Command, Stdio};
+
+        use zbus::blocking::fdo::PropertiesProxy;
+        use zbus::names::InterfaceName;
+
+        use super::*;
+
+        /// A D-Bus daemon of its own for a test, so that tests don't touch the user's session bus.
+        struct PrivateBus {
+            daemon: Child,
+            address: String,
+        }
+
+        impl PrivateBus {
+            /// Start the daemon. Returns `None` if `dbus-daemon` isn't installed, in which case the test is skipped.
+            fn start() -> Option<PrivateBus> {
+                let Ok(mut daemon) = Command::new("dbus-daemon")
+                    .args(["--session", "--nofork", "--print-address"])
+                    .stdout(Stdio::piped())
+                    .spawn()
+                else {
+                    eprintln!("dbus-daemon is not installed, skipping");
+                    return None;
+                };
+
+                let mut address = String::new();
+                BufReader::new(daemon.stdout.take().unwrap())
+                    .read_line(&mut address)
+                    .unwrap();
+
+                Some(PrivateBus {
+                    daemon,
+                    address: address.trim().to_string(),
+                })
+            }
+
+            fn connect(&self) -> Connection {
+                connection::Builder::address(self.address.as_str())
+                    .unwrap()
+                    .build()
+                    .unwrap()
+            }
+
+            fn start_server(&self) -> (MprisServer, mpsc::Receiver<MprisCommand>) {
+                let (sender, receiver) = mpsc::channel();
+                let builder = connection::Builder::address(self.address.as_str()).unwrap();
+                let server = MprisServer::start_on(builder, state(), sender).unwrap();
+
+                (server, receiver)
+            }
+        }
+
+        impl Drop for PrivateBus {
+            fn drop(&mut self) {
+                let _ = self.daemon.kill();
+                let _ = self.daemon.wait();
+            }
+        }
+
+        fn state() -> MprisState {
+            MprisState {
+                playing: true,
+                position: Duration::from_secs(10),
+                volume: 1.0,
+                can_go_next: true,
+                can_go_previous: false,
+                metadata: MprisMetadata {
+                    track_number: 3,
+                    title: "less than lovers".to_string(),
+                    artist: "Kensuke Ushio".to_string(),
+                    album: None,
+                    art_url: None,
+                    length: Some(Duration::from_secs(60)),
+                    url: None,
+                },
+            }
+        }
+
+        fn properties(client: &Connection) -> PropertiesProxy<'_> {
+            PropertiesProxy::builder(client)
+                .destination(MprisServer::BUS_NAME)
+                .unwrap()
+                .path(MprisServer::OBJECT_PATH)
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+
+        fn call(
+            client: &Connection,
+            method: &str,
+            body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+        ) {
+            client
+                .call_method(
+                    Some(MprisServer::BUS_NAME),
+                    MprisServer::OBJECT_PATH,
+                    Some(MprisServer::PLAYER_INTERFACE),
+                    method,
+                    body,
+                )
+                .unwrap();
+        }
+
+        #[test]
+        fn test_open_uri() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            call(&client, "OpenUri", &("file:///music/track.mp3",));
+
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::OpenUri("file:///music/track.mp3".to_string()))
+            );
+        }
+
+        #[test]
+        fn test_play_pause() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            call(&client, "PlayPause", &());
+
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::PlayPause)
+            );
+        }
+
+        #[test]
+        fn test_seek() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            call(&client, "Seek", &(5_000_000i64,));
+
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::SetPosition(Duration::from_secs(15)))
+            );
+        }
+
+        #[test]
+        fn test_seek_past_end() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            call(&client, "Seek", &(60_000_000i64,));
+
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::Next)
+            );
+        }
+
+        #[test]
+        fn test_set_position() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            let track = ObjectPath::from_static_str_unchecked("/audio_player/track/3");
+            call(&client, "SetPosition", &(track, 5_000_000i64));
+
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::SetPosition(Duration::from_secs(5)))
+            );
+        }
+
+        #[test]
+        fn test_set_position_of_other_track() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, commands) = bus.start_server();
+            let client = bus.connect();
+
+            let other_track = ObjectPath::from_static_str_unchecked("/audio_player/track/1");
+            call(&client, "SetPosition", &(other_track, 5_000_000i64));
+            call(&client, "Play", &());
+
+            // The position is ignored, so the next command is the one after it
+            assert_eq!(
+                commands.recv_timeout(Duration::from_secs(5)),
+                Ok(MprisCommand::Play)
+            );
+        }
+
+        #[test]
+        fn test_properties() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (_server, _commands) = bus.start_server();
+            let client = bus.connect();
+            let properties = properties(&client);
+            let interface = InterfaceName::from_static_str_unchecked(MprisServer::PLAYER_INTERFACE);
+
+            let status = properties.get(interface.clone(), "PlaybackStatus").unwrap();
+            assert_eq!(String::try_from(status).unwrap(), "Playing");
+
+            // Nothing was played before the current track
+            let can_go_previous = properties.get(interface.clone(), "CanGoPrevious").unwrap();
+            assert_eq!(bool::try_from(can_go_previous), Ok(false));
+
+            let metadata = properties.get(interface, "Metadata").unwrap();
+            let metadata = HashMap::<String, OwnedValue>::try_from(metadata).unwrap();
+            let title = String::try_from(metadata["xesam:title"].clone()).unwrap();
+            assert_eq!(title, "less than lovers");
+            let track_id = ObjectPath::try_from(metadata["mpris:trackid"].clone()).unwrap();
+            assert_eq!(track_id.as_str(), "/audio_player/track/3");
+        }
+
+        #[test]
+        fn test_properties_changed() {
+            let Some(bus) = PrivateBus::start() else {
+                return;
+            };
+            let (server, _commands) = bus.start_server();
+            let client = bus.connect();
+            let mut changes = properties(&client).receive_properties_changed().unwrap();
+
+            server.update(MprisState {
+                playing: false,
+                ..state()
+            });
+
+            // Wait for the signal on another thread, so that a missing signal fails the test instead of hanging it
+            let (sender, receiver) = mpsc::channel();
+            std::thread::spawn(move || {
+                let signal = changes.next().unwrap();
+                let args = signal.args().unwrap();
+                let status = args.changed_properties()["PlaybackStatus"].clone();
+                sender.send(String::try_from(status).unwrap()).unwrap();
+            });
+
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(5)).as_deref(),
+                Ok("Paused")
+            );
+        }
+    }
+}
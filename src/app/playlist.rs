@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::queue;
+use crate::app::stream;
+
+/// The extensions of the playlists that can be opened, which are M3U and PLS files.
+pub(crate) const EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+/// Return whether `path` is a playlist, judging by its extension.
+pub(crate) fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            EXTENSIONS
+                .iter()
+                .any(|playlist| playlist.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Read the tracks of the playlist at `path`, in order. Entries that are relative are relative to the playlist's folder.
+/// # Errors
+/// - If the playlist can't be read
+pub(crate) fn read(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+
+    // Old M3U files aren't always UTF-8, so keep whatever can be read
+    let contents = String::from_utf8_lossy(&bytes);
+    let folder = path.parent().unwrap_or(Path::new(""));
+
+    let is_pls = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pls"));
+    let entries = if is_pls {
+        parse_pls(&contents)
+    } else {
+        parse_m3u(&contents)
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| resolve(entry, folder))
+        .collect())
+}
+
+/// Return the entries of an M3U playlist, which are every line that isn't blank or a comment.
+fn parse_m3u(contents: &str) -> Vec<&str> {
+    contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// Return the entries of a PLS playlist, which are the `FileN=` lines, ordered by their number.
+fn parse_pls(contents: &str) -> Vec<&str> {
+    let mut entries: Vec<(u32, &str)> = contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let number = key.trim().strip_prefix("File")?.parse().ok()?;
+
+            Some((number, value.trim()))
+        })
+        .collect();
+    entries.sort_by_key(|(number, _)| *number);
+
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Turn an entry of a playlist into the path of a track, relative to the playlist's `folder`.
+fn resolve(entry: &str, folder: &Path) -> PathBuf {
+    let path = queue::location_path(entry);
+
+    if stream::is_stream(&path) || path.is_absolute() {
+        path
+    } else {
+        folder.join(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    fn temp_playlist(name: &str, contents: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("audio_player_playlist_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    mod is_playlist {
+        use super::*;
+
+        #[test]
+        fn test_extensions() {
+            assert!(is_playlist(Path::new("/music/mix.m3u")));
+            assert!(is_playlist(Path::new("/music/mix.M3U8")));
+            assert!(is_playlist(Path::new("/music/radio.pls")));
+            assert!(!is_playlist(Path::new("/music/track.mp3")));
+            assert!(!is_playlist(Path::new("/music/m3u")));
+        }
+    }
+
+    mod read {
+        use super::*;
+
+        #[test]
+        fn test_m3u() {
+            let path = temp_playlist(
+                "mix.m3u8",
+                "\u{feff}#EXTM3U\n#EXTINF:123,Artist - Title\nalbum/a.mp3\n\n/music/b.flac\r\nhttps://radio.example/stream\nfile:///music/my%20track.ogg\n",
+            );
+            let folder = path.parent().unwrap().to_path_buf();
+
+            assert_eq!(
+                read(&path),
+                Ok(vec![
+                    folder.join("album/a.mp3"),
+                    PathBuf::from("/music/b.flac"),
+                    PathBuf::from("https://radio.example/stream"),
+                    PathBuf::from("/music/my track.ogg"),
+                ])
+            );
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_pls() {
+            let path = temp_playlist(
+                "radio.pls",
+                "[playlist]\nFile2=/music/b.mp3\nTitle2=B\nFile1=/music/a.mp3\nNumberOfEntries=2\nVersion=2\n",
+            );
+
+            assert_eq!(
+                read(&path),
+                Ok(vec![
+                    PathBuf::from("/music/a.mp3"),
+                    PathBuf::from("/music/b.mp3")
+                ])
+            );
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_non_existent_file() {
+            assert!(read(Path::new("does_not_exist.m3u")).is_err());
+        }
+    }
+}
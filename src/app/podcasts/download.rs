@@ -0,0 +1,217 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::app::podcasts::feed::{self, Feed};
+
+/// How long to wait for a server to answer before giving up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a download can stall before giving up. What was downloaded so far is kept, so it can be resumed.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build()
+}
+
+/// Download and parse the feed at `url`.
+/// # Errors
+/// - If the feed can't be downloaded
+/// - If the feed can't be parsed
+pub(crate) fn fetch_feed(url: &str) -> Result<Feed, String> {
+    let xml = agent()
+        .get(url)
+        .call()
+        .map_err(|e| e.to_string())
+        .and_then(|response| response.into_string().map_err(|e| e.to_string()))
+        .map_err(|e| format!("Unable to fetch {}: {}", url, e))?;
+
+    feed::parse_feed(&xml).map_err(|e| format!("{} ({})", e, url))
+}
+
+/// Download the file at `url` to `path`, calling `progress` with how far along the download is, from 0.0 to 1.0.
+/// The download is written to a partial file next to `path` first, and picks up where it left off if it was interrupted before.
+/// # Errors
+/// - If the file can't be downloaded
+/// - If the file can't be written
+pub(crate) fn download(url: &str, path: &Path, progress: impl FnMut(f64)) -> Result<(), String> {
+    download_to(url, path, progress).map_err(|e| format!("Unable to download {}: {}", url, e))
+}
+
+fn download_to(url: &str, path: &Path, mut progress: impl FnMut(f64)) -> io::Result<()> {
+    let partial_path = partial_path(path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let downloaded = fs::metadata(&partial_path).map_or(0, |metadata| metadata.len());
+
+    let mut request = agent().get(url);
+    if downloaded > 0 {
+        request = request.set("Range", &format!("bytes={}-", downloaded));
+    }
+
+    let response = match request.call() {
+        Ok(response) => response,
+        // The partial file already has everything there is to download
+        Err(ureq::Error::Status(416, _)) if downloaded > 0 => {
+            return fs::rename(&partial_path, path);
+        }
+        Err(e) => return Err(io::Error::other(e)),
+    };
+
+    // Servers that don't support ranges send the whole file again, so start over
+    let resumed = response.status() == 206;
+    let (mut file, offset) = if resumed {
+        let file = OpenOptions::new().append(true).open(&partial_path)?;
+        (file, downloaded)
+    } else {
+        (File::create(&partial_path)?, 0)
+    };
+
+    let total = response
+        .header("Content-Length")
+        .and_then(|length| length.trim().parse::<u64>().ok())
+        .map(|length| length + offset);
+
+    let mut body = response.into_reader();
+    let mut chunk = [0; 8192];
+    let mut written = offset;
+    let mut reported_percent = None;
+
+    loop {
+        let read = match body.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        file.write_all(&chunk[..read])?;
+        written += read as u64;
+
+        // Only report whole percents, so that the UI isn't flooded with updates
+        if let Some(total) = total.filter(|&total| total > 0) {
+            let percent = (written * 100 / total).min(100);
+            if reported_percent != Some(percent) {
+                reported_percent = Some(percent);
+                progress(percent as f64 / 100.0);
+            }
+        }
+    }
+
+    if total.is_some_and(|total| written < total) {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the connection closed before the download finished",
+        ));
+    }
+
+    file.flush()?;
+    fs::rename(&partial_path, path)
+}
+
+/// Return where the unfinished download of `path` is kept.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+    use crate::app::podcasts::test::serve;
+
+    const RSS: &str = include_str!("tests/files/rss.xml");
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("audio_player_download_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn body() -> Vec<u8> {
+        (0..50_000).map(|i| (i % 251) as u8).collect()
+    }
+
+    mod fetch_feed {
+        use super::*;
+
+        #[test]
+        fn test_rss() {
+            let (url, _) = serve(RSS.as_bytes().to_vec(), "application/rss+xml");
+
+            let feed = fetch_feed(&format!("{}/feed.xml", url)).unwrap();
+
+            assert_eq!(feed.title, "Test Podcast");
+            assert_eq!(feed.episodes.len(), 2);
+        }
+
+        #[test]
+        fn test_not_a_feed() {
+            let (url, _) = serve(b"<html/>".to_vec(), "text/html");
+
+            assert!(fetch_feed(&url).is_err());
+        }
+    }
+
+    mod download {
+        use super::*;
+
+        #[test]
+        fn test_whole_file() {
+            let (url, ranges) = serve(body(), "audio/ogg");
+            let path = temp_path("whole.ogg");
+            let mut reported = Vec::new();
+
+            download(&url, &path, |progress| reported.push(progress)).unwrap();
+
+            assert_eq!(fs::read(&path).unwrap(), body());
+            assert!(!partial_path(&path).exists());
+            assert_eq!(*ranges.lock().unwrap(), vec![None]);
+            assert_eq!(reported.last(), Some(&1.0));
+        }
+
+        #[test]
+        fn test_resume() {
+            let (url, ranges) = serve(body(), "audio/ogg");
+            let path = temp_path("resume.ogg");
+            fs::write(partial_path(&path), &body()[..20_000]).unwrap();
+
+            download(&url, &path, |_| {}).unwrap();
+
+            assert_eq!(fs::read(&path).unwrap(), body());
+            assert_eq!(
+                *ranges.lock().unwrap(),
+                vec![Some("bytes=20000-".to_string())]
+            );
+        }
+
+        #[test]
+        fn test_already_downloaded() {
+            let (url, _) = serve(body(), "audio/ogg");
+            let path = temp_path("already_downloaded.ogg");
+            fs::write(partial_path(&path), body()).unwrap();
+
+            download(&url, &path, |_| {}).unwrap();
+
+            assert_eq!(fs::read(&path).unwrap(), body());
+        }
+
+        #[test]
+        fn test_unreachable() {
+            let path = temp_path("unreachable.ogg");
+
+            assert!(download("http://127.0.0.1:1/episode.ogg", &path, |_| {}).is_err());
+            assert!(!path.exists());
+        }
+    }
+}
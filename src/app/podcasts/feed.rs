@@ -0,0 +1,396 @@
+use roxmltree::{Document, Node};
+
+use crate::app::podcasts::Episode;
+use crate::cli::parse_timestamp;
+
+/// A podcast, as described by its feed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Feed {
+    pub(crate) title: String,
+    pub(crate) description: String,
+
+    /// The episodes in the order the feed lists them, which is newest first for almost every podcast
+    pub(crate) episodes: Vec<Episode>,
+}
+
+const ITUNES_NS: &str = "http://www.itunes.com/dtds/podcast-1.0.dtd";
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+/// Parse an RSS 2.0 or Atom feed, including the iTunes extensions that podcasts use.
+/// Entries without any audio attached are left out, since there is nothing to play.
+/// # Errors
+/// - If the feed is not valid XML
+/// - If the feed is neither RSS nor Atom
+pub(crate) fn parse_feed(xml: &str) -> Result<Feed, String> {
+    let document = Document::parse(xml).map_err(|e| format!("Invalid feed: {}", e))?;
+    let root = document.root_element();
+
+    match root.tag_name().name() {
+        "rss" => child(root, "channel")
+            .map(parse_rss_channel)
+            .ok_or_else(|| "Invalid feed: the RSS feed has no channel".to_string()),
+        "feed" if root.tag_name().namespace() == Some(ATOM_NS) => Ok(parse_atom_feed(root)),
+        name => Err(format!("Not a podcast feed: <{}>", name)),
+    }
+}
+
+/// Return the URL of every feed listed in an OPML file, which is how podcast apps export their subscriptions.
+/// # Errors
+/// - If the file is not valid XML
+/// - If the file is not OPML
+pub(crate) fn parse_opml(xml: &str) -> Result<Vec<String>, String> {
+    let document = Document::parse(xml).map_err(|e| format!("Invalid OPML file: {}", e))?;
+    let root = document.root_element();
+
+    if root.tag_name().name() != "opml" {
+        return Err(format!("Not an OPML file: <{}>", root.tag_name().name()));
+    }
+
+    // Outlines can be nested in folders, so look through all of them
+    Ok(root
+        .descendants()
+        .filter(|node| node.has_tag_name("outline"))
+        .filter_map(|outline| outline.attribute("xmlUrl"))
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect())
+}
+
+fn parse_rss_channel(channel: Node) -> Feed {
+    let episodes = channel
+        .children()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(parse_rss_item)
+        .collect();
+
+    Feed {
+        title: child_text(channel, "title").unwrap_or_default(),
+        description: child_text(channel, "description")
+            .or_else(|| child_text(channel, (ITUNES_NS, "summary")))
+            .map(|description| strip_html(&description))
+            .unwrap_or_default(),
+        episodes,
+    }
+}
+
+fn parse_rss_item(item: Node) -> Option<Episode> {
+    let audio_url = child(item, "enclosure")?
+        .attribute("url")?
+        .trim()
+        .to_string();
+
+    // The full show notes are usually in content:encoded, with a shorter version in the description
+    let notes = child_text(item, (CONTENT_NS, "encoded"))
+        .or_else(|| child_text(item, "description"))
+        .or_else(|| child_text(item, (ITUNES_NS, "summary")));
+
+    Some(Episode {
+        id: child_text(item, "guid").unwrap_or_else(|| audio_url.clone()),
+        title: child_text(item, "title").unwrap_or_else(|| audio_url.clone()),
+        notes: notes.map(|notes| strip_html(&notes)).unwrap_or_default(),
+        published: child_text(item, "pubDate"),
+        length: child_text(item, (ITUNES_NS, "duration"))
+            .and_then(|length| parse_timestamp(&length).ok()),
+        audio_url,
+        ..Episode::default()
+    })
+}
+
+fn parse_atom_feed(feed: Node) -> Feed {
+    let episodes = feed
+        .children()
+        .filter(|node| node.has_tag_name((ATOM_NS, "entry")))
+        .filter_map(parse_atom_entry)
+        .collect();
+
+    Feed {
+        title: child_text(feed, (ATOM_NS, "title")).unwrap_or_default(),
+        description: child_text(feed, (ATOM_NS, "subtitle"))
+            .map(|description| strip_html(&description))
+            .unwrap_or_default(),
+        episodes,
+    }
+}
+
+fn parse_atom_entry(entry: Node) -> Option<Episode> {
+    let audio_url = entry
+        .children()
+        .filter(|node| node.has_tag_name((ATOM_NS, "link")))
+        .find(|link| link.attribute("rel") == Some("enclosure"))?
+        .attribute("href")?
+        .trim()
+        .to_string();
+
+    let notes =
+        child_text(entry, (ATOM_NS, "content")).or_else(|| child_text(entry, (ATOM_NS, "summary")));
+
+    Some(Episode {
+        id: child_text(entry, (ATOM_NS, "id")).unwrap_or_else(|| audio_url.clone()),
+        title: child_text(entry, (ATOM_NS, "title")).unwrap_or_else(|| audio_url.clone()),
+        notes: notes.map(|notes| strip_html(&notes)).unwrap_or_default(),
+        published: child_text(entry, (ATOM_NS, "published"))
+            .or_else(|| child_text(entry, (ATOM_NS, "updated"))),
+        length: child_text(entry, (ITUNES_NS, "duration"))
+            .and_then(|length| parse_timestamp(&length).ok()),
+        audio_url,
+        ..Episode::default()
+    })
+}
+
+/// Return the first child of `node` with the given name. A name without a namespace matches the element in any namespace.
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: impl Into<roxmltree::ExpandedName<'a, 'a>>,
+) -> Option<Node<'a, 'input>> {
+    let name = name.into();
+
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// Return the trimmed text of the first child of `node` with the given name, unless it is empty.
+fn child_text<'a>(
+    node: Node<'a, '_>,
+    name: impl Into<roxmltree::ExpandedName<'a, 'a>>,
+) -> Option<String> {
+    // The text can be split up by CDATA sections, so join all of it together
+    let text: String = child(node, name)?
+        .descendants()
+        .filter_map(|node| node.is_text().then(|| node.text()).flatten())
+        .collect();
+    let text = text.trim();
+
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Turn the HTML that show notes are written in into plain text, keeping the line breaks.
+pub(crate) fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(tag_start) = rest.find('<') {
+        text.push_str(&rest[..tag_start]);
+
+        let Some(tag_len) = rest[tag_start..].find('>') else {
+            // Not a tag after all, so keep the rest as it is
+            rest = &rest[tag_start..];
+            break;
+        };
+        let tag = rest[tag_start + 1..tag_start + tag_len]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if matches!(tag.as_str(), "br" | "p" | "div" | "li" | "h1" | "h2" | "h3") {
+            text.push('\n');
+        }
+
+        rest = &rest[tag_start + tag_len + 1..];
+    }
+    text.push_str(rest);
+
+    let text = decode_entities(&text);
+
+    // Tags that follow each other leave lots of blank lines behind, so keep at most one between paragraphs
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+
+    lines.join("\n").trim().to_string()
+}
+
+/// Decode the HTML entities that show up in show notes, such as `&amp;`.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // Entities are short, so a semicolon that is far away belongs to something else
+        let entity = rest
+            .get(1..rest.len().min(10))
+            .and_then(|entity| entity.split_once(';'))
+            .map(|(entity, _)| entity);
+
+        match entity.and_then(decode_entity) {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[entity.unwrap_or_default().len() + 2..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let code = match entity {
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "nbsp" => return Some(' '),
+        _ => entity.strip_prefix('#')?,
+    };
+
+    let code = match code.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => code.parse().ok()?,
+    };
+
+    char::from_u32(code)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    const RSS: &str = include_str!("tests/files/rss.xml");
+    const ATOM: &str = include_str!("tests/files/atom.xml");
+    const OPML: &str = include_str!("tests/files/subscriptions.opml");
+
+    mod parse_feed {
+        use super::*;
+
+        #[test]
+        fn test_rss() {
+            let feed = parse_feed(RSS).unwrap();
+
+            assert_eq!(feed.title, "Test Podcast");
+            assert_eq!(feed.description, "A podcast for testing.");
+            assert_eq!(feed.episodes.len(), 2);
+
+            let episode = &feed.episodes[0];
+            assert_eq!(episode.id, "episode-2");
+            assert_eq!(episode.title, "Episode 2");
+            assert_eq!(episode.audio_url, "http://podcast.example/episode-2.ogg");
+            assert_eq!(
+                episode.published.as_deref(),
+                Some("Tue, 02 Jan 2024 08:00:00 GMT")
+            );
+            assert_eq!(episode.length, Some(Duration::from_secs(3725)));
+            assert!(!episode.played);
+        }
+
+        #[test]
+        fn test_rss_show_notes() {
+            let feed = parse_feed(RSS).unwrap();
+
+            // content:encoded is preferred over the description
+            assert_eq!(
+                feed.episodes[0].notes,
+                "In this episode:\nTesting & more\n\nThanks for listening!"
+            );
+            assert_eq!(feed.episodes[1].notes, "The first episode.");
+        }
+
+        #[test]
+        fn test_rss_without_enclosure() {
+            let feed = parse_feed(RSS).unwrap();
+
+            assert!(
+                feed.episodes
+                    .iter()
+                    .all(|episode| episode.title != "Announcement")
+            );
+        }
+
+        #[test]
+        fn test_rss_without_guid() {
+            let feed = parse_feed(RSS).unwrap();
+
+            assert_eq!(feed.episodes[1].id, "http://podcast.example/episode-1.ogg");
+            assert_eq!(feed.episodes[1].length, Some(Duration::from_secs(90)));
+        }
+
+        #[test]
+        fn test_atom() {
+            let feed = parse_feed(ATOM).unwrap();
+
+            assert_eq!(feed.title, "Atom Podcast");
+            assert_eq!(feed.description, "Episodes in an Atom feed.");
+            assert_eq!(feed.episodes.len(), 1);
+
+            let episode = &feed.episodes[0];
+            assert_eq!(episode.id, "urn:uuid:1");
+            assert_eq!(episode.title, "Atom Episode");
+            assert_eq!(episode.notes, "Notes for the Atom episode.");
+            assert_eq!(episode.audio_url, "http://podcast.example/atom-1.ogg");
+            assert_eq!(episode.published.as_deref(), Some("2024-01-03T08:00:00Z"));
+            assert_eq!(episode.length, None);
+        }
+
+        #[test]
+        fn test_not_a_feed() {
+            assert_eq!(
+                parse_feed("<html><body/></html>"),
+                Err("Not a podcast feed: <html>".to_string())
+            );
+        }
+
+        #[test]
+        fn test_invalid_xml() {
+            assert!(parse_feed("<rss><channel>").is_err());
+        }
+    }
+
+    mod parse_opml {
+        use super::*;
+
+        #[test]
+        fn test_subscriptions() {
+            assert_eq!(
+                parse_opml(OPML).unwrap(),
+                vec![
+                    "http://podcast.example/feed.xml".to_string(),
+                    "http://podcast.example/atom.xml".to_string(),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_not_opml() {
+            assert!(parse_opml(RSS).is_err());
+        }
+    }
+
+    mod strip_html {
+        use super::*;
+
+        #[test]
+        fn test_tags() {
+            assert_eq!(
+                strip_html("<p>First <b>bold</b></p><p>Second<br/>line</p>"),
+                "First bold\n\nSecond\nline"
+            );
+        }
+
+        #[test]
+        fn test_entities() {
+            assert_eq!(
+                strip_html("Q&amp;A &lt;live&gt; &#8211; &#x41;&nbsp;B"),
+                "Q&A <live> \u{2013} A B"
+            );
+        }
+
+        #[test]
+        fn test_not_html() {
+            assert_eq!(strip_html("Rock & roll: 1 < 2"), "Rock & roll: 1 < 2");
+        }
+    }
+}
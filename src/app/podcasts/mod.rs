@@ -0,0 +1,1008 @@
+mod download;
+mod feed;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::stable_hash::stable_hash;
+use feed::Feed;
+
+/// An episode of a podcast, along with how far the user got with it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Episode {
+    /// What the feed identifies the episode by, which stays the same when the feed changes
+    pub(crate) id: String,
+
+    pub(crate) title: String,
+
+    /// The show notes, as plain text
+    pub(crate) notes: String,
+
+    pub(crate) audio_url: String,
+
+    /// When the episode came out, as written in the feed
+    pub(crate) published: Option<String>,
+
+    /// The length that the feed gives, if it gives one
+    pub(crate) length: Option<Duration>,
+
+    pub(crate) played: bool,
+
+    /// Where playback stopped the last time the episode was listened to, so that it can carry on from there
+    pub(crate) position: Option<Duration>,
+
+    /// Where the episode was downloaded to, if it was downloaded
+    pub(crate) file: Option<PathBuf>,
+}
+
+/// A podcast that the user is subscribed to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Podcast {
+    /// The URL of the feed
+    pub(crate) url: String,
+
+    pub(crate) title: String,
+    pub(crate) description: String,
+
+    /// The episodes in the order the feed lists them, which is newest first for almost every podcast
+    pub(crate) episodes: Vec<Episode>,
+}
+
+/// Settings that control which episodes are downloaded and kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PodcastSettings {
+    /// How many of the latest episodes of every podcast to download whenever its feed is fetched
+    pub(crate) auto_download: usize,
+
+    /// Whether to delete the downloads of episodes once they are played
+    pub(crate) delete_played: bool,
+}
+
+/// Everything that is saved to disk.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct LibraryData {
+    settings: PodcastSettings,
+    podcasts: Vec<Podcast>,
+}
+
+/// Points to an episode of a podcast.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct EpisodeKey {
+    pub(crate) feed_url: String,
+    pub(crate) episode_id: String,
+}
+
+impl EpisodeKey {
+    pub(crate) fn new(podcast: &Podcast, episode: &Episode) -> EpisodeKey {
+        EpisodeKey {
+            feed_url: podcast.url.clone(),
+            episode_id: episode.id.clone(),
+        }
+    }
+}
+
+/// What the threads that fetch feeds and download episodes report back.
+enum PodcastEvent {
+    Fetched {
+        url: String,
+        result: Result<Feed, String>,
+    },
+    Progress {
+        key: EpisodeKey,
+        progress: f64,
+    },
+    Downloaded {
+        key: EpisodeKey,
+        file: PathBuf,
+    },
+    DownloadFailed {
+        key: EpisodeKey,
+        error: String,
+    },
+}
+
+/// The podcasts that the user is subscribed to, which are saved to disk along with the state of every episode.
+/// Feeds are fetched and episodes are downloaded in the background, and taken in with `update`.
+pub(crate) struct PodcastLibrary {
+    /// Where the library is saved. If this is `None`, the library only lives in memory.
+    path: Option<PathBuf>,
+
+    /// Where episodes are downloaded to. If this is `None`, episodes can't be downloaded.
+    cache_dir: Option<PathBuf>,
+
+    data: LibraryData,
+
+    /// The URLs of the feeds that are being fetched
+    fetching: HashSet<String>,
+
+    /// How far along each running download is, from 0.0 to 1.0
+    downloads: HashMap<EpisodeKey, f64>,
+
+    /// The errors of the background work, which haven't been shown to the user yet
+    errors: Vec<String>,
+
+    event_sender: mpsc::Sender<PodcastEvent>,
+    event_receiver: mpsc::Receiver<PodcastEvent>,
+}
+
+impl Default for PodcastLibrary {
+    fn default() -> PodcastLibrary {
+        let (event_sender, event_receiver) = mpsc::channel();
+
+        PodcastLibrary {
+            path: None,
+            cache_dir: None,
+            data: LibraryData::default(),
+            fetching: HashSet::new(),
+            downloads: HashMap::new(),
+            errors: Vec::new(),
+            event_sender,
+            event_receiver,
+        }
+    }
+}
+
+impl PodcastLibrary {
+    const FILE_NAME: &str = "podcasts.json";
+
+    /// How close to its end an episode has to be stopped at to count as played, since the end is usually just the outro
+    const PLAYED_MARGIN: Duration = Duration::from_secs(30);
+
+    /// Load the library from the user's data directory, downloading episodes to their cache directory.
+    /// If the library can't be read, start with an empty one so that the player still works.
+    pub(crate) fn load() -> PodcastLibrary {
+        let Some(path) = dirs::data_dir().map(|dir| dir.join("audio_player").join(Self::FILE_NAME))
+        else {
+            eprintln!("Unable to find the data directory, podcasts will not be saved");
+            return PodcastLibrary::default();
+        };
+
+        let mut library = PodcastLibrary::load_from(path);
+        library.cache_dir = dirs::cache_dir().map(|dir| dir.join("audio_player").join("podcasts"));
+
+        library
+    }
+
+    /// Load the library from a specific file. A missing or invalid file results in an empty library.
+    pub(crate) fn load_from(path: PathBuf) -> PodcastLibrary {
+        let data = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Unable to parse {}: {}", path.display(), e);
+                LibraryData::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => LibraryData::default(),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path.display(), e);
+                LibraryData::default()
+            }
+        };
+
+        PodcastLibrary {
+            path: Some(path),
+            data,
+            ..PodcastLibrary::default()
+        }
+    }
+
+    pub(crate) fn podcasts(&self) -> &[Podcast] {
+        &self.data.podcasts
+    }
+
+    pub(crate) fn settings(&self) -> PodcastSettings {
+        self.data.settings
+    }
+
+    /// Change the settings, and download or delete episodes to match them.
+    pub(crate) fn set_settings(&mut self, settings: PodcastSettings) {
+        self.data.settings = settings;
+
+        let urls: Vec<String> = self.podcasts().iter().map(|p| p.url.clone()).collect();
+        for url in urls {
+            self.auto_download(&url);
+        }
+        self.clean_up();
+        self.save();
+    }
+
+    /// Subscribe to the feed at `url`. The podcast shows up once its feed has been fetched.
+    pub(crate) fn subscribe(&mut self, url: &str) {
+        self.fetch(url.trim().to_string());
+    }
+
+    /// Subscribe to every feed in an OPML file that isn't subscribed to yet. Returns how many feeds that is.
+    /// # Errors
+    /// - If the file is not OPML
+    pub(crate) fn import_opml(&mut self, opml: &str) -> Result<usize, String> {
+        let urls: Vec<String> = feed::parse_opml(opml)?
+            .into_iter()
+            .filter(|url| self.podcast(url).is_none())
+            .collect();
+
+        for url in &urls {
+            self.fetch(url.clone());
+        }
+
+        Ok(urls.len())
+    }
+
+    /// Fetch the feed of every podcast again, to find new episodes.
+    pub(crate) fn refresh(&mut self) {
+        let urls: Vec<String> = self.podcasts().iter().map(|p| p.url.clone()).collect();
+        for url in urls {
+            self.fetch(url);
+        }
+    }
+
+    /// Unsubscribe from a podcast, and delete its downloaded episodes.
+    pub(crate) fn unsubscribe(&mut self, url: &str) {
+        let Some(index) = self.data.podcasts.iter().position(|p| p.url == url) else {
+            return;
+        };
+
+        let podcast = self.data.podcasts.remove(index);
+        for file in podcast.episodes.into_iter().filter_map(|e| e.file) {
+            PodcastLibrary::delete_download(&file);
+        }
+
+        self.save();
+    }
+
+    /// Start downloading an episode, unless it is already downloaded or downloading.
+    pub(crate) fn download(&mut self, key: &EpisodeKey) {
+        if self.downloads.contains_key(key) {
+            return;
+        }
+
+        let Some(episode) = self.episode(key).filter(|episode| episode.file.is_none()) else {
+            return;
+        };
+
+        let Some(file) = self.download_path(&key.feed_url, episode) else {
+            self.errors.push(
+                "Unable to find the cache directory, episodes can't be downloaded".to_string(),
+            );
+            return;
+        };
+
+        let url = episode.audio_url.clone();
+        let sender = self.event_sender.clone();
+        let thread_key = key.clone();
+
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress_key = thread_key.clone();
+            let result = download::download(&url, &file, |progress| {
+                let _ = progress_sender.send(PodcastEvent::Progress {
+                    key: progress_key.clone(),
+                    progress,
+                });
+            });
+
+            // The player may have closed in the meantime, in which case nobody is waiting for the download
+            let _ = sender.send(match result {
+                Ok(()) => PodcastEvent::Downloaded {
+                    key: thread_key,
+                    file,
+                },
+                Err(error) => PodcastEvent::DownloadFailed {
+                    key: thread_key,
+                    error,
+                },
+            });
+        });
+
+        self.downloads.insert(key.clone(), 0.0);
+    }
+
+    /// Return how far along the download of an episode is, if it is downloading.
+    pub(crate) fn download_progress(&self, key: &EpisodeKey) -> Option<f64> {
+        self.downloads.get(key).copied()
+    }
+
+    /// Return whether feeds are being fetched or episodes are being downloaded.
+    pub(crate) fn is_busy(&self) -> bool {
+        !self.fetching.is_empty() || !self.downloads.is_empty()
+    }
+
+    /// Mark an episode as played or unplayed, which also forgets where playback stopped.
+    pub(crate) fn set_played(&mut self, key: &EpisodeKey, played: bool) {
+        let Some(episode) = self.episode_mut(key) else {
+            return;
+        };
+        episode.played = played;
+        episode.position = None;
+
+        self.clean_up();
+        self.save();
+    }
+
+    /// Remember where playback of a downloaded episode stopped, marking it as played if it was listened to the end.
+    /// Files that aren't episodes are ignored.
+    pub(crate) fn record_progress(
+        &mut self,
+        file: &Path,
+        position: Duration,
+        length: Option<Duration>,
+    ) {
+        let Some(episode) = self.episode_with_file_mut(file) else {
+            return;
+        };
+
+        let length = length.or(episode.length);
+        if length.is_some_and(|length| position + PodcastLibrary::PLAYED_MARGIN >= length) {
+            episode.played = true;
+            episode.position = None;
+        } else {
+            episode.position = (!position.is_zero()).then_some(position);
+        }
+
+        self.clean_up();
+        self.save();
+    }
+
+    /// Mark a downloaded episode as played once it has played to its end. Files that aren't episodes are ignored.
+    pub(crate) fn finished(&mut self, file: &Path) {
+        let Some(episode) = self.episode_with_file_mut(file) else {
+            return;
+        };
+
+        episode.played = true;
+        episode.position = None;
+
+        self.clean_up();
+        self.save();
+    }
+
+    /// Return where playback of a downloaded episode should carry on from, if it was stopped partway through.
+    pub(crate) fn resume_position(&self, file: &Path) -> Option<Duration> {
+        self.data
+            .podcasts
+            .iter()
+            .flat_map(|podcast| &podcast.episodes)
+            .find(|episode| episode.file.as_deref() == Some(file))
+            .filter(|episode| !episode.played)
+            .and_then(|episode| episode.position)
+    }
+
+    /// Return the errors of the background work since the last time this was called.
+    pub(crate) fn take_errors(&mut self) -> Vec<String> {
+        mem::take(&mut self.errors)
+    }
+
+    /// Take in the feeds that were fetched and the episodes that were downloaded. Returns `true` if anything changed.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub(crate) fn update(&mut self) -> bool {
+        let events: Vec<PodcastEvent> = self.event_receiver.try_iter().collect();
+        let changed = !events.is_empty();
+
+        for event in events {
+            match event {
+                PodcastEvent::Fetched {
+                    url,
+                    result: Ok(feed),
+                } => {
+                    self.fetching.remove(&url);
+                    self.merge_feed(url.clone(), feed);
+                    self.auto_download(&url);
+                    self.clean_up();
+                    self.save();
+                }
+                PodcastEvent::Fetched {
+                    url,
+                    result: Err(error),
+                } => {
+                    self.fetching.remove(&url);
+                    self.errors.push(error);
+                }
+                PodcastEvent::Progress { key, progress } => {
+                    if let Some(download) = self.downloads.get_mut(&key) {
+                        *download = progress;
+                    }
+                }
+                PodcastEvent::Downloaded { key, file } => {
+                    self.downloads.remove(&key);
+
+                    match self.episode_mut(&key) {
+                        Some(episode) => episode.file = Some(file),
+                        // The podcast was unsubscribed from while the episode downloaded
+                        None => PodcastLibrary::delete_download(&file),
+                    }
+                    self.save();
+                }
+                PodcastEvent::DownloadFailed { key, error } => {
+                    self.downloads.remove(&key);
+                    self.errors.push(error);
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Fetch a feed in the background, unless it is already being fetched.
+    fn fetch(&mut self, url: String) {
+        if url.is_empty() || !self.fetching.insert(url.clone()) {
+            return;
+        }
+
+        let sender = self.event_sender.clone();
+        thread::spawn(move || {
+            let result = download::fetch_feed(&url);
+            let _ = sender.send(PodcastEvent::Fetched { url, result });
+        });
+    }
+
+    /// Add or update a podcast from its feed, keeping what is known about the episodes that were already in it.
+    fn merge_feed(&mut self, url: String, feed: Feed) {
+        let index = self.data.podcasts.iter().position(|p| p.url == url);
+        let old_episodes = match index {
+            Some(index) => mem::take(&mut self.data.podcasts[index].episodes),
+            None => Vec::new(),
+        };
+
+        let mut episodes = feed.episodes;
+        for episode in &mut episodes {
+            if let Some(old) = old_episodes.iter().find(|old| old.id == episode.id) {
+                episode.played = old.played;
+                episode.position = old.position;
+                episode.file = old.file.clone();
+            }
+        }
+
+        // Keep the downloads of episodes that dropped out of the feed, so that they can still be played and cleaned up
+        let dropped: Vec<Episode> = old_episodes
+            .into_iter()
+            .filter(|old| old.file.is_some() && !episodes.iter().any(|e| e.id == old.id))
+            .collect();
+        episodes.extend(dropped);
+
+        let podcast = Podcast {
+            url,
+            title: feed.title,
+            description: feed.description,
+            episodes,
+        };
+
+        match index {
+            Some(index) => self.data.podcasts[index] = podcast,
+            None => self.data.podcasts.push(podcast),
+        }
+    }
+
+    /// Download the latest episodes of a podcast that haven't been played, as many as the settings ask for.
+    fn auto_download(&mut self, url: &str) {
+        let Some(podcast) = self.podcast(url) else {
+            return;
+        };
+
+        let keys: Vec<EpisodeKey> = podcast
+            .episodes
+            .iter()
+            .take(self.data.settings.auto_download)
+            .filter(|episode| !episode.played && episode.file.is_none())
+            .map(|episode| EpisodeKey::new(podcast, episode))
+            .collect();
+
+        for key in keys {
+            self.download(&key);
+        }
+    }
+
+    /// Delete the downloads of played episodes, if the settings ask for it.
+    fn clean_up(&mut self) {
+        if !self.data.settings.delete_played {
+            return;
+        }
+
+        let played = self
+            .data
+            .podcasts
+            .iter_mut()
+            .flat_map(|podcast| &mut podcast.episodes)
+            .filter(|episode| episode.played);
+
+        for episode in played {
+            if let Some(file) = episode.file.take() {
+                PodcastLibrary::delete_download(&file);
+            }
+        }
+    }
+
+    fn delete_download(file: &Path) {
+        match fs::remove_file(file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Unable to delete {}: {}", file.display(), e),
+        }
+    }
+
+    /// Return where an episode is downloaded to. Every podcast gets its own directory.
+    fn download_path(&self, feed_url: &str, episode: &Episode) -> Option<PathBuf> {
+        let cache_dir = self.cache_dir.as_ref()?;
+
+        // Keep the extension of the audio, so that its format can be recognized
+        let url_path = episode
+            .audio_url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        let extension = Path::new(url_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .filter(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or("mp3");
+
+        // The title keeps the name readable, and the hash keeps it unique
+        let file_name = format!(
+            "{}-{:016x}.{}",
+            PodcastLibrary::slug(&episode.title),
+            stable_hash(episode.id.as_bytes()),
+            extension
+        );
+
+        Some(
+            cache_dir
+                .join(format!("{:016x}", stable_hash(feed_url.as_bytes())))
+                .join(file_name),
+        )
+    }
+
+    /// Turn a title into something that can safely be used in a file name.
+    fn slug(title: &str) -> String {
+        const MAX_LEN: usize = 50;
+
+        let slug: String = title
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .take(MAX_LEN)
+            .collect();
+
+        slug.split('-')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    fn podcast(&self, url: &str) -> Option<&Podcast> {
+        self.data.podcasts.iter().find(|podcast| podcast.url == url)
+    }
+
+    fn episode(&self, key: &EpisodeKey) -> Option<&Episode> {
+        self.podcast(&key.feed_url)?
+            .episodes
+            .iter()
+            .find(|episode| episode.id == key.episode_id)
+    }
+
+    fn episode_mut(&mut self, key: &EpisodeKey) -> Option<&mut Episode> {
+        self.data
+            .podcasts
+            .iter_mut()
+            .find(|podcast| podcast.url == key.feed_url)?
+            .episodes
+            .iter_mut()
+            .find(|episode| episode.id == key.episode_id)
+    }
+
+    fn episode_with_file_mut(&mut self, file: &Path) -> Option<&mut Episode> {
+        self.data
+            .podcasts
+            .iter_mut()
+            .flat_map(|podcast| &mut podcast.episodes)
+            .find(|episode| episode.file.as_deref() == Some(file))
+    }
+
+    /// Write the library to disk, reporting any errors.
+    fn save(&self) {
+        if let Err(e) = self.try_save() {
+            eprintln!("Unable to save podcasts: {}", e);
+        }
+    }
+
+    /// # Errors
+    /// - If the directory of the library could not be created
+    /// - If the library could not be written
+    fn try_save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.data).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use super::*;
+
+    const RSS: &str = include_str!("tests/files/rss.xml");
+
+    /// Serve `body` at every path, honouring `Range` headers like a podcast host would.
+    /// Returns the base URL of the server, and the `Range` header of every request that was made.
+    pub(crate) fn serve(
+        body: Vec<u8>,
+        content_type: &str,
+    ) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let content_type: tiny_http::Header =
+            format!("Content-Type: {}", content_type).parse().unwrap();
+
+        {
+            let ranges = Arc::clone(&ranges);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let range = request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv("Range"))
+                        .map(|header| header.value.to_string());
+                    ranges.lock().unwrap().push(range.clone());
+
+                    let start: usize = range
+                        .as_deref()
+                        .and_then(|range| range.strip_prefix("bytes="))
+                        .and_then(|range| range.trim_end_matches('-').parse().ok())
+                        .unwrap_or(0);
+
+                    let response = if start > 0 && start >= body.len() {
+                        tiny_http::Response::from_data(Vec::new()).with_status_code(416)
+                    } else if start > 0 {
+                        tiny_http::Response::from_data(body[start..].to_vec()).with_status_code(206)
+                    } else {
+                        tiny_http::Response::from_data(body.clone())
+                    };
+                    // Send the length of every file up front like a file host would, rather than in chunks
+                    let response = response
+                        .with_header(content_type.clone())
+                        .with_chunked_threshold(usize::MAX);
+                    let _ = request.respond(response);
+                }
+            });
+        }
+
+        (url, ranges)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "audio_player_podcasts_{}_{}",
+            std::process::id(),
+            name
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Create a library that is saved to, and downloads to, a directory of its own.
+    fn library(name: &str) -> PodcastLibrary {
+        let dir = temp_dir(name);
+        let mut library = PodcastLibrary::load_from(dir.join(PodcastLibrary::FILE_NAME));
+        library.cache_dir = Some(dir.join("cache"));
+        library
+    }
+
+    /// Keep updating the library until the background work is done.
+    fn wait_until_idle(library: &mut PodcastLibrary) {
+        let start = Instant::now();
+
+        while library.is_busy() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            library.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn episode(id: &str) -> Episode {
+        Episode {
+            id: id.to_string(),
+            title: format!("Episode {}", id),
+            audio_url: format!("http://podcast.example/{}.ogg", id),
+            length: Some(Duration::from_secs(600)),
+            ..Episode::default()
+        }
+    }
+
+    fn feed(episodes: Vec<Episode>) -> Feed {
+        Feed {
+            title: "Test Podcast".to_string(),
+            description: String::new(),
+            episodes,
+        }
+    }
+
+    fn key(episode_id: &str) -> EpisodeKey {
+        EpisodeKey {
+            feed_url: "feed".to_string(),
+            episode_id: episode_id.to_string(),
+        }
+    }
+
+    mod subscribe {
+        use super::*;
+
+        #[test]
+        fn test_fetches_feed() {
+            let (url, _) = serve(RSS.as_bytes().to_vec(), "application/rss+xml");
+            let mut library = library("fetches_feed");
+
+            library.subscribe(&url);
+            wait_until_idle(&mut library);
+
+            assert_eq!(library.podcasts().len(), 1);
+            assert_eq!(library.podcasts()[0].url, url);
+            assert_eq!(library.podcasts()[0].title, "Test Podcast");
+            assert_eq!(library.podcasts()[0].episodes.len(), 2);
+            assert!(library.take_errors().is_empty());
+        }
+
+        #[test]
+        fn test_saved_between_loads() {
+            let (url, _) = serve(RSS.as_bytes().to_vec(), "application/rss+xml");
+            let mut library = library("saved_between_loads");
+
+            library.subscribe(&url);
+            wait_until_idle(&mut library);
+
+            let loaded = PodcastLibrary::load_from(library.path.clone().unwrap());
+            assert_eq!(loaded.podcasts(), library.podcasts());
+        }
+
+        #[test]
+        fn test_unreachable() {
+            let mut library = library("unreachable");
+
+            library.subscribe("http://127.0.0.1:1/feed.xml");
+            wait_until_idle(&mut library);
+
+            assert!(library.podcasts().is_empty());
+            assert_eq!(library.take_errors().len(), 1);
+            assert!(library.take_errors().is_empty());
+        }
+    }
+
+    mod import_opml {
+        use super::*;
+
+        #[test]
+        fn test_subscribes_to_feeds() {
+            let (url, _) = serve(RSS.as_bytes().to_vec(), "application/rss+xml");
+            let mut library = library("import_opml");
+            let opml = format!(
+                r#"<opml version="2.0"><body><outline text="Test" xmlUrl="{}"/></body></opml>"#,
+                url
+            );
+
+            assert_eq!(library.import_opml(&opml), Ok(1));
+            wait_until_idle(&mut library);
+            assert_eq!(library.podcasts().len(), 1);
+
+            // Feeds that are already subscribed to are skipped
+            assert_eq!(library.import_opml(&opml), Ok(0));
+        }
+    }
+
+    mod merge_feed {
+        use super::*;
+
+        #[test]
+        fn test_keeps_episode_state() {
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1")]));
+            library.set_played(&key("1"), true);
+
+            library.merge_feed("feed".to_string(), feed(vec![episode("2"), episode("1")]));
+
+            let episodes = &library.podcasts()[0].episodes;
+            assert_eq!(episodes.len(), 2);
+            assert!(!episodes[0].played);
+            assert!(episodes[1].played);
+        }
+
+        #[test]
+        fn test_keeps_dropped_downloads() {
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1"), episode("2")]));
+            library.episode_mut(&key("1")).unwrap().file = Some(PathBuf::from("1.ogg"));
+
+            library.merge_feed("feed".to_string(), feed(vec![episode("3")]));
+
+            let ids: Vec<&str> = library.podcasts()[0]
+                .episodes
+                .iter()
+                .map(|episode| episode.id.as_str())
+                .collect();
+            assert_eq!(ids, ["3", "1"]);
+        }
+    }
+
+    mod record_progress {
+        use super::*;
+
+        fn downloaded_library() -> PodcastLibrary {
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1")]));
+            library.episode_mut(&key("1")).unwrap().file = Some(PathBuf::from("1.ogg"));
+            library
+        }
+
+        #[test]
+        fn test_resume_position() {
+            let mut library = downloaded_library();
+
+            library.record_progress(Path::new("1.ogg"), Duration::from_secs(100), None);
+
+            assert_eq!(
+                library.resume_position(Path::new("1.ogg")),
+                Some(Duration::from_secs(100))
+            );
+        }
+
+        #[test]
+        fn test_played_near_end() {
+            let mut library = downloaded_library();
+
+            library.record_progress(
+                Path::new("1.ogg"),
+                Duration::from_secs(590),
+                Some(Duration::from_secs(600)),
+            );
+
+            assert!(library.episode(&key("1")).unwrap().played);
+            assert_eq!(library.resume_position(Path::new("1.ogg")), None);
+        }
+
+        #[test]
+        fn test_not_an_episode() {
+            let mut library = downloaded_library();
+
+            library.record_progress(Path::new("song.ogg"), Duration::from_secs(100), None);
+
+            assert_eq!(library.resume_position(Path::new("song.ogg")), None);
+            assert_eq!(library.episode(&key("1")).unwrap().position, None);
+        }
+    }
+
+    mod finished {
+        use super::*;
+
+        #[test]
+        fn test_played_to_end() {
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1")]));
+            library.episode_mut(&key("1")).unwrap().file = Some(PathBuf::from("1.ogg"));
+            library.episode_mut(&key("1")).unwrap().position = Some(Duration::from_secs(100));
+
+            library.finished(Path::new("1.ogg"));
+
+            assert!(library.episode(&key("1")).unwrap().played);
+            assert_eq!(library.resume_position(Path::new("1.ogg")), None);
+        }
+    }
+
+    mod clean_up {
+        use super::*;
+
+        #[test]
+        fn test_deletes_played() {
+            let dir = temp_dir("deletes_played");
+            let file = dir.join("1.ogg");
+            fs::write(&file, b"audio").unwrap();
+
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1")]));
+            library.episode_mut(&key("1")).unwrap().file = Some(file.clone());
+            library.set_settings(PodcastSettings {
+                delete_played: true,
+                ..PodcastSettings::default()
+            });
+
+            library.set_played(&key("1"), true);
+
+            assert!(!file.exists());
+            assert_eq!(library.episode(&key("1")).unwrap().file, None);
+        }
+
+        #[test]
+        fn test_keeps_played_by_default() {
+            let dir = temp_dir("keeps_played");
+            let file = dir.join("1.ogg");
+            fs::write(&file, b"audio").unwrap();
+
+            let mut library = PodcastLibrary::default();
+            library.merge_feed("feed".to_string(), feed(vec![episode("1")]));
+            library.episode_mut(&key("1")).unwrap().file = Some(file.clone());
+
+            library.set_played(&key("1"), true);
+
+            assert!(file.exists());
+        }
+    }
+
+    mod auto_download {
+        use super::*;
+
+        #[test]
+        fn test_latest_episodes() {
+            let (url, _) = serve(b"audio".to_vec(), "audio/ogg");
+            let mut library = library("auto_download");
+            let episodes = ["3", "2", "1"]
+                .map(|id| Episode {
+                    audio_url: format!("{}/{}.ogg", url, id),
+                    ..episode(id)
+                })
+                .to_vec();
+            library.merge_feed("feed".to_string(), feed(episodes));
+
+            library.set_settings(PodcastSettings {
+                auto_download: 2,
+                ..PodcastSettings::default()
+            });
+            wait_until_idle(&mut library);
+
+            let files: Vec<Option<Vec<u8>>> = library.podcasts()[0]
+                .episodes
+                .iter()
+                .map(|episode| episode.file.as_ref().map(|file| fs::read(file).unwrap()))
+                .collect();
+            assert_eq!(
+                files,
+                vec![Some(b"audio".to_vec()), Some(b"audio".to_vec()), None]
+            );
+        }
+    }
+
+    mod download_path {
+        use super::*;
+
+        #[test]
+        fn test_file_name() {
+            let library = PodcastLibrary {
+                cache_dir: Some(PathBuf::from("cache")),
+                ..PodcastLibrary::default()
+            };
+            let episode = Episode {
+                title: "Episode 1: Hello, World!".to_string(),
+                audio_url: "http://podcast.example/1.ogg?source=feed".to_string(),
+                ..episode("1")
+            };
+
+            let path = library.download_path("feed", &episode).unwrap();
+            let file_name = path.file_name().unwrap().to_string_lossy();
+
+            assert!(file_name.starts_with("episode-1-hello-world-"));
+            assert!(file_name.ends_with(".ogg"));
+            assert_eq!(path, library.download_path("feed", &episode).unwrap());
+        }
+
+        #[test]
+        fn test_no_cache_dir() {
+            let library = PodcastLibrary::default();
+
+            assert_eq!(library.download_path("feed", &episode("1")), None);
+        }
+    }
+}
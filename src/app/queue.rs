@@ -0,0 +1,688 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::app::playlist;
+use crate::app::stream::{self, StreamInfo};
+
+/// The extensions of the audio files that can be opened, and that are queued when a folder is added.
+pub(crate) const AUDIO_EXTENSIONS: [&str; 10] = [
+    "mp3", "flac", "ogg", "opus", "wav", "m4a", "aac", "mp4", "aif", "aiff",
+];
+
+/// A track in the play queue.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct QueuedTrack {
+    /// The path of the file, or the URL of an internet stream
+    pub(crate) path: PathBuf,
+
+    /// How long the track is, which isn't known for internet streams
+    pub(crate) length: Option<Duration>,
+
+    /// What the station is playing, if the track is an internet stream
+    pub(crate) stream: Option<StreamInfo>,
+
+    /// Where the track was skipped, once the queue has moved on from it. Tracks that played to their end have no position
+    pub(crate) skipped_at: Option<Duration>,
+}
+
+/// The tracks that have been and will be played, in order.
+/// Tracks that have finished stay in the queue, so that it can be shown as a whole.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Queue {
+    tracks: Vec<QueuedTrack>,
+
+    /// The index of the track that is playing
+    current: usize,
+
+    /// How many tracks have ever been added, including the ones that were removed since
+    added: usize,
+
+    /// How many tracks the queue has moved on by. Removing tracks changes the index of the current one, but not this
+    advanced: usize,
+
+    /// Whether the queue last moved back to the track before, rather than on to a later one
+    went_back: bool,
+}
+
+impl Queue {
+    /// Create a queue that starts with `first`.
+    pub(crate) fn new(first: QueuedTrack) -> Queue {
+        Queue {
+            tracks: vec![first],
+            current: 0,
+            added: 1,
+            advanced: 0,
+            went_back: false,
+        }
+    }
+
+    /// Replace every track with `first`, which starts playing straight away.
+    pub(crate) fn replace(&mut self, first: QueuedTrack) {
+        self.tracks = vec![first];
+        self.current = 0;
+        self.added += 1;
+        self.advanced += 1;
+        self.went_back = false;
+    }
+
+    /// Add a track to the end of the queue.
+    pub(crate) fn push(&mut self, track: QueuedTrack) {
+        self.tracks.push(track);
+        self.added += 1;
+    }
+
+    /// Return the track that is playing.
+    pub(crate) fn current(&self) -> &QueuedTrack {
+        &self.tracks[self.current]
+    }
+
+    pub(crate) fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Return the tracks that will play after the current one.
+    pub(crate) fn upcoming(&self) -> &[QueuedTrack] {
+        &self.tracks[self.current + 1..]
+    }
+
+    /// Return every track in the queue, including the ones that already finished.
+    pub(crate) fn tracks(&self) -> &[QueuedTrack] {
+        &self.tracks
+    }
+
+    /// Return how many tracks have ever been added to the queue. The tracks that were added last are always at the end,
+    /// until they are moved.
+    pub(crate) fn added(&self) -> usize {
+        self.added
+    }
+
+    /// Return how many tracks the queue has moved on by, which changes whenever a different track starts.
+    pub(crate) fn advanced(&self) -> usize {
+        self.advanced
+    }
+
+    /// Return whether the queue last moved back to the track before, in which case the track it left is the one after
+    /// the current one.
+    pub(crate) fn went_back(&self) -> bool {
+        self.went_back
+    }
+
+    /// Fill in the length of the track at `index`, once it has been worked out.
+    pub(crate) fn set_length(&mut self, index: usize, length: Duration) {
+        if let Some(track) = self.tracks.get_mut(index) {
+            track.length = Some(length);
+        }
+    }
+
+    /// Remember where the current track was skipped. Only the first skip counts, since the track may still be playing
+    /// when another one is asked for.
+    pub(crate) fn skip_current(&mut self, position: Duration) {
+        self.tracks[self.current].skipped_at.get_or_insert(position);
+    }
+
+    /// Move the track at `from` to `to`, shifting the tracks in between. Only tracks that haven't started yet can be moved,
+    /// and only to places that haven't been played yet. Returns `false` if the track can't be moved.
+    pub(crate) fn move_track(&mut self, from: usize, to: usize) -> bool {
+        let upcoming = self.current + 1..self.tracks.len();
+        if !upcoming.contains(&from) || !upcoming.contains(&to) {
+            return false;
+        }
+
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+
+        true
+    }
+
+    /// Remove the track at `index`, which can't be the one that is playing. Returns `false` if there is no such track.
+    pub(crate) fn remove(&mut self, index: usize) -> bool {
+        if index == self.current || index >= self.tracks.len() {
+            return false;
+        }
+
+        self.tracks.remove(index);
+        if index < self.current {
+            self.current -= 1;
+        }
+
+        true
+    }
+
+    /// Remove the tracks that have already been played. Returns `false` if nothing has been played yet.
+    pub(crate) fn clear_played(&mut self) -> bool {
+        if self.current == 0 {
+            return false;
+        }
+
+        self.tracks.drain(..self.current);
+        self.current = 0;
+
+        true
+    }
+
+    /// Move on by `count` tracks, without going past the last one.
+    pub(crate) fn advance(&mut self, count: usize) {
+        let current = (self.current + count).min(self.tracks.len() - 1);
+        if current == self.current {
+            return;
+        }
+
+        self.advanced += current - self.current;
+        self.current = current;
+        self.went_back = false;
+
+        // The track may have been skipped before the queue went back past it, but it plays from the start this time
+        self.tracks[current].skipped_at = None;
+    }
+
+    /// Go back to the track before the current one, which plays again from the start, followed by the current one.
+    /// Returns `false` if nothing was played before the current track.
+    pub(crate) fn go_back(&mut self) -> bool {
+        if self.current == 0 {
+            return false;
+        }
+
+        self.current -= 1;
+        self.advanced += 1;
+        self.went_back = true;
+        self.tracks[self.current].skipped_at = None;
+
+        true
+    }
+
+    /// Return how many tracks have finished since the queue last advanced, given how many sources the sink has left.
+    /// Once the sink runs out, the last track stays the current one.
+    pub(crate) fn finished_tracks(&self, sources_left: usize) -> usize {
+        (self.upcoming().len() + 1).saturating_sub(sources_left.max(1))
+    }
+}
+
+/// Return the tracks to queue from what was dropped onto the window, which is a path or a `file://` URL on each line.
+pub(crate) fn dropped_paths(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .flat_map(|line| tracks_to_open(&location_path(line)))
+        .collect()
+}
+
+/// Return the tracks that opening `path` plays: the audio files in a folder, the entries of a playlist, or else the track itself.
+/// Internet streams are kept as they are.
+pub(crate) fn tracks_to_open(path: &Path) -> Vec<PathBuf> {
+    if stream::is_stream(path) {
+        return vec![path.to_path_buf()];
+    }
+
+    if playlist::is_playlist(path) {
+        return playlist::read(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Vec::new()
+        });
+    }
+
+    audio_files_in(path)
+}
+
+/// Turn a path or a `file://` URL into a path.
+pub(crate) fn location_path(location: &str) -> PathBuf {
+    match location.strip_prefix("file://") {
+        Some(url) => PathBuf::from(decode_url_path(url)),
+        None => PathBuf::from(location),
+    }
+}
+
+/// Return the audio files in `path` and the folders below it, by name. A file is returned on its own, whatever it is.
+pub(crate) fn audio_files_in(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut entries: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+
+    entries
+        .into_iter()
+        .flat_map(|entry| {
+            if entry.is_dir() {
+                audio_files_in(&entry)
+            } else if is_audio_file(&entry) {
+                vec![entry]
+            } else {
+                Vec::new()
+            }
+        })
+        .collect()
+}
+
+/// Return whether `path` has the extension of an audio file that can be opened.
+pub(crate) fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            AUDIO_EXTENSIONS
+                .iter()
+                .any(|audio| audio.eq_ignore_ascii_case(extension))
+        })
+}
+
+/// Undo the escaping of a `file://` URL, leaving anything that isn't a valid escape as it is.
+/// File managers may also put a host before the path, which is always the local machine.
+fn decode_url_path(url: &str) -> String {
+    let url = match url.find('/') {
+        Some(start) => &url[start..],
+        None => url,
+    };
+
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+
+    fn track(name: &str) -> QueuedTrack {
+        QueuedTrack {
+            path: PathBuf::from(name),
+            length: Some(Duration::from_secs(60)),
+            stream: None,
+            skipped_at: None,
+        }
+    }
+
+    mod replace {
+        use super::*;
+
+        #[test]
+        fn test_new_track() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.replace(track("c.mp3"));
+
+            assert_eq!(queue.tracks(), &[track("c.mp3")]);
+            assert_eq!(queue.current_index(), 0);
+            assert_eq!(queue.added(), 3);
+            assert_eq!(queue.advanced(), 1);
+        }
+    }
+
+    mod push {
+        use super::*;
+
+        #[test]
+        fn test_upcoming() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.push(track("c.mp3"));
+
+            assert_eq!(queue.current(), &track("a.mp3"));
+            assert_eq!(queue.upcoming(), &[track("b.mp3"), track("c.mp3")]);
+        }
+    }
+
+    mod advance {
+        use super::*;
+
+        #[test]
+        fn test_next_track() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.advance(1);
+
+            assert_eq!(queue.current(), &track("b.mp3"));
+            assert_eq!(queue.current_index(), 1);
+            assert!(queue.upcoming().is_empty());
+            assert_eq!(queue.tracks().len(), 2);
+        }
+
+        #[test]
+        fn test_past_end() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.advance(5);
+
+            assert_eq!(queue.current(), &track("b.mp3"));
+            assert_eq!(queue.advanced(), 1);
+        }
+    }
+
+    mod go_back {
+        use super::*;
+
+        #[test]
+        fn test_previous_track() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.skip_current(Duration::from_secs(20));
+            queue.advance(1);
+            queue.skip_current(Duration::from_secs(5));
+
+            assert!(queue.go_back());
+            assert_eq!(queue.current(), &track("a.mp3"));
+            assert_eq!(queue.upcoming()[0].skipped_at, Some(Duration::from_secs(5)));
+            assert_eq!(queue.advanced(), 2);
+            assert!(queue.went_back());
+
+            // The track that was left plays from the start once the queue gets back to it
+            queue.advance(1);
+            assert_eq!(queue.current(), &track("b.mp3"));
+            assert!(!queue.went_back());
+        }
+
+        #[test]
+        fn test_first_track() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+
+            assert!(!queue.go_back());
+            assert_eq!(queue.current_index(), 0);
+            assert_eq!(queue.advanced(), 0);
+        }
+    }
+
+    mod skip_current {
+        use super::*;
+
+        #[test]
+        fn test_first_skip_counts() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.skip_current(Duration::from_secs(20));
+            queue.skip_current(Duration::from_secs(0));
+
+            assert_eq!(queue.current().skipped_at, Some(Duration::from_secs(20)));
+            assert_eq!(queue.upcoming()[0].skipped_at, None);
+        }
+    }
+
+    mod set_length {
+        use super::*;
+
+        #[test]
+        fn test_unknown_length() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(QueuedTrack {
+                length: None,
+                ..track("b.mp3")
+            });
+            queue.set_length(1, Duration::from_secs(90));
+
+            assert_eq!(queue.tracks()[1].length, Some(Duration::from_secs(90)));
+        }
+
+        #[test]
+        fn test_out_of_range() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.set_length(3, Duration::from_secs(90));
+
+            assert_eq!(queue.tracks(), &[track("a.mp3")]);
+        }
+    }
+
+    mod move_track {
+        use super::*;
+
+        fn queue(names: &[&str]) -> Queue {
+            let mut queue = Queue::new(track(names[0]));
+            for name in &names[1..] {
+                queue.push(track(name));
+            }
+            queue
+        }
+
+        #[test]
+        fn test_play_next() {
+            let mut queue = queue(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+
+            assert!(queue.move_track(3, 1));
+            assert_eq!(
+                queue.upcoming(),
+                &[track("d.mp3"), track("b.mp3"), track("c.mp3")]
+            );
+        }
+
+        #[test]
+        fn test_move_to_end() {
+            let mut queue = queue(&["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+
+            assert!(queue.move_track(1, 3));
+            assert_eq!(
+                queue.upcoming(),
+                &[track("c.mp3"), track("d.mp3"), track("b.mp3")]
+            );
+        }
+
+        #[test]
+        fn test_played_tracks_stay() {
+            let mut queue = queue(&["a.mp3", "b.mp3", "c.mp3"]);
+            queue.advance(1);
+
+            assert!(!queue.move_track(0, 2));
+            assert!(!queue.move_track(2, 0));
+            assert!(!queue.move_track(1, 2));
+            assert!(!queue.move_track(2, 5));
+            assert_eq!(
+                queue.tracks(),
+                self::queue(&["a.mp3", "b.mp3", "c.mp3"]).tracks()
+            );
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn test_upcoming() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.push(track("c.mp3"));
+
+            assert!(queue.remove(1));
+            assert_eq!(queue.upcoming(), &[track("c.mp3")]);
+        }
+
+        #[test]
+        fn test_played() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.advance(1);
+
+            assert!(queue.remove(0));
+            assert_eq!(queue.current(), &track("b.mp3"));
+            assert_eq!(queue.current_index(), 0);
+        }
+
+        #[test]
+        fn test_current() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+
+            assert!(!queue.remove(0));
+            assert!(!queue.remove(2));
+            assert_eq!(queue.tracks().len(), 2);
+        }
+    }
+
+    mod clear_played {
+        use super::*;
+
+        #[test]
+        fn test_played() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.push(track("c.mp3"));
+            queue.advance(2);
+
+            assert!(queue.clear_played());
+            assert_eq!(queue.tracks(), &[track("c.mp3")]);
+            assert_eq!(queue.current(), &track("c.mp3"));
+            assert_eq!(queue.advanced(), 2);
+        }
+
+        #[test]
+        fn test_nothing_played() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+
+            assert!(!queue.clear_played());
+            assert_eq!(queue.tracks().len(), 2);
+        }
+    }
+
+    mod dropped_paths {
+        use super::*;
+
+        #[test]
+        fn test_file_urls() {
+            assert_eq!(
+                dropped_paths(
+                    "file:///music/my%20track%231.mp3\r\nfile://localhost/music/b.flac\n"
+                ),
+                vec![
+                    PathBuf::from("/music/my track#1.mp3"),
+                    PathBuf::from("/music/b.flac")
+                ]
+            );
+        }
+
+        #[test]
+        fn test_plain_paths_and_streams() {
+            assert_eq!(
+                dropped_paths("/music/a.mp3\nhttps://radio.example/stream"),
+                vec![
+                    PathBuf::from("/music/a.mp3"),
+                    PathBuf::from("https://radio.example/stream")
+                ]
+            );
+        }
+
+        #[test]
+        fn test_invalid_escape() {
+            assert_eq!(
+                dropped_paths("file:///music/100%.mp3"),
+                vec![PathBuf::from("/music/100%.mp3")]
+            );
+        }
+    }
+
+    mod tracks_to_open {
+        use super::*;
+
+        #[test]
+        fn test_playlist() {
+            let path = env::temp_dir().join("audio_player_queue_test_playlist.m3u");
+            fs::write(&path, "/music/a.mp3\n/music/b.mp3\n").unwrap();
+
+            assert_eq!(
+                tracks_to_open(&path),
+                vec![PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")]
+            );
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_stream() {
+            assert_eq!(
+                tracks_to_open(Path::new("https://radio.example/stream.m3u")),
+                vec![PathBuf::from("https://radio.example/stream.m3u")]
+            );
+        }
+    }
+
+    mod audio_files_in {
+        use super::*;
+
+        #[test]
+        fn test_folder() {
+            let folder = env::temp_dir().join("audio_player_queue_test_folder");
+            let _ = fs::remove_dir_all(&folder);
+            fs::create_dir_all(folder.join("disc 2")).unwrap();
+            for name in ["b.MP3", "a.flac", "cover.jpg", "disc 2/c.ogg"] {
+                fs::write(folder.join(name), b"").unwrap();
+            }
+
+            assert_eq!(
+                audio_files_in(&folder),
+                vec![
+                    folder.join("a.flac"),
+                    folder.join("b.MP3"),
+                    folder.join("disc 2/c.ogg")
+                ]
+            );
+
+            fs::remove_dir_all(&folder).unwrap();
+        }
+
+        #[test]
+        fn test_file() {
+            assert_eq!(
+                audio_files_in(Path::new("/music/a.mp3")),
+                vec![PathBuf::from("/music/a.mp3")]
+            );
+        }
+    }
+
+    mod finished_tracks {
+        use super::*;
+
+        #[test]
+        fn test_still_playing() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+
+            assert_eq!(queue.finished_tracks(2), 0);
+        }
+
+        #[test]
+        fn test_one_finished() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+            queue.push(track("c.mp3"));
+
+            assert_eq!(queue.finished_tracks(2), 1);
+        }
+
+        #[test]
+        fn test_sink_ran_out() {
+            let mut queue = Queue::new(track("a.mp3"));
+            queue.push(track("b.mp3"));
+
+            // The last track stays current, so only the first one counts
+            assert_eq!(queue.finished_tracks(0), 1);
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Sink, Source};
+
+use crate::app::visualizer::{SampleTap, Tapped};
+
+/// The tracks after the current one, as the sink will play them.
+/// A sink can't reorder or remove what was appended to it, so every track is appended in a slot, whose source can be
+/// swapped for another one until the sink reaches it. Removing a track leaves an empty slot at the end, which the sink
+/// goes straight past, and which the next track that is added fills in.
+pub(crate) struct QueueSlots {
+    /// The slots of the tracks after the current one, in order, followed by the slots that were emptied
+    slots: Vec<Arc<Mutex<SlotContents>>>,
+
+    /// How many of the slots at the end are empty
+    empty: usize,
+
+    /// The tap that every track is played through, for the visualizer
+    tap: Arc<SampleTap>,
+}
+
+enum SlotContents {
+    /// The track that the slot will play, once the sink reaches it
+    Waiting(Box<dyn Source + Send>),
+
+    /// The track was removed, so the slot plays nothing
+    Empty,
+
+    /// The sink has reached the slot, so it can't be changed anymore
+    Started,
+}
+
+impl QueueSlots {
+    pub(crate) fn new(tap: Arc<SampleTap>) -> QueueSlots {
+        QueueSlots {
+            slots: Vec::new(),
+            empty: 0,
+            tap,
+        }
+    }
+
+    /// Play `source` after every other track.
+    pub(crate) fn push(&mut self, source: Box<dyn Source + Send>, sink: &Sink) {
+        // Once the sink runs out, any empty slots are gone too
+        if sink.empty() {
+            self.forget_all();
+        }
+
+        if self.empty > 0 {
+            let index = self.slots.len() - self.empty;
+            let mut contents = self.slots[index].lock().unwrap();
+
+            // The sink may have just gone past the empty slots, in which case the track needs a slot of its own
+            if matches!(*contents, SlotContents::Empty) {
+                *contents = SlotContents::Waiting(source);
+                drop(contents);
+                self.empty -= 1;
+                return;
+            }
+
+            drop(contents);
+            self.forget_all();
+        }
+
+        let contents = Arc::new(Mutex::new(SlotContents::Waiting(source)));
+        sink.append(Tapped::new(
+            Slot::new(Arc::clone(&contents)),
+            Arc::clone(&self.tap),
+        ));
+        self.slots.push(contents);
+    }
+
+    /// Move the track `from` places after the current one to `to` places after it.
+    /// Returns `false` if the sink reached one of the tracks in the meantime.
+    pub(crate) fn move_track(&mut self, from: usize, to: usize) -> bool {
+        self.rearrange(|sources| {
+            if from >= sources.len() || to >= sources.len() {
+                return false;
+            }
+
+            let source = sources.remove(from);
+            sources.insert(to, source);
+            true
+        })
+    }
+
+    /// Remove the track `index` places after the current one.
+    /// Returns `false` if the sink reached one of the tracks in the meantime.
+    pub(crate) fn remove(&mut self, index: usize) -> bool {
+        self.rearrange(|sources| {
+            if index >= sources.len() {
+                return false;
+            }
+
+            sources.remove(index);
+            true
+        })
+    }
+
+    /// Take the tracks after the current one out of their slots, in order, so that they can be played again after something
+    /// else. Returns `None` if the sink reached one of the tracks in the meantime.
+    pub(crate) fn take_upcoming(&mut self, sink: &Sink) -> Option<Vec<Box<dyn Source + Send>>> {
+        if sink.empty() {
+            self.forget_all();
+        }
+
+        let mut upcoming = None;
+        self.rearrange(|sources| {
+            upcoming = Some(mem::take(sources));
+            true
+        });
+
+        upcoming
+    }
+
+    /// Forget the slots of the `count` tracks that the sink has moved on to.
+    pub(crate) fn advance(&mut self, count: usize) {
+        let count = count.min(self.slots.len());
+        self.slots.drain(..count);
+        self.empty = self.empty.min(self.slots.len());
+    }
+
+    /// Return how many sources the sink has left, not counting the empty slots.
+    pub(crate) fn sources_left(&mut self, sink: &Sink) -> usize {
+        if sink.empty() {
+            self.forget_all();
+        }
+
+        sink.len().saturating_sub(self.empty)
+    }
+
+    /// Take the tracks out of their slots, change them with `change`, and put them back in order.
+    /// Nothing changes if `change` returns `false`, or if the sink has reached one of the slots.
+    fn rearrange<F>(&mut self, change: F) -> bool
+    where
+        F: FnOnce(&mut Vec<Box<dyn Source + Send>>) -> bool,
+    {
+        // Hold on to every slot, so that the sink can't start any of them halfway through
+        let mut slots: Vec<_> = self.slots.iter().map(|slot| slot.lock().unwrap()).collect();
+        let filled = slots.len() - self.empty;
+        if slots[..filled]
+            .iter()
+            .any(|contents| !matches!(**contents, SlotContents::Waiting(_)))
+        {
+            return false;
+        }
+
+        let mut sources: Vec<_> = slots[..filled]
+            .iter_mut()
+            .filter_map(
+                |contents| match mem::replace(&mut **contents, SlotContents::Empty) {
+                    SlotContents::Waiting(source) => Some(source),
+                    _ => None,
+                },
+            )
+            .collect();
+
+        let changed = change(&mut sources);
+        let filled = sources.len();
+
+        let mut sources = sources.into_iter();
+        for contents in slots.iter_mut() {
+            if let Some(source) = sources.next() {
+                **contents = SlotContents::Waiting(source);
+            }
+        }
+        drop(slots);
+
+        self.empty = self.slots.len() - filled;
+
+        changed
+    }
+
+    fn forget_all(&mut self) {
+        self.slots.clear();
+        self.empty = 0;
+    }
+}
+
+/// A source that plays whatever is in its slot once the sink reaches it.
+struct Slot {
+    contents: Arc<Mutex<SlotContents>>,
+
+    /// The track that is playing, once the slot has started
+    source: Option<Box<dyn Source + Send>>,
+    started: bool,
+}
+
+impl Slot {
+    /// What an empty slot reports about itself. It ends straight away, so its format doesn't matter.
+    const EMPTY_CHANNELS: ChannelCount = 2;
+    const EMPTY_SAMPLE_RATE: SampleRate = 44100;
+
+    fn new(contents: Arc<Mutex<SlotContents>>) -> Slot {
+        Slot {
+            contents,
+            source: None,
+            started: false,
+        }
+    }
+
+    /// Take the track out of the slot, so that it can't be changed anymore.
+    fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
+        let mut contents = self.contents.lock().unwrap();
+        if let SlotContents::Waiting(source) = mem::replace(&mut *contents, SlotContents::Started) {
+            self.source = Some(source);
+        }
+    }
+
+    /// Ask the track about itself, or return `empty` if there is no track.
+    fn query<R>(&self, f: impl FnOnce(&dyn Source) -> R, empty: R) -> R {
+        if let Some(source) = self.source.as_ref() {
+            return f(source.as_ref());
+        }
+        if self.started {
+            return empty;
+        }
+
+        match &*self.contents.lock().unwrap() {
+            SlotContents::Waiting(source) => f(source.as_ref()),
+            _ => empty,
+        }
+    }
+}
+
+impl Iterator for Slot {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.start();
+        self.source.as_mut()?.next()
+    }
+}
+
+impl Source for Slot {
+    fn current_span_len(&self) -> Option<usize> {
+        self.query(|source| source.current_span_len(), None)
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.query(|source| source.channels(), Slot::EMPTY_CHANNELS)
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.query(|source| source.sample_rate(), Slot::EMPTY_SAMPLE_RATE)
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.query(|source| source.total_duration(), None)
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.start();
+        match self.source.as_mut() {
+            Some(source) => source.try_seek(pos),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::queue::SourcesQueueOutput;
+
+    /// A track whose samples are all `value`, so that the order they play in can be told apart.
+    fn track(value: f32) -> Box<dyn Source + Send> {
+        Box::new(SamplesBuffer::new(1, 44100, vec![value; 4]))
+    }
+
+    fn slots_with(values: &[f32]) -> (QueueSlots, Sink, SourcesQueueOutput) {
+        let (sink, output) = Sink::new();
+        let mut slots = QueueSlots::new(Arc::new(SampleTap::new()));
+        for value in values {
+            slots.push(track(*value), &sink);
+        }
+
+        (slots, sink, output)
+    }
+
+    /// Return which tracks play, in order.
+    fn played(output: SourcesQueueOutput, tracks: usize) -> Vec<f32> {
+        let mut played: Vec<f32> = output.take(tracks * 4).collect();
+        played.dedup();
+        played
+    }
+
+    mod move_track {
+        use super::*;
+
+        #[test]
+        fn test_reorder() {
+            let (mut slots, _sink, output) = slots_with(&[1.0, 2.0, 3.0]);
+
+            assert!(slots.move_track(2, 0));
+            assert_eq!(played(output, 3), vec![3.0, 1.0, 2.0]);
+        }
+
+        #[test]
+        fn test_out_of_range() {
+            let (mut slots, _sink, output) = slots_with(&[1.0, 2.0]);
+
+            assert!(!slots.move_track(0, 2));
+            assert_eq!(played(output, 2), vec![1.0, 2.0]);
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn test_empty_slot() {
+            let (mut slots, sink, output) = slots_with(&[1.0, 2.0, 3.0]);
+
+            assert!(slots.remove(1));
+            assert_eq!(slots.sources_left(&sink), 2);
+            assert_eq!(played(output, 2), vec![1.0, 3.0]);
+        }
+
+        #[test]
+        fn test_refilled() {
+            let (mut slots, sink, output) = slots_with(&[1.0, 2.0, 3.0]);
+
+            assert!(slots.remove(0));
+            slots.push(track(4.0), &sink);
+
+            assert_eq!(slots.sources_left(&sink), 3);
+            assert_eq!(played(output, 3), vec![2.0, 3.0, 4.0]);
+        }
+    }
+
+    mod take_upcoming {
+        use super::*;
+
+        #[test]
+        fn test_played_again() {
+            let (mut slots, sink, output) = slots_with(&[1.0, 2.0]);
+
+            let upcoming = slots.take_upcoming(&sink).unwrap();
+            assert_eq!(slots.sources_left(&sink), 0);
+
+            // The taken tracks can be played after a different one
+            slots.push(track(3.0), &sink);
+            for source in upcoming {
+                slots.push(source, &sink);
+            }
+            assert_eq!(played(output, 3), vec![3.0, 1.0, 2.0]);
+        }
+
+        #[test]
+        fn test_started_slot() {
+            let (mut slots, sink, mut output) = slots_with(&[1.0, 2.0]);
+            output.next();
+
+            assert!(slots.take_upcoming(&sink).is_none());
+        }
+    }
+
+    mod advance {
+        use super::*;
+
+        #[test]
+        fn test_started_slot() {
+            let (mut slots, _sink, mut output) = slots_with(&[1.0, 2.0]);
+
+            // Start playing the first track, which can't be moved anymore
+            output.next();
+            assert!(!slots.move_track(0, 1));
+
+            slots.advance(1);
+            assert!(slots.remove(0));
+        }
+    }
+}
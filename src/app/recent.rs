@@ -0,0 +1,258 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::app::stream;
+
+/// The files, folders and playlists that were opened last, newest first, which are saved between runs.
+#[derive(Debug, Default)]
+pub(crate) struct RecentFiles {
+    /// Where the list is saved. If this is `None`, the list only lives in memory.
+    path: Option<PathBuf>,
+
+    items: Vec<PathBuf>,
+
+    /// An item that was opened along with its tracks, which is only added once one of them starts playing
+    pending: Option<(PathBuf, Vec<PathBuf>)>,
+}
+
+impl RecentFiles {
+    const FILE_NAME: &str = "recent.json";
+
+    /// How many items are remembered. Older ones are forgotten.
+    const MAX_ITEMS: usize = 20;
+
+    /// Load the list from the user's config directory.
+    /// If the list can't be read, start with an empty one so that the player still works.
+    pub(crate) fn load() -> RecentFiles {
+        let Some(path) =
+            dirs::config_dir().map(|dir| dir.join("audio_player").join(Self::FILE_NAME))
+        else {
+            eprintln!("Unable to find the config directory, recent files will not be saved");
+            return RecentFiles::default();
+        };
+
+        RecentFiles::load_from(path)
+    }
+
+    /// Load the list from a specific file. A missing or invalid file results in an empty list.
+    pub(crate) fn load_from(path: PathBuf) -> RecentFiles {
+        let items = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Unable to parse {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+
+        RecentFiles {
+            path: Some(path),
+            items,
+            pending: None,
+        }
+    }
+
+    /// Return the items, newest first.
+    pub(crate) fn items(&self) -> &[PathBuf] {
+        &self.items
+    }
+
+    /// Put `opened` at the top of the list and save it. The path is made absolute where possible,
+    /// so that it can be opened again no matter which directory the player was started from.
+    pub(crate) fn add(&mut self, opened: &Path) {
+        let opened = if stream::is_stream(opened) {
+            opened.to_path_buf()
+        } else {
+            fs::canonicalize(opened).unwrap_or_else(|_| opened.to_path_buf())
+        };
+
+        self.items.retain(|item| *item != opened);
+        self.items.insert(0, opened);
+        self.items.truncate(RecentFiles::MAX_ITEMS);
+
+        self.save_or_warn();
+    }
+
+    /// Add `opened` once one of its `tracks` starts playing, since it may turn out that none of them can be played.
+    /// Only the item that was opened last is waited for.
+    pub(crate) fn add_once_playing(&mut self, opened: &Path, tracks: Vec<PathBuf>) {
+        self.pending = Some((opened.to_path_buf(), tracks));
+    }
+
+    /// Add the item that is waited for, if `track` is one of its tracks.
+    pub(crate) fn started_playing(&mut self, track: &Path) {
+        if let Some((opened, _)) = self
+            .pending
+            .take_if(|(_, tracks)| tracks.iter().any(|pending| pending == track))
+        {
+            self.add(&opened);
+        }
+    }
+
+    /// Forget an item, such as one that no longer exists.
+    pub(crate) fn remove(&mut self, item: &Path) {
+        self.items.retain(|kept| kept != item);
+        self.save_or_warn();
+    }
+
+    /// Forget every item.
+    pub(crate) fn clear(&mut self) {
+        self.items.clear();
+        self.save_or_warn();
+    }
+
+    fn save_or_warn(&self) {
+        if let Err(e) = self.save() {
+            eprintln!("Unable to save recent files: {}", e);
+        }
+    }
+
+    /// Write the list to disk.
+    /// # Errors
+    /// - If the directory of the list could not be created
+    /// - If the list could not be written
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.items).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_list_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("audio_player_recent_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    mod add {
+        use super::*;
+
+        #[test]
+        fn test_newest_first() {
+            let mut recent = RecentFiles::default();
+            recent.add(Path::new("/music/a.mp3"));
+            recent.add(Path::new("/music/b.mp3"));
+            recent.add(Path::new("/music/a.mp3"));
+
+            assert_eq!(
+                recent.items(),
+                &[PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b.mp3")]
+            );
+        }
+
+        #[test]
+        fn test_oldest_forgotten() {
+            let mut recent = RecentFiles::default();
+            for i in 0..=RecentFiles::MAX_ITEMS {
+                recent.add(&PathBuf::from(format!("/music/{}.mp3", i)));
+            }
+
+            assert_eq!(recent.items().len(), RecentFiles::MAX_ITEMS);
+            assert_eq!(
+                recent.items()[0],
+                PathBuf::from(format!("/music/{}.mp3", RecentFiles::MAX_ITEMS))
+            );
+            assert!(!recent.items().contains(&PathBuf::from("/music/0.mp3")));
+        }
+
+        #[test]
+        fn test_saved_between_loads() {
+            let path = temp_list_path("saved_between_loads.json");
+
+            let mut recent = RecentFiles::load_from(path.clone());
+            recent.add(Path::new("https://radio.example/stream"));
+
+            let recent = RecentFiles::load_from(path.clone());
+            assert_eq!(
+                recent.items(),
+                &[PathBuf::from("https://radio.example/stream")]
+            );
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod add_once_playing {
+        use super::*;
+
+        #[test]
+        fn test_added_once_playing() {
+            let mut recent = RecentFiles::default();
+            recent.add_once_playing(
+                Path::new("/music/album"),
+                vec![
+                    PathBuf::from("/music/album/1.mp3"),
+                    PathBuf::from("/music/album/2.mp3"),
+                ],
+            );
+            assert!(recent.items().is_empty());
+
+            recent.started_playing(Path::new("/music/other.mp3"));
+            assert!(recent.items().is_empty());
+
+            recent.started_playing(Path::new("/music/album/2.mp3"));
+            assert_eq!(recent.items(), &[PathBuf::from("/music/album")]);
+        }
+    }
+
+    mod remove {
+        use super::*;
+
+        #[test]
+        fn test_others_are_kept() {
+            let mut recent = RecentFiles::default();
+            recent.add(Path::new("/music/a.mp3"));
+            recent.add(Path::new("/music/b.mp3"));
+            recent.remove(Path::new("/music/a.mp3"));
+
+            assert_eq!(recent.items(), &[PathBuf::from("/music/b.mp3")]);
+        }
+    }
+
+    mod clear {
+        use super::*;
+
+        #[test]
+        fn test_saved() {
+            let path = temp_list_path("cleared.json");
+
+            let mut recent = RecentFiles::load_from(path.clone());
+            recent.add(Path::new("/music/a.mp3"));
+            recent.clear();
+
+            assert!(RecentFiles::load_from(path.clone()).items().is_empty());
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod load_from {
+        use super::*;
+
+        #[test]
+        fn test_invalid_file() {
+            let path = temp_list_path("invalid_file.json");
+            fs::write(&path, "not json").unwrap();
+
+            assert!(RecentFiles::load_from(path.clone()).items().is_empty());
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
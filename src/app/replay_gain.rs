@@ -0,0 +1,197 @@
+use std::path::Path;
+
+use lofty::config::ParseOptions;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::{ItemKey, Tag};
+use serde::Deserialize;
+
+/// Which ReplayGain tags even out the volume of the tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReplayGainMode {
+    /// Tracks are played as loud as they are
+    #[default]
+    Off,
+
+    /// Every track is played equally loud
+    Track,
+
+    /// Every album is played equally loud, keeping the differences between its tracks.
+    /// Tracks without an album gain use their track gain
+    Album,
+}
+
+/// How the volume of each track is evened out, using the ReplayGain tags of its file.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct ReplayGain {
+    pub(crate) mode: ReplayGainMode,
+
+    /// How many decibels are added on top of the gain, since the gain makes most tracks quieter
+    pub(crate) preamp: f32,
+}
+
+impl ReplayGain {
+    /// Return how much to amplify the file at `path` by, or `None` if it is played as loud as it is.
+    pub(crate) fn factor(&self, path: &Path) -> Option<f32> {
+        if self.mode == ReplayGainMode::Off {
+            return None;
+        }
+
+        // Only the tags are needed, so the audio properties don't need to be read
+        let file = Probe::open(path)
+            .and_then(|probe| {
+                probe
+                    .options(ParseOptions::new().read_properties(false))
+                    .read()
+            })
+            .ok()?;
+        let tag = file.primary_tag().or_else(|| file.first_tag())?;
+
+        self.factor_from_tag(tag)
+    }
+
+    /// Return how much to amplify a track with `tag` by, or `None` if the tag has no gain.
+    fn factor_from_tag(&self, tag: &Tag) -> Option<f32> {
+        let value = |key: ItemKey| tag.get_string(&key).and_then(parse_tag_value);
+
+        let track =
+            value(ItemKey::ReplayGainTrackGain).map(|gain| (gain, ItemKey::ReplayGainTrackPeak));
+        let album =
+            value(ItemKey::ReplayGainAlbumGain).map(|gain| (gain, ItemKey::ReplayGainAlbumPeak));
+        let (gain, peak_key) = match self.mode {
+            ReplayGainMode::Off => None,
+            ReplayGainMode::Track => track,
+            ReplayGainMode::Album => album.or(track),
+        }?;
+
+        Some(self.gain_factor(gain, value(peak_key)))
+    }
+
+    /// Turn a gain in decibels into a factor, without letting the loudest sample of the track clip.
+    fn gain_factor(&self, gain: f32, peak: Option<f32>) -> f32 {
+        let factor = 10_f32.powf((gain + self.preamp) / 20.0);
+
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parse a gain such as "-6.48 dB", or a peak such as "0.988".
+fn parse_tag_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+
+    number.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use lofty::tag::TagType;
+
+    fn tag(items: &[(ItemKey, &str)]) -> Tag {
+        let mut tag = Tag::new(TagType::VorbisComments);
+        for (key, value) in items {
+            tag.insert_text(key.clone(), value.to_string());
+        }
+        tag
+    }
+
+    fn replay_gain(mode: ReplayGainMode) -> ReplayGain {
+        ReplayGain { mode, preamp: 0.0 }
+    }
+
+    mod parse_tag_value {
+        use super::*;
+
+        #[test]
+        fn test_gain() {
+            assert_eq!(parse_tag_value("-6.5 dB"), Some(-6.5));
+            assert_eq!(parse_tag_value("+2.00dB"), Some(2.0));
+        }
+
+        #[test]
+        fn test_peak() {
+            assert_eq!(parse_tag_value("0.988"), Some(0.988));
+        }
+
+        #[test]
+        fn test_invalid() {
+            assert_eq!(parse_tag_value("loud"), None);
+        }
+    }
+
+    mod factor_from_tag {
+        use super::*;
+
+        #[test]
+        fn test_track_gain() {
+            let tag = tag(&[
+                (ItemKey::ReplayGainTrackGain, "-20 dB"),
+                (ItemKey::ReplayGainAlbumGain, "-40 dB"),
+            ]);
+
+            let factor = replay_gain(ReplayGainMode::Track)
+                .factor_from_tag(&tag)
+                .unwrap();
+            assert!((factor - 0.1).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_album_falls_back_to_track() {
+            let tag = tag(&[(ItemKey::ReplayGainTrackGain, "-20 dB")]);
+
+            let factor = replay_gain(ReplayGainMode::Album)
+                .factor_from_tag(&tag)
+                .unwrap();
+            assert!((factor - 0.1).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_peak_stops_clipping() {
+            let tag = tag(&[
+                (ItemKey::ReplayGainTrackGain, "+6 dB"),
+                (ItemKey::ReplayGainTrackPeak, "0.8"),
+            ]);
+
+            let factor = replay_gain(ReplayGainMode::Track)
+                .factor_from_tag(&tag)
+                .unwrap();
+            assert_eq!(factor, 1.25);
+        }
+
+        #[test]
+        fn test_preamp() {
+            let tag = tag(&[(ItemKey::ReplayGainTrackGain, "-26 dB")]);
+            let replay_gain = ReplayGain {
+                mode: ReplayGainMode::Track,
+                preamp: 6.0,
+            };
+
+            let factor = replay_gain.factor_from_tag(&tag).unwrap();
+            assert!((factor - 0.1).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_without_gain() {
+            let tag = tag(&[]);
+            assert_eq!(
+                replay_gain(ReplayGainMode::Track).factor_from_tag(&tag),
+                None
+            );
+        }
+
+        #[test]
+        fn test_off() {
+            let tag = tag(&[(ItemKey::ReplayGainTrackGain, "-20 dB")]);
+            assert_eq!(replay_gain(ReplayGainMode::Off).factor_from_tag(&tag), None);
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::time::{Duration, Instant};
+
+use rodio::Sink;
+
+/// When the sleep timer should stop playback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SleepTimerMode {
+    /// Stop after a fixed amount of time
+    After(Duration),
+
+    /// Stop at the end of a number of tracks, where 1 means the end of the current track
+    AfterTracks(u32),
+}
+
+/// How much longer the sleep timer has left, as shown to the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SleepCountdown {
+    Time(Duration),
+    Tracks(u32),
+}
+
+/// What the audio thread tells the UI about the sleep timer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SleepTimerStatus {
+    Running(SleepCountdown),
+
+    /// The timer was cancelled
+    Off,
+
+    /// The timer ran out and playback was stopped
+    Expired,
+}
+
+/// The state of a running timer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Deadline {
+    At(Instant),
+    Tracks(u32),
+}
+
+/// Stops playback once the sleep timer runs out, fading the volume out over the last few seconds.
+/// This lives on the audio thread, so that it keeps working even when the UI is busy.
+pub(crate) struct SleepTimer {
+    deadline: Option<Deadline>,
+
+    /// The volume from before the fade started, which is restored once the timer stops
+    volume_before_fade: Option<f32>,
+
+    /// Whether the end of the current track has already been counted
+    track_end_counted: bool,
+
+    /// The last status that was sent to the UI, so that the same status isn't sent over and over
+    last_status: Option<SleepTimerStatus>,
+}
+
+impl SleepTimer {
+    /// How long the volume takes to fade out before playback stops.
+    pub(crate) const FADE_DURATION: Duration = Duration::from_secs(30);
+
+    /// How much time a timed sleep timer gets when it's extended.
+    pub(crate) const EXTEND_DURATION: Duration = Duration::from_secs(10 * 60);
+
+    pub(crate) fn new() -> SleepTimer {
+        SleepTimer {
+            deadline: None,
+            volume_before_fade: None,
+            track_end_counted: false,
+            last_status: None,
+        }
+    }
+
+    /// Start the timer, or cancel it with `None`.
+    pub(crate) fn set(&mut self, mode: Option<SleepTimerMode>, sink: &Sink) {
+        self.restore_volume(sink);
+
+        self.deadline = mode.map(|mode| match mode {
+            SleepTimerMode::After(duration) => Deadline::At(Instant::now() + duration),
+            SleepTimerMode::AfterTracks(tracks) => Deadline::Tracks(tracks.max(1)),
+        });
+    }
+
+    /// Give the running timer more time: another few minutes for a timed timer, or another track otherwise.
+    pub(crate) fn extend(&mut self, sink: &Sink) {
+        self.restore_volume(sink);
+
+        self.deadline = match self.deadline {
+            Some(Deadline::At(instant)) => {
+                // Extending a timer that's fading out starts the extra time from now
+                let from = instant.max(Instant::now());
+                Some(Deadline::At(from + SleepTimer::EXTEND_DURATION))
+            }
+            Some(Deadline::Tracks(tracks)) => Some(Deadline::Tracks(tracks + 1)),
+            None => None,
+        };
+    }
+
+    /// Change the volume. While the timer is fading out, the fade carries on from the new volume instead.
+    pub(crate) fn set_volume(&mut self, volume: f32, sink: &Sink) {
+        match self.volume_before_fade.as_mut() {
+            Some(volume_before_fade) => *volume_before_fade = volume,
+            None => sink.set_volume(volume),
+        }
+    }
+
+    /// Count down the timer, fade the volume out, and stop playback once the timer runs out.
+    /// Returns the new status of the timer if it changed since the last tick.
+    /// `track_length` is the length of the current track, if it's known.
+    /// This function is intended to be called continuously on the audio thread.
+    pub(crate) fn tick(
+        &mut self,
+        sink: &Sink,
+        track_length: Option<Duration>,
+    ) -> Option<SleepTimerStatus> {
+        // Count every track that finishes
+        if sink.empty() && !self.track_end_counted {
+            self.track_end_counted = true;
+            self.track_finished();
+        } else if !sink.empty() {
+            self.track_end_counted = false;
+        }
+
+        let status = match self.remaining(sink, track_length) {
+            None if self.deadline.is_some() => {
+                // The timer has run out
+                self.deadline = None;
+                sink.pause();
+                self.restore_volume(sink);
+                SleepTimerStatus::Expired
+            }
+            None => SleepTimerStatus::Off,
+            Some((countdown, time_left)) => {
+                if let Some(time_left) = time_left {
+                    self.fade(sink, time_left);
+                }
+                SleepTimerStatus::Running(countdown)
+            }
+        };
+
+        // Only tell the UI when what it shows would change
+        let status = match status {
+            SleepTimerStatus::Running(SleepCountdown::Time(remaining)) => {
+                SleepTimerStatus::Running(SleepCountdown::Time(Duration::from_secs(
+                    remaining.as_secs(),
+                )))
+            }
+            status => status,
+        };
+
+        if self.last_status == Some(status) {
+            return None;
+        }
+
+        self.last_status = Some(status);
+        Some(status)
+    }
+
+    /// Count down a track-based timer by one track.
+    pub(crate) fn track_finished(&mut self) {
+        if let Some(Deadline::Tracks(tracks)) = self.deadline {
+            self.deadline = Some(Deadline::Tracks(tracks.saturating_sub(1)));
+        }
+    }
+
+    /// Return what the countdown shows, and how much time is left before playback stops if that is known.
+    /// Returns `None` if the timer is off or has run out.
+    fn remaining(
+        &self,
+        sink: &Sink,
+        track_length: Option<Duration>,
+    ) -> Option<(SleepCountdown, Option<Duration>)> {
+        match self.deadline? {
+            Deadline::At(instant) => {
+                let time_left = instant
+                    .checked_duration_since(Instant::now())
+                    .filter(|time_left| !time_left.is_zero())?;
+                Some((SleepCountdown::Time(time_left), Some(time_left)))
+            }
+            Deadline::Tracks(0) => None,
+            Deadline::Tracks(tracks) => {
+                // Only the last track can fade out, and only if its length is known
+                let time_left = track_length
+                    .filter(|_| tracks == 1)
+                    .map(|length| length.saturating_sub(sink.get_pos()));
+
+                Some((SleepCountdown::Tracks(tracks), time_left))
+            }
+        }
+    }
+
+    /// Lower the volume according to how much time is left.
+    fn fade(&mut self, sink: &Sink, time_left: Duration) {
+        let factor = fade_factor(time_left);
+
+        if factor >= 1.0 {
+            self.restore_volume(sink);
+            return;
+        }
+
+        let volume = *self.volume_before_fade.get_or_insert_with(|| sink.volume());
+        sink.set_volume(volume * factor);
+    }
+
+    /// Undo the fade, if there is one.
+    fn restore_volume(&mut self, sink: &Sink) {
+        if let Some(volume) = self.volume_before_fade.take() {
+            sink.set_volume(volume);
+        }
+    }
+}
+
+/// Return how loud playback should be with `time_left` before the timer runs out, from 0.0 to 1.0.
+fn fade_factor(time_left: Duration) -> f32 {
+    (time_left.as_secs_f32() / SleepTimer::FADE_DURATION.as_secs_f32()).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod fade_factor {
+        use super::*;
+
+        #[test]
+        fn test_before_fade() {
+            assert_eq!(fade_factor(Duration::from_secs(60)), 1.0);
+        }
+
+        #[test]
+        fn test_halfway() {
+            assert_eq!(fade_factor(Duration::from_secs(15)), 0.5);
+        }
+
+        #[test]
+        fn test_end() {
+            assert_eq!(fade_factor(Duration::ZERO), 0.0);
+        }
+    }
+
+    mod tick {
+        use super::*;
+
+        fn sink() -> Sink {
+            let (sink, _output) = Sink::new();
+            sink
+        }
+
+        #[test]
+        fn test_off() {
+            let mut timer = SleepTimer::new();
+
+            assert_eq!(timer.tick(&sink(), None), Some(SleepTimerStatus::Off));
+            assert_eq!(timer.tick(&sink(), None), None);
+        }
+
+        #[test]
+        fn test_counting_down() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::After(Duration::from_secs(600))), &sink);
+
+            let Some(SleepTimerStatus::Running(SleepCountdown::Time(remaining))) =
+                timer.tick(&sink, None)
+            else {
+                panic!("The timer should be running");
+            };
+            assert!(remaining <= Duration::from_secs(600));
+            assert!(remaining >= Duration::from_secs(598));
+        }
+
+        #[test]
+        fn test_expired() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::After(Duration::ZERO)), &sink);
+
+            assert_eq!(timer.tick(&sink, None), Some(SleepTimerStatus::Expired));
+            assert!(sink.is_paused());
+        }
+
+        #[test]
+        fn test_fades_and_restores_volume() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::After(Duration::from_secs(15))), &sink);
+
+            timer.tick(&sink, None);
+            assert!(sink.volume() < 0.51);
+
+            timer.set(None, &sink);
+            assert_eq!(sink.volume(), 1.0);
+        }
+
+        #[test]
+        fn test_volume_change_during_fade() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::After(Duration::from_secs(15))), &sink);
+            timer.tick(&sink, None);
+
+            timer.set_volume(0.5, &sink);
+            timer.tick(&sink, None);
+            assert!(sink.volume() < 0.26);
+
+            timer.set(None, &sink);
+            assert_eq!(sink.volume(), 0.5);
+        }
+
+        #[test]
+        fn test_counts_tracks() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::AfterTracks(2)), &sink);
+
+            // The sink has nothing to play, so the current track counts as finished
+            assert_eq!(
+                timer.tick(&sink, None),
+                Some(SleepTimerStatus::Running(SleepCountdown::Tracks(1)))
+            );
+
+            timer.track_finished();
+            assert_eq!(timer.tick(&sink, None), Some(SleepTimerStatus::Expired));
+        }
+
+        #[test]
+        fn test_extend_tracks() {
+            let sink = sink();
+            let mut timer = SleepTimer::new();
+            timer.set(Some(SleepTimerMode::AfterTracks(1)), &sink);
+            timer.extend(&sink);
+
+            assert_eq!(
+                timer.tick(&sink, None),
+                Some(SleepTimerStatus::Running(SleepCountdown::Tracks(1)))
+            );
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/// Hash `bytes` the same way on every run and every platform, unlike the standard library's hashers.
+/// This makes it suitable for naming files that should be found again after a restart.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    // 64-bit FNV-1a
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod stable_hash {
+        use super::*;
+
+        #[test]
+        fn test_known_values() {
+            // The FNV-1a test vectors
+            assert_eq!(stable_hash(b""), 0xcbf29ce484222325);
+            assert_eq!(stable_hash(b"a"), 0xaf63dc4c8601ec8c);
+            assert_eq!(stable_hash(b"foobar"), 0x85944171f73967e8);
+        }
+    }
+}
@@ -0,0 +1,664 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Return whether `path` is the URL of an internet stream, such as a radio station, rather than a file.
+pub(crate) fn is_stream(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| {
+        let path = path.to_ascii_lowercase();
+        path.starts_with("http://") || path.starts_with("https://")
+    })
+}
+
+/// What a station has told about itself and the song that it is playing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct StreamDetails {
+    /// The name of the station, from the `icy-name` header
+    pub(crate) station: Option<String>,
+
+    /// The song that is playing, from the ICY metadata that is mixed into the stream
+    pub(crate) title: Option<String>,
+}
+
+/// The details of a stream, which change while it plays.
+/// Clones share the same details, so that the UI sees the song titles that the download thread reads from the stream.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StreamInfo(Arc<Mutex<StreamDetails>>);
+
+impl StreamInfo {
+    pub(crate) fn details(&self) -> StreamDetails {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set_station(&self, station: Option<String>) {
+        self.0.lock().unwrap().station = station;
+    }
+
+    fn set_title(&self, title: Option<String>) {
+        self.0.lock().unwrap().title = title;
+    }
+}
+
+impl PartialEq for StreamInfo {
+    /// Two infos are the same if they belong to the same stream
+    fn eq(&self, other: &StreamInfo) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// An internet stream, such as an Icecast or Shoutcast radio station, that is being downloaded in the background.
+pub(crate) struct Stream {
+    pub(crate) reader: StreamReader,
+
+    /// The format of the audio as the station reports it, e.g. `audio/mpeg`
+    pub(crate) content_type: String,
+
+    pub(crate) info: StreamInfo,
+}
+
+impl Stream {
+    /// How much of the stream is downloaded ahead of what is playing, which is about a minute of a 128 kbit/s station
+    const BUFFER_CAPACITY: usize = 1024 * 1024;
+
+    /// How much of the stream has to be downloaded before it starts playing
+    const PREBUFFER: usize = 32 * 1024;
+
+    /// How long the station has to send the prebuffer in, before it is taken to have stalled
+    const PREBUFFER_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How many times in a row the stream is reconnected to before giving up on it
+    const MAX_RECONNECTS: u32 = 5;
+
+    /// How long to wait before the second attempt to reconnect, which doubles with every attempt after it
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long the station can go quiet before the connection is taken to be lost
+    const READ_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Connect to the stream at `url` and start downloading it in the background.
+    /// Returns once enough of the stream is buffered to start playing it.
+    /// # Errors
+    /// - If the station can't be reached
+    /// - If the station answers with an error
+    /// - If the station doesn't send enough of the stream to start playing it in time
+    pub(crate) fn open(url: &str) -> io::Result<Stream> {
+        Stream::open_within(url, Stream::PREBUFFER_TIMEOUT)
+    }
+
+    /// Connect to the stream at `url`, giving the station `prebuffer_timeout` to send enough of it to start playing.
+    fn open_within(url: &str, prebuffer_timeout: Duration) -> io::Result<Stream> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Stream::CONNECT_TIMEOUT)
+            .timeout_read(Stream::READ_TIMEOUT)
+            .build();
+        let info = StreamInfo::default();
+
+        let (body, content_type) = Stream::connect(&agent, url, &info)?;
+
+        let buffer = Arc::new(NetworkBuffer::default());
+        {
+            let url = url.to_string();
+            let buffer = Arc::clone(&buffer);
+            let info = info.clone();
+            thread::spawn(move || Stream::download(&agent, &url, body, &buffer, &info));
+        }
+
+        if !buffer.wait_for(Stream::PREBUFFER, prebuffer_timeout) {
+            // Stops the download, once the station sends anything again
+            buffer.close();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "The station stopped sending audio",
+            ));
+        }
+
+        Ok(Stream {
+            reader: StreamReader {
+                buffer,
+                position: 0,
+            },
+            content_type,
+            info,
+        })
+    }
+
+    /// Request the stream, asking for the song titles to be mixed into it. Returns the audio and its format.
+    fn connect(
+        agent: &ureq::Agent,
+        url: &str,
+        info: &StreamInfo,
+    ) -> io::Result<(IcyReader, String)> {
+        let response = agent
+            .get(url)
+            .set("Icy-MetaData", "1")
+            .call()
+            .map_err(io::Error::other)?;
+
+        // Without this header, the station doesn't mix any metadata into the stream
+        let metadata_interval = response
+            .header("icy-metaint")
+            .and_then(|interval| interval.trim().parse().ok())
+            .filter(|&interval| interval > 0);
+
+        info.set_station(
+            response
+                .header("icy-name")
+                .map(str::trim)
+                .filter(|station| !station.is_empty())
+                .map(str::to_string),
+        );
+        let content_type = response.content_type().to_string();
+
+        let body = IcyReader::new(response.into_reader(), metadata_interval, info.clone());
+
+        Ok((body, content_type))
+    }
+
+    /// Copy the stream into the buffer until nobody listens to it anymore, reconnecting whenever the connection drops.
+    fn download(
+        agent: &ureq::Agent,
+        url: &str,
+        mut body: IcyReader,
+        buffer: &NetworkBuffer,
+        info: &StreamInfo,
+    ) {
+        let mut chunk = [0; 8192];
+        let mut failed_attempts = 0;
+
+        loop {
+            match body.read(&mut chunk) {
+                Ok(0) => {}
+                Ok(count) => {
+                    failed_attempts = 0;
+                    if !buffer.write(&chunk[..count]) {
+                        return;
+                    }
+                    continue;
+                }
+                Err(e) => eprintln!("Lost the connection to {}: {}", url, e),
+            }
+
+            // The station hung up or the connection dropped, so keep trying to get it back
+            loop {
+                if buffer.is_closed() {
+                    return;
+                }
+                if failed_attempts == Stream::MAX_RECONNECTS {
+                    eprintln!("Unable to reconnect to {}. Giving up...", url);
+                    buffer.end();
+                    return;
+                }
+
+                // Most drops are brief, so the first attempt is made straight away
+                if failed_attempts > 0 {
+                    thread::sleep(Stream::RECONNECT_DELAY * 2u32.pow(failed_attempts - 1));
+                }
+                failed_attempts += 1;
+
+                match Stream::connect(agent, url, info) {
+                    Ok((new_body, _)) => {
+                        body = new_body;
+                        break;
+                    }
+                    Err(e) => eprintln!("Unable to reconnect to {}: {}", url, e),
+                }
+            }
+        }
+    }
+}
+
+/// The downloaded part of a stream that hasn't been played yet, shared between the download thread and the decoder.
+#[derive(Default)]
+struct NetworkBuffer {
+    state: Mutex<BufferState>,
+
+    /// Notified whenever bytes are added or taken, or the buffer is ended or closed
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct BufferState {
+    bytes: VecDeque<u8>,
+
+    /// The stream can't be downloaded anymore, so no more bytes will arrive
+    ended: bool,
+
+    /// Nobody reads from the buffer anymore, so the download should stop
+    closed: bool,
+}
+
+impl NetworkBuffer {
+    /// Add downloaded bytes, waiting while the buffer is full. Returns `false` once nobody reads from the buffer anymore.
+    fn write(&self, mut bytes: &[u8]) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        while !bytes.is_empty() {
+            state = self
+                .changed
+                .wait_while(state, |state| {
+                    state.bytes.len() >= Stream::BUFFER_CAPACITY && !state.closed
+                })
+                .unwrap();
+            if state.closed {
+                return false;
+            }
+
+            let count = bytes.len().min(Stream::BUFFER_CAPACITY - state.bytes.len());
+            state.bytes.extend(&bytes[..count]);
+            bytes = &bytes[count..];
+            self.changed.notify_all();
+        }
+
+        true
+    }
+
+    /// Take bytes out of the buffer, waiting until there are any. Returns 0 once the stream has ended.
+    fn read(&self, buf: &mut [u8]) -> usize {
+        let mut state = self
+            .changed
+            .wait_while(self.state.lock().unwrap(), |state| {
+                state.bytes.is_empty() && !state.ended
+            })
+            .unwrap();
+
+        let count = state.bytes.read(buf).unwrap_or(0);
+        self.changed.notify_all();
+
+        count
+    }
+
+    /// Wait until at least `len` bytes are buffered, or until the stream has ended.
+    /// Returns `false` if that didn't happen within `timeout`.
+    fn wait_for(&self, len: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+
+        // Waking up early doesn't mean that the time is up, so wait again for whatever is left of it
+        while state.bytes.len() < len && !state.ended {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            state = self.changed.wait_timeout(state, left).unwrap().0;
+        }
+
+        true
+    }
+
+    fn end(&self) {
+        self.state.lock().unwrap().ended = true;
+        self.changed.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+}
+
+/// Reads a stream as it is being downloaded, so that it can be decoded like a file.
+/// The download stops once the reader is dropped.
+pub(crate) struct StreamReader {
+    buffer: Arc<NetworkBuffer>,
+
+    /// How many bytes have been read so far
+    position: u64,
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.buffer.read(buf);
+        self.position += count as u64;
+
+        Ok(count)
+    }
+}
+
+impl Seek for StreamReader {
+    /// A stream only goes forwards, so the only thing that can be asked is where the reader is
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Current(0) => Ok(self.position),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "internet streams can't be seeked",
+            )),
+        }
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        self.buffer.close();
+    }
+}
+
+/// Takes the ICY metadata out of a stream, leaving only the audio.
+/// Stations that are asked for it mix a block of metadata into the audio after every `metadata_interval` bytes.
+struct IcyReader {
+    inner: Box<dyn Read + Send + Sync>,
+    metadata_interval: Option<usize>,
+
+    /// How many bytes of audio are left until the next block of metadata
+    audio_left: usize,
+
+    info: StreamInfo,
+}
+
+impl IcyReader {
+    fn new(
+        inner: Box<dyn Read + Send + Sync>,
+        metadata_interval: Option<usize>,
+        info: StreamInfo,
+    ) -> IcyReader {
+        IcyReader {
+            inner,
+            metadata_interval,
+            audio_left: metadata_interval.unwrap_or(0),
+            info,
+        }
+    }
+
+    /// Read a block of metadata, which starts with its length in multiples of 16 bytes.
+    /// Stations only send the metadata when it changes, and send an empty block otherwise.
+    fn read_metadata(&mut self) -> io::Result<()> {
+        let mut len = [0];
+        self.inner.read_exact(&mut len)?;
+
+        if len[0] == 0 {
+            return Ok(());
+        }
+
+        let mut block = vec![0; usize::from(len[0]) * 16];
+        self.inner.read_exact(&mut block)?;
+
+        self.info.set_title(parse_stream_title(&block));
+
+        Ok(())
+    }
+}
+
+impl Read for IcyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metadata_interval) = self.metadata_interval else {
+            return self.inner.read(buf);
+        };
+
+        if self.audio_left == 0 {
+            self.read_metadata()?;
+            self.audio_left = metadata_interval;
+        }
+
+        // Stop at the next block of metadata, so that it never ends up in the audio
+        let len = buf.len().min(self.audio_left);
+        let count = self.inner.read(&mut buf[..len])?;
+        self.audio_left -= count;
+
+        Ok(count)
+    }
+}
+
+/// Return the song title in a block of ICY metadata, such as `StreamTitle='Artist - Song';StreamUrl='';`.
+/// Returns `None` if the block has no title, or if the title is empty.
+fn parse_stream_title(block: &[u8]) -> Option<String> {
+    const START: &str = "StreamTitle='";
+
+    // The block is padded with zeros up to a multiple of 16 bytes
+    let text = String::from_utf8_lossy(block);
+    let text = text.trim_end_matches('\0');
+
+    let title_start = text.find(START)? + START.len();
+    let title = &text[title_start..];
+
+    // The title may contain apostrophes itself, so it only ends where the next field starts
+    let title_end = title.find("';").or_else(|| title.rfind('\''))?;
+    let title = title[..title_end].trim();
+
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::{Cursor, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Mix a block of metadata with `title` into `audio` after every `interval` bytes, the way a station would.
+    fn with_metadata(audio: &[u8], interval: usize, title: &str) -> Vec<u8> {
+        let mut metadata = format!("StreamTitle='{}';", title).into_bytes();
+        metadata.resize(metadata.len().div_ceil(16) * 16, 0);
+
+        let mut stream = Vec::new();
+        for chunk in audio.chunks(interval) {
+            stream.extend(chunk);
+            if chunk.len() == interval {
+                stream.push((metadata.len() / 16) as u8);
+                stream.extend(&metadata);
+            }
+        }
+
+        stream
+    }
+
+    /// Serve `audio` to every request the way an Icecast station would, with "Artist - Song" mixed in as the song title.
+    /// Returns the URL of the stream, and how many requests were made so that reconnecting can be checked.
+    fn serve_station(
+        content_type: &str,
+        audio: &[u8],
+        interval: usize,
+    ) -> (String, Arc<AtomicUsize>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/radio", server.server_addr().to_ip().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let body = with_metadata(audio, interval, "Artist - Song");
+        let headers: Vec<tiny_http::Header> = [
+            format!("Content-Type: {}", content_type),
+            "icy-name: Test FM".to_string(),
+            format!("icy-metaint: {}", interval),
+        ]
+        .iter()
+        .map(|header| header.parse().unwrap())
+        .collect();
+
+        {
+            let requests = Arc::clone(&requests);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    requests.fetch_add(1, Ordering::SeqCst);
+
+                    let mut response = tiny_http::Response::from_data(body.clone());
+                    for header in &headers {
+                        response.add_header(header.clone());
+                    }
+                    let _ = request.respond(response);
+                }
+            });
+        }
+
+        (url, requests)
+    }
+
+    mod is_stream {
+        use super::*;
+
+        #[test]
+        fn test_urls() {
+            assert!(is_stream(Path::new("http://radio.example/stream")));
+            assert!(is_stream(Path::new("HTTPS://radio.example/stream.ogg")));
+        }
+
+        #[test]
+        fn test_files() {
+            assert!(!is_stream(Path::new("/music/http://track.mp3")));
+            assert!(!is_stream(Path::new("track.mp3")));
+        }
+    }
+
+    mod parse_stream_title {
+        use super::*;
+
+        #[test]
+        fn test_title() {
+            assert_eq!(
+                parse_stream_title(b"StreamTitle='Artist - Song';StreamUrl='';\0\0\0"),
+                Some("Artist - Song".to_string())
+            );
+        }
+
+        #[test]
+        fn test_apostrophe() {
+            assert_eq!(
+                parse_stream_title(b"StreamTitle='Don't Stop';"),
+                Some("Don't Stop".to_string())
+            );
+        }
+
+        #[test]
+        fn test_empty_title() {
+            assert_eq!(parse_stream_title(b"StreamTitle='';\0\0"), None);
+        }
+
+        #[test]
+        fn test_no_title() {
+            assert_eq!(parse_stream_title(b"StreamUrl='http://x';"), None);
+        }
+    }
+
+    mod icy_reader {
+        use super::*;
+
+        #[test]
+        fn test_removes_metadata() {
+            let audio: Vec<u8> = (0..100).collect();
+            let info = StreamInfo::default();
+            let mut reader = IcyReader::new(
+                Box::new(Cursor::new(with_metadata(&audio, 16, "Song"))),
+                Some(16),
+                info.clone(),
+            );
+
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+
+            assert_eq!(read, audio);
+            assert_eq!(info.details().title, Some("Song".to_string()));
+        }
+
+        #[test]
+        fn test_no_metadata() {
+            let audio: Vec<u8> = (0..100).collect();
+            let mut reader = IcyReader::new(
+                Box::new(Cursor::new(audio.clone())),
+                None,
+                StreamInfo::default(),
+            );
+
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+
+            assert_eq!(read, audio);
+        }
+    }
+
+    mod open {
+        use super::*;
+
+        #[test]
+        fn test_station() {
+            let audio: Vec<u8> = (0..=255).cycle().take(64 * 1024).collect();
+            let (url, _) = serve_station("audio/mpeg", &audio, 1000);
+
+            let mut stream = Stream::open(&url).unwrap();
+
+            let mut read = vec![0; audio.len()];
+            stream.reader.read_exact(&mut read).unwrap();
+
+            assert_eq!(read, audio);
+            assert_eq!(stream.content_type, "audio/mpeg");
+            assert_eq!(
+                stream.info.details(),
+                StreamDetails {
+                    station: Some("Test FM".to_string()),
+                    title: Some("Artist - Song".to_string()),
+                }
+            );
+        }
+
+        #[test]
+        fn test_reconnect() {
+            let audio: Vec<u8> = (0..=255).cycle().take(1000).collect();
+            let (url, requests) = serve_station("audio/mpeg", &audio, 100);
+
+            let mut stream = Stream::open(&url).unwrap();
+
+            // Every response ends after 1000 bytes of audio, so reading more than that needs a new connection
+            let mut read = vec![0; 3000];
+            stream.reader.read_exact(&mut read).unwrap();
+
+            assert_eq!(read, audio.repeat(3));
+            assert!(requests.load(Ordering::SeqCst) >= 3);
+        }
+
+        #[test]
+        fn test_stalled() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/radio", listener.local_addr().unwrap());
+            thread::spawn(move || {
+                for mut connection in listener.incoming().flatten() {
+                    // The headers and a few bytes arrive, and then nothing more
+                    let _ = connection.write_all(
+                        b"HTTP/1.0 200 OK\r\nContent-Type: audio/mpeg\r\n\r\n0123456789",
+                    );
+                    thread::sleep(Duration::from_secs(60));
+                }
+            });
+
+            let e = Stream::open_within(&url, Duration::from_millis(200))
+                .err()
+                .unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        }
+
+        #[test]
+        fn test_not_found() {
+            let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/radio", server.server_addr().to_ip().unwrap());
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let _ = request.respond(tiny_http::Response::empty(404));
+                }
+            });
+
+            assert!(Stream::open(&url).is_err());
+        }
+
+        #[test]
+        fn test_decode() {
+            let audio =
+                std::fs::read("./src/app/ui/tests/files/audio/without-metadata/test.ogg").unwrap();
+            let (url, _) = serve_station("application/ogg", &audio, 8192);
+
+            let stream = Stream::open(&url).unwrap();
+            let decoder = rodio::Decoder::builder()
+                .with_data(stream.reader)
+                .with_mime_type(&stream.content_type)
+                .with_seekable(false)
+                .build()
+                .unwrap();
+
+            // A stream has no end, so its length can't be known
+            assert_eq!(rodio::Source::total_duration(&decoder), None);
+            assert_eq!(decoder.take(44100).count(), 44100);
+        }
+    }
+}
@@ -0,0 +1,572 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::{Picture, PictureType};
+use lofty::read_from_path;
+use lofty::tag::{Accessor, ItemKey, Tag, TagType};
+
+/// A field of a tag that can be edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Year,
+    Track,
+    Genre,
+}
+
+impl TagField {
+    /// Every field, in the order they are shown to the user.
+    pub(crate) const ALL: [TagField; 7] = [
+        TagField::Title,
+        TagField::Artist,
+        TagField::Album,
+        TagField::AlbumArtist,
+        TagField::Year,
+        TagField::Track,
+        TagField::Genre,
+    ];
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            TagField::Title => "Title",
+            TagField::Artist => "Artist",
+            TagField::Album => "Album",
+            TagField::AlbumArtist => "Album artist",
+            TagField::Year => "Year",
+            TagField::Track => "Track",
+            TagField::Genre => "Genre",
+        }
+    }
+
+    /// Return the value of the field in `tag`, or `None` if the tag doesn't have it.
+    pub(crate) fn get(&self, tag: &Tag) -> Option<String> {
+        match self {
+            TagField::Title => tag.title().map(Cow::into_owned),
+            TagField::Artist => tag.artist().map(Cow::into_owned),
+            TagField::Album => tag.album().map(Cow::into_owned),
+            TagField::AlbumArtist => tag.get_string(&ItemKey::AlbumArtist).map(str::to_string),
+            TagField::Year => tag.year().map(|year| year.to_string()),
+            TagField::Track => tag.track().map(|track| track.to_string()),
+            TagField::Genre => tag.genre().map(Cow::into_owned),
+        }
+    }
+
+    /// Return the value that every tag in `tags` has for the field, which is empty if none of them have it.
+    /// Returns `None` if the tags have different values.
+    pub(crate) fn common_value(&self, tags: &[Tag]) -> Option<String> {
+        let mut values = tags.iter().map(|tag| self.get(tag).unwrap_or_default());
+        let first = values.next().unwrap_or_default();
+
+        values.all(|value| value == first).then_some(first)
+    }
+
+    /// Check that `value` can be written to the field.
+    /// # Errors
+    /// - If the field is the title or artist and `value` is empty, since the player can't show a track without them
+    /// - If the field is the year or track and `value` isn't a number
+    fn validate(&self, value: &str) -> Result<(), String> {
+        let value = value.trim();
+
+        match self {
+            TagField::Title | TagField::Artist if value.is_empty() => Err(format!(
+                "The {} can't be empty",
+                self.label().to_lowercase()
+            )),
+            TagField::Year | TagField::Track if !value.is_empty() => {
+                self.parse_number(value).map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set the field in `tag` to `value`, removing it if `value` is empty.
+    /// Fields that the tag type has no room for, such as the album artist of an ID3v1 tag, are left out.
+    fn set(&self, tag: &mut Tag, value: &str) -> Result<(), String> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            match self {
+                TagField::Title => tag.remove_title(),
+                TagField::Artist => tag.remove_artist(),
+                TagField::Album => tag.remove_album(),
+                TagField::AlbumArtist => tag.remove_key(&ItemKey::AlbumArtist),
+                TagField::Year => tag.remove_year(),
+                TagField::Track => tag.remove_track(),
+                TagField::Genre => tag.remove_genre(),
+            }
+            return Ok(());
+        }
+
+        match self {
+            TagField::Title => tag.set_title(value.to_string()),
+            TagField::Artist => tag.set_artist(value.to_string()),
+            TagField::Album => tag.set_album(value.to_string()),
+            TagField::AlbumArtist => {
+                tag.insert_text(ItemKey::AlbumArtist, value.to_string());
+            }
+            TagField::Year => tag.set_year(self.parse_number(value)?),
+            TagField::Track => tag.set_track(self.parse_number(value)?),
+            TagField::Genre => tag.set_genre(value.to_string()),
+        }
+
+        Ok(())
+    }
+
+    fn parse_number(&self, value: &str) -> Result<u32, String> {
+        value
+            .parse()
+            .map_err(|_| format!("The {} must be a number", self.label().to_lowercase()))
+    }
+}
+
+/// What to do with the cover of a track, which is the picture that [`cover_picture`] picks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) enum CoverEdit {
+    #[default]
+    Keep,
+
+    /// Replace the cover with a front cover, or add one if the track has none.
+    /// Any other pictures, such as one of the artist, are kept
+    Replace(Picture),
+
+    /// Remove every picture, since any picture that was left would be shown as the cover instead
+    Remove,
+}
+
+impl CoverEdit {
+    /// Read the image at `path` to use as the new front cover.
+    /// # Errors
+    /// - If the file can't be read
+    /// - If the file isn't an image that can be embedded in a tag
+    pub(crate) fn from_image_file(path: &Path) -> Result<CoverEdit, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+
+        let mut picture = Picture::from_reader(&mut data.as_slice())
+            .map_err(|e| format!("Unable to use {} as a cover: {}", path.display(), e))?;
+        picture.set_pic_type(PictureType::CoverFront);
+
+        Ok(CoverEdit::Replace(picture))
+    }
+}
+
+/// A change to the tags of one or more tracks.
+/// Only the fields that are listed are changed, so that editing several tracks at once leaves the rest of their fields as they are.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TagEdit {
+    /// The fields to change, and what to change them to. An empty value removes the field
+    pub(crate) fields: Vec<(TagField, String)>,
+
+    pub(crate) cover: CoverEdit,
+}
+
+impl TagEdit {
+    /// Return whether the edit doesn't change anything.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.cover == CoverEdit::Keep
+    }
+
+    /// Check that every field can be written, before any file is touched.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        self.fields
+            .iter()
+            .try_for_each(|(field, value)| field.validate(value))
+    }
+
+    /// Write the edit to the tags of the track at `path`.
+    /// Every tag the file already has is edited, so that it keeps its tag types, such as ID3v2 in an MP3 or Vorbis
+    /// comments in an Ogg file. The format's main tag is created if the file has no tags at all.
+    /// # Errors
+    /// - If the edit is invalid
+    /// - If the file can't be read or written
+    pub(crate) fn apply(&self, path: &Path) -> Result<(), String> {
+        self.validate()?;
+
+        let mut tagged_file = read_from_path(path)
+            .map_err(|e| format!("Unable to read the tags of {}: {}", path.display(), e))?;
+
+        if tagged_file.tags().is_empty() {
+            tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+        }
+
+        let tag_types: Vec<TagType> = tagged_file.tags().iter().map(Tag::tag_type).collect();
+        for tag_type in tag_types {
+            if let Some(tag) = tagged_file.tag_mut(tag_type) {
+                self.apply_to_tag(tag)?;
+            }
+        }
+
+        tagged_file
+            .save_to_path(path, WriteOptions::default())
+            .map_err(|e| format!("Unable to save the tags of {}: {}", path.display(), e))
+    }
+
+    fn apply_to_tag(&self, tag: &mut Tag) -> Result<(), String> {
+        for (field, value) in &self.fields {
+            field.set(tag, value)?;
+        }
+
+        match &self.cover {
+            CoverEdit::Keep => {}
+            CoverEdit::Replace(picture) => {
+                // Every front cover goes, since the first one that was left would be shown instead of the new one
+                if tag.get_picture_type(PictureType::CoverFront).is_some() {
+                    tag.remove_picture_type(PictureType::CoverFront);
+                } else if !tag.pictures().is_empty() {
+                    tag.remove_picture(0);
+                }
+                tag.push_picture(picture.clone());
+            }
+            CoverEdit::Remove => {
+                while !tag.pictures().is_empty() {
+                    tag.remove_picture(0);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Return the picture of a tag that is most likely to be its cover.
+/// This is the front cover if there is one, and otherwise whichever picture comes first.
+pub(crate) fn cover_picture(tag: &Tag) -> Option<&Picture> {
+    tag.pictures()
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or_else(|| tag.pictures().first())
+}
+
+/// Read the tag that the player shows for the track at `path`: its main tag, or whichever tag it has otherwise.
+/// Returns an empty tag of the format's main type if the track has no tags.
+pub(crate) fn read_tag(path: &Path) -> Result<Tag, String> {
+    let tagged_file = read_from_path(path)
+        .map_err(|e| format!("Unable to read the tags of {}: {}", path.display(), e))?;
+
+    Ok(tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type())))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::path::PathBuf;
+
+    use lofty::picture::MimeType;
+
+    use super::*;
+
+    const TEST_FILES: &str = "./src/app/ui/tests/files";
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "audio_player_tag_editor_{}_{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    /// Write a short, silent WAV file without any tags
+    fn silent_wav(name: &str) -> PathBuf {
+        let path = temp_path(name);
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..800 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        path
+    }
+
+    fn cover() -> Picture {
+        let cover = Path::new(TEST_FILES).join("images/covers/test_cover.png");
+        match CoverEdit::from_image_file(&cover).unwrap() {
+            CoverEdit::Replace(picture) => picture,
+            edit => panic!("Expected a new cover, got {:?}", edit),
+        }
+    }
+
+    mod common_value {
+        use super::*;
+
+        fn tag_with_album(album: Option<&str>) -> Tag {
+            let mut tag = Tag::new(TagType::VorbisComments);
+            if let Some(album) = album {
+                tag.set_album(album.to_string());
+            }
+            tag
+        }
+
+        #[test]
+        fn test_shared_value() {
+            let tags = [tag_with_album(Some("Blue")), tag_with_album(Some("Blue"))];
+            assert_eq!(
+                TagField::Album.common_value(&tags),
+                Some("Blue".to_string())
+            );
+        }
+
+        #[test]
+        fn test_different_values() {
+            let tags = [tag_with_album(Some("Blue")), tag_with_album(None)];
+            assert_eq!(TagField::Album.common_value(&tags), None);
+        }
+
+        #[test]
+        fn test_missing_everywhere() {
+            let tags = [tag_with_album(None), tag_with_album(None)];
+            assert_eq!(TagField::Album.common_value(&tags), Some(String::new()));
+        }
+    }
+
+    mod validate {
+        use super::*;
+
+        fn edit(field: TagField, value: &str) -> TagEdit {
+            TagEdit {
+                fields: vec![(field, value.to_string())],
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn test_valid() {
+            assert!(edit(TagField::Year, "1999").validate().is_ok());
+            assert!(edit(TagField::Year, "").validate().is_ok());
+            assert!(edit(TagField::Album, "").validate().is_ok());
+        }
+
+        #[test]
+        fn test_number_required() {
+            assert!(edit(TagField::Track, "three").validate().is_err());
+        }
+
+        #[test]
+        fn test_title_required() {
+            assert!(edit(TagField::Title, " ").validate().is_err());
+        }
+    }
+
+    mod apply_to_tag {
+        use super::*;
+
+        /// The tag types keep their own keys for each field, so check that every field can be set in each of them
+        #[test]
+        fn test_every_tag_type() {
+            let edit = TagEdit {
+                fields: vec![
+                    (TagField::Title, "Title".to_string()),
+                    (TagField::Artist, "Artist".to_string()),
+                    (TagField::Album, "Album".to_string()),
+                    (TagField::AlbumArtist, "Album artist".to_string()),
+                    (TagField::Year, "1999".to_string()),
+                    (TagField::Track, "3".to_string()),
+                    (TagField::Genre, "Genre".to_string()),
+                ],
+                cover: CoverEdit::Replace(cover()),
+            };
+
+            for tag_type in [
+                TagType::Id3v2,
+                TagType::VorbisComments,
+                TagType::Ape,
+                TagType::Mp4Ilst,
+            ] {
+                let mut tag = Tag::new(tag_type);
+                edit.apply_to_tag(&mut tag).unwrap();
+
+                for (field, value) in &edit.fields {
+                    assert_eq!(field.get(&tag).as_ref(), Some(value), "{:?}", tag_type);
+                }
+                assert_eq!(tag.pictures().len(), 1);
+            }
+        }
+
+        #[test]
+        fn test_replace_cover() {
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::CoverFront,
+                Some(MimeType::Jpeg),
+                None,
+                vec![0; 8],
+            ));
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::Artist,
+                Some(MimeType::Jpeg),
+                None,
+                vec![0; 8],
+            ));
+
+            let edit = TagEdit {
+                cover: CoverEdit::Replace(cover()),
+                ..Default::default()
+            };
+            edit.apply_to_tag(&mut tag).unwrap();
+
+            // The artist picture is kept
+            let types: Vec<_> = tag.pictures().iter().map(Picture::pic_type).collect();
+            assert_eq!(types, [PictureType::Artist, PictureType::CoverFront]);
+            assert_eq!(tag.pictures()[1].mime_type(), Some(&MimeType::Png));
+        }
+
+        #[test]
+        fn test_replace_cover_without_front_cover() {
+            // The artist picture is shown as the cover, since there is no front cover
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::Artist,
+                Some(MimeType::Jpeg),
+                None,
+                vec![0; 8],
+            ));
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::Other,
+                Some(MimeType::Jpeg),
+                None,
+                vec![0; 8],
+            ));
+
+            let edit = TagEdit {
+                cover: CoverEdit::Replace(cover()),
+                ..Default::default()
+            };
+            edit.apply_to_tag(&mut tag).unwrap();
+
+            let types: Vec<_> = tag.pictures().iter().map(Picture::pic_type).collect();
+            assert_eq!(types, [PictureType::Other, PictureType::CoverFront]);
+            assert_eq!(
+                cover_picture(&tag).map(Picture::mime_type),
+                Some(Some(&MimeType::Png))
+            );
+        }
+
+        #[test]
+        fn test_remove_cover_without_front_cover() {
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::Artist,
+                Some(MimeType::Jpeg),
+                None,
+                vec![0; 8],
+            ));
+
+            let edit = TagEdit {
+                cover: CoverEdit::Remove,
+                ..Default::default()
+            };
+            edit.apply_to_tag(&mut tag).unwrap();
+
+            assert_eq!(cover_picture(&tag), None);
+        }
+
+        #[test]
+        fn test_remove_field() {
+            let mut tag = Tag::new(TagType::VorbisComments);
+            tag.set_genre("Jazz".to_string());
+
+            let edit = TagEdit {
+                fields: vec![(TagField::Genre, String::new())],
+                ..Default::default()
+            };
+            edit.apply_to_tag(&mut tag).unwrap();
+
+            assert_eq!(tag.genre(), None);
+        }
+    }
+
+    mod apply {
+        use super::*;
+
+        fn edit(fields: &[(TagField, &str)]) -> TagEdit {
+            TagEdit {
+                fields: fields
+                    .iter()
+                    .map(|(field, value)| (*field, value.to_string()))
+                    .collect(),
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn test_written_and_read_back() {
+            let path = silent_wav("written.wav");
+
+            edit(&[(TagField::Title, "Title"), (TagField::Artist, "Artist")])
+                .apply(&path)
+                .unwrap();
+
+            let mut second_edit = edit(&[
+                (TagField::Title, "New title"),
+                (TagField::Album, "New album"),
+                (TagField::Year, "2001"),
+            ]);
+            second_edit.cover = CoverEdit::Replace(cover());
+            let result = second_edit.apply(&path);
+            let tag = read_tag(&path);
+            fs::remove_file(&path).unwrap();
+
+            result.unwrap();
+            let tag = tag.unwrap();
+
+            // WAV files keep their ID3v2 tag
+            assert_eq!(tag.tag_type(), TagType::Id3v2);
+            assert_eq!(tag.title().as_deref(), Some("New title"));
+            assert_eq!(tag.album().as_deref(), Some("New album"));
+            assert_eq!(tag.year(), Some(2001));
+            assert_eq!(tag.pictures(), [cover()]);
+
+            // The fields that weren't edited are left as they were
+            assert_eq!(tag.artist().as_deref(), Some("Artist"));
+        }
+
+        #[test]
+        fn test_invalid_edit_is_not_written() {
+            let path = silent_wav("invalid.wav");
+            let before = fs::read(&path).unwrap();
+
+            let result =
+                edit(&[(TagField::Album, "New album"), (TagField::Year, "soon")]).apply(&path);
+            let after = fs::read(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert!(result.is_err());
+            assert_eq!(before, after);
+        }
+
+        #[test]
+        fn test_unreadable_file_is_not_written() {
+            let path = temp_path("unreadable.ogg");
+            fs::copy(
+                format!("{}/audio/with-metadata/test.ogg", TEST_FILES),
+                &path,
+            )
+            .unwrap();
+            let before = fs::read(&path).unwrap();
+
+            // The fixture's first stream is video, which lofty can't read the tags of
+            let result = edit(&[(TagField::Album, "New album")]).apply(&path);
+            let after = fs::read(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert!(result.is_err());
+            assert_eq!(before, after);
+        }
+    }
+}
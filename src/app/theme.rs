@@ -0,0 +1,675 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use fltk::app;
+use fltk_theme::{ColorMap, ColorTheme};
+use lofty::picture::Picture;
+use serde::Deserialize;
+
+/// A color, as written in the config file, e.g. `"#3399ff"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct Rgb(pub(crate) u8, pub(crate) u8, pub(crate) u8);
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Rgb, String> {
+        Rgb::parse(&hex)
+    }
+}
+
+impl Rgb {
+    /// Parse a color written as `#rrggbb`. The `#` can be left out.
+    /// # Errors
+    /// - If `hex` isn't six hexadecimal digits
+    pub(crate) fn parse(hex: &str) -> Result<Rgb, String> {
+        let digits = hex.trim().trim_start_matches('#');
+        let channel = |i: usize| {
+            digits
+                .get(i..i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+
+        match (digits.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Rgb(r, g, b)),
+            _ => Err(format!("\"{}\" isn't a color like \"#3399ff\"", hex)),
+        }
+    }
+
+    /// Return how bright the color looks, from 0.0 for black to 1.0 for white.
+    fn luminance(self) -> f32 {
+        let Rgb(r, g, b) = self;
+        (0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)) / 255.0
+    }
+
+    /// Return how colorful the color is, from 0.0 for a gray to 1.0 for a pure hue.
+    fn saturation(self) -> f32 {
+        let Rgb(r, g, b) = self;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+
+        match max {
+            0 => 0.0,
+            max => f32::from(max - min) / f32::from(max),
+        }
+    }
+
+    /// Return the color `amount` of the way towards `other`, where 0.0 is this color and 1.0 is `other`.
+    fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let channel = |from: u8, to: u8| {
+            (f32::from(from) + (f32::from(to) - f32::from(from)) * amount).round() as u8
+        };
+
+        Rgb(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+        )
+    }
+}
+
+/// The colors of the whole player.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ColorScheme {
+    /// The background of the main window and of text fields
+    pub(crate) window: Rgb,
+
+    /// The background of the dialogs and buttons
+    pub(crate) panel: Rgb,
+
+    pub(crate) text: Rgb,
+
+    /// The color of the progress bar, the waveform, the visualizer and the lyric that is being sung
+    pub(crate) accent: Rgb,
+}
+
+impl ColorScheme {
+    pub(crate) const LIGHT: ColorScheme = ColorScheme {
+        window: Rgb(0xff, 0xff, 0xff),
+        panel: Rgb(0xe8, 0xe8, 0xe8),
+        text: Rgb(0x1e, 0x1e, 0x1e),
+        accent: Rgb(0x33, 0x99, 0xff),
+    };
+
+    pub(crate) const DARK: ColorScheme = ColorScheme {
+        window: Rgb(0x1e, 0x1e, 0x1e),
+        panel: Rgb(0x2d, 0x2d, 0x2d),
+        text: Rgb(0xe6, 0xe6, 0xe6),
+        accent: Rgb(0x4d, 0xa6, 0xff),
+    };
+
+    /// Return whether the scheme has light text on a dark background.
+    pub(crate) fn is_dark(&self) -> bool {
+        self.window.luminance() < 0.5
+    }
+
+    /// Return the scheme with `accent` in place of its own, made lighter or darker until it stands out from the window.
+    fn with_accent(self, accent: Rgb) -> ColorScheme {
+        const STEPS: u8 = 10;
+
+        let (towards, stands_out): (Rgb, fn(f32) -> bool) = if self.is_dark() {
+            (Rgb(0xff, 0xff, 0xff), |luminance| luminance >= 0.45)
+        } else {
+            (Rgb(0, 0, 0), |luminance| luminance <= 0.55)
+        };
+
+        let accent = (0..=STEPS)
+            .map(|step| accent.mix(towards, f32::from(step) / f32::from(STEPS)))
+            .find(|accent| stands_out(accent.luminance()))
+            .unwrap_or(accent);
+
+        ColorScheme { accent, ..self }
+    }
+
+    /// Return fltk's color map for the scheme.
+    /// Besides the named colors, the gray ramp runs from the text to the window, so that anything drawn in a shade of gray
+    /// is inverted along with the background in dark schemes.
+    fn color_map(&self) -> Vec<ColorMap> {
+        /// The indices of the named colors in fltk's color map
+        const FOREGROUND: u8 = 0;
+        const BACKGROUND2: u8 = 7;
+        const INACTIVE: u8 = 8;
+        const SELECTION: u8 = 15;
+        const GRAY_RAMP: u8 = 32;
+        const GRAY_RAMP_LEN: u8 = 24;
+        const BACKGROUND: u8 = 49;
+
+        let entry = |index: u8, Rgb(r, g, b): Rgb| ColorMap { index, r, g, b };
+
+        let mut map: Vec<ColorMap> = (0..GRAY_RAMP_LEN)
+            .map(|step| {
+                let shade = self
+                    .text
+                    .mix(self.window, f32::from(step) / f32::from(GRAY_RAMP_LEN - 1));
+                entry(GRAY_RAMP + step, shade)
+            })
+            .collect();
+
+        map.extend([
+            entry(FOREGROUND, self.text),
+            entry(BACKGROUND2, self.window),
+            entry(INACTIVE, self.text.mix(self.window, 0.55)),
+            entry(SELECTION, self.accent),
+            entry(BACKGROUND, self.panel),
+        ]);
+
+        map
+    }
+}
+
+/// Whether the player is light, dark, or follows the desktop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ThemeMode {
+    Light,
+    Dark,
+    #[default]
+    System,
+}
+
+/// The `[theme]` table of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ThemeSettings {
+    #[serde(default)]
+    pub(crate) mode: ThemeMode,
+
+    /// Whether the accent is taken from the cover of the track that is playing
+    #[serde(default = "ThemeSettings::default_accent_from_cover")]
+    pub(crate) accent_from_cover: bool,
+
+    /// The names of the schemes in `schemes` that are used instead of the built-in light and dark ones
+    #[serde(default)]
+    pub(crate) light_scheme: Option<String>,
+    #[serde(default)]
+    pub(crate) dark_scheme: Option<String>,
+
+    /// The user's own schemes, by name, e.g. `[theme.schemes.nord]`
+    #[serde(default)]
+    pub(crate) schemes: HashMap<String, ColorScheme>,
+}
+
+impl Default for ThemeSettings {
+    fn default() -> ThemeSettings {
+        ThemeSettings {
+            mode: ThemeMode::default(),
+            accent_from_cover: ThemeSettings::default_accent_from_cover(),
+            light_scheme: None,
+            dark_scheme: None,
+            schemes: HashMap::new(),
+        }
+    }
+}
+
+impl ThemeSettings {
+    fn default_accent_from_cover() -> bool {
+        true
+    }
+
+    /// Return the scheme to use when the player is dark or light.
+    /// A scheme name that isn't in `schemes` is reported, and the built-in scheme is used instead.
+    pub(crate) fn scheme(&self, dark: bool) -> ColorScheme {
+        let (name, built_in) = match dark {
+            true => (&self.dark_scheme, ColorScheme::DARK),
+            false => (&self.light_scheme, ColorScheme::LIGHT),
+        };
+
+        let Some(name) = name else {
+            return built_in;
+        };
+
+        match self.schemes.get(name) {
+            Some(scheme) => *scheme,
+            None => {
+                eprintln!("Unknown color scheme \"{}\" in the theme", name);
+                built_in
+            }
+        }
+    }
+}
+
+/// The colors that the player is drawn in, which follow the desktop's dark mode and the cover of the track if the user wants.
+pub(crate) struct Theme {
+    settings: ThemeSettings,
+    dark: bool,
+
+    /// The most striking color of the current cover, if it has one and the accent follows the cover
+    cover_accent: Option<Rgb>,
+
+    /// Tells the theme when the desktop switches between light and dark, if the player follows it
+    system_receiver: Option<mpsc::Receiver<bool>>,
+}
+
+impl Theme {
+    /// How often the desktop is asked whether it is dark, when the player follows it
+    const SYSTEM_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// How small the cover is shrunk before its colors are counted, since every pixel isn't needed to tell its main color
+    const COVER_SAMPLE_SIZE: u32 = 32;
+
+    /// Create the theme from the `[theme]` table of the config file, and apply it to every widget.
+    pub(crate) fn new(settings: ThemeSettings) -> Theme {
+        let (dark, system_receiver) = Theme::follow_mode(settings.mode);
+
+        let theme = Theme {
+            settings,
+            dark,
+            cover_accent: None,
+            system_receiver,
+        };
+        theme.apply();
+
+        theme
+    }
+
+    /// Switch to the settings of a config file that was changed, keeping the accent of the cover if it is still wanted.
+    pub(crate) fn set_settings(&mut self, settings: ThemeSettings) {
+        if settings == self.settings {
+            return;
+        }
+
+        if settings.mode != self.settings.mode {
+            (self.dark, self.system_receiver) = Theme::follow_mode(settings.mode);
+        }
+        if !settings.accent_from_cover {
+            self.cover_accent = None;
+        }
+
+        self.settings = settings;
+        self.apply();
+    }
+
+    /// Return whether the player is dark in `mode`, and what tells it when the desktop switches, if it follows the desktop.
+    fn follow_mode(mode: ThemeMode) -> (bool, Option<mpsc::Receiver<bool>>) {
+        match mode {
+            ThemeMode::Light => (false, None),
+            ThemeMode::Dark => (true, None),
+            ThemeMode::System => {
+                let dark = system_prefers_dark().unwrap_or(false);
+                (dark, Some(Theme::watch_system(dark)))
+            }
+        }
+    }
+
+    /// Return the colors that are currently applied.
+    pub(crate) fn scheme(&self) -> ColorScheme {
+        let scheme = self.settings.scheme(self.dark);
+        match self.cover_accent {
+            Some(accent) => scheme.with_accent(accent),
+            None => scheme,
+        }
+    }
+
+    /// Take the accent from `cover`, the cover of the track that is now playing.
+    /// Without a cover, or one without any strong colors, the scheme's own accent is used.
+    pub(crate) fn set_cover(&mut self, cover: Option<&Picture>) {
+        if !self.settings.accent_from_cover {
+            return;
+        }
+
+        let cover_accent = cover.and_then(Theme::cover_accent);
+        if cover_accent != self.cover_accent {
+            self.cover_accent = cover_accent;
+            self.apply();
+        }
+    }
+
+    /// Switch between light and dark once the desktop does.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub(crate) fn update(&mut self) {
+        let Some(dark) = self
+            .system_receiver
+            .as_ref()
+            .and_then(|receiver| receiver.try_iter().last())
+        else {
+            return;
+        };
+
+        if dark != self.dark {
+            self.dark = dark;
+            self.apply();
+        }
+    }
+
+    /// Change fltk's colors to the theme's, and redraw every window.
+    fn apply(&self) {
+        ColorTheme::new(&self.scheme().color_map()).apply();
+        app::redraw();
+    }
+
+    /// Ask the desktop whether it is dark every so often in the background, since asking it takes a moment.
+    /// Only changes are sent, starting from `dark`.
+    fn watch_system(mut dark: bool) -> mpsc::Receiver<bool> {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Theme::SYSTEM_POLL_INTERVAL);
+
+                let Some(system_dark) = system_prefers_dark() else {
+                    continue;
+                };
+                if system_dark == dark {
+                    continue;
+                }
+                dark = system_dark;
+
+                // The player was closed
+                if sender.send(dark).is_err() {
+                    return;
+                }
+            }
+        });
+
+        receiver
+    }
+
+    /// Return the most striking color of `cover`, if it has any.
+    fn cover_accent(cover: &Picture) -> Option<Rgb> {
+        let image = image::load_from_memory(cover.data())
+            .inspect_err(|e| eprintln!("Unable to read the colors of the cover: {}", e))
+            .ok()?
+            .thumbnail(Theme::COVER_SAMPLE_SIZE, Theme::COVER_SAMPLE_SIZE)
+            .to_rgb8();
+
+        dominant_color(
+            image
+                .pixels()
+                .map(|pixel| Rgb(pixel[0], pixel[1], pixel[2])),
+        )
+    }
+}
+
+/// Return the most common strong color among `pixels`. Grays, and colors that are nearly black, don't count.
+/// Returns `None` if every pixel is gray, such as in a black and white photo.
+fn dominant_color(pixels: impl IntoIterator<Item = Rgb>) -> Option<Rgb> {
+    /// How many bits of each channel are kept when grouping similar colors together
+    const BUCKET_BITS: u8 = 3;
+    const MIN_SATURATION: f32 = 0.3;
+    const MIN_LUMINANCE: f32 = 0.15;
+
+    let mut buckets: HashMap<Rgb, (u32, [u32; 3])> = HashMap::new();
+
+    for pixel in pixels {
+        if pixel.saturation() < MIN_SATURATION || pixel.luminance() < MIN_LUMINANCE {
+            continue;
+        }
+
+        let Rgb(r, g, b) = pixel;
+        let shift = 8 - BUCKET_BITS;
+        let (count, sum) = buckets
+            .entry(Rgb(r >> shift, g >> shift, b >> shift))
+            .or_default();
+        *count += 1;
+        sum[0] += u32::from(r);
+        sum[1] += u32::from(g);
+        sum[2] += u32::from(b);
+    }
+
+    // Break ties by the color itself, so that the same cover always gets the same accent
+    let (_, (count, [r, g, b])) = buckets
+        .into_iter()
+        .max_by_key(|(bucket, (count, _))| (*count, *bucket))?;
+
+    Some(Rgb((r / count) as u8, (g / count) as u8, (b / count) as u8))
+}
+
+/// Return whether the desktop is set to dark mode, or `None` if that can't be told.
+fn system_prefers_dark() -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        let output = Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.interface", "color-scheme"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success());
+
+        match output {
+            Some(output) => parse_gnome_color_scheme(&String::from_utf8_lossy(&output.stdout)),
+            // Without GNOME's settings, go by the GTK theme, such as "Adwaita:dark"
+            None => std::env::var("GTK_THEME")
+                .ok()
+                .map(|theme| theme.to_lowercase().contains("dark")),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // The setting is only there while the desktop is dark
+        let output = Command::new("defaults")
+            .args(["read", "-g", "AppleInterfaceStyle"])
+            .output()
+            .ok()?;
+
+        Some(output.status.success() && String::from_utf8_lossy(&output.stdout).contains("Dark"))
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let output = Command::new("reg")
+            .args([
+                "query",
+                r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+                "/v",
+                "AppsUseLightTheme",
+            ])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+
+        Some(String::from_utf8_lossy(&output.stdout).contains("0x0"))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        None
+    }
+}
+
+/// Parse the output of `gsettings get org.gnome.desktop.interface color-scheme`, such as `'prefer-dark'`.
+#[cfg(target_os = "linux")]
+fn parse_gnome_color_scheme(output: &str) -> Option<bool> {
+    match output.trim().trim_matches('\'') {
+        "prefer-dark" => Some(true),
+        "default" | "prefer-light" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod rgb {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(Rgb::parse("#3399ff"), Ok(Rgb(0x33, 0x99, 0xff)));
+            assert_eq!(Rgb::parse("1E1E1E"), Ok(Rgb(0x1e, 0x1e, 0x1e)));
+        }
+
+        #[test]
+        fn test_parse_invalid() {
+            assert!(Rgb::parse("#39f").is_err());
+            assert!(Rgb::parse("#3399fg").is_err());
+            assert!(Rgb::parse("blue").is_err());
+            // Multi-byte characters can't be split into channels
+            assert!(Rgb::parse("#ééé").is_err());
+        }
+
+        #[test]
+        fn test_mix() {
+            let black = Rgb(0, 0, 0);
+            let white = Rgb(0xff, 0xff, 0xff);
+
+            assert_eq!(black.mix(white, 0.0), black);
+            assert_eq!(black.mix(white, 1.0), white);
+            assert_eq!(black.mix(white, 0.5), Rgb(0x80, 0x80, 0x80));
+        }
+    }
+
+    mod color_scheme {
+        use super::*;
+
+        #[test]
+        fn test_is_dark() {
+            assert!(ColorScheme::DARK.is_dark());
+            assert!(!ColorScheme::LIGHT.is_dark());
+        }
+
+        #[test]
+        fn test_dark_accent_is_lightened() {
+            let scheme = ColorScheme::DARK.with_accent(Rgb(0x10, 0x00, 0x40));
+            assert!(scheme.accent.luminance() >= 0.45);
+        }
+
+        #[test]
+        fn test_light_accent_is_darkened() {
+            let scheme = ColorScheme::LIGHT.with_accent(Rgb(0xff, 0xee, 0x80));
+            assert!(scheme.accent.luminance() <= 0.55);
+        }
+
+        #[test]
+        fn test_readable_accent_is_kept() {
+            let accent = Rgb(0x33, 0x99, 0xff);
+            assert_eq!(ColorScheme::DARK.with_accent(accent).accent, accent);
+        }
+
+        #[test]
+        fn test_gray_ramp_is_inverted_when_dark() {
+            let map = ColorScheme::DARK.color_map();
+            let shade = |index: u8| {
+                let entry = map.iter().find(|entry| entry.index == index).unwrap();
+                Rgb(entry.r, entry.g, entry.b)
+            };
+
+            // The darkest shade of the ramp is drawn in the text color, which is light in a dark scheme
+            assert_eq!(shade(32), ColorScheme::DARK.text);
+            assert_eq!(shade(55), ColorScheme::DARK.window);
+        }
+    }
+
+    mod theme_settings {
+        use super::*;
+
+        use std::fs;
+        use std::path::Path;
+
+        use crate::app::config::Config;
+
+        fn load(name: &str, contents: &str) -> ThemeSettings {
+            let path = std::env::temp_dir().join(format!(
+                "audio_player_test_theme_{}_{}.toml",
+                std::process::id(),
+                name
+            ));
+            fs::write(&path, contents).unwrap();
+
+            let settings = Config::load_from(&path).theme;
+            fs::remove_file(&path).unwrap();
+
+            settings
+        }
+
+        #[test]
+        fn test_load_missing_file() {
+            let settings = Config::load_from(Path::new("./does/not/exist.toml")).theme;
+            assert_eq!(settings, ThemeSettings::default());
+        }
+
+        #[test]
+        fn test_load_without_theme() {
+            let settings = load("without_theme", "[keybindings]\nnext = [\"Ctrl+N\"]\n");
+            assert_eq!(settings, ThemeSettings::default());
+        }
+
+        #[test]
+        fn test_custom_scheme() {
+            let settings = load(
+                "custom_scheme",
+                "[theme]\n\
+                 mode = \"dark\"\n\
+                 accent_from_cover = false\n\
+                 dark_scheme = \"nord\"\n\
+                 \n\
+                 [theme.schemes.nord]\n\
+                 window = \"#2e3440\"\n\
+                 panel = \"#3b4252\"\n\
+                 text = \"#eceff4\"\n\
+                 accent = \"#88c0d0\"\n",
+            );
+
+            assert_eq!(settings.mode, ThemeMode::Dark);
+            assert!(!settings.accent_from_cover);
+            assert_eq!(settings.scheme(true).window, Rgb(0x2e, 0x34, 0x40));
+            // The light scheme wasn't replaced
+            assert_eq!(settings.scheme(false), ColorScheme::LIGHT);
+        }
+
+        #[test]
+        fn test_unknown_scheme() {
+            let settings = ThemeSettings {
+                dark_scheme: Some("missing".to_string()),
+                ..ThemeSettings::default()
+            };
+
+            assert_eq!(settings.scheme(true), ColorScheme::DARK);
+        }
+
+        #[test]
+        fn test_invalid_color() {
+            // The whole config can't be parsed, so the defaults are used
+            let settings = load(
+                "invalid_color",
+                "[theme]\nmode = \"dark\"\n[theme.schemes.bad]\nwindow = \"red\"\n",
+            );
+            assert_eq!(settings, ThemeSettings::default());
+        }
+    }
+
+    mod dominant_color {
+        use super::*;
+
+        #[test]
+        fn test_most_common_color() {
+            let red = Rgb(0xd0, 0x20, 0x20);
+            let blue = Rgb(0x20, 0x20, 0xd0);
+            let pixels = [vec![red; 10], vec![blue; 20]].concat();
+
+            assert_eq!(dominant_color(pixels), Some(blue));
+        }
+
+        #[test]
+        fn test_grays_are_ignored() {
+            let gray = Rgb(0x80, 0x80, 0x80);
+            let orange = Rgb(0xff, 0x99, 0x00);
+            let pixels = [vec![gray; 100], vec![orange; 5]].concat();
+
+            assert_eq!(dominant_color(pixels), Some(orange));
+        }
+
+        #[test]
+        fn test_black_and_white() {
+            let pixels = [Rgb(0, 0, 0), Rgb(0xff, 0xff, 0xff), Rgb(0x40, 0x40, 0x40)];
+            assert_eq!(dominant_color(pixels), None);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod parse_gnome_color_scheme {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(parse_gnome_color_scheme("'prefer-dark'\n"), Some(true));
+            assert_eq!(parse_gnome_color_scheme("'default'\n"), Some(false));
+            assert_eq!(parse_gnome_color_scheme("No such key"), None);
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use lofty::file::AudioFile;
+
+use crate::app::queue::Queue;
+
+/// Return the length that the container of the file at `path` reports, for when the decoder can't tell how long a file is.
+/// Returns `None` if the file can't be read, or if it doesn't report a length.
+pub(crate) fn from_properties(path: &Path) -> Option<Duration> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let length = tagged_file.properties().duration();
+
+    (!length.is_zero()).then_some(length)
+}
+
+/// Work out how long the file at `path` is by reading the headers of its frames, without decoding any audio.
+/// This reads through the whole of an MP3, but only the start and end of an Ogg file.
+/// Returns `None` if the format is not supported, or if no frames could be found.
+pub(crate) fn scan_frames(path: &Path) -> Option<Duration> {
+    /// The extensions of MPEG audio files. Other formats aren't scanned for MPEG frames, since their data can look like them
+    const MPEG_EXTENSIONS: [&str; 4] = ["mp3", "mp2", "mp1", "mpga"];
+
+    let mut file = BufReader::new(File::open(path).ok()?);
+
+    // Every format is recognized by its first few bytes
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic).ok()?;
+    file.seek(SeekFrom::Start(0)).ok()?;
+
+    let is_mpeg = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            MPEG_EXTENSIONS
+                .iter()
+                .any(|mpeg| mpeg.eq_ignore_ascii_case(extension))
+        });
+
+    let length = if &magic == b"OggS" {
+        scan_ogg(&mut file)
+    } else if is_mpeg {
+        scan_mpeg(&mut file)
+    } else {
+        return None;
+    };
+
+    length.ok().flatten()
+}
+
+/// Add up the length of every frame of an MPEG audio file, such as an MP3.
+fn scan_mpeg(file: &mut BufReader<impl Read + Seek>) -> io::Result<Option<Duration>> {
+    skip_id3v2_tag(file)?;
+
+    let mut samples = 0;
+    let mut sample_rate = 0;
+    let mut header = [0; 4];
+
+    // Whether the last frame ended where this one starts, in which case its header can be trusted
+    let mut in_sync = false;
+
+    loop {
+        match file.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+
+        // An ID3v1 tag at the end of the file comes after the last frame
+        if &header[..3] == b"TAG" {
+            break;
+        }
+
+        let frame = match MpegFrame::parse(header) {
+            Some(frame) if !in_sync && !frame_follows(file, frame)? => None,
+            frame => frame,
+        };
+        in_sync = frame.is_some();
+
+        match frame {
+            Some(frame) => {
+                samples += u64::from(frame.samples);
+                sample_rate = frame.sample_rate;
+                // Seeking relatively keeps what is buffered, which matters since frames are small
+                file.seek_relative(i64::from(frame.len) - 4)?;
+            }
+            // Not a frame, so move on by one byte until the next frame is found
+            None => file.seek_relative(-3)?,
+        }
+    }
+
+    if sample_rate == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Duration::from_secs_f64(
+        samples as f64 / f64::from(sample_rate),
+    )))
+}
+
+/// Return whether more frames like `frame` follow it, since any four bytes in the junk between frames can look like a
+/// header. The file is left where it was, just after the header of `frame`.
+fn frame_follows(file: &mut BufReader<impl Read + Seek>, frame: MpegFrame) -> io::Result<bool> {
+    /// How many frames have to follow a frame that is found after junk before it is trusted
+    const FOLLOWING_FRAMES: usize = 2;
+
+    let start = file.stream_position()?;
+    let mut next = frame;
+    let mut follows = true;
+
+    for _ in 0..FOLLOWING_FRAMES {
+        file.seek_relative(i64::from(next.len) - 4)?;
+
+        let mut header = [0; 4];
+        match file.read_exact(&mut header) {
+            Ok(()) => {}
+            // A file with only a frame or two is trusted, as long as they end where it does
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if &header[..3] == b"TAG" {
+            break;
+        }
+
+        // Every frame of a file has the same layer and sample rate
+        match MpegFrame::parse(header) {
+            Some(following)
+                if following.samples == frame.samples
+                    && following.sample_rate == frame.sample_rate =>
+            {
+                next = following;
+            }
+            _ => {
+                follows = false;
+                break;
+            }
+        }
+    }
+
+    let end = file.stream_position()?;
+    file.seek_relative(start as i64 - end as i64)?;
+
+    Ok(follows)
+}
+
+/// Skip the ID3v2 tag at the start of a file, if it has one.
+fn skip_id3v2_tag(file: &mut BufReader<impl Read + Seek>) -> io::Result<()> {
+    const FOOTER_FLAG: u8 = 0x10;
+
+    let mut header = [0; 10];
+    if file.read_exact(&mut header).is_err() || &header[..3] != b"ID3" {
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(());
+    }
+
+    // The size is stored as four 7-bit bytes, and doesn't include the header or footer
+    let size = header[6..10]
+        .iter()
+        .fold(0, |size, &byte| (size << 7) | i64::from(byte & 0x7f));
+    let footer = if header[5] & FOOTER_FLAG != 0 { 10 } else { 0 };
+
+    file.seek_relative(size + footer)
+}
+
+/// What the header of an MPEG audio frame says about the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MpegFrame {
+    /// The length of the frame in bytes, including its header
+    len: u32,
+
+    samples: u32,
+    sample_rate: u32,
+}
+
+impl MpegFrame {
+    /// The bitrates in kbit/s, by bitrate index, for MPEG-1 layers I, II and III and MPEG-2 layers I and II/III
+    const BITRATES: [[u32; 15]; 5] = [
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ];
+
+    /// The sample rates of MPEG-1, which are halved for MPEG-2 and quartered for MPEG-2.5
+    const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    /// Parse the four bytes of a frame header. Returns `None` if they aren't a valid header.
+    fn parse(header: [u8; 4]) -> Option<MpegFrame> {
+        // Every header starts with 11 set bits
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+
+        // 3 is MPEG-1, 2 is MPEG-2, 0 is MPEG-2.5, and 1 is reserved
+        let version = (header[1] >> 3) & 0b11;
+        // 3 is layer I, 2 is layer II, 1 is layer III, and 0 is reserved
+        let layer = (header[1] >> 1) & 0b11;
+        let bitrate_index = usize::from(header[2] >> 4);
+        let sample_rate_index = usize::from((header[2] >> 2) & 0b11);
+        let padding = u32::from((header[2] >> 1) & 1);
+
+        // A bitrate index of 0 is a free bitrate, whose frames can't be measured from the header
+        if version == 1 || layer == 0 || !(1..15).contains(&bitrate_index) || sample_rate_index == 3
+        {
+            return None;
+        }
+
+        let mpeg1 = version == 3;
+        let bitrates = match (mpeg1, layer) {
+            (true, 3) => &MpegFrame::BITRATES[0],
+            (true, 2) => &MpegFrame::BITRATES[1],
+            (true, _) => &MpegFrame::BITRATES[2],
+            (false, 3) => &MpegFrame::BITRATES[3],
+            (false, _) => &MpegFrame::BITRATES[4],
+        };
+        let bitrate = bitrates[bitrate_index] * 1000;
+        let sample_rate = match version {
+            3 => MpegFrame::SAMPLE_RATES[sample_rate_index],
+            2 => MpegFrame::SAMPLE_RATES[sample_rate_index] / 2,
+            _ => MpegFrame::SAMPLE_RATES[sample_rate_index] / 4,
+        };
+
+        let (samples, len) = match layer {
+            3 => (384, (12 * bitrate / sample_rate + padding) * 4),
+            2 => (1152, 144 * bitrate / sample_rate + padding),
+            _ if mpeg1 => (1152, 144 * bitrate / sample_rate + padding),
+            _ => (576, 72 * bitrate / sample_rate + padding),
+        };
+
+        Some(MpegFrame {
+            len,
+            samples,
+            sample_rate,
+        })
+    }
+}
+
+/// Work out the length of an Ogg Vorbis or Opus file from the position of its last page.
+fn scan_ogg(file: &mut (impl Read + Seek)) -> io::Result<Option<Duration>> {
+    // The last page is well within this many bytes of the end
+    const TAIL_LEN: u64 = 64 * 1024;
+
+    // The first page holds the identification header, which has the sample rate
+    let mut start = [0; 512];
+    let start_len = read_up_to(file, &mut start)?;
+    let Some((sample_rate, pre_skip)) = ogg_sample_rate(&start[..start_len]) else {
+        return Ok(None);
+    };
+
+    let file_len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(file_len.saturating_sub(TAIL_LEN)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    // The granule position of the last page is how many samples there are up to its end
+    let Some(page_start) = tail.windows(4).rposition(|bytes| bytes == b"OggS") else {
+        return Ok(None);
+    };
+    let Some(granule) = tail.get(page_start + 6..page_start + 14) else {
+        return Ok(None);
+    };
+    let granule = u64::from_le_bytes(granule.try_into().unwrap_or_default());
+
+    let samples = granule.saturating_sub(pre_skip);
+    if samples == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(Duration::from_secs_f64(
+        samples as f64 / f64::from(sample_rate),
+    )))
+}
+
+/// Return the rate of the granule positions of an Ogg file from its first page, along with how many samples are skipped at the start.
+fn ogg_sample_rate(first_page: &[u8]) -> Option<(u32, u64)> {
+    // The packet starts after the page header and its table of segment lengths
+    let segments = usize::from(*first_page.get(26)?);
+    let packet = first_page.get(27 + segments..)?;
+
+    if packet.starts_with(b"\x01vorbis") {
+        let rate = packet.get(12..16)?;
+        let rate = u32::from_le_bytes(rate.try_into().ok()?);
+        return (rate > 0).then_some((rate, 0));
+    }
+
+    // Opus always counts its granule positions at 48 kHz
+    if packet.starts_with(b"OpusHead") {
+        let pre_skip = packet.get(10..12)?;
+        let pre_skip = u16::from_le_bytes(pre_skip.try_into().ok()?);
+        return Some((48000, u64::from(pre_skip)));
+    }
+
+    None
+}
+
+/// Fill as much of `buf` as the reader has, returning how much was read.
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            count => read += count,
+        }
+    }
+
+    Ok(read)
+}
+
+/// Scans the frames of tracks whose length is unknown in the background, since a long file can take a while to scan.
+/// This lives on the audio thread, which fills in the lengths of the queue as the scans finish.
+pub(crate) struct LengthScanner {
+    /// How many of the tracks that were added to the queue have been looked at. Tracks are added to the end of the queue,
+    /// and are looked at before they can be moved.
+    seen: usize,
+
+    /// The tracks for the scanning thread to scan, one after the other, so that a long queue doesn't start a thread for
+    /// every track
+    path_sender: mpsc::Sender<PathBuf>,
+
+    /// The lengths that were worked out, by path, since the track may have moved in the queue by the time its scan finishes
+    length_receiver: mpsc::Receiver<(PathBuf, Duration)>,
+}
+
+impl LengthScanner {
+    /// Start the thread that scans the tracks.
+    pub(crate) fn new() -> LengthScanner {
+        let (path_sender, path_receiver) = mpsc::channel::<PathBuf>();
+        let (length_sender, length_receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for path in path_receiver {
+                match scan_frames(&path) {
+                    Some(length) => {
+                        // The audio thread is gone, so nobody needs the lengths anymore
+                        if length_sender.send((path, length)).is_err() {
+                            return;
+                        }
+                    }
+                    None => eprintln!("Unable to work out the length of {}", path.display()),
+                }
+            }
+        });
+
+        LengthScanner {
+            seen: 0,
+            path_sender,
+            length_receiver,
+        }
+    }
+
+    /// Scan the track at `path` once the tracks before it have been scanned.
+    fn scan(&self, path: PathBuf) {
+        if let Err(e) = self.path_sender.send(path) {
+            eprintln!("Unable to work out the length of the track: {:?}", e);
+        }
+    }
+
+    /// Start scanning the tracks that were added to the queue without a length, and fill in the lengths of the scans that finished.
+    /// Returns `true` if the queue changed.
+    /// This function is intended to be called continuously in the audio thread's loop.
+    pub(crate) fn tick(&mut self, queue: &mut Queue) -> bool {
+        let unseen = queue.added() - self.seen;
+        let tracks = queue.tracks();
+        for track in &tracks[tracks.len().saturating_sub(unseen)..] {
+            // Streams have no length to find
+            if track.length.is_none() && track.stream.is_none() {
+                self.scan(track.path.clone());
+            }
+        }
+        self.seen = queue.added();
+
+        let mut changed = false;
+
+        for (path, length) in self.length_receiver.try_iter() {
+            // The same file can be queued more than once
+            let indices: Vec<_> = queue
+                .tracks()
+                .iter()
+                .enumerate()
+                .filter(|(_, track)| track.path == path && track.length.is_none())
+                .map(|(index, _)| index)
+                .collect();
+            for index in indices {
+                queue.set_length(index, length);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+
+    use crate::app::queue::QueuedTrack;
+
+    const TEST_FILES: &str = "./src/app/ui/tests/files/audio";
+
+    /// The header of an MPEG-1 layer III frame at 128 kbit/s and 44.1 kHz, which is 417 bytes long.
+    const FRAME_HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x00];
+
+    /// Create an MP3 with `frames` silent frames, after an ID3v2 tag and some junk.
+    fn mp3(frames: usize) -> Vec<u8> {
+        // A tag with 20 bytes of content
+        let mut bytes = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        bytes.extend([0; 20]);
+
+        // Junk that has to be skipped to find the first frame
+        bytes.extend([0x12, 0xff, 0x00]);
+
+        for _ in 0..frames {
+            bytes.extend(FRAME_HEADER);
+            bytes.extend([0; 413]);
+        }
+
+        bytes.extend(b"TAG");
+        bytes.extend([0; 125]);
+
+        bytes
+    }
+
+    fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("audio_player_{}_{}", std::process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    mod mpeg_frame {
+        use super::*;
+
+        #[test]
+        fn test_parse() {
+            assert_eq!(
+                MpegFrame::parse(FRAME_HEADER),
+                Some(MpegFrame {
+                    len: 417,
+                    samples: 1152,
+                    sample_rate: 44100,
+                })
+            );
+        }
+
+        #[test]
+        fn test_padding() {
+            assert_eq!(MpegFrame::parse([0xff, 0xfb, 0x92, 0x00]).unwrap().len, 418);
+        }
+
+        #[test]
+        fn test_mpeg2() {
+            // MPEG-2 layer III at 64 kbit/s and 22.05 kHz
+            assert_eq!(
+                MpegFrame::parse([0xff, 0xf3, 0x80, 0x00]),
+                Some(MpegFrame {
+                    len: 208,
+                    samples: 576,
+                    sample_rate: 22050,
+                })
+            );
+        }
+
+        #[test]
+        fn test_invalid() {
+            assert_eq!(MpegFrame::parse([0x00, 0xfb, 0x90, 0x00]), None);
+            // Free bitrate
+            assert_eq!(MpegFrame::parse([0xff, 0xfb, 0x00, 0x00]), None);
+            // Reserved sample rate
+            assert_eq!(MpegFrame::parse([0xff, 0xfb, 0x9c, 0x00]), None);
+        }
+    }
+
+    mod scan_mpeg {
+        use super::*;
+
+        #[test]
+        fn test_frames() {
+            let length = scan_mpeg(&mut BufReader::new(Cursor::new(mp3(100))))
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(length, Duration::from_secs_f64(100.0 * 1152.0 / 44100.0));
+        }
+
+        #[test]
+        fn test_false_sync() {
+            // A frame header in the junk, which isn't followed by another frame
+            let mut bytes = vec![0; 100];
+            bytes.extend(FRAME_HEADER);
+            bytes.extend([0; 10]);
+            bytes.extend(mp3(10));
+
+            let length = scan_mpeg(&mut BufReader::new(Cursor::new(bytes)))
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(length, Duration::from_secs_f64(10.0 * 1152.0 / 44100.0));
+        }
+
+        #[test]
+        fn test_no_frames() {
+            assert_eq!(
+                scan_mpeg(&mut BufReader::new(Cursor::new(vec![0; 1000]))).unwrap(),
+                None
+            );
+        }
+    }
+
+    mod scan_frames {
+        use super::*;
+
+        #[test]
+        fn test_mp3() {
+            let path = temp_file("scan.mp3", &mp3(10));
+
+            assert_eq!(
+                scan_frames(&path),
+                Some(Duration::from_secs_f64(10.0 * 1152.0 / 44100.0))
+            );
+        }
+
+        #[test]
+        fn test_ogg() {
+            let path = Path::new(TEST_FILES).join("without-metadata/test.ogg");
+
+            let scanned = scan_frames(&path).unwrap();
+            let expected = from_properties(&path).unwrap();
+
+            assert!(scanned.abs_diff(expected) < Duration::from_millis(50));
+        }
+
+        #[test]
+        fn test_not_mpeg() {
+            // Only MPEG files are searched for frames, whatever their data looks like
+            let path = temp_file("scan.wav", &mp3(10));
+
+            assert_eq!(scan_frames(&path), None);
+        }
+
+        #[test]
+        fn test_non_existent_file() {
+            assert_eq!(scan_frames(Path::new("does_not_exist.mp3")), None);
+        }
+    }
+
+    mod length_scanner {
+        use super::*;
+
+        #[test]
+        fn test_fills_in_length() {
+            let path = temp_file("scanner.mp3", &mp3(10));
+            let mut queue = Queue::new(QueuedTrack {
+                path: path.clone(),
+                length: None,
+                stream: None,
+                skipped_at: None,
+            });
+            let mut scanner = LengthScanner::new();
+
+            let mut changed = false;
+            for _ in 0..100 {
+                changed = scanner.tick(&mut queue);
+                if changed {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            assert!(changed);
+            assert_eq!(
+                queue.current().length,
+                Some(Duration::from_secs_f64(10.0 * 1152.0 / 44100.0))
+            );
+        }
+
+        #[test]
+        fn test_known_length() {
+            let mut queue = Queue::new(QueuedTrack {
+                path: PathBuf::from("does_not_exist.mp3"),
+                length: Some(Duration::from_secs(60)),
+                stream: None,
+                skipped_at: None,
+            });
+            let mut scanner = LengthScanner::new();
+
+            assert!(!scanner.tick(&mut queue));
+            assert_eq!(scanner.seen, 1);
+        }
+    }
+}
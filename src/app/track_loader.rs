@@ -0,0 +1,228 @@
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::app::audio_handler::AudioHandler;
+use crate::app::queue::QueuedTrack;
+use crate::app::replay_gain::ReplayGain;
+use crate::app::stream::{self, Stream};
+use crate::app::track_length;
+
+/// A source that plays a track, along with its entry in the queue.
+pub(crate) type OpenedTrack = (Box<dyn Source + Send>, QueuedTrack);
+
+/// What the loader is asked to do.
+enum Request {
+    Enqueue(PathBuf),
+    Open(Vec<PathBuf>),
+    GoBack { previous: PathBuf, current: PathBuf },
+    SetReplayGain(ReplayGain),
+}
+
+/// A track that the loader opened, and where it goes in the queue.
+pub(crate) enum Loaded {
+    /// The track goes at the end of the queue
+    Enqueue(OpenedTrack),
+
+    /// The track replaces the queue. The rest of the tracks that replace it follow as [`Loaded::Enqueue`]
+    Open(OpenedTrack),
+
+    /// The track before the current one, which plays again, followed by the current one from the start.
+    /// They are boxed so that the other kinds of tracks don't take up the space of two
+    GoBack {
+        previous: Box<OpenedTrack>,
+        current: Box<OpenedTrack>,
+    },
+}
+
+/// Opens tracks in the background, so that the audio thread doesn't stop answering while files are read or a station
+/// is connected to. Tracks are handed back in the order they were asked for.
+pub(crate) struct TrackLoader {
+    request_sender: mpsc::Sender<Request>,
+    loaded_receiver: mpsc::Receiver<Loaded>,
+}
+
+impl TrackLoader {
+    /// Start the thread that opens the tracks, evening out their volume with `replay_gain`.
+    pub(crate) fn start(mut replay_gain: ReplayGain) -> TrackLoader {
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (loaded_sender, loaded_receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for request in request_receiver {
+                // The audio thread is gone, so nothing needs opening anymore
+                if TrackLoader::handle(request, &mut replay_gain, &loaded_sender).is_err() {
+                    return;
+                }
+            }
+        });
+
+        TrackLoader {
+            request_sender,
+            loaded_receiver,
+        }
+    }
+
+    /// Open a track to go at the end of the queue.
+    pub(crate) fn enqueue(&self, path: PathBuf) {
+        self.request(Request::Enqueue(path));
+    }
+
+    /// Open tracks to replace the queue with, starting with the first one that can be played.
+    /// If none of them can be played, nothing is handed back and the queue stays as it was.
+    pub(crate) fn open(&self, paths: Vec<PathBuf>) {
+        self.request(Request::Open(paths));
+    }
+
+    /// Open the track before the current one again, along with the current one, so that the queue can go back to it.
+    pub(crate) fn go_back(&self, previous: PathBuf, current: PathBuf) {
+        self.request(Request::GoBack { previous, current });
+    }
+
+    /// Even out the volume of the tracks that are opened from now on with `replay_gain`.
+    pub(crate) fn set_replay_gain(&self, replay_gain: ReplayGain) {
+        self.request(Request::SetReplayGain(replay_gain));
+    }
+
+    /// Return the tracks that were opened since this was last called, in the order they were asked for.
+    pub(crate) fn loaded(&self) -> Vec<Loaded> {
+        self.loaded_receiver.try_iter().collect()
+    }
+
+    /// Open the tracks of `request`, handing each one over as soon as it is opened.
+    /// # Errors
+    /// - If the audio thread is gone
+    fn handle(
+        request: Request,
+        replay_gain: &mut ReplayGain,
+        loaded_sender: &mpsc::Sender<Loaded>,
+    ) -> Result<(), mpsc::SendError<Loaded>> {
+        match request {
+            Request::Enqueue(path) => {
+                TrackLoader::enqueue_opened(path, *replay_gain, loaded_sender)?
+            }
+            Request::Open(paths) => {
+                let mut paths = paths.into_iter();
+                let Some(first) = open_first(&mut paths, *replay_gain) else {
+                    eprintln!("There is nothing to play in what was opened");
+                    return Ok(());
+                };
+
+                // The first track starts playing while the rest are opened
+                loaded_sender.send(Loaded::Open(first))?;
+                for path in paths {
+                    TrackLoader::enqueue_opened(path, *replay_gain, loaded_sender)?;
+                }
+            }
+            Request::GoBack { previous, current } => {
+                let opened = open_track(previous, *replay_gain)
+                    .and_then(|previous| Ok((previous, open_track(current, *replay_gain)?)));
+                match opened {
+                    Ok((previous, current)) => loaded_sender.send(Loaded::GoBack {
+                        previous: Box::new(previous),
+                        current: Box::new(current),
+                    })?,
+                    Err(e) => eprintln!("{}. Unable to go back to the previous track", e),
+                }
+            }
+            Request::SetReplayGain(new_replay_gain) => *replay_gain = new_replay_gain,
+        }
+
+        Ok(())
+    }
+
+    /// Open the track at `path` and hand it over to go at the end of the queue. A track that can't be played is skipped.
+    /// # Errors
+    /// - If the audio thread is gone
+    fn enqueue_opened(
+        path: PathBuf,
+        replay_gain: ReplayGain,
+        loaded_sender: &mpsc::Sender<Loaded>,
+    ) -> Result<(), mpsc::SendError<Loaded>> {
+        match open_track(path, replay_gain) {
+            Ok(opened) => loaded_sender.send(Loaded::Enqueue(opened)),
+            Err(e) => {
+                eprintln!("{}. Skipping...", e);
+                Ok(())
+            }
+        }
+    }
+
+    fn request(&self, request: Request) {
+        if let Err(e) = self.request_sender.send(request) {
+            eprintln!("Unable to open the track: {:?}", e);
+        }
+    }
+}
+
+/// Open the first of `paths` that can be played, skipping the ones before it.
+pub(crate) fn open_first(
+    paths: &mut impl Iterator<Item = PathBuf>,
+    replay_gain: ReplayGain,
+) -> Option<OpenedTrack> {
+    paths.find_map(|path| {
+        open_track(path, replay_gain)
+            .inspect_err(|e| eprintln!("{}. Skipping...", e))
+            .ok()
+    })
+}
+
+/// Open a file or an internet stream, returning the source that plays it and its entry in the queue.
+/// Files are made louder or quieter by their ReplayGain tags, if `replay_gain` is turned on.
+/// # Errors
+/// - If the file does not exist, or the station can't be reached
+/// - If the format is not supported
+fn open_track(path: PathBuf, replay_gain: ReplayGain) -> Result<OpenedTrack, String> {
+    if stream::is_stream(&path) {
+        return open_stream(path);
+    }
+
+    let file =
+        File::open(&path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+    let decoder = AudioHandler::build_decoder(file)
+        .map_err(|e| format!("Unable to play {}: {}", path.display(), e))?;
+
+    // Some files don't say how long they are until they've been decoded, so ask the container instead.
+    // If that fails too, the audio thread works the length out in the background
+    let length = decoder
+        .total_duration()
+        .or_else(|| track_length::from_properties(&path));
+    let source: Box<dyn Source + Send> = match replay_gain.factor(&path) {
+        Some(factor) => Box::new(decoder.amplify(factor)),
+        None => Box::new(decoder),
+    };
+    let track = QueuedTrack {
+        length,
+        path,
+        stream: None,
+        skipped_at: None,
+    };
+
+    Ok((source, track))
+}
+
+/// Connect to an internet stream, such as a radio station. Streams have no length, since they don't end.
+fn open_stream(path: PathBuf) -> Result<OpenedTrack, String> {
+    let url = path.to_string_lossy().into_owned();
+
+    let stream = Stream::open(&url).map_err(|e| format!("Unable to connect to {}: {}", url, e))?;
+    let decoder = Decoder::builder()
+        .with_data(stream.reader)
+        // The station says what format it sends, which saves guessing it from the audio
+        .with_mime_type(&stream.content_type)
+        // A stream can only be played forwards
+        .with_seekable(false)
+        .build()
+        .map_err(|e| format!("Unable to play {}: {}", url, e))?;
+
+    let track = QueuedTrack {
+        path,
+        length: None,
+        stream: Some(stream.info),
+        skipped_at: None,
+    };
+
+    Ok((Box::new(decoder), track))
+}
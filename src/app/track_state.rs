@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::ab_loop::AbLoop;
+use crate::app::markers::Bookmark;
+
+/// Everything that is remembered about a single track between runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct TrackState {
+    /// The A–B loop that was set on the track
+    pub(crate) ab_loop: Option<AbLoop>,
+
+    /// The bookmarks that were added to the track, sorted by their position
+    pub(crate) bookmarks: Vec<Bookmark>,
+}
+
+/// Saves the state of every track to disk, keyed by the track's path.
+#[derive(Debug, Default)]
+pub(crate) struct TrackStateStore {
+    /// Where the store is saved. If this is `None`, the store only lives in memory.
+    path: Option<PathBuf>,
+
+    tracks: HashMap<String, TrackState>,
+}
+
+impl TrackStateStore {
+    const FILE_NAME: &str = "tracks.json";
+
+    /// Load the store from the user's data directory.
+    /// If the store can't be read, start with an empty one so that the player still works.
+    pub(crate) fn load() -> TrackStateStore {
+        let Some(path) = dirs::data_dir().map(|dir| dir.join("audio_player").join(Self::FILE_NAME))
+        else {
+            eprintln!("Unable to find the data directory, track state will not be saved");
+            return TrackStateStore::default();
+        };
+
+        TrackStateStore::load_from(path)
+    }
+
+    /// Load the store from a specific file. A missing or invalid file results in an empty store.
+    pub(crate) fn load_from(path: PathBuf) -> TrackStateStore {
+        let tracks = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Unable to parse {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+
+        TrackStateStore {
+            path: Some(path),
+            tracks,
+        }
+    }
+
+    /// Return the saved state of a track, or the default state if nothing was saved.
+    pub(crate) fn get(&self, track_path: &Path) -> TrackState {
+        self.tracks
+            .get(&TrackStateStore::key(track_path))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Change the state of a track and save the store to disk.
+    pub(crate) fn update<F>(&mut self, track_path: &Path, f: F)
+    where
+        F: FnOnce(&mut TrackState),
+    {
+        let key = TrackStateStore::key(track_path);
+        let state = self.tracks.entry(key.clone()).or_default();
+        f(state);
+
+        // Don't keep entries around that have nothing worth remembering
+        if *state == TrackState::default() {
+            self.tracks.remove(&key);
+        }
+
+        if let Err(e) = self.save() {
+            eprintln!("Unable to save track state: {}", e);
+        }
+    }
+
+    /// Write the store to disk.
+    /// # Errors
+    /// - If the directory of the store could not be created
+    /// - If the store could not be written
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let contents = serde_json::to_string_pretty(&self.tracks).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+
+    /// Return the key that a track is stored under. The path is made absolute where possible,
+    /// so that the same track is found no matter which directory the player was started from.
+    fn key(track_path: &Path) -> String {
+        fs::canonicalize(track_path)
+            .unwrap_or_else(|_| track_path.to_path_buf())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::app::ab_loop::PracticeSettings;
+
+    fn temp_store_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("audio_player_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    mod get {
+        use super::*;
+
+        #[test]
+        fn test_unknown_track() {
+            let store = TrackStateStore::default();
+
+            assert_eq!(store.get(Path::new("nothing.mp3")), TrackState::default());
+        }
+    }
+
+    mod update {
+        use super::*;
+
+        #[test]
+        fn test_saved_between_loads() {
+            let path = temp_store_path("saved_between_loads.json");
+            let track = Path::new("track.mp3");
+            let ab_loop = AbLoop::new(
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                PracticeSettings::default(),
+            );
+
+            let mut store = TrackStateStore::load_from(path.clone());
+            store.update(track, |state| state.ab_loop = Some(ab_loop));
+
+            let store = TrackStateStore::load_from(path.clone());
+            assert_eq!(store.get(track).ab_loop, Some(ab_loop));
+
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_default_state_is_removed() {
+            let mut store = TrackStateStore::default();
+            let track = Path::new("track.mp3");
+
+            store.update(track, |state| {
+                state.ab_loop = Some(AbLoop::new(
+                    Duration::ZERO,
+                    Duration::from_secs(1),
+                    PracticeSettings::default(),
+                ))
+            });
+            store.update(track, |state| state.ab_loop = None);
+
+            assert!(store.tracks.is_empty());
+        }
+
+        #[test]
+        fn test_invalid_file() {
+            let path = temp_store_path("invalid_file.json");
+            fs::write(&path, "not json").unwrap();
+
+            let store = TrackStateStore::load_from(path.clone());
+            assert!(store.tracks.is_empty());
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
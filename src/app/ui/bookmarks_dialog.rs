@@ -0,0 +1,191 @@
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use fltk::{
+    app, browser::HoldBrowser, button::Button, dialog, group::Group, prelude::*, window::Window,
+};
+
+use crate::app::ui::markers::Markers;
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// A dialog that lists the bookmarks and chapters of the current track, and lets the user add, remove and jump to bookmarks.
+#[derive(Clone)]
+pub struct BookmarksDialog {
+    window: Window,
+    browser: HoldBrowser,
+    markers: Markers,
+
+    /// What each line in the browser points to
+    lines: Rc<RefCell<Vec<MarkerLine>>>,
+}
+
+/// A line in the list of markers.
+#[derive(Debug, Clone, Copy)]
+struct MarkerLine {
+    position: Duration,
+
+    /// The index of the bookmark, or `None` if the line is a chapter
+    bookmark_index: Option<usize>,
+}
+
+impl BookmarksDialog {
+    const WIDTH: i32 = 300;
+    const HEIGHT: i32 = 300;
+
+    const CHAPTER_ICON: &str = "\u{f0bb9}";
+    const BOOKMARK_ICON: &str = "\u{f00c0}";
+
+    /// Create the bookmarks dialog. The dialog stays hidden until it is shown.
+    /// `current_audio_pos` is where new bookmarks are added.
+    pub fn new(markers: Markers, current_audio_pos: Rc<RefCell<Duration>>) -> BookmarksDialog {
+        const MARGIN: i32 = 15;
+        const BTN_WIDTH: i32 = 80;
+        const BTN_HEIGHT: i32 = 25;
+        const BTN_SPACING: i32 = 10;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(BookmarksDialog::WIDTH, BookmarksDialog::HEIGHT)
+            .with_label("Bookmarks");
+
+        let btn_y = BookmarksDialog::HEIGHT - MARGIN - BTN_HEIGHT;
+
+        let mut browser = HoldBrowser::new(
+            MARGIN,
+            MARGIN,
+            BookmarksDialog::WIDTH - 2 * MARGIN,
+            btn_y - 2 * MARGIN,
+            None,
+        );
+        browser.set_column_char('\t');
+        browser.set_column_widths(&[70]);
+
+        let btn_x = |i: i32| MARGIN + i * (BTN_WIDTH + BTN_SPACING);
+        let mut add_btn = Button::new(btn_x(0), btn_y, BTN_WIDTH, BTN_HEIGHT, "Add");
+        let mut jump_btn = Button::new(btn_x(1), btn_y, BTN_WIDTH, BTN_HEIGHT, "Go to");
+        let mut delete_btn = Button::new(btn_x(2), btn_y, BTN_WIDTH, BTN_HEIGHT, "Delete");
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let bookmarks_dialog = BookmarksDialog {
+            window,
+            browser,
+            markers,
+            lines: Rc::new(RefCell::new(Vec::new())),
+        };
+        bookmarks_dialog.refresh();
+
+        let dialog = bookmarks_dialog.clone();
+        add_btn.set_callback(move |_| {
+            let position = *current_audio_pos.borrow();
+            let default_name = format!("Bookmark {}", dialog.markers.bookmarks().len() + 1);
+
+            let Some(name) = dialog::input_default("Bookmark name", &default_name) else {
+                return;
+            };
+
+            let name = match name.trim() {
+                "" => default_name,
+                name => name.to_string(),
+            };
+
+            dialog.markers.add_bookmark(position, name);
+            dialog.refresh();
+        });
+
+        let dialog = bookmarks_dialog.clone();
+        jump_btn.set_callback(move |_| dialog.jump_to_selected());
+
+        // Double clicking a line also jumps to it
+        let dialog = bookmarks_dialog.clone();
+        bookmarks_dialog.browser.clone().set_callback(move |_| {
+            if app::event_clicks() {
+                dialog.jump_to_selected();
+            }
+        });
+
+        let dialog = bookmarks_dialog.clone();
+        delete_btn.set_callback(move |_| {
+            // Chapters are part of the file, so only bookmarks can be deleted
+            if let Some(MarkerLine {
+                bookmark_index: Some(index),
+                ..
+            }) = dialog.selected()
+            {
+                dialog.markers.remove_bookmark(index);
+                dialog.refresh();
+            }
+        });
+
+        bookmarks_dialog
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Fill the browser with every chapter and bookmark, in the order they appear in the track.
+    fn refresh(&self) {
+        let chapters = self.markers.chapters().iter().map(|chapter| {
+            (
+                chapter.start,
+                None,
+                BookmarksDialog::CHAPTER_ICON,
+                chapter.title.clone(),
+            )
+        });
+        let bookmarks = self
+            .markers
+            .bookmarks()
+            .into_iter()
+            .enumerate()
+            .map(|(i, bookmark)| {
+                (
+                    bookmark.position,
+                    Some(i),
+                    BookmarksDialog::BOOKMARK_ICON,
+                    bookmark.name,
+                )
+            });
+
+        let mut entries: Vec<_> = chapters.chain(bookmarks).collect();
+        entries.sort_by_key(|(position, ..)| *position);
+
+        let mut browser = self.browser.clone();
+        let mut lines = self.lines.borrow_mut();
+        browser.clear();
+        lines.clear();
+
+        for (position, bookmark_index, icon, name) in entries {
+            // "@." stops the browser from treating the name as formatting
+            browser.add(&format!(
+                "{}\t@.{} {}",
+                ProgressBar::format_duration(position),
+                icon,
+                name
+            ));
+            lines.push(MarkerLine {
+                position,
+                bookmark_index,
+            });
+        }
+    }
+
+    /// Return the selected line, if a line is selected.
+    fn selected(&self) -> Option<MarkerLine> {
+        // Browser lines start at 1, and 0 means that nothing is selected
+        let line = usize::try_from(self.browser.value() - 1).ok()?;
+
+        self.lines.borrow().get(line).copied()
+    }
+
+    fn jump_to_selected(&self) {
+        if let Some(line) = self.selected() {
+            self.markers.jump_to(line.position);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use fltk::{
+    button::{Button, CheckButton},
+    dialog::{self, NativeFileChooser, NativeFileChooserOptions, NativeFileChooserType},
+    frame::Frame,
+    group::Group,
+    input::Input,
+    menu::Choice,
+    misc::{Progress, Spinner},
+    prelude::*,
+    window::Window,
+};
+
+use crate::app::dsp::DspSettings;
+use crate::app::export::{ExportFormat, ExportOptions, ExportProgress, Exporter};
+use crate::cli::parse_timestamp;
+
+/// A dialog to export the current track, or a selection of it, to WAV, FLAC or Opus.
+pub struct ExportDialog {
+    window: Window,
+
+    /// Shows the user how far along the export is
+    progress: Progress,
+
+    /// Tells the user whether the export is running, finished or failed
+    status: Frame,
+
+    /// The receiver for the running export's progress updates, if there is one
+    progress_receiver: Rc<RefCell<Option<mpsc::Receiver<ExportProgress>>>>,
+}
+
+/// The widgets that make up the export settings.
+#[derive(Clone)]
+struct ExportForm {
+    format: Choice,
+    sample_rate: Choice,
+    bit_depth: Choice,
+    start: Input,
+    end: Input,
+
+    /// Whether the speed and normalization below are applied, which can only be changed while it is checked
+    apply_dsp: CheckButton,
+    speed: Spinner,
+    normalize: CheckButton,
+
+    copy_tags: CheckButton,
+}
+
+impl ExportDialog {
+    const WIDTH: i32 = 300;
+    const HEIGHT: i32 = 360;
+
+    /// The sample rates that can be chosen, where `None` keeps the original sample rate.
+    const SAMPLE_RATES: [Option<u32>; 5] =
+        [None, Some(44100), Some(48000), Some(88200), Some(96000)];
+    const BIT_DEPTHS: [u16; 4] = [16, 24, 32, 8];
+
+    /// Create the export dialog. The dialog stays hidden until its window is shown.
+    pub fn new(track_path: &str) -> ExportDialog {
+        const LABEL_WIDTH: i32 = 110;
+        const FIELD_WIDTH: i32 = 170;
+        const ROW_HEIGHT: i32 = 25;
+        const ROW_SPACING: i32 = 30;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(ExportDialog::WIDTH, ExportDialog::HEIGHT)
+            .with_label("Export");
+
+        let row_y = |row: i32| 15 + row * ROW_SPACING;
+
+        let mut format = Choice::new(LABEL_WIDTH, row_y(0), FIELD_WIDTH, ROW_HEIGHT, "Format");
+        for export_format in ExportFormat::ALL {
+            format.add_choice(&export_format.extension().to_uppercase());
+        }
+        format.set_value(0);
+
+        let mut sample_rate = Choice::new(
+            LABEL_WIDTH,
+            row_y(1),
+            FIELD_WIDTH,
+            ROW_HEIGHT,
+            "Sample rate",
+        );
+        for rate in ExportDialog::SAMPLE_RATES {
+            match rate {
+                Some(rate) => sample_rate.add_choice(&format!("{} Hz", rate)),
+                None => sample_rate.add_choice("Original"),
+            };
+        }
+        sample_rate.set_value(0);
+
+        let mut bit_depth =
+            Choice::new(LABEL_WIDTH, row_y(2), FIELD_WIDTH, ROW_HEIGHT, "Bit depth");
+        for depth in ExportDialog::BIT_DEPTHS {
+            bit_depth.add_choice(&format!("{}-bit", depth));
+        }
+        bit_depth.set_value(0);
+
+        let mut start = Input::new(LABEL_WIDTH, row_y(3), FIELD_WIDTH, ROW_HEIGHT, "Start");
+        start.set_tooltip("Leave empty to export from the beginning, e.g. 1:30");
+
+        let mut end = Input::new(LABEL_WIDTH, row_y(4), FIELD_WIDTH, ROW_HEIGHT, "End");
+        end.set_tooltip("Leave empty to export until the end, e.g. 2:45");
+
+        let mut apply_dsp = CheckButton::new(
+            LABEL_WIDTH,
+            row_y(5),
+            FIELD_WIDTH,
+            ROW_HEIGHT,
+            "Apply effects",
+        );
+
+        let mut speed =
+            Spinner::new(LABEL_WIDTH, row_y(6), FIELD_WIDTH, ROW_HEIGHT, None).with_label("Speed");
+        speed.set_range(0.25, 4.0);
+        speed.set_step(0.05);
+        speed.set_value(f64::from(DspSettings::default().speed));
+        speed.set_tooltip("Changes the pitch along with the speed");
+        speed.deactivate();
+
+        let mut normalize =
+            CheckButton::new(LABEL_WIDTH, row_y(7), FIELD_WIDTH, ROW_HEIGHT, "Normalize");
+        normalize.set_tooltip("Scale the audio so that its peak is just below 0 dBFS");
+        normalize.deactivate();
+
+        let copy_tags =
+            CheckButton::new(LABEL_WIDTH, row_y(8), FIELD_WIDTH, ROW_HEIGHT, "Copy tags");
+        copy_tags.set_checked(true);
+
+        let mut progress = Progress::new(15, row_y(9) + 5, ExportDialog::WIDTH - 30, 15, "");
+        progress.set_minimum(0.0);
+        progress.set_maximum(1.0);
+        progress.set_value(0.0);
+
+        let status = Frame::new(15, row_y(10), FIELD_WIDTH, ROW_HEIGHT, "");
+
+        let mut export_btn = Button::new(
+            ExportDialog::WIDTH - 95,
+            row_y(10),
+            80,
+            ROW_HEIGHT,
+            "Export…",
+        );
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let form = ExportForm {
+            format,
+            sample_rate,
+            bit_depth,
+            start,
+            end,
+            apply_dsp: apply_dsp.clone(),
+            speed: speed.clone(),
+            normalize: normalize.clone(),
+            copy_tags,
+        };
+
+        // The effects can only be changed while they are applied
+        apply_dsp.set_callback(move |apply_dsp| {
+            if apply_dsp.is_checked() {
+                speed.activate();
+                normalize.activate();
+            } else {
+                speed.deactivate();
+                normalize.deactivate();
+            }
+        });
+
+        let export_dialog = ExportDialog {
+            window,
+            progress,
+            status,
+            progress_receiver: Rc::new(RefCell::new(None)),
+        };
+
+        // Clone a bunch of values that will be moved into the callback
+        let track_path = PathBuf::from(track_path);
+        let progress_receiver = Rc::clone(&export_dialog.progress_receiver);
+        let mut status = export_dialog.status.clone();
+
+        export_btn.set_callback(move |_| {
+            // Only run one export at a time
+            if progress_receiver.borrow().is_some() {
+                return;
+            }
+
+            let options = match ExportDialog::read_options(&form) {
+                Ok(options) => options,
+                Err(e) => {
+                    dialog::alert_default(&e);
+                    return;
+                }
+            };
+
+            let Some(output) = ExportDialog::choose_output_path(&track_path, options.format) else {
+                return;
+            };
+
+            let (sender, receiver) = mpsc::channel();
+            Exporter::spawn(track_path.clone(), output, options, sender);
+
+            *progress_receiver.borrow_mut() = Some(receiver);
+            status.set_label("Exporting…");
+        });
+
+        export_dialog
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Update the progress of the running export, if there is one.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) {
+        // Drain the messages first, so the receiver isn't borrowed while an alert is shown
+        let messages: Vec<ExportProgress> = match self.progress_receiver.borrow().as_ref() {
+            Some(receiver) => receiver.try_iter().collect(),
+            None => return,
+        };
+
+        for message in messages {
+            match message {
+                ExportProgress::Progress(progress) => self.progress.set_value(progress),
+                ExportProgress::Finished => {
+                    self.progress.set_value(1.0);
+                    self.status.set_label("Done");
+                    *self.progress_receiver.borrow_mut() = None;
+                }
+                ExportProgress::Failed(e) => {
+                    self.progress.set_value(0.0);
+                    self.status.set_label("Failed");
+                    *self.progress_receiver.borrow_mut() = None;
+                    dialog::alert_default(&e);
+                }
+            }
+        }
+    }
+
+    /// Turn the values of the form into export options.
+    /// # Errors
+    /// - If the start or end timestamps are invalid
+    /// - If the options are not supported by the chosen format
+    fn read_options(form: &ExportForm) -> Result<ExportOptions, String> {
+        let format = ExportFormat::ALL[form.format.value().max(0) as usize];
+
+        let mut options = ExportOptions::new(format);
+        options.sample_rate = ExportDialog::SAMPLE_RATES[form.sample_rate.value().max(0) as usize];
+        options.bit_depth = ExportDialog::BIT_DEPTHS[form.bit_depth.value().max(0) as usize];
+        options.start = ExportDialog::read_timestamp(&form.start)?;
+        options.end = ExportDialog::read_timestamp(&form.end)?;
+        options.dsp = form.apply_dsp.is_checked().then(|| DspSettings {
+            speed: form.speed.value() as f32,
+            normalize: form.normalize.is_checked(),
+        });
+        options.copy_tags = form.copy_tags.is_checked();
+
+        // Opus is always 48 kHz, so don't make the user pick it by hand
+        if format == ExportFormat::Opus {
+            options.sample_rate = None;
+        }
+
+        options.validate().map_err(|e| e.to_string())?;
+
+        Ok(options)
+    }
+
+    /// Read an optional timestamp from an input, where an empty input means no timestamp.
+    fn read_timestamp(input: &Input) -> Result<Option<Duration>, String> {
+        let value = input.value();
+
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+
+        parse_timestamp(&value).map(Some)
+    }
+
+    /// Ask the user where to save the exported file. Returns `None` if they cancel.
+    fn choose_output_path(track_path: &Path, format: ExportFormat) -> Option<PathBuf> {
+        let extension = format.extension();
+
+        let mut chooser = NativeFileChooser::new(NativeFileChooserType::BrowseSaveFile);
+        chooser.set_option(NativeFileChooserOptions::SaveAsConfirm);
+        chooser.set_filter(&format!("*.{}", extension));
+
+        // Suggest the name of the track with the new extension
+        if let Some(stem) = track_path.file_stem() {
+            chooser.set_preset_file(&format!("{}.{}", stem.to_string_lossy(), extension));
+        }
+
+        chooser.show();
+
+        let mut output = chooser.filename();
+        if output.as_os_str().is_empty() {
+            return None;
+        }
+
+        if output.extension().is_none() {
+            output.set_extension(extension);
+        }
+
+        Some(output)
+    }
+}
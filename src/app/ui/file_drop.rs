@@ -0,0 +1,35 @@
+use std::sync::mpsc;
+
+use fltk::{app, enums::Event};
+
+use crate::app::Message;
+use crate::app::queue;
+
+/// Queues the files, folders and playlists that are dropped onto the main window from a file manager.
+#[derive(Clone)]
+pub struct FileDrop {
+    audio_sender: mpsc::Sender<Message>,
+}
+
+impl FileDrop {
+    pub fn new(audio_sender: mpsc::Sender<Message>) -> FileDrop {
+        FileDrop { audio_sender }
+    }
+
+    /// Handle an event sent to the main window. Returns `true` if the event was used.
+    pub fn handle(&self, event: Event) -> bool {
+        match event {
+            // Taking the drag events is what lets the files be dropped, and they arrive as a paste once they are
+            Event::DndEnter | Event::DndDrag | Event::DndLeave | Event::DndRelease => true,
+            Event::Paste => {
+                for path in queue::dropped_paths(&app::event_text()) {
+                    if let Err(e) = self.audio_sender.send(Message::Enqueue(path)) {
+                        eprintln!("Unable to queue the dropped track: {:?}", e);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use fltk::{
+    browser::Browser,
+    enums::{Align, Color, Font, FrameType},
+    frame::Frame,
+    group::Group,
+    prelude::*,
+};
+
+use crate::app::keybindings::{Keybindings, SeekSteps};
+
+/// A panel that covers the main window and lists every keyboard shortcut.
+#[derive(Clone)]
+pub struct HelpOverlay {
+    group: Group,
+}
+
+impl HelpOverlay {
+    const MARGIN: i32 = 20;
+    const TITLE_HEIGHT: i32 = 30;
+    const HINT_HEIGHT: i32 = 20;
+
+    /// Create the overlay, hidden. It has to be created after everything else in the window, so that it is drawn on top.
+    /// The seek shortcuts are described with how far `seek_steps` jump.
+    pub fn new(
+        win_width: i32,
+        win_height: i32,
+        keybindings: &Keybindings,
+        seek_steps: SeekSteps,
+    ) -> HelpOverlay {
+        let mut group = Group::new(0, 0, win_width, win_height, None);
+        group.set_frame(FrameType::FlatBox);
+        group.set_color(Color::Background2);
+
+        let mut title = Frame::new(
+            HelpOverlay::MARGIN,
+            HelpOverlay::MARGIN / 2,
+            win_width - 2 * HelpOverlay::MARGIN,
+            HelpOverlay::TITLE_HEIGHT,
+            "Keyboard shortcuts",
+        );
+        title.set_label_font(Font::HelveticaBold);
+
+        let mut browser = Browser::new(
+            HelpOverlay::MARGIN,
+            HelpOverlay::MARGIN / 2 + HelpOverlay::TITLE_HEIGHT,
+            win_width - 2 * HelpOverlay::MARGIN,
+            win_height - HelpOverlay::MARGIN - HelpOverlay::TITLE_HEIGHT - HelpOverlay::HINT_HEIGHT,
+            None,
+        );
+        browser.set_column_char('\t');
+        browser.set_column_widths(&[140]);
+
+        for (action, keys) in keybindings.bindings() {
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+            // "@." stops the browser from treating the keys and description as formatting
+            browser.add(&format!(
+                "@.{}\t@.{}",
+                keys.join(", "),
+                action.description(seek_steps)
+            ));
+        }
+
+        let mut hint = Frame::new(
+            HelpOverlay::MARGIN,
+            win_height - HelpOverlay::MARGIN / 2 - HelpOverlay::HINT_HEIGHT,
+            win_width - 2 * HelpOverlay::MARGIN,
+            HelpOverlay::HINT_HEIGHT,
+            "Press any key to close",
+        );
+        hint.set_align(Align::Center | Align::Inside);
+        hint.set_label_color(Color::Dark2);
+
+        group.end();
+        group.hide();
+
+        HelpOverlay { group }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.group.visible()
+    }
+
+    /// Show the overlay if it is hidden, and hide it otherwise.
+    pub fn toggle(&self) {
+        if self.is_visible() {
+            self.hide();
+        } else {
+            self.group.clone().show();
+        }
+    }
+
+    pub fn hide(&self) {
+        let mut group = self.group.clone();
+        group.hide();
+
+        // The overlay covers the whole window, so everything behind it needs to be drawn again
+        if let Some(mut parent) = group.parent() {
+            parent.redraw();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+/// How much of the player is shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// The whole window, with every component
+    #[default]
+    Full,
+
+    /// A small strip that stays on top of other windows, with only the cover, the title, play/pause, next and the progress bar
+    Mini,
+}
+
+impl Layout {
+    /// Return the other layout, for the button that switches between them.
+    pub fn toggled(self) -> Layout {
+        match self {
+            Layout::Full => Layout::Mini,
+            Layout::Mini => Layout::Full,
+        }
+    }
+}
+
+/// The part of the window that a component lays itself out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Bounds {
+    pub const fn new(x: i32, y: i32, width: i32, height: i32) -> Bounds {
+        Bounds {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn center_x(&self) -> i32 {
+        self.x + self.width / 2
+    }
+
+    pub fn center_y(&self) -> i32 {
+        self.y + self.height / 2
+    }
+}
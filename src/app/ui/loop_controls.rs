@@ -0,0 +1,204 @@
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use fltk::{button::Button, prelude::*, window::Window};
+
+use crate::app::Message;
+use crate::app::ab_loop::{AbLoop, PracticeSettings};
+use crate::app::track_state::TrackStateStore;
+use crate::app::ui::practice_dialog::PracticeDialog;
+
+/// The A–B loop of the current track, shared between every widget that shows or changes it.
+#[derive(Clone)]
+pub struct LoopRegion {
+    ab_loop: Rc<RefCell<Option<AbLoop>>>,
+
+    /// The sender that tells the audio thread about the loop
+    audio_sender: mpsc::Sender<Message>,
+
+    /// Where the loop is saved, so that it is still there the next time the track is opened
+    track_state: Rc<RefCell<TrackStateStore>>,
+    track_path: PathBuf,
+}
+
+impl LoopRegion {
+    /// Load the saved loop of a track, and tell the audio thread about it.
+    pub fn new(
+        track_path: PathBuf,
+        track_state: Rc<RefCell<TrackStateStore>>,
+        audio_sender: mpsc::Sender<Message>,
+    ) -> LoopRegion {
+        let saved_loop = track_state.borrow().get(&track_path).ab_loop;
+
+        let region = LoopRegion {
+            ab_loop: Rc::new(RefCell::new(None)),
+            audio_sender,
+            track_state,
+            track_path,
+        };
+
+        // The loop is already saved, so it only needs to be applied
+        if saved_loop.is_some() {
+            region.apply(saved_loop);
+        }
+
+        region
+    }
+
+    /// Return the current loop, if there is one.
+    pub fn get(&self) -> Option<AbLoop> {
+        *self.ab_loop.borrow()
+    }
+
+    /// Show a loop without applying it yet, e.g. while one of its handles is being dragged.
+    pub fn preview(&self, ab_loop: Option<AbLoop>) {
+        *self.ab_loop.borrow_mut() = ab_loop;
+    }
+
+    /// Apply a loop to the audio and save it.
+    pub fn set(&self, ab_loop: Option<AbLoop>) {
+        self.apply(ab_loop);
+
+        self.track_state
+            .borrow_mut()
+            .update(&self.track_path, |state| state.ab_loop = ab_loop);
+    }
+
+    /// Apply a loop to the audio without saving it.
+    fn apply(&self, ab_loop: Option<AbLoop>) {
+        *self.ab_loop.borrow_mut() = ab_loop;
+
+        if let Err(e) = self.audio_sender.send(Message::SetLoop(ab_loop)) {
+            eprintln!("Unable to set loop: {:?}", e);
+        }
+    }
+}
+
+/// The buttons to mark the start and end of a loop, clear it, and change the practice settings.
+pub struct LoopControls {
+    practice_window: Window,
+}
+
+impl LoopControls {
+    const BTN_SIZE: i32 = 30;
+    const BTN_Y: i32 = 369;
+    const BTN_SPACING: i32 = 40;
+
+    const CLEAR_BUTTON: &str = "\u{f0156}";
+    const PRACTICE_BUTTON: &str = "\u{f07da}";
+
+    /// Create the loop controls.
+    /// `current_audio_pos` is read whenever the start or end of the loop is marked.
+    pub fn new(
+        win_width: i32,
+        region: LoopRegion,
+        current_audio_pos: Rc<RefCell<Duration>>,
+    ) -> LoopControls {
+        // The practice settings that new loops start with
+        let practice = Rc::new(Cell::new(
+            region
+                .get()
+                .map(|ab_loop| ab_loop.practice)
+                .unwrap_or_default(),
+        ));
+
+        // The start that has been marked before the end has been
+        let pending_start = Rc::new(Cell::new(None));
+
+        let first_btn_x = (win_width - Self::BTN_SIZE - 3 * Self::BTN_SPACING) / 2;
+        let btn_x = |i: i32| first_btn_x + i * Self::BTN_SPACING;
+
+        let mut start_btn =
+            LoopControls::create_button(btn_x(0), "A", "Mark the start of the loop");
+        {
+            let region = region.clone();
+            let current_audio_pos = Rc::clone(&current_audio_pos);
+            let pending_start = Rc::clone(&pending_start);
+
+            start_btn.set_callback(move |_| {
+                let pos = *current_audio_pos.borrow();
+
+                match region.get() {
+                    // Move the start of the existing loop
+                    Some(ab_loop) => {
+                        LoopControls::set_points(&region, pos, ab_loop.end, ab_loop.practice)
+                    }
+                    None => pending_start.set(Some(pos)),
+                }
+            });
+        }
+
+        let mut end_btn = LoopControls::create_button(btn_x(1), "B", "Mark the end of the loop");
+        {
+            let region = region.clone();
+            let practice = Rc::clone(&practice);
+
+            end_btn.set_callback(move |_| {
+                let pos = *current_audio_pos.borrow();
+
+                match (region.get(), pending_start.take()) {
+                    // Move the end of the existing loop
+                    (Some(ab_loop), _) => {
+                        LoopControls::set_points(&region, ab_loop.start, pos, ab_loop.practice)
+                    }
+                    (None, Some(start)) => {
+                        LoopControls::set_points(&region, start, pos, practice.get())
+                    }
+                    // Without a start, loop from the beginning of the track
+                    (None, None) => {
+                        LoopControls::set_points(&region, Duration::ZERO, pos, practice.get())
+                    }
+                }
+            });
+        }
+
+        let mut clear_btn =
+            LoopControls::create_button(btn_x(2), Self::CLEAR_BUTTON, "Clear the loop");
+        {
+            let region = region.clone();
+            clear_btn.set_callback(move |_| region.set(None));
+        }
+
+        let practice_dialog = PracticeDialog::new(region, practice);
+        let practice_window = practice_dialog.window();
+
+        let mut practice_btn =
+            LoopControls::create_button(btn_x(3), Self::PRACTICE_BUTTON, "Practice settings");
+        practice_btn.set_callback(move |_| practice_dialog.show());
+
+        LoopControls { practice_window }
+    }
+
+    /// Return a handle to the window of the practice settings.
+    pub fn practice_window(&self) -> Window {
+        self.practice_window.clone()
+    }
+
+    /// Set the loop to be between two points. A loop with no length is ignored.
+    fn set_points(region: &LoopRegion, a: Duration, b: Duration, practice: PracticeSettings) {
+        if a == b {
+            return;
+        }
+
+        region.set(Some(AbLoop::new(a, b, practice)));
+    }
+
+    fn create_button(btn_x: i32, label: &str, tooltip: &str) -> Button {
+        let mut btn = Button::default()
+            .with_size(Self::BTN_SIZE, Self::BTN_SIZE)
+            .with_pos(btn_x, Self::BTN_Y)
+            .with_label(label);
+        btn.set_tooltip(tooltip);
+
+        // Remove focus border and background, like the playback buttons
+        btn.clear_visible_focus();
+        btn.set_frame(fltk::enums::FrameType::NoBox);
+
+        btn
+    }
+}
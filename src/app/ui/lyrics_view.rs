@@ -0,0 +1,224 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use fltk::{
+    app::{self, MouseButton},
+    draw,
+    enums::{Color, Event, Font},
+    frame::Frame,
+    prelude::{WidgetBase, WidgetExt},
+};
+
+use crate::app::Message;
+use crate::app::lyrics::Lyrics;
+
+/// Shows the lyrics of the track under its title and artist.
+/// Synced lyrics follow the audio, highlighting the line that is being sung and keeping it in the middle of the view.
+/// Clicking a synced line jumps to it, and lyrics that aren't synced can be scrolled with the mouse wheel instead.
+pub struct LyricsView {
+    widget: Frame,
+
+    lyrics: Rc<Lyrics>,
+
+    current_audio_pos: Rc<RefCell<Duration>>,
+
+    /// The line that is being sung and how much of it has been sung, as last drawn.
+    /// The view is only redrawn when this changes, rather than every time the position does
+    shown: Option<(usize, usize)>,
+}
+
+impl LyricsView {
+    const Y: i32 = 221;
+    const WIDTH: i32 = 360;
+
+    /// How many lines fit in the view. Odd, so that the line being sung can sit in the middle
+    const VISIBLE_LINES: usize = 5;
+    const LINE_HEIGHT: i32 = 14;
+    const FONTSIZE: i32 = 12;
+
+    /// The accent of the theme
+    const SUNG_COLOR: Color = Color::Selection;
+    const PLACEHOLDER: &str = "No lyrics";
+
+    /// The height of the view, which the widgets below it leave room for
+    const HEIGHT: i32 = LyricsView::VISIBLE_LINES as i32 * LyricsView::LINE_HEIGHT;
+
+    /// Create the lyrics view. `lyrics` is `None` if the track has no lyrics.
+    pub fn new(
+        win_width: i32,
+        lyrics: Option<Lyrics>,
+        current_audio_pos: Rc<RefCell<Duration>>,
+        audio_sender: mpsc::Sender<Message>,
+    ) -> LyricsView {
+        let x = (win_width - LyricsView::WIDTH) / 2;
+        let mut widget = Frame::new(x, LyricsView::Y, LyricsView::WIDTH, LyricsView::HEIGHT, "");
+
+        let lyrics = Rc::new(lyrics.unwrap_or_default());
+
+        // The first line shown for lyrics that aren't synced, which the mouse wheel moves
+        let scroll = Rc::new(Cell::new(0));
+
+        {
+            let lyrics = Rc::clone(&lyrics);
+            let current_audio_pos = Rc::clone(&current_audio_pos);
+            let scroll = Rc::clone(&scroll);
+
+            widget.draw(move |widget| {
+                LyricsView::draw_lines(widget, &lyrics, *current_audio_pos.borrow(), scroll.get());
+            });
+        }
+
+        {
+            let lyrics = Rc::clone(&lyrics);
+            let current_audio_pos = Rc::clone(&current_audio_pos);
+
+            widget.handle(move |widget, event| match event {
+                Event::Push if app::event_mouse_button() == MouseButton::Left => {
+                    let top =
+                        LyricsView::top_line(&lyrics, *current_audio_pos.borrow(), scroll.get());
+                    let row = (app::event_y() - widget.y()) / LyricsView::LINE_HEIGHT;
+
+                    let Some(time) = lyrics
+                        .lines()
+                        .get(top + row as usize)
+                        .and_then(|line| line.time)
+                    else {
+                        return false;
+                    };
+
+                    if let Err(e) = audio_sender.send(Message::Seek(time)) {
+                        eprintln!("Unable to jump to the lyric: {:?}", e);
+                    }
+                    true
+                }
+                Event::MouseWheel if !lyrics.is_synced() => {
+                    let last_top = lyrics
+                        .lines()
+                        .len()
+                        .saturating_sub(LyricsView::VISIBLE_LINES);
+                    let top = if app::event_dy_value() > 0 {
+                        (scroll.get() + 1).min(last_top)
+                    } else {
+                        scroll.get().saturating_sub(1)
+                    };
+
+                    scroll.set(top);
+                    widget.redraw();
+                    true
+                }
+                _ => false,
+            });
+        }
+
+        LyricsView {
+            widget,
+            lyrics,
+            current_audio_pos,
+            shown: None,
+        }
+    }
+
+    /// Follow the audio's position, redrawing the lyrics once a different line or word is being sung.
+    /// This function is intended to be called continuously in the app's main loop, after the progress bar is updated.
+    pub fn update(&mut self) {
+        let position = *self.current_audio_pos.borrow();
+        let shown = self
+            .lyrics
+            .line_at(position)
+            .map(|index| (index, self.lyrics.lines()[index].sung_len(position)));
+
+        if shown != self.shown {
+            self.shown = shown;
+            self.widget.redraw();
+        }
+    }
+
+    /// Return the index of the first line to show. Synced lyrics keep the line being sung in the middle.
+    fn top_line(lyrics: &Lyrics, position: Duration, scroll: usize) -> usize {
+        if !lyrics.is_synced() {
+            return scroll;
+        }
+
+        let last_top = lyrics
+            .lines()
+            .len()
+            .saturating_sub(LyricsView::VISIBLE_LINES);
+        lyrics
+            .line_at(position)
+            .map_or(0, |index| {
+                index.saturating_sub(LyricsView::VISIBLE_LINES / 2)
+            })
+            .min(last_top)
+    }
+
+    fn draw_lines(widget: &Frame, lyrics: &Lyrics, position: Duration, scroll: usize) {
+        draw::push_clip(widget.x(), widget.y(), widget.w(), widget.h());
+
+        if lyrics.lines().is_empty() {
+            draw::set_font(Font::HelveticaItalic, LyricsView::FONTSIZE);
+            draw::set_draw_color(Color::Inactive);
+            LyricsView::draw_centered(
+                widget,
+                LyricsView::PLACEHOLDER,
+                LyricsView::VISIBLE_LINES / 2,
+            );
+            draw::pop_clip();
+            return;
+        }
+
+        let top = LyricsView::top_line(lyrics, position, scroll);
+        let current = lyrics.line_at(position);
+
+        for (row, (index, line)) in lyrics
+            .lines()
+            .iter()
+            .enumerate()
+            .skip(top)
+            .take(LyricsView::VISIBLE_LINES)
+            .enumerate()
+        {
+            if Some(index) != current {
+                draw::set_font(Font::Helvetica, LyricsView::FONTSIZE);
+                draw::set_draw_color(Color::Inactive);
+                LyricsView::draw_centered(widget, &line.text, row);
+                continue;
+            }
+
+            // Draw the part that has been sung in a different color, which moves word by word if the line has word timings
+            draw::set_font(Font::HelveticaBold, LyricsView::FONTSIZE);
+            let (text_width, _) = draw::measure(&line.text, false);
+            let x = widget.x() + (widget.w() - text_width) / 2;
+            let (sung, unsung) = line.text.split_at(line.sung_len(position));
+            let (sung_width, _) = draw::measure(sung, false);
+
+            draw::set_draw_color(LyricsView::SUNG_COLOR);
+            LyricsView::draw_row(sung, x, widget, row);
+            draw::set_draw_color(Color::Foreground);
+            LyricsView::draw_row(unsung, x + sung_width, widget, row);
+        }
+
+        draw::pop_clip();
+    }
+
+    /// Draw `text` in the middle of a row of the view, with the font that is currently set.
+    fn draw_centered(widget: &Frame, text: &str, row: usize) {
+        let (text_width, _) = draw::measure(text, false);
+
+        LyricsView::draw_row(
+            text,
+            widget.x() + (widget.w() - text_width) / 2,
+            widget,
+            row,
+        );
+    }
+
+    fn draw_row(text: &str, x: i32, widget: &Frame, row: usize) {
+        let baseline = widget.y() + (row as i32 + 1) * LyricsView::LINE_HEIGHT - draw::descent();
+
+        draw::draw_text(text, x, baseline);
+    }
+}
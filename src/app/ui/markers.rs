@@ -0,0 +1,133 @@
+use std::{cell::RefCell, path::PathBuf, rc::Rc, sync::mpsc, time::Duration};
+
+use crate::app::Message;
+use crate::app::chapters::{self, Chapter};
+use crate::app::markers::{self, Bookmark};
+use crate::app::track_state::TrackStateStore;
+
+/// The bookmarks and chapters of the current track, shared between every widget that shows or changes them.
+#[derive(Clone)]
+pub struct Markers {
+    /// The bookmarks of the track, sorted by their position
+    bookmarks: Rc<RefCell<Vec<Bookmark>>>,
+
+    /// The chapters that are embedded in the track
+    chapters: Rc<Vec<Chapter>>,
+
+    /// The sender that jumps to a marker
+    audio_sender: mpsc::Sender<Message>,
+
+    /// Where the bookmarks are saved, so that they are still there the next time the track is opened
+    track_state: Rc<RefCell<TrackStateStore>>,
+    track_path: PathBuf,
+}
+
+impl Markers {
+    /// Load the saved bookmarks and embedded chapters of a track.
+    pub fn new(
+        track_path: PathBuf,
+        track_state: Rc<RefCell<TrackStateStore>>,
+        audio_sender: mpsc::Sender<Message>,
+    ) -> Markers {
+        let bookmarks = track_state.borrow().get(&track_path).bookmarks;
+        let chapters = chapters::read_chapters(&track_path);
+
+        Markers {
+            bookmarks: Rc::new(RefCell::new(bookmarks)),
+            chapters: Rc::new(chapters),
+            audio_sender,
+            track_state,
+            track_path,
+        }
+    }
+
+    /// Return the bookmarks of the track, sorted by their position.
+    pub fn bookmarks(&self) -> Vec<Bookmark> {
+        self.bookmarks.borrow().clone()
+    }
+
+    /// Return the chapters of the track, sorted by their start.
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Return the name of the bookmark within `tolerance` of `position`, or otherwise of the chapter that `position` is in.
+    pub fn label_at(&self, position: Duration, tolerance: Duration) -> Option<String> {
+        markers::label_at(
+            &self.bookmarks.borrow(),
+            &self.chapters,
+            position,
+            tolerance,
+        )
+    }
+
+    /// Add a bookmark and save it.
+    pub fn add_bookmark(&self, position: Duration, name: String) {
+        {
+            let mut bookmarks = self.bookmarks.borrow_mut();
+
+            // Keep the bookmarks sorted, so they can be listed and stepped through in order
+            let index = bookmarks.partition_point(|bookmark| bookmark.position <= position);
+            bookmarks.insert(index, Bookmark { position, name });
+        }
+
+        self.save();
+    }
+
+    /// Remove the bookmark at `index` and save the change.
+    pub fn remove_bookmark(&self, index: usize) {
+        {
+            let mut bookmarks = self.bookmarks.borrow_mut();
+            if index >= bookmarks.len() {
+                return;
+            }
+            bookmarks.remove(index);
+        }
+
+        self.save();
+    }
+
+    /// Jump to a position in the track.
+    pub fn jump_to(&self, position: Duration) {
+        if let Err(e) = self.audio_sender.send(Message::Seek(position)) {
+            eprintln!("Unable to jump to marker: {:?}", e);
+        }
+    }
+
+    /// Jump to the next chapter, or the next bookmark if the track has no chapters.
+    pub fn next(&self, current_pos: Duration) {
+        if let Some(position) = markers::next_marker(&self.step_positions(), current_pos) {
+            self.jump_to(position);
+        }
+    }
+
+    /// Jump to the start of the current chapter, or to the previous one if the current chapter has only just started.
+    /// Bookmarks are used instead if the track has no chapters.
+    pub fn previous(&self, current_pos: Duration) {
+        self.jump_to(markers::previous_marker(
+            &self.step_positions(),
+            current_pos,
+        ));
+    }
+
+    /// Return the positions that next and previous step between.
+    fn step_positions(&self) -> Vec<Duration> {
+        if self.chapters.is_empty() {
+            self.bookmarks
+                .borrow()
+                .iter()
+                .map(|bookmark| bookmark.position)
+                .collect()
+        } else {
+            self.chapters.iter().map(|chapter| chapter.start).collect()
+        }
+    }
+
+    fn save(&self) {
+        let bookmarks = self.bookmarks();
+
+        self.track_state
+            .borrow_mut()
+            .update(&self.track_path, |state| state.bookmarks = bookmarks);
+    }
+}
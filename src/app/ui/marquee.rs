@@ -0,0 +1,338 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use fltk::{
+    draw,
+    enums::{Event, Font},
+    frame::Frame,
+    prelude::{WidgetBase, WidgetExt},
+};
+
+/// Which way a line of text is read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    LeftToRight,
+    RightToLeft,
+}
+
+impl Direction {
+    /// Return the direction of `text`, which is the direction of its first letter.
+    /// Text without any letters, such as a number, is read left to right.
+    fn of(text: &str) -> Direction {
+        text.chars()
+            .find(|c| c.is_alphabetic())
+            .filter(|&c| Direction::is_right_to_left(c))
+            .map_or(Direction::LeftToRight, |_| Direction::RightToLeft)
+    }
+
+    /// Return whether `c` is a letter of a script that is written right to left, such as Hebrew or Arabic.
+    fn is_right_to_left(c: char) -> bool {
+        matches!(
+            c,
+            '\u{0590}'..='\u{08ff}' | '\u{fb1d}'..='\u{fdff}' | '\u{fe70}'..='\u{feff}'
+        )
+    }
+}
+
+/// What the marquee shows, and how far it has scrolled.
+struct MarqueeState {
+    text: String,
+    direction: Direction,
+    font: Font,
+    fontsize: i32,
+
+    /// How wide the whole text is, which is wider than the widget if the text doesn't fit
+    text_width: i32,
+
+    /// The text cut short to fit the widget, which is shown while it isn't scrolling
+    ellipsized: String,
+
+    /// How far the text has scrolled, in pixels. This is 0.0 while it isn't scrolling
+    offset: f64,
+
+    /// Whether the text is scrolling. It scrolls while the mouse is over it, and every so often on its own
+    scrolling: bool,
+    hovered: bool,
+
+    /// When the text last stopped scrolling, or was last changed
+    idle_since: Instant,
+    last_update: Instant,
+}
+
+impl MarqueeState {
+    fn fits(&self, width: i32) -> bool {
+        self.text_width <= width
+    }
+}
+
+/// A line of text that is cut short with an ellipsis if it doesn't fit, and scrolls to show the rest of it.
+/// Text that is written right to left, such as Hebrew or Arabic, is drawn and scrolled right to left.
+#[derive(Clone)]
+pub struct Marquee {
+    widget: Frame,
+    state: Rc<RefCell<MarqueeState>>,
+}
+
+impl Marquee {
+    /// How fast the text scrolls, in pixels per second
+    const SPEED: f64 = 30.0;
+
+    /// How long a text that doesn't fit waits between scrolling on its own
+    const SCROLL_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// The space between the end of the text and its start coming around again
+    const GAP: i32 = 40;
+
+    const ELLIPSIS: &str = "…";
+
+    pub fn new(x: i32, y: i32, width: i32, text: &str, font: Font, fontsize: i32) -> Marquee {
+        let mut widget = Frame::new(x, y, width, fontsize, None);
+
+        let state = Rc::new(RefCell::new(MarqueeState {
+            text: String::new(),
+            direction: Direction::LeftToRight,
+            font,
+            fontsize,
+            text_width: 0,
+            ellipsized: String::new(),
+            offset: 0.0,
+            scrolling: false,
+            hovered: false,
+            idle_since: Instant::now(),
+            last_update: Instant::now(),
+        }));
+
+        {
+            let state = Rc::clone(&state);
+            widget.draw(move |widget| Marquee::draw_text(widget, &state.borrow()));
+        }
+
+        {
+            let state = Rc::clone(&state);
+            widget.handle(move |_, event| match event {
+                // Taking the mouse entering the text is what lets its tooltip show
+                Event::Enter => {
+                    let mut state = state.borrow_mut();
+                    state.hovered = true;
+                    state.last_update = Instant::now();
+                    true
+                }
+                Event::Leave => {
+                    state.borrow_mut().hovered = false;
+                    true
+                }
+                _ => false,
+            });
+        }
+
+        let mut marquee = Marquee { widget, state };
+        marquee.set_text(text);
+
+        marquee
+    }
+
+    pub fn widget(&self) -> &Frame {
+        &self.widget
+    }
+
+    /// Show a different text, from the start.
+    pub fn set_text(&mut self, text: &str) {
+        let width = self.widget.w();
+
+        {
+            let mut state = self.state.borrow_mut();
+            let (font, fontsize) = (state.font, state.fontsize);
+            let measure = |text: &str| text_width(text, font, fontsize);
+
+            state.text = text.to_string();
+            state.direction = Direction::of(text);
+            state.text_width = measure(text);
+            state.ellipsized = Marquee::ellipsize(text, width, measure);
+            state.offset = 0.0;
+            state.scrolling = false;
+            state.idle_since = Instant::now();
+
+            // Only text that is cut short needs a tooltip to show the rest of it
+            if state.fits(width) {
+                self.widget.set_tooltip("");
+            } else {
+                self.widget.set_tooltip(text);
+            }
+        }
+
+        self.widget.redraw();
+    }
+
+    /// Scroll the text if it doesn't fit and it is time to.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) {
+        let width = self.widget.w();
+        let mut state = self.state.borrow_mut();
+
+        let elapsed = state.last_update.elapsed();
+        state.last_update = Instant::now();
+
+        if state.fits(width) || !self.widget.visible_r() {
+            return;
+        }
+
+        if !state.scrolling {
+            if !state.hovered && state.idle_since.elapsed() < Marquee::SCROLL_INTERVAL {
+                return;
+            }
+            state.scrolling = true;
+        }
+
+        state.offset += Marquee::SPEED * elapsed.as_secs_f64();
+
+        // Once the start of the text has come back around, stop unless the mouse is still over it
+        let cycle = f64::from(state.text_width + Marquee::GAP);
+        if state.offset >= cycle {
+            if state.hovered {
+                state.offset -= cycle;
+            } else {
+                state.offset = 0.0;
+                state.scrolling = false;
+                state.idle_since = Instant::now();
+            }
+        }
+
+        self.widget.redraw();
+    }
+
+    /// Return as much of the start of `text` as fits in `max_width` along with an ellipsis, or all of it if it fits.
+    /// `measure` returns how wide a text is drawn. Text is only cut between characters, so that CJK text isn't cut in half.
+    fn ellipsize(text: &str, max_width: i32, measure: impl Fn(&str) -> i32) -> String {
+        if measure(text) <= max_width {
+            return text.to_string();
+        }
+
+        // Find the longest start that fits, trying shorter ones from the end
+        text.char_indices()
+            .map(|(i, _)| i)
+            .rev()
+            .map(|end| format!("{}{}", text[..end].trim_end(), Marquee::ELLIPSIS))
+            .find(|ellipsized| measure(ellipsized) <= max_width)
+            .unwrap_or_else(|| Marquee::ELLIPSIS.to_string())
+    }
+
+    fn draw_text(widget: &Frame, state: &MarqueeState) {
+        draw::set_font(state.font, state.fontsize);
+        draw::set_draw_color(widget.label_color());
+
+        // Center the line of text vertically
+        let baseline = widget.y() + (widget.h() + draw::height()) / 2 - draw::descent();
+
+        draw::push_clip(widget.x(), widget.y(), widget.w(), widget.h());
+
+        if state.fits(widget.w()) || !state.scrolling {
+            let text = if state.fits(widget.w()) {
+                &state.text
+            } else {
+                &state.ellipsized
+            };
+            let width = draw::measure(text, false).0;
+            Marquee::draw_run(
+                text,
+                state.direction,
+                widget.x() + (widget.w() - width) / 2,
+                width,
+                baseline,
+            );
+        } else {
+            // Draw the text twice, so that its start comes around again after its end scrolls past
+            let offset = state.offset as i32;
+            let cycle = state.text_width + Marquee::GAP;
+
+            for start in [0, cycle] {
+                let x = match state.direction {
+                    Direction::LeftToRight => widget.x() - offset + start,
+                    // Right-to-left text starts at the right edge and scrolls to the right
+                    Direction::RightToLeft => {
+                        widget.x() + widget.w() + offset - start - state.text_width
+                    }
+                };
+                Marquee::draw_run(&state.text, state.direction, x, state.text_width, baseline);
+            }
+        }
+
+        draw::pop_clip();
+    }
+
+    /// Draw `text` with its left edge at `x`, in the direction that it is read.
+    fn draw_run(text: &str, direction: Direction, x: i32, width: i32, baseline: i32) {
+        match direction {
+            Direction::LeftToRight => draw::draw_text(text, x, baseline),
+            // This draws from the right edge of the text
+            Direction::RightToLeft => draw::rtl_draw(text, x + width, baseline),
+        }
+    }
+}
+
+/// Return how wide `text` is drawn.
+fn text_width(text: &str, font: Font, fontsize: i32) -> i32 {
+    draw::set_font(font, fontsize);
+    let (text_width, _) = draw::measure(text, false);
+
+    text_width
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Measure text as if every character were 10 pixels wide.
+    fn measure(text: &str) -> i32 {
+        text.chars().count() as i32 * 10
+    }
+
+    mod ellipsize {
+        use super::*;
+
+        #[test]
+        fn test_fits() {
+            assert_eq!(Marquee::ellipsize("Short", 100, measure), "Short");
+        }
+
+        #[test]
+        fn test_cut_short() {
+            assert_eq!(
+                Marquee::ellipsize("A very long title", 80, measure),
+                "A very…"
+            );
+        }
+
+        #[test]
+        fn test_cjk() {
+            // Each character takes several bytes, which can't be cut apart
+            assert_eq!(Marquee::ellipsize("東京事変の歌", 40, measure), "東京事…");
+        }
+
+        #[test]
+        fn test_nothing_fits() {
+            assert_eq!(Marquee::ellipsize("Title", 5, measure), "…");
+        }
+    }
+
+    mod direction {
+        use super::*;
+
+        #[test]
+        fn test_left_to_right() {
+            assert_eq!(Direction::of("Song"), Direction::LeftToRight);
+            assert_eq!(Direction::of("東京"), Direction::LeftToRight);
+            assert_eq!(Direction::of("1999"), Direction::LeftToRight);
+        }
+
+        #[test]
+        fn test_right_to_left() {
+            assert_eq!(Direction::of("שיר"), Direction::RightToLeft);
+            assert_eq!(Direction::of("أغنية"), Direction::RightToLeft);
+            // Numbers and punctuation at the start don't decide the direction
+            assert_eq!(Direction::of("1. שיר"), Direction::RightToLeft);
+        }
+    }
+}
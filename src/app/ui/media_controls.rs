@@ -0,0 +1,201 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use crate::app::Message;
+use crate::app::keybindings::Action;
+use crate::app::mpris::{self, MprisCommand, MprisMetadata, MprisServer, MprisState};
+use crate::app::queue::{self, Queue};
+use crate::app::stream;
+use crate::app::ui::now_playing::NowPlaying;
+use crate::app::ui::shortcuts::Shortcuts;
+
+/// Connects the player to the desktop's media controls, such as media keys, desktop widgets and `playerctl`.
+pub struct MediaControls {
+    server: MprisServer,
+
+    /// The receiver for the commands that the desktop sends
+    command_receiver: mpsc::Receiver<MprisCommand>,
+
+    metadata: MprisMetadata,
+
+    /// Playback, seeking and the volume are shared with the keyboard shortcuts
+    shortcuts: Shortcuts,
+
+    /// The sender for the messages that move through the queue
+    audio_sender: mpsc::Sender<Message>,
+
+    /// The queue, which decides whether there is a next or previous track to go to
+    queue: Rc<RefCell<Queue>>,
+
+    /// The position at the last update, and when that update was, so that jumps can be told apart from normal playback
+    last_update: (Duration, Instant),
+}
+
+impl MediaControls {
+    /// Start serving the media controls. Returns `None` if there is no session bus to serve them on, since the player works fine without them.
+    pub fn new(
+        now_playing: &NowPlaying,
+        track_path: &Path,
+        shortcuts: Shortcuts,
+        audio_sender: mpsc::Sender<Message>,
+        queue: Rc<RefCell<Queue>>,
+    ) -> Option<MediaControls> {
+        let metadata = MediaControls::metadata(now_playing, track_path, &shortcuts, &queue);
+
+        let position = shortcuts.position();
+        let state = MprisState {
+            playing: shortcuts.is_playing(),
+            position,
+            volume: f64::from(shortcuts.volume()),
+            can_go_next: !queue.borrow().upcoming().is_empty(),
+            can_go_previous: queue.borrow().current_index() > 0,
+            metadata: metadata.clone(),
+        };
+
+        let (command_sender, command_receiver) = mpsc::channel();
+        let server = MprisServer::start(state, command_sender)
+            .inspect_err(|e| eprintln!("Unable to start the media controls: {}", e))
+            .ok()?;
+
+        Some(MediaControls {
+            server,
+            command_receiver,
+            metadata,
+            shortcuts,
+            audio_sender,
+            queue,
+            last_update: (position, Instant::now()),
+        })
+    }
+
+    /// Show a different track in the media controls, once the queue has moved on to it.
+    pub fn set_track(&mut self, now_playing: &NowPlaying, track_path: &Path, shortcuts: Shortcuts) {
+        self.metadata = MediaControls::metadata(now_playing, track_path, &shortcuts, &self.queue);
+        self.last_update = (shortcuts.position(), Instant::now());
+        self.shortcuts = shortcuts;
+    }
+
+    /// Show a different title or artist, such as when a stream moves on to the next song.
+    pub fn set_now_playing(&mut self, now_playing: &NowPlaying, track_path: &Path) {
+        self.metadata =
+            MediaControls::metadata(now_playing, track_path, &self.shortcuts, &self.queue);
+    }
+
+    /// Carry out the commands from the desktop, and tell it about any changes to the player.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) {
+        for command in self.command_receiver.try_iter().collect::<Vec<_>>() {
+            self.perform(command);
+        }
+
+        let playing = self.shortcuts.is_playing();
+        let position = self.shortcuts.position();
+
+        let (last_position, last_update) = self.last_update;
+        if mpris::is_jump(last_position, position, last_update.elapsed(), playing) {
+            self.server.seeked(position);
+        }
+        self.last_update = (position, Instant::now());
+
+        self.server.update(MprisState {
+            playing,
+            position,
+            volume: f64::from(self.shortcuts.volume()),
+            can_go_next: !self.queue.borrow().upcoming().is_empty(),
+            can_go_previous: self.queue.borrow().current_index() > 0,
+            metadata: self.metadata.clone(),
+        });
+    }
+
+    fn perform(&self, command: MprisCommand) {
+        match command {
+            MprisCommand::PlayPause => self.shortcuts.perform(Action::PlayPause),
+            MprisCommand::Play => self.shortcuts.set_playing(true),
+            MprisCommand::Pause => self.shortcuts.set_playing(false),
+            MprisCommand::Stop => {
+                self.shortcuts.set_playing(false);
+                self.shortcuts.seek(Duration::ZERO);
+            }
+            // These move through the queue, while the keyboard shortcuts of the same name step through chapters
+            MprisCommand::Next => self.send(Message::Next),
+            MprisCommand::Previous => self.send(Message::Previous),
+            MprisCommand::SetPosition(position) => self.shortcuts.seek(position),
+            MprisCommand::SetVolume(volume) => self.shortcuts.set_volume_level(volume as f32),
+            MprisCommand::OpenUri(uri) => {
+                let tracks = queue::tracks_to_open(&queue::location_path(&uri));
+                if tracks.is_empty() {
+                    eprintln!("There is nothing to play in {}", uri);
+                    return;
+                }
+
+                self.send(Message::Open(tracks));
+            }
+        }
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) = self.audio_sender.send(message) {
+            eprintln!("Unable to send the media control: {:?}", e);
+        }
+    }
+
+    fn metadata(
+        now_playing: &NowPlaying,
+        track_path: &Path,
+        shortcuts: &Shortcuts,
+        queue: &RefCell<Queue>,
+    ) -> MprisMetadata {
+        MprisMetadata {
+            track_number: queue.borrow().advanced(),
+            title: now_playing.title().to_string(),
+            artist: now_playing.artist().to_string(),
+            album: now_playing.album().map(str::to_string),
+            art_url: MediaControls::cover_url(now_playing),
+            length: shortcuts.audio_length(),
+            url: if stream::is_stream(track_path) {
+                Some(track_path.to_string_lossy().into_owned())
+            } else {
+                fs::canonicalize(track_path)
+                    .ok()
+                    .map(|path| mpris::file_url(&path))
+            },
+        }
+    }
+
+    /// Save the cover of the track where the desktop can read it, and return its URL.
+    /// Tracks without a cover use the default cover.
+    fn cover_url(now_playing: &NowPlaying) -> Option<String> {
+        let Some(cover) = now_playing.cover() else {
+            return fs::canonicalize("assets/default.png")
+                .ok()
+                .map(|path| mpris::file_url(&path));
+        };
+
+        let extension = cover
+            .mime_type()
+            .and_then(|mime_type| mime_type.ext())
+            .unwrap_or("img");
+        let path: PathBuf = dirs::cache_dir()?
+            .join("audio_player")
+            .join(format!("cover.{}", extension));
+
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, cover.data()));
+
+        match written {
+            Ok(()) => Some(mpris::file_url(&path)),
+            Err(e) => {
+                eprintln!("Unable to save the cover for the media controls: {}", e);
+                None
+            }
+        }
+    }
+}
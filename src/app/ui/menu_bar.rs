@@ -0,0 +1,232 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+};
+
+use fltk::{
+    dialog::{self, NativeFileChooser, NativeFileChooserType},
+    enums::{FrameType, Shortcut},
+    menu::{self, MenuFlag},
+    prelude::*,
+};
+
+use crate::app::Message;
+use crate::app::playlist;
+use crate::app::queue;
+use crate::app::recent::RecentFiles;
+
+/// What the user can pick to open from the menu bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenKind {
+    File,
+    Folder,
+    Playlist,
+}
+
+/// The menu bar at the top of the full window, which opens files, folders and playlists in place of the queue.
+#[derive(Clone)]
+pub struct MenuBar {
+    menu: menu::MenuBar,
+    audio_sender: mpsc::Sender<Message>,
+
+    /// The folder that the open dialogs start in
+    music_folder: Option<PathBuf>,
+
+    /// The items that were opened last, which the "Open Recent" submenu lists
+    recent: Rc<RefCell<RecentFiles>>,
+
+    /// The items that the submenu lists, so that it is only filled again once they change
+    listed: Rc<RefCell<Option<Vec<PathBuf>>>>,
+}
+
+impl MenuBar {
+    pub const HEIGHT: i32 = 25;
+
+    const RECENT_MENU: &str = "&File/Open &Recent";
+
+    /// Create the menu bar along the top of a window that is `width` wide. Its open dialogs start in `music_folder`.
+    pub fn new(
+        width: i32,
+        music_folder: Option<PathBuf>,
+        recent: Rc<RefCell<RecentFiles>>,
+        audio_sender: mpsc::Sender<Message>,
+    ) -> MenuBar {
+        let mut menu = menu::MenuBar::new(0, 0, width, MenuBar::HEIGHT, None);
+        menu.set_frame(FrameType::FlatBox);
+        menu.clear_visible_focus();
+
+        let menu_bar = MenuBar {
+            menu: menu.clone(),
+            audio_sender,
+            music_folder,
+            recent,
+            listed: Rc::new(RefCell::new(None)),
+        };
+
+        let items = [
+            ("&File/&Open File...", Shortcut::Ctrl | 'o', OpenKind::File),
+            (
+                "&File/Open F&older...",
+                Shortcut::Ctrl | Shortcut::Shift | 'o',
+                OpenKind::Folder,
+            ),
+            (
+                "&File/Open &Playlist...",
+                Shortcut::None,
+                OpenKind::Playlist,
+            ),
+        ];
+        for (label, shortcut, kind) in items {
+            let bar = menu_bar.clone();
+            menu.add(label, shortcut, MenuFlag::Normal, move |_| {
+                if let Some(path) = MenuBar::choose(kind, bar.music_folder.as_deref()) {
+                    bar.open(&path);
+                }
+            });
+        }
+        menu.add(
+            MenuBar::RECENT_MENU,
+            Shortcut::None,
+            MenuFlag::Submenu,
+            |_| {},
+        );
+
+        menu_bar.update();
+        menu_bar
+    }
+
+    /// Ask the user for a file, folder or playlist to open, starting in `folder` if there is one.
+    /// Returns `None` if they cancel.
+    pub fn choose(kind: OpenKind, folder: Option<&Path>) -> Option<PathBuf> {
+        let (chooser_type, title, filter) = match kind {
+            OpenKind::File => (
+                NativeFileChooserType::BrowseFile,
+                "Open file",
+                Some(format!(
+                    "Audio files\t*.{{{}}}",
+                    queue::AUDIO_EXTENSIONS.join(",")
+                )),
+            ),
+            OpenKind::Folder => (NativeFileChooserType::BrowseDir, "Open folder", None),
+            OpenKind::Playlist => (
+                NativeFileChooserType::BrowseFile,
+                "Open playlist",
+                Some(format!(
+                    "Playlists\t*.{{{}}}",
+                    playlist::EXTENSIONS.join(",")
+                )),
+            ),
+        };
+
+        let mut chooser = NativeFileChooser::new(chooser_type);
+        chooser.set_title(title);
+        if let Some(filter) = filter {
+            chooser.set_filter(&filter);
+        }
+        if let Some(folder) = folder
+            && let Err(e) = chooser.set_directory(&folder)
+        {
+            eprintln!("Unable to open {}: {:?}", folder.display(), e);
+        }
+        chooser.show();
+
+        let path = chooser.filename();
+        if path.as_os_str().is_empty() {
+            return None;
+        }
+
+        Some(path)
+    }
+
+    /// List the recent items again if they changed since they were last listed.
+    /// This function is intended to be called continuously in the main loop.
+    pub fn update(&self) {
+        let recent = self.recent.borrow();
+        if self.listed.borrow().as_deref() == Some(recent.items()) {
+            return;
+        }
+
+        let mut menu = self.menu.clone();
+        let index = menu.find_index(MenuBar::RECENT_MENU);
+        if let Err(e) = menu.clear_submenu(index) {
+            eprintln!("Unable to list recent files: {:?}", e);
+            return;
+        }
+
+        let item_label = |label: &str| format!("{}/{}", MenuBar::RECENT_MENU, label);
+
+        if recent.items().is_empty() {
+            menu.add(
+                &item_label("No recent items"),
+                Shortcut::None,
+                MenuFlag::Inactive,
+                |_| {},
+            );
+        }
+
+        for (i, path) in recent.items().iter().enumerate() {
+            // The last item is divided from the entry that clears them
+            let flag = if i + 1 == recent.items().len() {
+                MenuFlag::MenuDivider
+            } else {
+                MenuFlag::Normal
+            };
+
+            let bar = self.clone();
+            let opened = path.clone();
+            menu.add(
+                &item_label(&MenuBar::escape_label(&path.to_string_lossy())),
+                Shortcut::None,
+                flag,
+                move |_| bar.open(&opened),
+            );
+        }
+
+        if !recent.items().is_empty() {
+            let recent = Rc::clone(&self.recent);
+            menu.add(
+                &item_label("&Clear Recent"),
+                Shortcut::None,
+                MenuFlag::Normal,
+                move |_| recent.borrow_mut().clear(),
+            );
+        }
+
+        *self.listed.borrow_mut() = Some(recent.items().to_vec());
+    }
+
+    /// Play what is at `path` in place of the queue, and remember that it was opened once it plays.
+    fn open(&self, path: &Path) {
+        let tracks = queue::tracks_to_open(path);
+        if tracks.is_empty() {
+            dialog::alert_default(&format!("There is nothing to play in {}", path.display()));
+            return;
+        }
+
+        // The item is only remembered once it plays, and the submenu is listed again in the main loop after that
+        self.recent
+            .borrow_mut()
+            .add_once_playing(path, tracks.clone());
+
+        if let Err(e) = self.audio_sender.send(Message::Open(tracks)) {
+            eprintln!("Unable to open {}: {:?}", path.display(), e);
+        }
+    }
+
+    /// Stop a menu from treating the characters of `label` as submenus, shortcuts or dividers.
+    fn escape_label(label: &str) -> String {
+        let mut escaped = String::with_capacity(label.len());
+        for c in label.chars() {
+            match c {
+                // The label itself underlines the letter after an ampersand
+                '&' => escaped.push('&'),
+                '\\' | '/' | '_' => escaped.push('\\'),
+                _ => {}
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+}
@@ -1,3 +1,25 @@
+pub mod bookmarks_dialog;
+pub mod export_dialog;
+pub mod file_drop;
+pub mod help_overlay;
+pub mod layout;
+pub mod loop_controls;
+pub mod lyrics_view;
+pub mod marquee;
+pub mod markers;
+#[cfg(target_os = "linux")]
+pub mod media_controls;
+pub mod menu_bar;
 pub mod playback_buttons;
+pub mod podcasts_dialog;
+pub mod practice_dialog;
 pub mod progress_bar;
+pub mod queue_panel;
+pub mod remote_control;
+pub mod seek_preview;
+pub mod shortcuts;
+pub mod sleep_timer_dialog;
+pub mod tag_editor_dialog;
+pub mod track_info_dialog;
+pub mod visualizer;
 pub mod now_playing;
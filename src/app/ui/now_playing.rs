@@ -1,49 +1,323 @@
 use fltk::draw::{self};
-use fltk::enums::{Font, FrameType};
+use fltk::enums::{ColorDepth, Event, Font, FrameType};
 use fltk::frame::Frame;
-use fltk::image::{JpegImage, PngImage, SharedImage};
+use fltk::image::{JpegImage, PngImage, RgbImage, SharedImage};
 use fltk::output::Output;
 use fltk::prelude::{InputExt, WidgetBase, WidgetExt};
+use fltk::window::Window;
+use image::ImageFormat;
+use lofty::config::ParseOptions;
 use lofty::error::{ErrorKind, LoftyError};
-use lofty::file::TaggedFileExt;
-use lofty::picture::{MimeType, PictureType};
+use lofty::file::{AudioFile, FileType, TaggedFileExt};
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::probe::Probe;
+use lofty::properties::FileProperties;
 use lofty::read_from_path;
-use lofty::tag::{Accessor, Tag};
+use lofty::tag::{Accessor, ItemKey, Tag, TagType};
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::fs;
 use std::path::Path;
 
-pub struct NowPlaying {}
+use crate::app::stream::StreamDetails;
+use crate::app::tag_editor;
+use crate::app::ui::layout::{Bounds, Layout};
+use crate::app::ui::marquee::Marquee;
+
+pub struct NowPlaying {
+    title: String,
+    artist: String,
+    album: Option<String>,
+
+    /// The cover of the track, which is embedded in it or an image in its folder
+    cover: Option<Picture>,
+
+    /// The widget that the title and artist are centered under
+    cover_widget: Frame,
+
+    /// Kept so that the title can change while a stream plays
+    title_widget: Marquee,
+
+    /// The artist and the line with the album details, which scroll if they are too long to fit.
+    /// The mini player has no room for the details
+    artist_widget: Marquee,
+    details_widget: Option<Marquee>,
+
+    /// The line with the codec, sample rate and bitrate, which the user can hide.
+    /// Streams don't have one
+    technical_widget: Option<Output>,
+}
 
 impl NowPlaying {
     const FONTSIZE: i32 = 14;
 
-    pub fn new(path: &str) -> NowPlaying {
-        let metadata_tag = NowPlaying::parse_file(path).unwrap();
+    /// The space on both sides of the title, artist and details, which are cut short if they don't fit between them
+    const TEXT_MARGIN: i32 = 20;
+
+    /// The size of the cover, unless it is a thumbnail next to the title in the mini player
+    const COVER_SIZE: i32 = 100;
+
+    /// The space between the thumbnail and the title in the mini player
+    const MINI_TEXT_GAP: i32 = 8;
+    const TITLE_FONT: Font = Font::HelveticaBold;
+
+    /// The size of the smaller lines under the artist
+    const DETAILS_FONTSIZE: i32 = 12;
+    const DETAILS_SEPARATOR: &str = " · ";
+
+    /// How far below the top of the cover the technical line goes.
+    /// It is under the details, even if the track has none, so that it doesn't move between tracks
+    const TECHNICAL_OFFSET: i32 = 140;
+
+    /// The names of the cover images that can be in the folder of a track, from most to least likely to be the cover
+    const FOLDER_COVER_NAMES: [&str; 3] = ["cover", "folder", "front"];
+
+    /// The prefix of the covers that Windows Media Player saves, such as "AlbumArtSmall.jpg"
+    const ALBUM_ART_PREFIX: &str = "albumart";
+
+    const IMAGE_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff"];
+
+    /// The artist of a track whose tags don't say who it is by
+    const UNKNOWN_ARTIST: &str = "Unknown artist";
 
-        NowPlaying::create_widgets(metadata_tag);
+    /// Show the track at `path` within `bounds`. `show_technical` is whether the line with the codec, sample rate and bitrate
+    /// is shown, which the mini player has no room for.
+    pub fn new(path: &str, show_technical: bool, bounds: Bounds, layout: Layout) -> NowPlaying {
+        // Untagged tracks, such as most WAVs, are shown by their file name
+        let metadata_tag =
+            NowPlaying::parse_file(path).unwrap_or_else(|_| NowPlaying::untagged(path));
 
-        NowPlaying {}
+        // Tracks without embedded art can have their cover saved next to them
+        let cover = tag_editor::cover_picture(&metadata_tag)
+            .cloned()
+            .or_else(|| NowPlaying::folder_cover(Path::new(path)));
+
+        let mut now_playing = NowPlaying::from_tag(&metadata_tag, cover, bounds, layout);
+        if layout == Layout::Mini {
+            return now_playing;
+        }
+
+        let technical = NowPlaying::read_technical_details(path).unwrap_or_default();
+        let mut technical_widget = NowPlaying::create_text_widget(
+            &technical,
+            Font::Helvetica,
+            NowPlaying::DETAILS_FONTSIZE,
+            &now_playing.cover_widget,
+            now_playing.cover_widget.y() + NowPlaying::TECHNICAL_OFFSET,
+        );
+        if !show_technical {
+            technical_widget.hide();
+        }
+        now_playing.technical_widget = Some(technical_widget);
+
+        now_playing
     }
 
-    fn create_title_widget(metadata_tag: &Tag, cover_widget: &Frame) -> Output {
-        const FONT: Font = Font::HelveticaBold;
+    /// Show an internet stream, with the song that the station is playing as the title and the station as the artist.
+    pub fn for_stream(
+        url: &str,
+        details: &StreamDetails,
+        bounds: Bounds,
+        layout: Layout,
+    ) -> NowPlaying {
+        // The tag type doesn't matter, since the tag is never written anywhere
+        let mut metadata_tag = Tag::new(TagType::Id3v2);
+        metadata_tag.set_title(NowPlaying::stream_title(details));
+        metadata_tag.set_artist(details.station.clone().unwrap_or_else(|| url.to_string()));
+
+        NowPlaying::from_tag(&metadata_tag, None, bounds, layout)
+    }
 
-        let title = NowPlaying::extract_title_from_tag(metadata_tag);
+    /// Return the title to show for a stream, which is the song that is playing if the station has said so.
+    pub fn stream_title(details: &StreamDetails) -> String {
+        details
+            .title
+            .clone()
+            .unwrap_or_else(|| "Live stream".to_string())
+    }
 
-        let title_widget_y = NowPlaying::below_widget(cover_widget);
+    fn from_tag(
+        metadata_tag: &Tag,
+        cover: Option<Picture>,
+        bounds: Bounds,
+        layout: Layout,
+    ) -> NowPlaying {
+        let (cover_widget, title_widget, artist_widget, details_widget) =
+            NowPlaying::create_widgets(metadata_tag, cover.as_ref(), bounds, layout);
+
+        NowPlaying {
+            title: NowPlaying::extract_title_from_tag(metadata_tag),
+            artist: NowPlaying::extract_artist_from_tag(metadata_tag),
+            album: metadata_tag.album().map(|album| album.to_string()),
+            cover,
+            cover_widget,
+            title_widget,
+            artist_widget,
+            details_widget,
+            technical_widget: None,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
 
-        NowPlaying::create_text_widget(&title, FONT, cover_widget, title_widget_y)
+    pub fn artist(&self) -> &str {
+        &self.artist
     }
 
-    fn create_artist_widget(metadata_tag: &Tag, cover_widget: &Frame, title_widget: &Output) {
+    pub fn album(&self) -> Option<&str> {
+        self.album.as_deref()
+    }
+
+    /// Return the cover of the track, if it has one.
+    pub fn cover(&self) -> Option<&Picture> {
+        self.cover.as_ref()
+    }
+
+    /// Return a handle to the line with the codec, sample rate and bitrate, so that it can be shown or hidden.
+    pub fn technical_widget(&self) -> Option<Output> {
+        self.technical_widget.clone()
+    }
+
+    /// Show `dialog` when the cover is clicked.
+    pub fn show_on_cover_click(&mut self, mut dialog: Window, tooltip: &str) {
+        self.cover_widget.set_tooltip(tooltip);
+
+        self.cover_widget.handle(move |_, event| match event {
+            // Taking the mouse entering the cover is what lets its tooltip show
+            Event::Enter | Event::Leave => true,
+            Event::Push => {
+                dialog.show();
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Show a different title, such as when the station of a stream moves on to the next song.
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.title_widget.set_text(title);
+    }
+
+    /// Return the title of the window for the track, such as "Title — Artist".
+    pub fn window_title(&self) -> String {
+        format!("{} — {}", self.title, self.artist)
+    }
+
+    /// Scroll the title, artist and details if they are too long to fit.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) {
+        self.title_widget.update();
+        self.artist_widget.update();
+        if let Some(details_widget) = self.details_widget.as_mut() {
+            details_widget.update();
+        }
+    }
+
+    /// Read the cover of the track at `path`, without creating any widgets.
+    /// The cover is embedded in the track, or an image in its folder.
+    pub fn read_cover(path: &str) -> Option<Picture> {
+        NowPlaying::parse_file(path)
+            .ok()
+            .and_then(|tag| tag_editor::cover_picture(&tag).cloned())
+            .or_else(|| NowPlaying::folder_cover(Path::new(path)))
+    }
+
+    /// Read the codec, sample rate and bitrate of the track at `path`, such as "FLAC · 24-bit/96 kHz · Stereo · 2.3 Mbps".
+    pub fn read_technical_details(path: &str) -> Option<String> {
+        // The tags aren't needed, and skipping them saves reading the cover again
+        let tagged_file = Probe::open(path)
+            .ok()?
+            .options(ParseOptions::new().read_tags(false))
+            .read()
+            .ok()?;
+
+        Some(NowPlaying::technical_details(
+            tagged_file.file_type(),
+            tagged_file.properties(),
+        ))
+    }
+
+    /// Return where the title, artist and details go: their left edge and width, and the top of the title.
+    /// They are centered under the cover, or next to it in the mini player.
+    fn text_column(cover_widget: &Frame, bounds: Bounds, layout: Layout) -> Bounds {
+        match layout {
+            Layout::Full => {
+                let width = bounds.width - 2 * NowPlaying::TEXT_MARGIN;
+                let y = NowPlaying::below_widget(cover_widget);
+
+                Bounds::new(
+                    NowPlaying::text_center_x_of_widget(cover_widget, width),
+                    y,
+                    width,
+                    bounds.y + bounds.height - y,
+                )
+            }
+            Layout::Mini => {
+                let x = cover_widget.x() + cover_widget.w() + NowPlaying::MINI_TEXT_GAP;
+
+                // Center the title and artist vertically next to the thumbnail
+                Bounds::new(
+                    x,
+                    bounds.center_y() - NowPlaying::FONTSIZE,
+                    bounds.x + bounds.width - x,
+                    2 * NowPlaying::FONTSIZE,
+                )
+            }
+        }
+    }
+
+    fn create_title_widget(metadata_tag: &Tag, text_column: Bounds) -> Marquee {
+        let title = NowPlaying::extract_title_from_tag(metadata_tag);
+
+        NowPlaying::create_marquee(
+            &title,
+            NowPlaying::TITLE_FONT,
+            NowPlaying::FONTSIZE,
+            text_column,
+            text_column.y,
+        )
+    }
+
+    /// Create the artist widget, and return it so that the details can be placed under it.
+    fn create_artist_widget(
+        metadata_tag: &Tag,
+        text_column: Bounds,
+        title_widget: &Marquee,
+    ) -> Marquee {
         const FONT: Font = Font::Helvetica;
 
         let artist = NowPlaying::extract_artist_from_tag(metadata_tag);
 
-        let artist_widget_y = NowPlaying::below_widget(title_widget);
+        let artist_widget_y = NowPlaying::below_widget(title_widget.widget());
 
         // Create the artist widget
-        NowPlaying::create_text_widget(&artist, FONT, cover_widget, artist_widget_y);
+        NowPlaying::create_marquee(
+            &artist,
+            FONT,
+            NowPlaying::FONTSIZE,
+            text_column,
+            artist_widget_y,
+        )
+    }
+
+    /// Create the line with the album, year, track number and genre under the artist, if the tag has any of them.
+    fn create_details_widget(
+        metadata_tag: &Tag,
+        text_column: Bounds,
+        artist_widget: &Marquee,
+    ) -> Option<Marquee> {
+        let details = NowPlaying::details_from_tag(metadata_tag)?;
+
+        Some(NowPlaying::create_marquee(
+            &details,
+            Font::HelveticaItalic,
+            NowPlaying::DETAILS_FONTSIZE,
+            text_column,
+            NowPlaying::below_widget(artist_widget.widget()),
+        ))
     }
 
     /// Add a unified style to a text widget. Will apply the same style to all text widgets that are passed to it, so it can be reused.
@@ -51,12 +325,15 @@ impl NowPlaying {
         text_widget.set_frame(FrameType::NoBox);
     }
 
-    fn create_text_widget(text: &str, font: Font, parent: &Frame, widget_y: i32) -> Output {
-        const HORIZONTAL_PADDING: i32 = 10;
-
-        // Add 10 because otherwise the user can scroll horizontally on the text
-        let text_width = text_width(text, font, Self::FONTSIZE) + HORIZONTAL_PADDING;
-        let text_height = Self::FONTSIZE;
+    fn create_text_widget(
+        text: &str,
+        font: Font,
+        fontsize: i32,
+        parent: &Frame,
+        widget_y: i32,
+    ) -> Output {
+        let text_width = NowPlaying::text_widget_width(text, font, fontsize);
+        let text_height = fontsize;
 
         let widget_x = NowPlaying::text_center_x_of_widget(parent, text_width);
 
@@ -65,12 +342,131 @@ impl NowPlaying {
         // Set the text of the widget
         widget.set_value(text);
         widget.set_text_font(font);
+        widget.set_text_size(fontsize);
 
         NowPlaying::style_text_widget(&mut widget);
 
         widget
     }
 
+    /// Create a line of text across `text_column`, which is cut short and scrolls if it doesn't fit.
+    fn create_marquee(
+        text: &str,
+        font: Font,
+        fontsize: i32,
+        text_column: Bounds,
+        widget_y: i32,
+    ) -> Marquee {
+        Marquee::new(
+            text_column.x,
+            widget_y,
+            text_column.width,
+            text,
+            font,
+            fontsize,
+        )
+    }
+
+    /// Return how wide a text widget has to be to fit `text`.
+    fn text_widget_width(text: &str, font: Font, fontsize: i32) -> i32 {
+        const HORIZONTAL_PADDING: i32 = 10;
+
+        // Add 10 because otherwise the user can scroll horizontally on the text
+        text_width(text, font, fontsize) + HORIZONTAL_PADDING
+    }
+
+    /// Return the album, year, track and disc number, and genre of a tag on one line, leaving out the ones it doesn't have.
+    /// The album artist is only shown if it isn't the artist of the track, such as on a compilation.
+    fn details_from_tag(tag: &Tag) -> Option<String> {
+        let mut details = Vec::new();
+
+        if let Some(album) = tag.album() {
+            match tag.get_string(&ItemKey::AlbumArtist) {
+                Some(album_artist) if Some(album_artist) != tag.artist().as_deref() => {
+                    details.push(format!("{} ({})", album, album_artist))
+                }
+                _ => details.push(album.to_string()),
+            }
+        }
+        if let Some(year) = tag.year() {
+            details.push(year.to_string());
+        }
+        if let Some(track) = tag.track() {
+            details.push(NowPlaying::numbered("Track", track, tag.track_total()));
+        }
+        if let Some(disk) = tag.disk() {
+            details.push(NowPlaying::numbered("Disc", disk, tag.disk_total()));
+        }
+        if let Some(genre) = tag.genre() {
+            details.push(genre.to_string());
+        }
+
+        (!details.is_empty()).then(|| details.join(NowPlaying::DETAILS_SEPARATOR))
+    }
+
+    /// Return something like "Track 3/12", or "Track 3" if the total isn't known.
+    fn numbered(name: &str, number: u32, total: Option<u32>) -> String {
+        match total {
+            Some(total) => format!("{} {}/{}", name, number, total),
+            None => format!("{} {}", name, number),
+        }
+    }
+
+    /// Describe the format of a file, such as "FLAC · 24-bit/96 kHz · Stereo · 2.3 Mbps".
+    /// Lossy formats don't have a bit depth, so it is left out for them.
+    pub fn technical_details(file_type: FileType, properties: &FileProperties) -> String {
+        let codec = match file_type {
+            FileType::Aac => "AAC",
+            FileType::Aiff => "AIFF",
+            FileType::Ape => "APE",
+            FileType::Flac => "FLAC",
+            FileType::Mpeg => "MP3",
+            FileType::Mp4 => "M4A",
+            FileType::Mpc => "Musepack",
+            FileType::Opus => "Opus",
+            FileType::Vorbis => "Vorbis",
+            FileType::Speex => "Speex",
+            FileType::Wav => "WAV",
+            FileType::WavPack => "WavPack",
+            FileType::Custom(name) => name,
+            _ => "Unknown format",
+        };
+        let mut details = vec![codec.to_string()];
+
+        let sample_rate = properties
+            .sample_rate()
+            .map(|rate| format!("{} kHz", rate as f64 / 1000.0));
+        match (properties.bit_depth(), sample_rate) {
+            (Some(bit_depth), Some(sample_rate)) => {
+                details.push(format!("{}-bit/{}", bit_depth, sample_rate))
+            }
+            (Some(bit_depth), None) => details.push(format!("{}-bit", bit_depth)),
+            (None, Some(sample_rate)) => details.push(sample_rate),
+            (None, None) => {}
+        }
+
+        if let Some(channels) = properties.channels() {
+            details.push(match channels {
+                1 => "Mono".to_string(),
+                2 => "Stereo".to_string(),
+                6 => "5.1".to_string(),
+                8 => "7.1".to_string(),
+                channels => format!("{} channels", channels),
+            });
+        }
+
+        // The bitrate is in kbps
+        match properties.overall_bitrate() {
+            Some(bitrate) if bitrate >= 1000 => {
+                details.push(format!("{:.1} Mbps", bitrate as f64 / 1000.0))
+            }
+            Some(bitrate) if bitrate > 0 => details.push(format!("{} kbps", bitrate)),
+            _ => {}
+        }
+
+        details.join(NowPlaying::DETAILS_SEPARATOR)
+    }
+
     /// Extract the title from a given metadata tag.
     ///
     /// This function determines what title to show in the Now Playing section
@@ -120,15 +516,20 @@ impl NowPlaying {
         widget_y + widget_h
     }
 
-    fn create_cover_widget(metadata_tag: &Tag) -> Frame {
-        const COVER_X: i32 = 150;
-        const COVER_Y: i32 = 40;
-        const COVER_SIZE: i32 = 100;
+    /// Create the cover centered at the top of `bounds`, or as a thumbnail on the left of them in the mini player.
+    fn create_cover_widget(cover: Option<&Picture>, bounds: Bounds, layout: Layout) -> Frame {
+        let (cover_x, cover_size) = match layout {
+            Layout::Full => (
+                bounds.center_x() - NowPlaying::COVER_SIZE / 2,
+                NowPlaying::COVER_SIZE,
+            ),
+            Layout::Mini => (bounds.x, bounds.height),
+        };
 
-        let mut cover_widget = Frame::new(COVER_X, COVER_Y, COVER_SIZE, COVER_SIZE, "");
+        let mut cover_widget = Frame::new(cover_x, bounds.y, cover_size, cover_size, "");
 
-        // Extract the image from the metadata tag
-        let cover_image = NowPlaying::extract_cover_image_from_tag(metadata_tag);
+        // Decode the cover into an image
+        let cover_image = NowPlaying::cover_image(cover);
 
         // Assign the image to the frame
         // Use set_image_scaled so that the image scales to the widget's size
@@ -160,50 +561,121 @@ impl NowPlaying {
         Ok(tag.clone())
     }
 
-    /// Extract the cover image from a given metadata tag.
+    /// Return a tag for a track whose own tags are missing or incomplete, with the file's name as the title.
+    fn untagged(path: &str) -> Tag {
+        let mut tag = Tag::new(TagType::Id3v2);
+        if let Some(stem) = Path::new(path).file_stem() {
+            tag.set_title(stem.to_string_lossy().into_owned());
+        }
+        tag.set_artist(NowPlaying::UNKNOWN_ARTIST.to_string());
+
+        tag
+    }
+
+    /// Decode a cover into an image that can be shown.
     ///
     /// This function determines what image to show in the Now Playing section
     /// of the audio player.
     ///
     /// # Returns
     /// A default cover if any of the following conditions is met:
-    /// - There are no images in the tag
-    /// - The mime type does not exist
-    /// - The mime type is not `MimeType::Png` or `MimeType::Jpeg`
-    fn extract_cover_image_from_tag(tag: &Tag) -> SharedImage {
-        // If there are no pictures, return the default cover
-        if tag.picture_count() == 0 {
-            return NowPlaying::default_cover();
-        }
+    /// - There is no cover
+    /// - The cover is not in a format that can be decoded
+    pub fn cover_image(cover: Option<&Picture>) -> SharedImage {
+        cover
+            .and_then(|cover| NowPlaying::decode_image(cover.data()))
+            .unwrap_or_else(NowPlaying::default_cover)
+    }
 
-        let cover = tag
-            .pictures()
-            .iter()
-            .find(|picture| picture.pic_type() == PictureType::CoverFront);
+    /// Decode an image in any format that the `image` crate supports.
+    /// The format is worked out from the data itself, since the mime type of a picture can be missing or wrong.
+    fn decode_image(data: &[u8]) -> Option<SharedImage> {
+        let image = match image::guess_format(data).ok()? {
+            // FLTK decodes PNGs and JPEGs itself, which keeps the transparency of PNGs
+            ImageFormat::Png => SharedImage::from_image(&PngImage::from_data(data).ok()?),
+            ImageFormat::Jpeg => SharedImage::from_image(&JpegImage::from_data(data).ok()?),
+            _ => {
+                // Everything else is converted to RGB, which FLTK can show as it is
+                let rgb = image::load_from_memory(data).ok()?.to_rgb8();
+                let rgb_image = RgbImage::new(
+                    rgb.as_raw(),
+                    rgb.width() as i32,
+                    rgb.height() as i32,
+                    ColorDepth::Rgb8,
+                )
+                .ok()?;
+
+                SharedImage::from_image(&rgb_image)
+            }
+        };
+
+        image.ok()
+    }
 
-        // Unwrap if there is a front cover, otherwise just return the default cover
-        let cover = if let Some(cover) = cover {
-            cover
-        } else {
-            return NowPlaying::default_cover();
+    /// Look for a cover image in the folder of the track at `track_path`, such as "cover.jpg" or "folder.png".
+    /// If there are several, the one with the best name is used, and the largest one if their names are as good.
+    fn folder_cover(track_path: &Path) -> Option<Picture> {
+        let folder = match track_path.parent() {
+            Some(folder) if !folder.as_os_str().is_empty() => folder,
+            _ => Path::new("."),
         };
 
-        let cover_bytes = cover.data();
+        let mut candidates: Vec<_> = fs::read_dir(folder)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let rank = NowPlaying::folder_cover_rank(&entry.file_name().to_string_lossy())?;
+                let size = entry.metadata().ok()?.len();
 
-        // Return different SharedImage's depending on what filetype the cover is
-        match cover
-            .mime_type()
-            .unwrap_or(&MimeType::Unknown("No mime type".to_string()))
-        {
-            MimeType::Png => {
-                // Should not panic, because the mime type is determined from the start
-                SharedImage::from_image(&PngImage::from_data(cover_bytes).unwrap()).unwrap()
-            }
-            MimeType::Jpeg => {
-                SharedImage::from_image(&JpegImage::from_data(cover_bytes).unwrap()).unwrap()
-            }
-            _ => NowPlaying::default_cover(),
+                Some((rank, Reverse(size), entry.path()))
+            })
+            .collect();
+        candidates.sort();
+
+        // Skip any file that only has the name of an image
+        candidates.into_iter().find_map(|(_, _, path)| {
+            let data = fs::read(path).ok()?;
+            let mime_type = NowPlaying::mime_type(&data)?;
+
+            Some(Picture::new_unchecked(
+                PictureType::CoverFront,
+                Some(mime_type),
+                None,
+                data,
+            ))
+        })
+    }
+
+    /// Return how good `file_name` is as the name of a cover image, where lower is better.
+    /// Returns `None` if it isn't the name of a cover image.
+    fn folder_cover_rank(file_name: &str) -> Option<usize> {
+        let file_name = file_name.to_lowercase();
+        let (stem, extension) = file_name.rsplit_once('.')?;
+
+        if stem.starts_with(NowPlaying::ALBUM_ART_PREFIX) {
+            return (extension == "jpg").then_some(NowPlaying::FOLDER_COVER_NAMES.len());
+        }
+        if !NowPlaying::IMAGE_EXTENSIONS.contains(&extension) {
+            return None;
         }
+
+        NowPlaying::FOLDER_COVER_NAMES
+            .iter()
+            .position(|name| *name == stem)
+    }
+
+    /// Return the mime type of an image, based on its data.
+    fn mime_type(data: &[u8]) -> Option<MimeType> {
+        let mime_type = match image::guess_format(data).ok()? {
+            ImageFormat::Png => MimeType::Png,
+            ImageFormat::Jpeg => MimeType::Jpeg,
+            ImageFormat::Gif => MimeType::Gif,
+            ImageFormat::Bmp => MimeType::Bmp,
+            ImageFormat::Tiff => MimeType::Tiff,
+            format => MimeType::Unknown(format.to_mime_type().to_string()),
+        };
+
+        Some(mime_type)
     }
 
     fn default_cover() -> SharedImage {
@@ -213,11 +685,28 @@ impl NowPlaying {
         SharedImage::load(default_cover_path).unwrap()
     }
 
-    /// Create the cover widget, the title widget, and the artist widget to show the user the cover, title, and artist respectively.
-    fn create_widgets(metadata_tag: Tag) {
-        let cover_widget = NowPlaying::create_cover_widget(&metadata_tag);
-        let title_widget = NowPlaying::create_title_widget(&metadata_tag, &cover_widget);
-        NowPlaying::create_artist_widget(&metadata_tag, &cover_widget, &title_widget);
+    /// Create the cover widget, the title widget, the artist widget and the details widget to show the user the cover, title,
+    /// artist, and album details respectively. The mini player has no details widget.
+    fn create_widgets(
+        metadata_tag: &Tag,
+        cover: Option<&Picture>,
+        bounds: Bounds,
+        layout: Layout,
+    ) -> (Frame, Marquee, Marquee, Option<Marquee>) {
+        let cover_widget = NowPlaying::create_cover_widget(cover, bounds, layout);
+        let text_column = NowPlaying::text_column(&cover_widget, bounds, layout);
+
+        let title_widget = NowPlaying::create_title_widget(metadata_tag, text_column);
+        let artist_widget =
+            NowPlaying::create_artist_widget(metadata_tag, text_column, &title_widget);
+        let details_widget = match layout {
+            Layout::Full => {
+                NowPlaying::create_details_widget(metadata_tag, text_column, &artist_widget)
+            }
+            Layout::Mini => None,
+        };
+
+        (cover_widget, title_widget, artist_widget, details_widget)
     }
 }
 
@@ -306,7 +795,23 @@ mod test {
         }
     }
 
-    mod extract_cover_image_from_tag {
+    mod new {
+        use super::*;
+
+        #[test]
+        fn test_untagged_file() {
+            let path = format!("{}/audio/without-metadata/test.ogg", TEST_FILES);
+
+            let now_playing =
+                NowPlaying::new(&path, true, Bounds::new(0, 0, 400, 300), Layout::Full);
+
+            assert_eq!(now_playing.title, "test");
+            assert_eq!(now_playing.artist, "Unknown artist");
+            assert!(now_playing.cover.is_none());
+        }
+    }
+
+    mod cover_image {
         use std::{
             fs,
             path::{Path, PathBuf},
@@ -320,6 +825,11 @@ mod test {
 
         use super::*;
 
+        /// Decode the cover that would be chosen from `tag`
+        fn tag_cover_image(tag: &Tag) -> SharedImage {
+            NowPlaying::cover_image(tag_editor::cover_picture(tag))
+        }
+
         fn create_picture(path: PathBuf, mime_type: MimeType, pic_type: PictureType) -> Picture {
             // Read the file bytes
             let data = fs::read(path).expect("Failed to read image file");
@@ -346,7 +856,7 @@ mod test {
             tag.push_picture(front_cover);
 
             let expected_cover = SharedImage::load(full_cover_path).unwrap();
-            let cover = NowPlaying::extract_cover_image_from_tag(&tag);
+            let cover = tag_cover_image(&tag);
 
             assert_eq!(expected_cover.width(), cover.width());
             assert_eq!(expected_cover.height(), cover.height());
@@ -379,57 +889,94 @@ mod test {
         fn test_non_existent_cover() {
             assert_default_cover_is_returned(|| {
                 let tag = Tag::new(TagType::Id3v2);
-                NowPlaying::extract_cover_image_from_tag(&tag)
+                tag_cover_image(&tag)
             });
         }
 
         #[test]
-        fn test_unsupported_gif_format() {
-            assert_default_cover_is_returned(|| {
-                let relative_path_cover =
-                    format!("{}/images/covers/{}", TEST_FILES, "test_cover.gif");
+        fn test_gif() {
+            let full_cover_path = test_cover_path("test_cover.gif");
 
-                let full_cover_path = Path::new(&relative_path_cover)
-                    .canonicalize()
-                    .expect("Failed to resolve absolute path");
+            // The tag type shouldn't matter
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.push_picture(create_picture(
+                full_cover_path.clone(),
+                MimeType::Gif,
+                PictureType::CoverFront,
+            ));
 
-                let mut tag = Tag::new(TagType::Id3v2);
+            let expected_img = image::open(full_cover_path).unwrap().to_rgb8();
+            let img = tag_cover_image(&tag);
+
+            assert_eq!(expected_img.width() as i32, img.width());
+            assert_eq!(expected_img.height() as i32, img.height());
+            assert_eq!(expected_img.into_raw(), img.to_rgb_data());
+        }
+
+        #[test]
+        fn test_format_without_mime_type() {
+            let expected_img = image::RgbImage::from_fn(4, 3, |x, y| {
+                image::Rgb([x as u8 * 60, y as u8 * 80, 200])
+            });
 
-                // Read the file bytes
-                let data = fs::read(full_cover_path.clone()).expect("Failed to read image file");
+            let mut data = std::io::Cursor::new(Vec::new());
+            expected_img
+                .write_to(&mut data, ImageFormat::Bmp)
+                .expect("Failed to encode image");
 
-                // Add a front cover
+            // A BMP that the tag doesn't give a mime type for
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.push_picture(Picture::new_unchecked(
+                PictureType::CoverFront,
+                None,
+                None,
+                data.into_inner(),
+            ));
+
+            let img = tag_cover_image(&tag);
+
+            assert_eq!(4, img.width());
+            assert_eq!(3, img.height());
+            assert_eq!(expected_img.into_raw(), img.to_rgb_data());
+        }
+
+        #[test]
+        fn test_undecodable_cover() {
+            assert_default_cover_is_returned(|| {
+                let mut tag = Tag::new(TagType::Id3v2);
+
+                // Claims to be a JPEG, but isn't an image at all
                 let front_cover = Picture::new_unchecked(
                     PictureType::CoverFront,
-                    Some(MimeType::Gif),
+                    Some(MimeType::Jpeg),
                     None,
-                    data,
+                    b"not an image".to_vec(),
                 );
-
                 tag.push_picture(front_cover);
 
-                NowPlaying::extract_cover_image_from_tag(&tag)
+                tag_cover_image(&tag)
             });
         }
 
         #[test]
-        fn test_no_cover_image() {
-            assert_default_cover_is_returned(|| {
-                let full_cover_path = test_cover_path("test_cover.jpg");
+        fn test_no_front_cover() {
+            let full_cover_path = test_cover_path("test_cover.jpg");
 
-                let mut tag = Tag::new(TagType::Id3v2);
-
-                // Read the file bytes
-                let data = fs::read(full_cover_path.clone()).expect("Failed to read image file");
+            let mut tag = Tag::new(TagType::Id3v2);
 
-                // Add an artist picture. This should not get returned
-                let front_cover =
-                    Picture::new_unchecked(PictureType::Artist, Some(MimeType::Jpeg), None, data);
+            // Add an artist picture. Without a front cover, this is the next best thing
+            tag.push_picture(create_picture(
+                full_cover_path.clone(),
+                MimeType::Jpeg,
+                PictureType::Artist,
+            ));
 
-                tag.push_picture(front_cover);
+            let expected_img = SharedImage::load(full_cover_path).unwrap();
+            let img = tag_cover_image(&tag);
 
-                NowPlaying::extract_cover_image_from_tag(&tag)
-            });
+            assert_eq!(expected_img.width(), img.width());
+            assert_eq!(expected_img.height(), img.height());
+            assert_eq!(expected_img.to_rgb_data(), img.to_rgb_data());
         }
 
         #[test]
@@ -458,7 +1005,7 @@ mod test {
             tag.push_picture(front_cover);
 
             let expected_img = SharedImage::load(full_expected_cover_path).unwrap();
-            let img = NowPlaying::extract_cover_image_from_tag(&tag);
+            let img = tag_cover_image(&tag);
 
             assert_eq!(expected_img.width(), img.width());
             assert_eq!(expected_img.height(), img.height());
@@ -491,7 +1038,7 @@ mod test {
             tag.push_picture(front_cover);
 
             let expected_img = SharedImage::load(full_cover_path).unwrap();
-            let img = NowPlaying::extract_cover_image_from_tag(&tag);
+            let img = tag_cover_image(&tag);
 
             assert_eq!(expected_img.width(), img.width());
             assert_eq!(expected_img.height(), img.height());
@@ -499,6 +1046,64 @@ mod test {
         }
     }
 
+    mod folder_cover_rank {
+        use super::*;
+
+        #[test]
+        fn test_cover_names() {
+            assert_eq!(NowPlaying::folder_cover_rank("cover.jpg"), Some(0));
+            assert_eq!(NowPlaying::folder_cover_rank("Folder.PNG"), Some(1));
+            assert_eq!(NowPlaying::folder_cover_rank("front.webp"), Some(2));
+        }
+
+        #[test]
+        fn test_album_art() {
+            assert_eq!(NowPlaying::folder_cover_rank("AlbumArtSmall.jpg"), Some(3));
+            assert_eq!(
+                NowPlaying::folder_cover_rank("AlbumArt_{ABC}_Large.jpg"),
+                Some(3)
+            );
+            assert_eq!(NowPlaying::folder_cover_rank("AlbumArt.png"), None);
+        }
+
+        #[test]
+        fn test_not_a_cover() {
+            assert_eq!(NowPlaying::folder_cover_rank("back.jpg"), None);
+            assert_eq!(NowPlaying::folder_cover_rank("cover.txt"), None);
+            assert_eq!(NowPlaying::folder_cover_rank("cover"), None);
+        }
+    }
+
+    mod folder_cover {
+        use super::*;
+
+        #[test]
+        fn test_best_name_is_used() {
+            let dir = std::env::temp_dir()
+                .join(format!("audio_player_folder_cover_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let covers = format!("{}/images/covers", TEST_FILES);
+            fs::copy(format!("{}/test_cover.png", covers), dir.join("folder.png")).unwrap();
+            fs::copy(format!("{}/test_cover.jpg", covers), dir.join("Cover.jpg")).unwrap();
+            // Larger than the real cover with the same name, but isn't an image, so it's skipped
+            fs::write(dir.join("cover.png"), vec![0; 1024 * 1024]).unwrap();
+
+            let cover = NowPlaying::folder_cover(&dir.join("track.mp3"));
+            fs::remove_dir_all(&dir).unwrap();
+
+            let cover = cover.expect("No cover was found");
+            assert_eq!(cover.mime_type(), Some(&MimeType::Jpeg));
+            assert_eq!(cover.pic_type(), PictureType::CoverFront);
+        }
+
+        #[test]
+        fn test_no_cover() {
+            let track = format!("{}/audio/with-metadata/test.ogg", TEST_FILES);
+            assert!(NowPlaying::folder_cover(Path::new(&track)).is_none());
+        }
+    }
+
     // TODO: I don't know how to test this without getting constant errors from fltk
     // mod get_title_widget_x {
     //     use fltk::{
@@ -578,6 +1183,121 @@ mod test {
         }
     }
 
+    mod details_from_tag {
+        use lofty::tag::{ItemKey, Tag};
+
+        use crate::app::ui::now_playing::NowPlaying;
+
+        fn tag(items: &[(ItemKey, &str)]) -> Tag {
+            // Vorbis comments have a field for everything that is shown
+            let mut tag = Tag::new(lofty::tag::TagType::VorbisComments);
+            for (key, value) in items {
+                tag.insert_text(key.clone(), value.to_string());
+            }
+            tag
+        }
+
+        #[test]
+        fn test_every_detail() {
+            let tag = tag(&[
+                (ItemKey::AlbumTitle, "Album"),
+                (ItemKey::Year, "2019"),
+                (ItemKey::TrackNumber, "3"),
+                (ItemKey::TrackTotal, "12"),
+                (ItemKey::DiscNumber, "1"),
+                (ItemKey::Genre, "Soundtrack"),
+            ]);
+
+            assert_eq!(
+                NowPlaying::details_from_tag(&tag).unwrap(),
+                "Album · 2019 · Track 3/12 · Disc 1 · Soundtrack"
+            );
+        }
+
+        #[test]
+        fn test_album_artist() {
+            let tag = tag(&[
+                (ItemKey::TrackArtist, "Someone"),
+                (ItemKey::AlbumTitle, "Compilation"),
+                (ItemKey::AlbumArtist, "Various Artists"),
+            ]);
+
+            assert_eq!(
+                NowPlaying::details_from_tag(&tag).unwrap(),
+                "Compilation (Various Artists)"
+            );
+        }
+
+        #[test]
+        fn test_same_album_artist() {
+            let tag = tag(&[
+                (ItemKey::TrackArtist, "Someone"),
+                (ItemKey::AlbumTitle, "Album"),
+                (ItemKey::AlbumArtist, "Someone"),
+            ]);
+
+            assert_eq!(NowPlaying::details_from_tag(&tag).unwrap(), "Album");
+        }
+
+        #[test]
+        fn test_no_details() {
+            assert_eq!(NowPlaying::details_from_tag(&tag(&[])), None);
+        }
+    }
+
+    mod technical_details {
+        use std::time::Duration;
+
+        use lofty::file::FileType;
+        use lofty::properties::FileProperties;
+
+        use crate::app::ui::now_playing::NowPlaying;
+
+        fn properties(
+            bitrate: u32,
+            sample_rate: u32,
+            bit_depth: Option<u8>,
+            channels: u8,
+        ) -> FileProperties {
+            FileProperties::new(
+                Duration::from_secs(60),
+                Some(bitrate),
+                Some(bitrate),
+                Some(sample_rate),
+                bit_depth,
+                Some(channels),
+                None,
+            )
+        }
+
+        #[test]
+        fn test_lossless() {
+            assert_eq!(
+                NowPlaying::technical_details(
+                    FileType::Flac,
+                    &properties(2300, 96000, Some(24), 2)
+                ),
+                "FLAC · 24-bit/96 kHz · Stereo · 2.3 Mbps"
+            );
+        }
+
+        #[test]
+        fn test_lossy() {
+            assert_eq!(
+                NowPlaying::technical_details(FileType::Mpeg, &properties(320, 44100, None, 1)),
+                "MP3 · 44.1 kHz · Mono · 320 kbps"
+            );
+        }
+
+        #[test]
+        fn test_unknown_properties() {
+            assert_eq!(
+                NowPlaying::technical_details(FileType::Opus, &FileProperties::default()),
+                "Opus"
+            );
+        }
+    }
+
     mod extract_artist_from_tag {
         use lofty::tag::{ItemKey, Tag};
 
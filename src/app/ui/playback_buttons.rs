@@ -1,39 +1,101 @@
-use std::{sync::mpsc, time::Duration};
+use std::{cell::RefCell, rc::Rc, sync::mpsc, time::Duration};
 
 use fltk::{button::Button, prelude::*};
 
 use crate::app::Message;
+use crate::app::ui::layout::{Bounds, Layout};
+use crate::app::ui::markers::Markers;
 
-/// A struct to create the playback buttons: the play, fast-forward, and rewind buttons.
-pub struct PlaybackButtons {}
+/// A struct to create the playback buttons: the play, fast-forward, rewind, and previous/next chapter buttons.
+/// The mini player only has room for the play and next track buttons.
+#[derive(Clone)]
+pub struct PlaybackButtons {
+    /// The play/pause button, kept so that it can be updated when playback stops on its own or is toggled from the keyboard
+    play_btn: Button,
+}
 
 impl PlaybackButtons {
-    const SEEK_DURATION: Duration = Duration::from_secs(5);
     const PLAY_BUTTON: &str = "";
     const PAUSE_BUTTON: &str = "";
+    const PREVIOUS_CHAPTER_BUTTON: &str = "󰒮";
+    const NEXT_CHAPTER_BUTTON: &str = "󰒭";
+    const NEXT_TRACK_BUTTON: &str = "󰒬";
 
-    /// Create new playback buttons
-    pub fn new(win_width: i32, sender: mpsc::Sender<Message>) -> PlaybackButtons {
-        const BTN_SIZE: i32 = 30;
-        const BTN_Y: i32 = 200; // Since every button will be at the same y-coordinate, each button shares the same constant
+    /// Create new playback buttons, centered in `bounds` and as tall as them.
+    /// The fast-forward and rewind buttons jump by `seek_step`, and `current_audio_pos` is where the previous/next chapter buttons step from.
+    pub fn new(
+        bounds: Bounds,
+        layout: Layout,
+        seek_step: Duration,
+        sender: mpsc::Sender<Message>,
+        markers: Markers,
+        current_audio_pos: Rc<RefCell<Duration>>,
+    ) -> PlaybackButtons {
         const BTN_OFFSET: i32 = 100;
+        const CHAPTER_BTN_OFFSET: i32 = 150;
+
+        // The space between the buttons of the mini player
+        const MINI_BTN_GAP: i32 = 6;
+
+        // Every button is square and at the same y-coordinate
+        let btn_size = bounds.height;
+        let btn_y = bounds.y;
+
+        if layout == Layout::Mini {
+            // Center the play and next track buttons side by side
+            let play_btn_x = bounds.center_x() - btn_size - MINI_BTN_GAP / 2;
+            let next_track_btn_x = play_btn_x + btn_size + MINI_BTN_GAP;
+
+            let play_btn =
+                PlaybackButtons::create_play_button(btn_size, play_btn_x, btn_y, sender.clone());
+            PlaybackButtons::create_next_track_button(btn_size, next_track_btn_x, btn_y, sender);
+
+            return PlaybackButtons { play_btn };
+        }
 
-        let play_btn_x = (win_width - BTN_SIZE) / 2; // Center the button horizontally
+        let play_btn_x = bounds.center_x() - btn_size / 2; // Center the button horizontally
         let fast_forward_btn_x = play_btn_x + BTN_OFFSET;
         let rewind_btn_x = play_btn_x - BTN_OFFSET;
+        let next_chapter_btn_x = play_btn_x + CHAPTER_BTN_OFFSET;
+        let previous_chapter_btn_x = play_btn_x - CHAPTER_BTN_OFFSET;
 
-        PlaybackButtons::create_play_button(BTN_SIZE, play_btn_x, BTN_Y, sender.clone());
+        let play_btn =
+            PlaybackButtons::create_play_button(btn_size, play_btn_x, btn_y, sender.clone());
 
         PlaybackButtons::create_fast_forward_button(
-            BTN_SIZE,
+            btn_size,
             fast_forward_btn_x,
-            BTN_Y,
+            btn_y,
+            seek_step,
             sender.clone(),
         );
 
-        PlaybackButtons::create_rewind_button(BTN_SIZE, rewind_btn_x, BTN_Y, sender);
+        PlaybackButtons::create_rewind_button(btn_size, rewind_btn_x, btn_y, seek_step, sender);
+
+        PlaybackButtons::create_chapter_buttons(
+            btn_size,
+            (previous_chapter_btn_x, next_chapter_btn_x),
+            btn_y,
+            markers,
+            current_audio_pos,
+        );
+
+        PlaybackButtons { play_btn }
+    }
+
+    /// Play or pause the audio, just like clicking the play/pause button.
+    pub fn toggle_play_pause(&self) {
+        self.play_btn.clone().do_callback();
+    }
+
+    /// Return whether the audio is playing, going by the play/pause button.
+    pub fn is_playing(&self) -> bool {
+        self.play_btn.label() == Self::PAUSE_BUTTON
+    }
 
-        PlaybackButtons {}
+    /// Show the play button, for when the audio was paused without the user clicking the button, such as by the sleep timer.
+    pub fn show_paused(&mut self) {
+        self.play_btn.set_label(Self::PLAY_BUTTON);
     }
 
     /// Style each playback button with a unified style
@@ -48,7 +110,12 @@ impl PlaybackButtons {
     }
 
     /// Create the play button and theme it.
-    fn create_play_button(btn_size: i32, btn_x: i32, btn_y: i32, sender: mpsc::Sender<Message>) {
+    fn create_play_button(
+        btn_size: i32,
+        btn_x: i32,
+        btn_y: i32,
+        sender: mpsc::Sender<Message>,
+    ) -> Button {
         let mut btn = PlaybackButtons::style_button(
             Button::default()
                 .with_size(btn_size, btn_size)
@@ -70,6 +137,8 @@ impl PlaybackButtons {
                 eprintln!("Unable to play/pause audio: {:?}", e);
             };
         });
+
+        btn
     }
 
     /// Create the fast-forwards button.
@@ -77,6 +146,7 @@ impl PlaybackButtons {
         btn_size: i32,
         btn_x: i32,
         btn_y: i32,
+        seek_step: Duration,
         sender: mpsc::Sender<Message>,
     ) {
         let mut seek_forwards_btn = PlaybackButtons::style_button(
@@ -88,14 +158,20 @@ impl PlaybackButtons {
 
         seek_forwards_btn.set_callback(move |_| {
             // Send a fast-forward message to the audio thread
-            if let Err(e) = sender.send(Message::FastForward(Self::SEEK_DURATION)) {
+            if let Err(e) = sender.send(Message::FastForward(seek_step)) {
                 eprintln!("Unable to fast-forward: {:?}", e);
             }
         });
     }
 
     /// Create the rewind button.
-    fn create_rewind_button(btn_size: i32, btn_x: i32, btn_y: i32, sender: mpsc::Sender<Message>) {
+    fn create_rewind_button(
+        btn_size: i32,
+        btn_x: i32,
+        btn_y: i32,
+        seek_step: Duration,
+        sender: mpsc::Sender<Message>,
+    ) {
         let mut seek_backwards_btn = PlaybackButtons::style_button(
             Button::default()
                 .with_size(btn_size, btn_size)
@@ -105,12 +181,68 @@ impl PlaybackButtons {
 
         seek_backwards_btn.set_callback(move |_| {
             // Send a rewind message to the audio thread
-            if let Err(e) = sender.send(Message::Rewind(Self::SEEK_DURATION)) {
+            if let Err(e) = sender.send(Message::Rewind(seek_step)) {
                 eprintln!("Unable to rewind: {:?}", e)
             }
         });
     }
 
+    /// Create the button that skips to the next track in the queue.
+    fn create_next_track_button(
+        btn_size: i32,
+        btn_x: i32,
+        btn_y: i32,
+        sender: mpsc::Sender<Message>,
+    ) {
+        let mut next_track_btn = PlaybackButtons::style_button(
+            Button::default()
+                .with_size(btn_size, btn_size)
+                .with_pos(btn_x, btn_y)
+                .with_label(Self::NEXT_TRACK_BUTTON),
+        );
+        next_track_btn.set_tooltip("Next track");
+
+        next_track_btn.set_callback(move |_| {
+            if let Err(e) = sender.send(Message::Next) {
+                eprintln!("Unable to skip to the next track: {:?}", e);
+            }
+        });
+    }
+
+    /// Create the buttons that step to the previous and next chapter.
+    /// Tracks without chapters step between their bookmarks instead.
+    fn create_chapter_buttons(
+        btn_size: i32,
+        (previous_btn_x, next_btn_x): (i32, i32),
+        btn_y: i32,
+        markers: Markers,
+        current_audio_pos: Rc<RefCell<Duration>>,
+    ) {
+        let mut previous_btn = PlaybackButtons::style_button(
+            Button::default()
+                .with_size(btn_size, btn_size)
+                .with_pos(previous_btn_x, btn_y)
+                .with_label(Self::PREVIOUS_CHAPTER_BUTTON),
+        );
+        previous_btn.set_tooltip("Previous chapter");
+
+        let mut next_btn = PlaybackButtons::style_button(
+            Button::default()
+                .with_size(btn_size, btn_size)
+                .with_pos(next_btn_x, btn_y)
+                .with_label(Self::NEXT_CHAPTER_BUTTON),
+        );
+        next_btn.set_tooltip("Next chapter");
+
+        {
+            let markers = markers.clone();
+            let current_audio_pos = Rc::clone(&current_audio_pos);
+            previous_btn.set_callback(move |_| markers.previous(*current_audio_pos.borrow()));
+        }
+
+        next_btn.set_callback(move |_| markers.next(*current_audio_pos.borrow()));
+    }
+
     /// Return the corresponding label and Message once the play/pause button is clicked.
     /// For instance, if the audio is paused, the function will return (Self::PAUSE_BUTTON, Message::Play).
     /// However, if the audio is playing, the function will return (Self::PLAY_BUTTON, Message::Pause).
@@ -0,0 +1,467 @@
+use std::{cell::RefCell, fs, rc::Rc, sync::mpsc};
+
+use fltk::{
+    app,
+    browser::HoldBrowser,
+    button::{Button, CheckButton},
+    dialog::{self, NativeFileChooser, NativeFileChooserType},
+    enums::Align,
+    frame::Frame,
+    group::Group,
+    misc::Spinner,
+    prelude::*,
+    text::{TextBuffer, TextDisplay, WrapMode},
+    window::Window,
+};
+
+use crate::app::Message;
+use crate::app::podcasts::{Episode, EpisodeKey, Podcast, PodcastLibrary, PodcastSettings};
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// A dialog to subscribe to podcasts, read the show notes of their episodes, and download and queue episodes.
+#[derive(Clone)]
+pub struct PodcastsDialog {
+    window: Window,
+    podcast_browser: HoldBrowser,
+    episode_browser: HoldBrowser,
+
+    /// Shows the show notes of the selected episode
+    notes: TextBuffer,
+
+    /// Marks the selected episode as played or unplayed, depending on what it is now
+    played_btn: Button,
+
+    /// Tells the user what is happening in the background, and what went wrong
+    status: Frame,
+
+    library: Rc<RefCell<PodcastLibrary>>,
+    audio_sender: mpsc::Sender<Message>,
+}
+
+impl PodcastsDialog {
+    const WIDTH: i32 = 560;
+    const HEIGHT: i32 = 400;
+
+    /// The highest number of episodes per podcast that can be downloaded automatically
+    const MAX_AUTO_DOWNLOAD: f64 = 20.0;
+
+    /// Create the podcasts dialog. The dialog stays hidden until its window is shown.
+    pub fn new(
+        library: Rc<RefCell<PodcastLibrary>>,
+        audio_sender: mpsc::Sender<Message>,
+    ) -> PodcastsDialog {
+        const MARGIN: i32 = 15;
+        const SPACING: i32 = 10;
+        const LIST_WIDTH: i32 = 170;
+        const BTN_WIDTH: i32 = 80;
+        const BTN_HEIGHT: i32 = 25;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(PodcastsDialog::WIDTH, PodcastsDialog::HEIGHT)
+            .with_label("Podcasts");
+
+        let episodes_x = MARGIN + LIST_WIDTH + SPACING;
+        let episodes_width = PodcastsDialog::WIDTH - episodes_x - MARGIN;
+        let first_row_y = 295;
+        let second_row_y = first_row_y + BTN_HEIGHT + SPACING;
+
+        let podcast_browser = HoldBrowser::new(
+            MARGIN,
+            MARGIN,
+            LIST_WIDTH,
+            first_row_y - MARGIN - SPACING,
+            None,
+        );
+
+        let mut episode_browser = HoldBrowser::new(episodes_x, MARGIN, episodes_width, 150, None);
+        episode_browser.set_column_char('\t');
+        episode_browser.set_column_widths(&[55]);
+
+        let notes = TextBuffer::default();
+        let mut notes_display = TextDisplay::new(
+            episodes_x,
+            MARGIN + 150 + SPACING,
+            episodes_width,
+            first_row_y - MARGIN - 150 - 2 * SPACING,
+            None,
+        );
+        notes_display.set_buffer(notes.clone());
+        notes_display.wrap_mode(WrapMode::AtBounds, 0);
+
+        let left_btn_x = |i: i32| MARGIN + i * (BTN_WIDTH + SPACING);
+        let mut subscribe_btn = Button::new(
+            left_btn_x(0),
+            first_row_y,
+            BTN_WIDTH,
+            BTN_HEIGHT,
+            "Subscribe…",
+        );
+        let mut import_btn =
+            Button::new(left_btn_x(1), first_row_y, BTN_WIDTH, BTN_HEIGHT, "Import…");
+        import_btn.set_tooltip("Subscribe to the podcasts in an OPML file");
+        let mut remove_btn =
+            Button::new(left_btn_x(0), second_row_y, BTN_WIDTH, BTN_HEIGHT, "Remove");
+        let mut refresh_btn = Button::new(
+            left_btn_x(1),
+            second_row_y,
+            BTN_WIDTH,
+            BTN_HEIGHT,
+            "Refresh",
+        );
+        refresh_btn.set_tooltip("Look for new episodes");
+
+        let mut download_btn =
+            Button::new(episodes_x, first_row_y, BTN_WIDTH, BTN_HEIGHT, "Download");
+        let mut queue_btn = Button::new(
+            episodes_x + BTN_WIDTH + SPACING,
+            first_row_y,
+            BTN_WIDTH + 20,
+            BTN_HEIGHT,
+            "Add to queue",
+        );
+        let played_btn = Button::new(
+            episodes_x + 2 * (BTN_WIDTH + SPACING) + 20,
+            first_row_y,
+            BTN_WIDTH + 20,
+            BTN_HEIGHT,
+            "Mark played",
+        );
+
+        let settings = library.borrow().settings();
+        let mut auto_download = Spinner::new(
+            episodes_x + 95,
+            second_row_y,
+            50,
+            BTN_HEIGHT,
+            "Auto-download",
+        );
+        auto_download.set_tooltip("How many of the latest episodes of every podcast to download");
+        auto_download.set_range(0.0, PodcastsDialog::MAX_AUTO_DOWNLOAD);
+        auto_download.set_step(1.0);
+        auto_download.set_value(settings.auto_download as f64);
+
+        let mut delete_played = CheckButton::new(
+            episodes_x + 95 + 50 + SPACING,
+            second_row_y,
+            BTN_WIDTH + 40,
+            BTN_HEIGHT,
+            "Delete played",
+        );
+        delete_played.set_tooltip("Delete downloaded episodes once they have been played");
+        delete_played.set_checked(settings.delete_played);
+
+        let mut status = Frame::new(
+            MARGIN,
+            PodcastsDialog::HEIGHT - MARGIN - BTN_HEIGHT,
+            PodcastsDialog::WIDTH - 2 * MARGIN,
+            BTN_HEIGHT,
+            "",
+        );
+        status.set_align(Align::Left | Align::Inside);
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let podcasts_dialog = PodcastsDialog {
+            window,
+            podcast_browser,
+            episode_browser,
+            notes,
+            played_btn,
+            status,
+            library,
+            audio_sender,
+        };
+        podcasts_dialog.refresh();
+
+        let dialog = podcasts_dialog.clone();
+        subscribe_btn.set_callback(move |_| {
+            let Some(url) = dialog::input_default("Feed URL", "") else {
+                return;
+            };
+            if url.trim().is_empty() {
+                return;
+            }
+
+            dialog.library.borrow_mut().subscribe(&url);
+            dialog.set_status(&format!("Fetching {}…", url.trim()));
+        });
+
+        let dialog = podcasts_dialog.clone();
+        import_btn.set_callback(move |_| dialog.import_opml());
+
+        let dialog = podcasts_dialog.clone();
+        remove_btn.set_callback(move |_| {
+            let Some(podcast) = dialog.selected_podcast() else {
+                return;
+            };
+
+            let question = format!(
+                "Unsubscribe from {}? Its downloaded episodes will be deleted.",
+                podcast.title
+            );
+            if dialog::choice2_default(&question, "Cancel", "Unsubscribe", "") != Some(1) {
+                return;
+            }
+
+            dialog.library.borrow_mut().unsubscribe(&podcast.url);
+            dialog.refresh();
+        });
+
+        let dialog = podcasts_dialog.clone();
+        refresh_btn.set_callback(move |_| {
+            dialog.library.borrow_mut().refresh();
+            dialog.set_status("Looking for new episodes…");
+        });
+
+        let dialog = podcasts_dialog.clone();
+        download_btn.set_callback(move |_| {
+            if let Some((key, _)) = dialog.selected_episode() {
+                dialog.library.borrow_mut().download(&key);
+                dialog.refresh();
+            }
+        });
+
+        let dialog = podcasts_dialog.clone();
+        queue_btn.set_callback(move |_| dialog.queue_selected());
+
+        let dialog = podcasts_dialog.clone();
+        podcasts_dialog.played_btn.clone().set_callback(move |_| {
+            if let Some((key, episode)) = dialog.selected_episode() {
+                dialog
+                    .library
+                    .borrow_mut()
+                    .set_played(&key, !episode.played);
+                dialog.refresh();
+            }
+        });
+
+        // Show the episodes of a podcast once it is selected
+        let dialog = podcasts_dialog.clone();
+        podcasts_dialog
+            .podcast_browser
+            .clone()
+            .set_callback(move |_| {
+                // The episodes of the previous podcast are no longer selected
+                dialog.episode_browser.clone().clear();
+                dialog.refresh();
+            });
+
+        // Show the notes of an episode once it is selected, and queue it when it is double clicked
+        let dialog = podcasts_dialog.clone();
+        podcasts_dialog
+            .episode_browser
+            .clone()
+            .set_callback(move |_| {
+                if app::event_clicks() {
+                    dialog.queue_selected();
+                }
+                dialog.show_selected_episode();
+            });
+
+        let dialog = podcasts_dialog.clone();
+        auto_download.set_callback(move |spinner| {
+            let settings = dialog.library.borrow().settings();
+            dialog.library.borrow_mut().set_settings(PodcastSettings {
+                auto_download: spinner.value() as usize,
+                ..settings
+            });
+            dialog.refresh();
+        });
+
+        let dialog = podcasts_dialog.clone();
+        delete_played.set_callback(move |check_button| {
+            let settings = dialog.library.borrow().settings();
+            dialog.library.borrow_mut().set_settings(PodcastSettings {
+                delete_played: check_button.is_checked(),
+                ..settings
+            });
+            dialog.refresh();
+        });
+
+        podcasts_dialog
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Show the feeds and episodes that finished in the background, along with anything that went wrong.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&self) {
+        let (changed, errors, busy) = {
+            let mut library = self.library.borrow_mut();
+            (library.update(), library.take_errors(), library.is_busy())
+        };
+
+        if changed {
+            self.refresh();
+        }
+
+        if let Some(error) = errors.last() {
+            self.set_status(error);
+        } else if changed && !busy {
+            self.set_status("");
+        }
+    }
+
+    /// Fill the browsers with the podcasts and the episodes of the selected podcast, keeping what is selected.
+    fn refresh(&self) {
+        let library = self.library.borrow();
+
+        let mut podcast_browser = self.podcast_browser.clone();
+        let selected_podcast = podcast_browser.value();
+        podcast_browser.clear();
+        for podcast in library.podcasts() {
+            let unplayed = podcast.episodes.iter().filter(|e| !e.played).count();
+
+            // "@." stops the browser from treating the title as formatting
+            podcast_browser.add(&format!("@.{} ({})", podcast.title, unplayed));
+        }
+        if (1..=podcast_browser.size()).contains(&selected_podcast) {
+            podcast_browser.select(selected_podcast);
+        }
+
+        let mut episode_browser = self.episode_browser.clone();
+        let selected_episode = episode_browser.value();
+        episode_browser.clear();
+        if let Some(podcast) = PodcastsDialog::podcast_at(&library, &podcast_browser) {
+            for episode in &podcast.episodes {
+                let key = EpisodeKey::new(podcast, episode);
+                let state = match library.download_progress(&key) {
+                    Some(progress) => format!("{:.0}%", progress * 100.0),
+                    None if episode.played => "Played".to_string(),
+                    None if episode.file.is_some() => "Saved".to_string(),
+                    None => String::new(),
+                };
+
+                episode_browser.add(&format!("{}\t@.{}", state, episode.title));
+            }
+        }
+        if (1..=episode_browser.size()).contains(&selected_episode) {
+            episode_browser.select(selected_episode);
+        }
+
+        drop(library);
+        self.show_selected_episode();
+    }
+
+    /// Show the notes of the selected episode, or the description of the podcast if no episode is selected.
+    fn show_selected_episode(&self) {
+        let mut notes = self.notes.clone();
+        let mut played_btn = self.played_btn.clone();
+
+        let Some((_, episode)) = self.selected_episode() else {
+            notes.set_text(
+                &self
+                    .selected_podcast()
+                    .map(|podcast| podcast.description)
+                    .unwrap_or_default(),
+            );
+            played_btn.set_label("Mark played");
+            return;
+        };
+
+        notes.set_text(&PodcastsDialog::episode_notes(&episode));
+        played_btn.set_label(if episode.played {
+            "Mark unplayed"
+        } else {
+            "Mark played"
+        });
+    }
+
+    /// Return the notes of an episode, along with when it came out and how long it is.
+    fn episode_notes(episode: &Episode) -> String {
+        let details: Vec<String> = [
+            episode.published.clone(),
+            episode.length.map(ProgressBar::format_duration),
+            episode
+                .position
+                .map(|position| format!("Stopped at {}", ProgressBar::format_duration(position))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        format!(
+            "{}\n{}\n\n{}",
+            episode.title,
+            details.join(" · "),
+            episode.notes
+        )
+    }
+
+    /// Add the selected episode to the queue, or download it first if it hasn't been downloaded.
+    fn queue_selected(&self) {
+        let Some((key, episode)) = self.selected_episode() else {
+            return;
+        };
+
+        let Some(file) = episode.file else {
+            self.library.borrow_mut().download(&key);
+            self.set_status(&format!(
+                "Downloading {}, it can be added to the queue once it is downloaded",
+                episode.title
+            ));
+            self.refresh();
+            return;
+        };
+
+        match self.audio_sender.send(Message::Enqueue(file)) {
+            Ok(()) => self.set_status(&format!("Added {} to the queue", episode.title)),
+            Err(e) => eprintln!("Unable to queue episode: {:?}", e),
+        }
+    }
+
+    /// Ask the user for an OPML file, and subscribe to every podcast in it.
+    fn import_opml(&self) {
+        let mut chooser = NativeFileChooser::new(NativeFileChooserType::BrowseFile);
+        chooser.set_filter("*.{opml,xml}");
+        chooser.show();
+
+        let path = chooser.filename();
+        if path.as_os_str().is_empty() {
+            return;
+        }
+
+        let imported = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))
+            .and_then(|opml| self.library.borrow_mut().import_opml(&opml));
+
+        match imported {
+            Ok(0) => self.set_status("Already subscribed to every podcast in the file"),
+            Ok(count) => self.set_status(&format!("Subscribing to {} podcasts…", count)),
+            Err(e) => dialog::alert_default(&e),
+        }
+    }
+
+    fn set_status(&self, status: &str) {
+        let mut status_frame = self.status.clone();
+        status_frame.set_label(status);
+    }
+
+    fn selected_podcast(&self) -> Option<Podcast> {
+        PodcastsDialog::podcast_at(&self.library.borrow(), &self.podcast_browser).cloned()
+    }
+
+    /// Return the podcast that is selected in `browser`, if a podcast is selected.
+    fn podcast_at<'a>(library: &'a PodcastLibrary, browser: &HoldBrowser) -> Option<&'a Podcast> {
+        // Browser lines start at 1, and 0 means that nothing is selected
+        let line = usize::try_from(browser.value() - 1).ok()?;
+
+        library.podcasts().get(line)
+    }
+
+    fn selected_episode(&self) -> Option<(EpisodeKey, Episode)> {
+        let podcast = self.selected_podcast()?;
+        let line = usize::try_from(self.episode_browser.value() - 1).ok()?;
+        let episode = podcast.episodes.get(line)?;
+
+        Some((EpisodeKey::new(&podcast, episode), episode.clone()))
+    }
+}
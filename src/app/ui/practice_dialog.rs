@@ -0,0 +1,142 @@
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use fltk::{button::Button, group::Group, misc::Spinner, prelude::*, window::Window};
+
+use crate::app::ab_loop::{AbLoop, PracticeSettings};
+use crate::app::ui::loop_controls::LoopRegion;
+
+/// A dialog to change the practice settings of the A–B loop: the gap and count-in between repeats, and how much faster each repeat gets.
+#[derive(Clone)]
+pub struct PracticeDialog {
+    window: Window,
+    form: PracticeForm,
+
+    /// The practice settings that new loops start with
+    practice: Rc<Cell<PracticeSettings>>,
+
+    region: LoopRegion,
+}
+
+/// The widgets that make up the practice settings.
+#[derive(Clone)]
+struct PracticeForm {
+    gap: Spinner,
+    count_in_beats: Spinner,
+    count_in_bpm: Spinner,
+    start_speed: Spinner,
+    speed_step: Spinner,
+    target_speed: Spinner,
+}
+
+impl PracticeDialog {
+    const WIDTH: i32 = 300;
+    const HEIGHT: i32 = 250;
+
+    /// Create the practice dialog. The dialog stays hidden until it is shown.
+    pub fn new(region: LoopRegion, practice: Rc<Cell<PracticeSettings>>) -> PracticeDialog {
+        const LABEL_WIDTH: i32 = 150;
+        const FIELD_WIDTH: i32 = 130;
+        const ROW_HEIGHT: i32 = 25;
+        const ROW_SPACING: i32 = 30;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(PracticeDialog::WIDTH, PracticeDialog::HEIGHT)
+            .with_label("Practice");
+
+        let row_y = |row: i32| 15 + row * ROW_SPACING;
+        let spinner = |row: i32, label: &str, min: f64, max: f64, step: f64| {
+            let mut spinner = Spinner::new(LABEL_WIDTH, row_y(row), FIELD_WIDTH, ROW_HEIGHT, None)
+                .with_label(label);
+            spinner.set_range(min, max);
+            spinner.set_step(step);
+            spinner
+        };
+
+        let form = PracticeForm {
+            gap: spinner(0, "Gap (seconds)", 0.0, 30.0, 0.5),
+            count_in_beats: spinner(1, "Count-in beats", 0.0, 16.0, 1.0),
+            count_in_bpm: spinner(2, "Count-in tempo (BPM)", 20.0, 300.0, 1.0),
+            start_speed: spinner(3, "Start speed", 0.25, 2.0, 0.05),
+            speed_step: spinner(4, "Speed-up per pass", -0.5, 0.5, 0.01),
+            target_speed: spinner(5, "Target speed", 0.25, 2.0, 0.05),
+        };
+
+        let mut apply_btn = Button::new(
+            PracticeDialog::WIDTH - 95,
+            row_y(6) + 5,
+            80,
+            ROW_HEIGHT,
+            "Apply",
+        );
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let practice_dialog = PracticeDialog {
+            window,
+            form,
+            practice,
+            region,
+        };
+
+        let dialog = practice_dialog.clone();
+        apply_btn.set_callback(move |_| {
+            let practice = dialog.read_settings();
+            dialog.practice.set(practice);
+
+            // Apply the settings to the current loop too, which starts it again from the first pass
+            if let Some(ab_loop) = dialog.region.get() {
+                dialog.region.set(Some(AbLoop {
+                    practice,
+                    ..ab_loop
+                }));
+            }
+
+            dialog.window.clone().hide();
+        });
+
+        practice_dialog
+    }
+
+    /// Return a handle to the dialog's window.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Fill the form in with the current settings and show the dialog.
+    pub fn show(&self) {
+        let practice = self
+            .region
+            .get()
+            .map_or_else(|| self.practice.get(), |ab_loop| ab_loop.practice);
+
+        let mut form = self.form.clone();
+        form.gap.set_value(practice.gap.as_secs_f64());
+        form.count_in_beats
+            .set_value(practice.count_in_beats as f64);
+        form.count_in_bpm.set_value(practice.count_in_bpm as f64);
+        form.start_speed.set_value(practice.start_speed as f64);
+        form.speed_step.set_value(practice.speed_step as f64);
+        form.target_speed.set_value(practice.target_speed as f64);
+
+        self.window.clone().show();
+    }
+
+    /// Turn the values of the form into practice settings.
+    fn read_settings(&self) -> PracticeSettings {
+        let form = &self.form;
+
+        PracticeSettings {
+            gap: Duration::from_secs_f64(form.gap.value().max(0.0)),
+            count_in_beats: form.count_in_beats.value() as u32,
+            count_in_bpm: form.count_in_bpm.value() as u32,
+            start_speed: form.start_speed.value() as f32,
+            speed_step: form.speed_step.value() as f32,
+            target_speed: form.target_speed.value() as f32,
+        }
+    }
+}
@@ -1,50 +1,195 @@
-use std::{cell::RefCell, rc::Rc, sync::mpsc, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
 
 use fltk::{
     app::{self, MouseButton},
     draw,
-    enums::{Color, Event, Font, FrameType},
+    enums::{Align, Color, ColorDepth, Event, Font},
     frame::Frame,
+    image::RgbImage,
     misc::Progress,
-    output,
-    prelude::{WidgetBase, WidgetExt},
+    prelude::{ImageExt, WidgetBase, WidgetExt},
+    window::Window,
 };
 
 use crate::app::Message;
+use crate::app::ab_loop::AbLoop;
+use crate::app::ui::layout::Bounds;
+use crate::app::ui::loop_controls::LoopRegion;
+use crate::app::ui::markers::Markers;
+use crate::app::ui::seek_preview::SeekPreview;
+use crate::app::waveform::{Peak, PeakCache, Waveform, WaveformLoader};
+
+/// One of the two ends of the A–B loop, which can be dragged to move it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoopHandle {
+    Start,
+    End,
+}
+
+/// The waveform that can be drawn in place of the progress bar, which is worked out the first time it is shown.
+#[derive(Default)]
+struct WaveformView {
+    /// Whether the waveform is shown instead of the plain progress bar, which carries over from one track to the next
+    shown: Rc<Cell<bool>>,
+
+    /// The track whose waveform is drawn, which is `None` for streams since they have no end
+    track_path: Option<PathBuf>,
+
+    loader: Option<WaveformLoader>,
+    waveform: Option<Waveform>,
+}
+
+impl WaveformView {
+    /// Return the waveform if it should be drawn.
+    fn drawn(&self) -> Option<&Waveform> {
+        self.waveform.as_ref().filter(|_| self.shown.get())
+    }
+
+    /// Start working out the waveform once it is shown, and keep it once it is ready.
+    fn update(&mut self, columns: usize) {
+        if !self.shown.get() || self.waveform.is_some() {
+            return;
+        }
+
+        match (&self.loader, &self.track_path) {
+            (Some(loader), _) => self.waveform = loader.try_recv(),
+            (None, Some(track_path)) => {
+                self.loader = Some(WaveformLoader::start(
+                    track_path.clone(),
+                    columns,
+                    PeakCache::new(),
+                ));
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// How the timestamps next to the progress bar are shown, which carries over from one track to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeDisplay {
+    /// Whether the right timestamp counts down the time that is left, rather than showing the length
+    pub remaining: bool,
+
+    /// Whether the timestamps show milliseconds, for transcribing
+    pub precise: bool,
+}
+
+impl TimeDisplay {
+    fn format(self, duration: Duration) -> String {
+        if self.precise {
+            ProgressBar::format_precise_duration(duration)
+        } else {
+            ProgressBar::format_duration(duration)
+        }
+    }
+}
 
 /// Stores the progress bar that shows the user how far into the audio track they are.
 /// The user can also click on the progress bar in order seek to a specific point in the audio
 pub struct ProgressBar {
     progress_bar: Rc<RefCell<Progress>>,
 
-    /// The receiver that will receive the audio's current position, and update accordingly
-    audio_pos_receiver: mpsc::Receiver<Duration>,
+    /// The receiver that will receive the audio's current position, and update accordingly.
+    /// The same receiver is handed to the progress bar of each track in the queue.
+    audio_pos_receiver: Rc<mpsc::Receiver<Duration>>,
 
-    audio_length: Duration,
+    /// The length of the audio, which is `None` for live streams and for files whose length is still being worked out.
+    /// Without a length, the progress bar only counts up and can't be clicked to seek.
+    audio_length: Rc<Cell<Option<Duration>>>,
 
     current_audio_pos: Rc<RefCell<Duration>>,
 
+    /// Whether the audio is a live stream, which has no length to show
+    live: bool,
+
     /// Display the audio's current position to the user
-    current_audio_pos_timestamp: output::Output,
+    current_audio_pos_timestamp: Frame,
+
+    /// Display the audio's length to the user, or how much of it is left
+    total_audio_duration_timestamp: Frame,
+
+    /// How the timestamps and the seek preview show times.
+    /// The inner cell is swapped for the shared one by `share_time_display`, so the hover handler always reads the current one.
+    time_display: Rc<RefCell<Rc<Cell<TimeDisplay>>>>,
 
     /// The overlay that is used to draw the knob on top of the progress bar
     knob_overlay: Rc<RefCell<Frame>>,
 
     /// The sender that will be used to rewind or fast-forward the audio when the progress bar is clicked
     audio_sender: mpsc::Sender<Message>,
+
+    /// The A–B loop, which is shaded on top of the progress bar
+    loop_region: LoopRegion,
+
+    /// The bookmarks and chapters, which are drawn as ticks on the progress bar
+    markers: Markers,
+
+    /// The waveform of the track, which can be drawn instead of the progress bar
+    waveform: Rc<RefCell<WaveformView>>,
+
+    /// Shows where a click would land while the mouse is over the progress bar
+    seek_preview: SeekPreview,
 }
 
 impl ProgressBar {
+    /// The accent of the theme
+    const LOOP_COLOR: Color = Color::Selection;
+
+    /// How opaque the shading of the loop is, from 0 to 255
+    const LOOP_SHADE_ALPHA: u8 = 70;
+
+    /// How close to a loop handle, in pixels, the mouse has to be to grab it
+    const LOOP_HANDLE_GRAB_DISTANCE: i32 = 4;
+
+    /// How close to a bookmark, in pixels, the mouse has to be for the seek preview to name it
+    const BOOKMARK_HOVER_DISTANCE: i32 = 4;
+
+    const BOOKMARK_COLOR: (u8, u8, u8) = (0xff, 0x99, 0x00);
+
+    /// The color of the part of the progress bar and the waveform that was played, which is the accent of the theme
+    const PLAYED_COLOR: Color = Color::Selection;
+
+    const KNOB_COLOR: Color = Color::Foreground;
+
+    /// The size of the timestamps on both sides of the progress bar
+    const TIMESTAMP_WIDTH: i32 = 66;
+    const TIMESTAMP_HEIGHT: i32 = 20;
+
+    /// The space between each timestamp and the progress bar
+    const TIMESTAMP_GAP: i32 = 6;
+
+    /// What is shown instead of the length of a live stream
+    const LIVE_LABEL: &str = "LIVE";
+
+    /// What is shown instead of the length of a file until it is known
+    const UNKNOWN_LENGTH_LABEL: &str = "--:--";
+
+    /// Create the progress bar across `bounds`, with the timestamps on both sides of it if the bounds are tall enough for them.
+    /// `live` is whether the audio is a live stream. Otherwise, an `audio_length` of `None` means that the length isn't known yet.
     pub fn new(
-        win_width: i32,
-        audio_length: Duration,
-        audio_pos_receiver: mpsc::Receiver<Duration>,
+        bounds: Bounds,
+        audio_length: Option<Duration>,
+        live: bool,
+        audio_pos_receiver: Rc<mpsc::Receiver<Duration>>,
         audio_sender: mpsc::Sender<Message>,
+        loop_region: LoopRegion,
+        markers: Markers,
     ) -> ProgressBar {
-        let progress_bar = ProgressBar::create_progress_widget(win_width, audio_length);
+        let progress_bar = ProgressBar::create_progress_widget(bounds, audio_length, live);
 
-        let (current_audio_pos_timestamp, _) =
-            ProgressBar::create_timestamps(&progress_bar.borrow(), audio_length);
+        let (mut current_audio_pos_timestamp, mut total_audio_duration_timestamp) =
+            ProgressBar::create_timestamps(&progress_bar.borrow(), audio_length, live);
+        if !ProgressBar::has_timestamps(bounds) {
+            current_audio_pos_timestamp.hide();
+            total_audio_duration_timestamp.hide();
+        }
 
         let knob_overlay = ProgressBar::create_knob_overlay_widget(&progress_bar);
 
@@ -55,10 +200,17 @@ impl ProgressBar {
             progress_bar,
             audio_pos_receiver,
             current_audio_pos,
-            audio_length,
+            audio_length: Rc::new(Cell::new(audio_length)),
+            live,
             current_audio_pos_timestamp,
+            total_audio_duration_timestamp,
+            time_display: Rc::new(RefCell::new(Rc::new(Cell::new(TimeDisplay::default())))),
             knob_overlay,
             audio_sender,
+            loop_region,
+            markers,
+            waveform: Rc::new(RefCell::new(WaveformView::default())),
+            seek_preview: SeekPreview::new(),
         };
 
         ProgressBar::add_knob_overlay_event_handler(&progress);
@@ -66,24 +218,45 @@ impl ProgressBar {
         progress
     }
 
-    fn create_progress_widget(win_width: i32, audio_length: Duration) -> Rc<RefCell<Progress>> {
-        const WIDTH: i32 = 250;
-        const PROGRESS_BAR_Y: i32 = 190;
+    /// Return whether `bounds` are tall enough for the timestamps. Otherwise, the progress bar takes up their whole width.
+    fn has_timestamps(bounds: Bounds) -> bool {
+        bounds.height >= ProgressBar::TIMESTAMP_HEIGHT
+    }
+
+    fn create_progress_widget(
+        bounds: Bounds,
+        audio_length: Option<Duration>,
+        live: bool,
+    ) -> Rc<RefCell<Progress>> {
+        const HEIGHT: i32 = 5;
 
-        let progress_bar_x = (win_width - WIDTH) / 2; // Center the progress bar horizontally
+        // Leave room for the timestamps on both sides of the progress bar
+        let timestamp_room = if ProgressBar::has_timestamps(bounds) {
+            ProgressBar::TIMESTAMP_WIDTH + ProgressBar::TIMESTAMP_GAP
+        } else {
+            0
+        };
+        let width = bounds.width - 2 * timestamp_room;
 
         let progress_bar = Rc::new(RefCell::new(
             Progress::default()
-                .with_pos(progress_bar_x, PROGRESS_BAR_Y)
-                .with_size(WIDTH, 5),
+                .with_pos(bounds.x + timestamp_room, bounds.center_y())
+                .with_size(width, HEIGHT),
         ));
 
         // Set the range to be from 0 - audio length so progress bar value can simply be set to current position without doing any calculations
+        // A live stream has no end to make progress towards, so its progress bar is always full
+        let (maximum, value) = match audio_length {
+            Some(audio_length) => (audio_length.as_millis() as f64, 0.0),
+            None if live => (1.0, 1.0),
+            None => (1.0, 0.0),
+        };
         progress_bar.borrow_mut().set_minimum(0.0);
+        progress_bar.borrow_mut().set_maximum(maximum);
+        progress_bar.borrow_mut().set_value(value);
         progress_bar
             .borrow_mut()
-            .set_maximum(audio_length.as_millis() as f64);
-        progress_bar.borrow_mut().set_value(0.0);
+            .set_selection_color(ProgressBar::PLAYED_COLOR);
 
         progress_bar
     }
@@ -108,38 +281,121 @@ impl ProgressBar {
         let diameter = 10;
         let knob_y = progress.progress_bar.borrow().y() + KNOB_Y_OFFSET;
 
-        // Clone/copy a bunch of values that will be moved into the handle closure
-        let knob_overlay = Rc::clone(&progress.knob_overlay);
+        // Whether the mouse is over the progress bar, in which case the knob is drawn
+        let hovered = Rc::new(Cell::new(false));
+
+        // The loop handle that is being dragged, along with the loop from before the drag started
+        let dragged_handle: Rc<Cell<Option<(LoopHandle, AbLoop)>>> = Rc::new(Cell::new(None));
+
+        // Clone/copy a bunch of values that will be moved into the closures
         let audio_sender = progress.audio_sender.clone();
-        let audio_length = progress.audio_length;
         let current_audio_pos = Rc::clone(&progress.current_audio_pos);
         let progress_bar = Rc::clone(&progress.progress_bar);
+        let loop_region = progress.loop_region.clone();
+        let audio_length = Rc::clone(&progress.audio_length);
+
+        // Always draw the loop and markers, but only draw the knob while hovering over the progress bar
+        {
+            let progress_bar = Rc::clone(&progress_bar);
+            let loop_region = loop_region.clone();
+            let markers = progress.markers.clone();
+            let hovered = Rc::clone(&hovered);
+            let audio_length = Rc::clone(&audio_length);
+            let waveform = Rc::clone(&progress.waveform);
+
+            progress.knob_overlay.borrow_mut().draw(move |overlay| {
+                let progress_bar = progress_bar.borrow();
+
+                if let Some(waveform) = waveform.borrow().drawn() {
+                    ProgressBar::draw_waveform(overlay, &progress_bar, waveform);
+                }
+
+                // Without a length, there is nowhere to place the loop, the markers or the knob
+                let Some(audio_length) = audio_length.get() else {
+                    return;
+                };
+
+                if let Some(ab_loop) = loop_region.get() {
+                    ProgressBar::draw_loop(overlay, &progress_bar, audio_length, &ab_loop);
+                }
+
+                ProgressBar::draw_markers(&progress_bar, audio_length, &markers);
+
+                if hovered.get() {
+                    // Update knob_x
+                    let knob_x = ProgressBar::knob_x(&progress_bar);
+
+                    // Draw the knob
+                    draw::draw_circle_fill(knob_x, knob_y, diameter, ProgressBar::KNOB_COLOR);
+                }
+            });
+        }
 
         // Handle hovering over progress bar
+        let waveform = Rc::clone(&progress.waveform);
+        let markers = progress.markers.clone();
+        let seek_preview = progress.seek_preview.clone();
+        let time_display = Rc::clone(&progress.time_display);
         progress
             .knob_overlay
             .borrow_mut()
-            .handle(move |_, event| match event {
+            .handle(move |overlay, event| match event {
                 Event::Enter => {
-                    // Clone the reference again so it can then be moved into the draw closure
-                    let progress_bar = Rc::clone(&progress_bar);
-
-                    // Update the knob overlay's draw function to draw the knob
-                    knob_overlay.borrow_mut().draw(move |_| {
-                        // Update knob_x
-                        let knob_x = ProgressBar::knob_x(&progress_bar.borrow());
-
-                        // Draw the knob
-                        draw::draw_circle_fill(knob_x, knob_y, diameter, Color::gray_ramp(1));
-                    });
+                    hovered.set(true);
+                    overlay.redraw();
                     true
                 }
                 Event::Leave => {
-                    // Update the knob overlay's draw function to draw nothing
-                    knob_overlay.borrow_mut().draw(move |_| {});
+                    hovered.set(false);
+                    seek_preview.hide();
+                    overlay.redraw();
+                    true
+                }
+                Event::Move => {
+                    match audio_length.get() {
+                        Some(audio_length) => ProgressBar::show_seek_preview(
+                            &seek_preview,
+                            overlay,
+                            &progress_bar.borrow(),
+                            audio_length,
+                            &markers,
+                            waveform.borrow().waveform.as_ref(),
+                            time_display.borrow().get(),
+                        ),
+                        // Without a length, there is nowhere to seek to
+                        None => seek_preview.hide(),
+                    }
+                    true
+                }
+                Event::Push if app::event_mouse_button() == MouseButton::Right => {
+                    let waveform = waveform.borrow();
+                    if waveform.track_path.is_none() {
+                        return false;
+                    }
+
+                    waveform.shown.set(!waveform.shown.get());
+                    overlay.redraw();
                     true
                 }
                 Event::Push if app::event_mouse_button() == MouseButton::Left => {
+                    // A track can only be seeked once its length is known
+                    let Some(audio_length) = audio_length.get() else {
+                        return false;
+                    };
+
+                    // Grab a loop handle if there is one under the mouse, otherwise seek
+                    if let Some(ab_loop) = loop_region.get()
+                        && let Some(handle) = ProgressBar::loop_handle_at(
+                            &progress_bar.borrow(),
+                            audio_length,
+                            &ab_loop,
+                            app::event_x(),
+                        )
+                    {
+                        dragged_handle.set(Some((handle, ab_loop)));
+                        return true;
+                    }
+
                     ProgressBar::handle_seek_event(
                         &audio_sender,
                         audio_length,
@@ -147,32 +403,327 @@ impl ProgressBar {
                         &progress_bar,
                     )
                 }
+                Event::Drag => {
+                    let Some(audio_length) = audio_length.get() else {
+                        return false;
+                    };
+
+                    let pos = ProgressBar::position_at(
+                        &progress_bar.borrow(),
+                        audio_length,
+                        app::event_x(),
+                    );
+
+                    // Dragging from anywhere but a loop handle keeps seeking, like the click that started it.
+                    // The position is sent as it is, since the ones sent before may not have been reported back yet
+                    let Some((handle, _)) = dragged_handle.get() else {
+                        if app::event_mouse_button() != MouseButton::Left {
+                            return false;
+                        }
+                        if let Err(e) = audio_sender.send(Message::Seek(pos)) {
+                            eprintln!("Unable to seek: {}", e);
+                            return false;
+                        }
+                        *current_audio_pos.borrow_mut() = pos;
+                        overlay.redraw();
+                        return true;
+                    };
+
+                    // Only show the moved loop while dragging, it gets applied once the handle is let go
+                    if let Some(ab_loop) = loop_region.get() {
+                        loop_region
+                            .preview(Some(ProgressBar::move_loop_handle(&ab_loop, handle, pos)));
+                    }
+                    overlay.redraw();
+                    true
+                }
+                Event::Released => {
+                    let Some((_, loop_before_drag)) = dragged_handle.take() else {
+                        return false;
+                    };
+
+                    // A loop without a length can't be played, so go back to how the loop was
+                    match loop_region.get() {
+                        Some(ab_loop) if ab_loop.start < ab_loop.end => {
+                            loop_region.set(Some(ab_loop))
+                        }
+                        _ => loop_region.preview(Some(loop_before_drag)),
+                    }
+                    overlay.redraw();
+                    true
+                }
                 _ => false,
             });
     }
 
+    /// Show where a click at the mouse's position would seek to, along with the marker and waveform there.
+    /// This uses the same position as a click, so that the preview and the seek always agree.
+    fn show_seek_preview(
+        seek_preview: &SeekPreview,
+        overlay: &Frame,
+        progress_bar: &Progress,
+        audio_length: Duration,
+        markers: &Markers,
+        waveform: Option<&Waveform>,
+        time_display: TimeDisplay,
+    ) {
+        let x = app::event_x();
+        let position = ProgressBar::position_at(progress_bar, audio_length, x);
+
+        let tolerance = audio_length
+            .mul_f64(ProgressBar::BOOKMARK_HOVER_DISTANCE as f64 / progress_bar.width() as f64);
+        let label = markers.label_at(position, tolerance);
+
+        let column = (x - progress_bar.x()).clamp(0, progress_bar.width()) as usize;
+        let peaks = waveform
+            .map(|waveform| ProgressBar::peaks_around(&waveform.peaks, column))
+            .unwrap_or_default();
+
+        // Float above the overlay, rather than above the mouse, so that it doesn't jump up and down
+        let overlay_y_root = app::event_y_root() - (app::event_y() - overlay.y());
+        seek_preview.show_at(
+            app::event_x_root(),
+            overlay_y_root,
+            time_display.format(position),
+            label,
+            peaks,
+        );
+    }
+
+    /// Return the peaks within `SeekPreview::WAVEFORM_RADIUS` columns of `column`, with `column` in the middle.
+    /// Columns past either end of the track are silent.
+    fn peaks_around(peaks: &[Peak], column: usize) -> Vec<Peak> {
+        let radius = SeekPreview::WAVEFORM_RADIUS as isize;
+
+        (column as isize - radius..=column as isize + radius)
+            .map(|i| {
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| peaks.get(i))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Draw a line from the lowest to the highest sample of each column, with the average loudness on top of it.
+    /// The part that was already played is drawn in a different color.
+    fn draw_waveform(overlay: &Frame, progress_bar: &Progress, waveform: &Waveform) {
+        let center_y = overlay.y() + overlay.height() / 2;
+        let half_height = overlay.height() as f32 / 2.0;
+        let played_x = ProgressBar::knob_x(progress_bar);
+
+        // The average loudness is drawn in a darker shade, on top of the peaks
+        let played_peak_color = ProgressBar::PLAYED_COLOR;
+        let played_rms_color = ProgressBar::PLAYED_COLOR.darker();
+
+        for (i, peak) in waveform.peaks.iter().enumerate() {
+            let x = progress_bar.x() + i as i32;
+            let (peak_color, rms_color) = if x < played_x {
+                (played_peak_color, played_rms_color)
+            } else {
+                (Color::gray_ramp(14), Color::gray_ramp(9))
+            };
+
+            // Always draw at least a pixel, so that silence still shows as a line
+            let top = center_y - (peak.max * half_height) as i32;
+            let bottom = center_y - (peak.min * half_height) as i32;
+            draw::draw_rect_fill(x, top, 1, (bottom - top).max(1), peak_color);
+
+            let rms_height = (peak.rms * half_height) as i32;
+            draw::draw_rect_fill(
+                x,
+                center_y - rms_height,
+                1,
+                (2 * rms_height).max(1),
+                rms_color,
+            );
+        }
+    }
+
+    /// Shade the section of the progress bar that is looped, and draw the handles at both of its ends.
+    fn draw_loop(
+        overlay: &Frame,
+        progress_bar: &Progress,
+        audio_length: Duration,
+        ab_loop: &AbLoop,
+    ) {
+        const HANDLE_WIDTH: i32 = 2;
+
+        let (r, g, b) = ProgressBar::LOOP_COLOR.to_rgb();
+        let start_x = ProgressBar::x_at(progress_bar, audio_length, ab_loop.start);
+        let end_x = ProgressBar::x_at(progress_bar, audio_length, ab_loop.end);
+        let width = (end_x - start_x).max(1);
+        let height = overlay.height();
+
+        // fltk can't fill a rectangle with a translucent color, so draw a translucent image instead
+        let pixels = [r, g, b, ProgressBar::LOOP_SHADE_ALPHA].repeat((width * height) as usize);
+        match RgbImage::new(&pixels, width, height, ColorDepth::Rgba8) {
+            Ok(mut shade) => shade.draw(start_x, overlay.y(), width, height),
+            Err(e) => eprintln!("Unable to draw loop: {:?}", e),
+        }
+
+        for handle_x in [start_x, end_x] {
+            draw::draw_rect_fill(
+                handle_x - HANDLE_WIDTH / 2,
+                overlay.y(),
+                HANDLE_WIDTH,
+                height,
+                ProgressBar::LOOP_COLOR,
+            );
+        }
+    }
+
+    /// Draw a tick on the progress bar for every chapter and bookmark.
+    fn draw_markers(progress_bar: &Progress, audio_length: Duration, markers: &Markers) {
+        const TICK_WIDTH: i32 = 1;
+        const TICK_OVERHANG: i32 = 3;
+
+        let tick_y = progress_bar.y() - TICK_OVERHANG;
+        let tick_height = progress_bar.height() + 2 * TICK_OVERHANG;
+
+        let chapters = markers
+            .chapters()
+            .iter()
+            .map(|chapter| (chapter.start, Color::gray_ramp(8)));
+
+        let (r, g, b) = ProgressBar::BOOKMARK_COLOR;
+        let bookmarks = markers
+            .bookmarks()
+            .into_iter()
+            .map(|bookmark| (bookmark.position, Color::from_rgb(r, g, b)));
+
+        // Draw the bookmarks last, so they stay visible when they are on top of a chapter
+        for (position, color) in chapters.chain(bookmarks) {
+            let tick_x = ProgressBar::x_at(progress_bar, audio_length, position);
+            draw::draw_rect_fill(tick_x, tick_y, TICK_WIDTH, tick_height, color);
+        }
+    }
+
+    /// Return the loop handle that is close enough to `x` to be grabbed, if any.
+    fn loop_handle_at(
+        progress_bar: &Progress,
+        audio_length: Duration,
+        ab_loop: &AbLoop,
+        x: i32,
+    ) -> Option<LoopHandle> {
+        let start_distance =
+            (ProgressBar::x_at(progress_bar, audio_length, ab_loop.start) - x).abs();
+        let end_distance = (ProgressBar::x_at(progress_bar, audio_length, ab_loop.end) - x).abs();
+
+        // Prefer the closest handle, since both can be in reach for short loops
+        let (handle, distance) = if end_distance < start_distance {
+            (LoopHandle::End, end_distance)
+        } else {
+            (LoopHandle::Start, start_distance)
+        };
+
+        (distance <= ProgressBar::LOOP_HANDLE_GRAB_DISTANCE).then_some(handle)
+    }
+
+    /// Return the loop with one of its handles moved to `pos`. A handle can't be moved past the other one.
+    fn move_loop_handle(ab_loop: &AbLoop, handle: LoopHandle, pos: Duration) -> AbLoop {
+        match handle {
+            LoopHandle::Start => AbLoop {
+                start: pos.min(ab_loop.end),
+                ..*ab_loop
+            },
+            LoopHandle::End => AbLoop {
+                end: pos.max(ab_loop.start),
+                ..*ab_loop
+            },
+        }
+    }
+
+    /// Let the user show the waveform of the track at `track_path` in place of the progress bar, by right-clicking it.
+    /// `shown` is whether it is shown, which carries over from one track to the next.
+    pub fn enable_waveform(&mut self, track_path: PathBuf, shown: Rc<Cell<bool>>) {
+        let mut waveform = self.waveform.borrow_mut();
+        waveform.track_path = Some(track_path);
+        waveform.shown = shown;
+
+        self.knob_overlay
+            .borrow_mut()
+            .set_tooltip("Right-click to show or hide the waveform");
+    }
+
+    /// Return a handle to the window of the seek preview, so that it can be deleted along with the track.
+    pub fn seek_preview_window(&self) -> Window {
+        self.seek_preview.window()
+    }
+
+    /// Return a handle to the audio's current position, as last reported by the audio thread.
+    pub fn current_audio_pos(&self) -> Rc<RefCell<Duration>> {
+        Rc::clone(&self.current_audio_pos)
+    }
+
+    /// Return a handle to the length of the audio, which is filled in once it is known.
+    pub fn audio_length_handle(&self) -> Rc<Cell<Option<Duration>>> {
+        Rc::clone(&self.audio_length)
+    }
+
+    /// Show the length of the audio, once it has been worked out. This makes the progress bar seekable.
+    pub fn set_audio_length(&mut self, audio_length: Duration) {
+        self.audio_length.set(Some(audio_length));
+
+        self.progress_bar
+            .borrow_mut()
+            .set_maximum(audio_length.as_millis() as f64);
+
+        self.update();
+    }
+
     /// Update the progress bar based on the audio's current position.
     /// This function is intended to be called continuously in the app's main loop.
     pub fn update(&mut self) {
         // Drain all available positions and keep the newest one, so the progress bar never lags behind
         while let Ok(pos) = self.audio_pos_receiver.try_recv() {
             // Ensure that current_audio_pos never goes over audio_length
-            *self.current_audio_pos.borrow_mut() = pos.clamp(Duration::ZERO, self.audio_length);
+            *self.current_audio_pos.borrow_mut() = match self.audio_length.get() {
+                Some(audio_length) => pos.clamp(Duration::ZERO, audio_length),
+                None => pos,
+            };
         }
 
         // Draw the knob
         self.knob_overlay.borrow_mut().redraw();
 
-        // Update the timestamp
-        self.current_audio_pos_timestamp
-            .set_label(&ProgressBar::format_duration(
-                *self.current_audio_pos.borrow(),
-            ));
+        // Update the timestamps
+        let time_display = self.time_display.borrow().get();
+        let current_audio_pos = *self.current_audio_pos.borrow();
+        ProgressBar::set_timestamp(
+            &mut self.current_audio_pos_timestamp,
+            &time_display.format(current_audio_pos),
+        );
 
-        // Update the progress bar
-        self.progress_bar
-            .borrow_mut()
-            .set_value(self.current_audio_pos.borrow().as_millis() as f64);
+        let total_label = match self.audio_length.get() {
+            Some(audio_length) if time_display.remaining => format!(
+                "-{}",
+                time_display.format(audio_length.saturating_sub(current_audio_pos))
+            ),
+            Some(audio_length) => time_display.format(audio_length),
+            None if self.live => ProgressBar::LIVE_LABEL.to_string(),
+            None => ProgressBar::UNKNOWN_LENGTH_LABEL.to_string(),
+        };
+        ProgressBar::set_timestamp(&mut self.total_audio_duration_timestamp, &total_label);
+
+        // Update the progress bar, unless it has no length to make progress towards
+        if self.audio_length.get().is_some() {
+            self.progress_bar
+                .borrow_mut()
+                .set_value(self.current_audio_pos.borrow().as_millis() as f64);
+        }
+
+        // The waveform takes the place of the progress bar once it is ready
+        let mut waveform = self.waveform.borrow_mut();
+        waveform.update(self.progress_bar.borrow().width() as usize);
+
+        let mut progress_bar = self.progress_bar.borrow_mut();
+        match waveform.drawn() {
+            Some(_) if progress_bar.visible() => progress_bar.hide(),
+            None if !progress_bar.visible() => progress_bar.show(),
+            _ => {}
+        }
     }
 
     fn handle_seek_event(
@@ -185,16 +736,8 @@ impl ProgressBar {
         let progress_bar = progress_bar.borrow();
         let current_audio_pos = *current_audio_pos.borrow();
 
-        let mouse_x = app::event_x();
-        let progress_bar_x = progress_bar.x();
-        let progress_bar_width = progress_bar.width();
-
-        // Get position relative to progress bar, and ensure value is never less than 0 or bigger than progress bar width
-        let rel_x = (mouse_x - progress_bar_x).max(0).min(progress_bar_width);
-        let percentage = rel_x as f64 / progress_bar_width as f64;
-
-        // Convert percentage to target position
-        let position_to_seek = audio_length.mul_f64(percentage);
+        let position_to_seek =
+            ProgressBar::position_at(&progress_bar, audio_length, app::event_x());
 
         // Compute how far to jump (positive = forward, negative = backward)
         if position_to_seek > current_audio_pos {
@@ -218,43 +761,134 @@ impl ProgressBar {
         }
     }
 
+    /// Return the position in the audio that the x coordinate `x` on the progress bar points to.
+    fn position_at(progress_bar: &Progress, audio_length: Duration, x: i32) -> Duration {
+        let progress_bar_width = progress_bar.width();
+
+        // Get position relative to progress bar, and ensure value is never less than 0 or bigger than progress bar width
+        let rel_x = (x - progress_bar.x()).max(0).min(progress_bar_width);
+        let percentage = rel_x as f64 / progress_bar_width as f64;
+
+        // Convert percentage to target position
+        audio_length.mul_f64(percentage)
+    }
+
+    /// Return the x coordinate on the progress bar that a position in the audio is drawn at.
+    fn x_at(progress_bar: &Progress, audio_length: Duration, pos: Duration) -> i32 {
+        let percentage = (pos.as_secs_f64() / audio_length.as_secs_f64()).clamp(0.0, 1.0);
+
+        progress_bar.x() + (percentage * progress_bar.width() as f64) as i32
+    }
+
     /// Create the timestamps on both sides of the progress bar.
+    /// Each one is as wide as the space between the progress bar and the edge of the window, and is aligned against the progress bar.
     fn create_timestamps(
         progress_bar: &Progress,
-        audio_length: Duration,
-    ) -> (output::Output, output::Output) {
-        const TIMESTAMP_WIDTH: i32 = 30;
-        const TIMESTAMP_HEIGHT: i32 = 1;
-
-        // The padding is different for both because god willed it to be
-        const CURRENT_AUDIO_POS_TIMESTAMP_PADDING: i32 = -25;
-        const TOTAL_AUDIO_DURATION_TIMESTAMP_PADDING: i32 = 40;
-
-        // Create the timestamp to show the viewer the total duration of the audio
-        let foramtted_duration = &ProgressBar::format_duration(audio_length);
-        let mut total_audio_duration_timestamp = output::Output::default()
-            .with_size(TIMESTAMP_WIDTH, TIMESTAMP_HEIGHT)
-            .right_of(progress_bar, TOTAL_AUDIO_DURATION_TIMESTAMP_PADDING)
-            .center_y(progress_bar)
-            .with_label(foramtted_duration);
-        total_audio_duration_timestamp.set_label_font(Font::Helvetica);
-        total_audio_duration_timestamp.set_frame(FrameType::NoBox);
+        audio_length: Option<Duration>,
+        live: bool,
+    ) -> (Frame, Frame) {
+        let timestamp_y =
+            progress_bar.y() + progress_bar.height() / 2 - ProgressBar::TIMESTAMP_HEIGHT / 2;
+
+        // Create the timestamp to show the viewer the total duration of the audio, or that it is live
+        let formatted_duration = match audio_length {
+            Some(audio_length) => ProgressBar::format_duration(audio_length),
+            None if live => ProgressBar::LIVE_LABEL.to_string(),
+            None => ProgressBar::UNKNOWN_LENGTH_LABEL.to_string(),
+        };
+        let mut total_audio_duration_timestamp = Frame::new(
+            progress_bar.x() + progress_bar.width() + ProgressBar::TIMESTAMP_GAP,
+            timestamp_y,
+            ProgressBar::TIMESTAMP_WIDTH,
+            ProgressBar::TIMESTAMP_HEIGHT,
+            None,
+        );
+        total_audio_duration_timestamp.set_align(Align::Left | Align::Inside);
 
         // Create the timestamp to show the viewer the current audio position
-        let default_timestamp = "0:00";
-        let mut current_audio_pos_timestamp = output::Output::default()
-            .with_size(TIMESTAMP_WIDTH, TIMESTAMP_HEIGHT)
-            .left_of(progress_bar, CURRENT_AUDIO_POS_TIMESTAMP_PADDING)
-            .center_y(progress_bar)
-            .with_label(default_timestamp);
-        current_audio_pos_timestamp.set_label_font(Font::Helvetica);
-        current_audio_pos_timestamp.set_frame(FrameType::NoBox);
+        let mut current_audio_pos_timestamp = Frame::new(
+            progress_bar.x() - ProgressBar::TIMESTAMP_GAP - ProgressBar::TIMESTAMP_WIDTH,
+            timestamp_y,
+            ProgressBar::TIMESTAMP_WIDTH,
+            ProgressBar::TIMESTAMP_HEIGHT,
+            None,
+        );
+        current_audio_pos_timestamp.set_align(Align::Right | Align::Inside);
+
+        for (timestamp, label) in [
+            (&mut current_audio_pos_timestamp, "0:00"),
+            (
+                &mut total_audio_duration_timestamp,
+                formatted_duration.as_str(),
+            ),
+        ] {
+            timestamp.set_label_font(Font::Helvetica);
+            ProgressBar::set_timestamp(timestamp, label);
+        }
 
         (current_audio_pos_timestamp, total_audio_duration_timestamp)
     }
 
+    /// Show `label` in a timestamp, shrinking it so that long timestamps still fit next to the progress bar.
+    fn set_timestamp(timestamp: &mut Frame, label: &str) {
+        if timestamp.label() == label {
+            return;
+        }
+
+        timestamp.set_label_size(ProgressBar::timestamp_font_size(label));
+        timestamp.set_label(label);
+    }
+
+    /// Return the font size that fits `label` in a timestamp, such as `-1:02:03.456` for a long track shown with milliseconds.
+    fn timestamp_font_size(label: &str) -> i32 {
+        match label.chars().count() {
+            ..=8 => 14,
+            9..=11 => 12,
+            _ => 10,
+        }
+    }
+
+    /// Let the user change how the timestamps are shown by clicking them.
+    /// Clicking the right timestamp switches between the length and the time that is left, and right-clicking either one shows milliseconds.
+    /// `time_display` carries over from one track to the next.
+    pub fn share_time_display(&mut self, time_display: Rc<Cell<TimeDisplay>>) {
+        self.time_display.replace(Rc::clone(&time_display));
+
+        self.current_audio_pos_timestamp
+            .set_tooltip("Right-click to show milliseconds");
+        self.total_audio_duration_timestamp.set_tooltip(
+            "Click to switch between the length and the time left, right-click to show milliseconds",
+        );
+
+        for (mut timestamp, toggles_remaining) in [
+            (self.current_audio_pos_timestamp.clone(), false),
+            (self.total_audio_duration_timestamp.clone(), true),
+        ] {
+            let time_display = Rc::clone(&time_display);
+
+            timestamp.handle(move |_, event| {
+                if event != Event::Push {
+                    return false;
+                }
+
+                let mut display = time_display.get();
+                match app::event_mouse_button() {
+                    MouseButton::Left if toggles_remaining => {
+                        display.remaining = !display.remaining
+                    }
+                    MouseButton::Right => display.precise = !display.precise,
+                    _ => return false,
+                }
+                time_display.set(display);
+                true
+            });
+        }
+
+        self.update();
+    }
+
     /// Format a Duration as mm:ss
-    fn format_duration(duration: Duration) -> String {
+    pub fn format_duration(duration: Duration) -> String {
         let total_secs = duration.as_secs();
 
         let hours = total_secs / 3600;
@@ -269,6 +903,15 @@ impl ProgressBar {
         format!("{}:{:02}", minutes, seconds)
     }
 
+    /// Format a Duration as mm:ss.mmm
+    pub fn format_precise_duration(duration: Duration) -> String {
+        format!(
+            "{}.{:03}",
+            ProgressBar::format_duration(duration),
+            duration.subsec_millis()
+        )
+    }
+
     /// Get the x position of the progress bar knob
     fn knob_x(progress_bar: &Progress) -> i32 {
         let progress = progress_bar.value() / progress_bar.maximum();
@@ -281,17 +924,145 @@ impl ProgressBar {
 
 #[cfg(test)]
 mod test {
+    use std::path::PathBuf;
+
     use super::*;
+    use crate::app::ab_loop::PracticeSettings;
+    use crate::app::track_state::TrackStateStore;
 
     impl Default for ProgressBar {
         /// Initialize a dummy ProgressBar for testing
         fn default() -> ProgressBar {
-            let (_, rx) = mpsc::channel();
-            let (tx, _) = mpsc::channel();
+            let (progress_bar, _) = with_length(Some(Duration::from_millis(100)), false);
+            progress_bar
+        }
+    }
+
+    /// Initialize a dummy ProgressBar for testing, along with the sender that reports the audio's position to it.
+    fn with_length(
+        audio_length: Option<Duration>,
+        live: bool,
+    ) -> (ProgressBar, mpsc::Sender<Duration>) {
+        let (audio_pos_sender, rx) = mpsc::channel();
+        let (tx, _) = mpsc::channel();
+        let loop_region = LoopRegion::new(
+            PathBuf::new(),
+            Rc::new(RefCell::new(TrackStateStore::default())),
+            tx.clone(),
+        );
+        let markers = Markers::new(
+            PathBuf::new(),
+            Rc::new(RefCell::new(TrackStateStore::default())),
+            tx.clone(),
+        );
+
+        let progress_bar = ProgressBar::new(
+            Bounds::new(3, 274, 394, 20),
+            audio_length,
+            live,
+            Rc::new(rx),
+            tx,
+            loop_region,
+            markers,
+        );
 
-            ProgressBar::new(400, Duration::from_millis(100), rx, tx)
+        (progress_bar, audio_pos_sender)
+    }
+
+    mod update {
+        use super::super::*;
+        use super::*;
+
+        #[test]
+        fn test_clamped_to_length() {
+            let (mut progress, audio_pos_sender) =
+                with_length(Some(Duration::from_secs(10)), false);
+
+            audio_pos_sender.send(Duration::from_secs(12)).unwrap();
+            progress.update();
+
+            assert_eq!(
+                *progress.current_audio_pos().borrow(),
+                Duration::from_secs(10)
+            );
+        }
+
+        #[test]
+        fn test_remaining_time() {
+            let (mut progress, audio_pos_sender) =
+                with_length(Some(Duration::from_secs(200)), false);
+            progress.share_time_display(Rc::new(Cell::new(TimeDisplay {
+                remaining: true,
+                precise: false,
+            })));
+
+            audio_pos_sender.send(Duration::from_secs(50)).unwrap();
+            progress.update();
+
+            assert_eq!(progress.current_audio_pos_timestamp.label(), "0:50");
+            assert_eq!(progress.total_audio_duration_timestamp.label(), "-2:30");
+        }
+
+        #[test]
+        fn test_live() {
+            let (mut progress, audio_pos_sender) = with_length(None, true);
+
+            audio_pos_sender.send(Duration::from_secs(3600)).unwrap();
+            progress.update();
+
+            // A live stream only counts up, and its progress bar stays full
+            assert_eq!(
+                *progress.current_audio_pos().borrow(),
+                Duration::from_secs(3600)
+            );
+            let progress_bar = progress.progress_bar.borrow();
+            assert_eq!(progress_bar.value(), progress_bar.maximum());
+        }
+
+        #[test]
+        fn test_unknown_length() {
+            let (mut progress, audio_pos_sender) = with_length(None, false);
+
+            audio_pos_sender.send(Duration::from_secs(30)).unwrap();
+            progress.update();
+
+            // Only the elapsed time is shown until the length is known
+            assert_eq!(
+                *progress.current_audio_pos().borrow(),
+                Duration::from_secs(30)
+            );
+            assert_eq!(progress.progress_bar.borrow().value(), 0.0);
+            assert_eq!(
+                progress.total_audio_duration_timestamp.label(),
+                ProgressBar::UNKNOWN_LENGTH_LABEL
+            );
         }
     }
+
+    mod set_audio_length {
+        use super::super::*;
+        use super::*;
+
+        #[test]
+        fn test_length_found() {
+            let (mut progress, audio_pos_sender) = with_length(None, false);
+
+            audio_pos_sender.send(Duration::from_secs(30)).unwrap();
+            progress.update();
+            progress.set_audio_length(Duration::from_secs(120));
+
+            assert_eq!(
+                progress.audio_length_handle().get(),
+                Some(Duration::from_secs(120))
+            );
+            assert_eq!(progress.total_audio_duration_timestamp.label(), "2:00");
+
+            let progress_bar = progress.progress_bar.borrow();
+            assert_eq!(progress_bar.maximum(), 120_000.0);
+            assert_eq!(progress_bar.value(), 30_000.0);
+        }
+    }
+
     mod format_duration {
         use super::super::*;
 
@@ -311,6 +1082,37 @@ mod test {
         }
     }
 
+    mod format_precise_duration {
+        use super::super::*;
+
+        #[test]
+        fn test_milliseconds() {
+            let duration = Duration::from_millis(61_005);
+            assert_eq!("1:01.005", ProgressBar::format_precise_duration(duration));
+        }
+
+        #[test]
+        fn test_hours() {
+            let duration = Duration::from_millis(3_601_250);
+            assert_eq!(
+                "1:00:01.250",
+                ProgressBar::format_precise_duration(duration)
+            );
+        }
+    }
+
+    mod timestamp_font_size {
+        use super::super::*;
+
+        #[test]
+        fn test_longer_timestamps_are_smaller() {
+            assert_eq!(ProgressBar::timestamp_font_size("2:38"), 14);
+            assert_eq!(ProgressBar::timestamp_font_size("-1:00:01"), 14);
+            assert_eq!(ProgressBar::timestamp_font_size("1:00:01.250"), 12);
+            assert_eq!(ProgressBar::timestamp_font_size("-1:00:01.250"), 10);
+        }
+    }
+
     mod knob_x {
         use super::super::*;
 
@@ -355,4 +1157,160 @@ mod test {
             assert_eq!(ProgressBar::knob_x(&progress.progress_bar.borrow()), 325);
         }
     }
+
+    mod position_at {
+        use super::super::*;
+
+        #[test]
+        fn test_middle() {
+            let progress = ProgressBar::default();
+
+            assert_eq!(
+                ProgressBar::position_at(
+                    &progress.progress_bar.borrow(),
+                    Duration::from_secs(10),
+                    200
+                ),
+                Duration::from_secs(5)
+            );
+        }
+
+        #[test]
+        fn test_outside_of_progress_bar() {
+            let progress = ProgressBar::default();
+            let progress_bar = progress.progress_bar.borrow();
+            let audio_length = Duration::from_secs(10);
+
+            assert_eq!(
+                ProgressBar::position_at(&progress_bar, audio_length, 0),
+                Duration::ZERO
+            );
+            assert_eq!(
+                ProgressBar::position_at(&progress_bar, audio_length, 400),
+                audio_length
+            );
+        }
+
+        #[test]
+        fn test_round_trip() {
+            let progress = ProgressBar::default();
+            let progress_bar = progress.progress_bar.borrow();
+            let audio_length = Duration::from_secs(10);
+
+            let x = ProgressBar::x_at(&progress_bar, audio_length, Duration::from_millis(2500));
+            assert_eq!(x, 137);
+            assert_eq!(
+                ProgressBar::position_at(&progress_bar, audio_length, x),
+                audio_length.mul_f64(62.0 / 250.0)
+            );
+        }
+    }
+
+    mod peaks_around {
+        use super::super::*;
+
+        #[test]
+        fn test_centered() {
+            let peaks: Vec<Peak> = (0..100)
+                .map(|i| Peak {
+                    max: i as f32 / 100.0,
+                    ..Peak::default()
+                })
+                .collect();
+            let around = ProgressBar::peaks_around(&peaks, 50);
+
+            assert_eq!(around.len(), 2 * SeekPreview::WAVEFORM_RADIUS + 1);
+            assert_eq!(around[SeekPreview::WAVEFORM_RADIUS].max, 0.5);
+        }
+
+        #[test]
+        fn test_start_is_padded() {
+            let peaks = vec![
+                Peak {
+                    min: -1.0,
+                    max: 1.0,
+                    rms: 0.5,
+                };
+                100
+            ];
+            let around = ProgressBar::peaks_around(&peaks, 0);
+
+            assert_eq!(around[0], Peak::default());
+            assert_eq!(around[SeekPreview::WAVEFORM_RADIUS], peaks[0]);
+        }
+    }
+
+    mod loop_handle_at {
+        use super::super::*;
+        use super::*;
+
+        fn ab_loop() -> AbLoop {
+            // Drawn from x = 137 to x = 200
+            AbLoop::new(
+                Duration::from_millis(2500),
+                Duration::from_secs(5),
+                PracticeSettings::default(),
+            )
+        }
+
+        #[test]
+        fn test_grab_start() {
+            let progress = ProgressBar::default();
+            let progress_bar = progress.progress_bar.borrow();
+
+            assert_eq!(
+                ProgressBar::loop_handle_at(
+                    &progress_bar,
+                    Duration::from_secs(10),
+                    &ab_loop(),
+                    139
+                ),
+                Some(LoopHandle::Start)
+            );
+        }
+
+        #[test]
+        fn test_grab_end() {
+            let progress = ProgressBar::default();
+            let progress_bar = progress.progress_bar.borrow();
+
+            assert_eq!(
+                ProgressBar::loop_handle_at(
+                    &progress_bar,
+                    Duration::from_secs(10),
+                    &ab_loop(),
+                    197
+                ),
+                Some(LoopHandle::End)
+            );
+        }
+
+        #[test]
+        fn test_between_handles() {
+            let progress = ProgressBar::default();
+            let progress_bar = progress.progress_bar.borrow();
+
+            assert_eq!(
+                ProgressBar::loop_handle_at(
+                    &progress_bar,
+                    Duration::from_secs(10),
+                    &ab_loop(),
+                    170
+                ),
+                None
+            );
+        }
+
+        #[test]
+        fn test_handle_cannot_pass_the_other() {
+            let moved = ProgressBar::move_loop_handle(
+                &ab_loop(),
+                LoopHandle::Start,
+                Duration::from_secs(8),
+            );
+
+            assert_eq!(moved.start, Duration::from_secs(5));
+            assert_eq!(moved.end, Duration::from_secs(5));
+        }
+    }
 }
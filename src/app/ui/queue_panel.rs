@@ -0,0 +1,310 @@
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+};
+
+use fltk::{
+    app,
+    browser::HoldBrowser,
+    button::Button,
+    enums::{Color, Event, Key},
+    group::Group,
+    prelude::*,
+    window::Window,
+};
+use lofty::config::ParseOptions;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::Accessor;
+
+use crate::app::Message;
+use crate::app::queue::{Queue, QueuedTrack};
+use crate::app::ui::now_playing::NowPlaying;
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// A dialog that lists every track in the queue, with the one that is playing highlighted.
+/// Tracks that haven't been played yet can be dragged into a different order, and any track but the current one can be removed.
+#[derive(Clone)]
+pub struct QueuePanel {
+    window: Window,
+    browser: HoldBrowser,
+    audio_sender: mpsc::Sender<Message>,
+
+    /// The queue, as last reported by the audio thread
+    queue: Rc<RefCell<Queue>>,
+
+    /// The queue that the browser lists, so that it is only filled again once the queue changes
+    shown: Rc<RefCell<Option<Queue>>>,
+
+    /// The track to select once the queue changes, so that a moved track stays selected
+    select_next: Rc<Cell<Option<usize>>>,
+
+    /// The line where the track that is being dragged was picked up
+    drag_from: Rc<Cell<Option<usize>>>,
+
+    /// The title and artist of every file that was listed, since reading the tags of a long queue takes a while
+    tags: Rc<RefCell<HashMap<PathBuf, (String, String)>>>,
+}
+
+impl QueuePanel {
+    const WIDTH: i32 = 400;
+    const HEIGHT: i32 = 360;
+
+    const CURRENT_ICON: &str = "\u{f040a}";
+
+    /// Create the queue panel. The panel stays hidden until it is shown.
+    pub fn new(queue: Rc<RefCell<Queue>>, audio_sender: mpsc::Sender<Message>) -> QueuePanel {
+        const MARGIN: i32 = 15;
+        const BTN_WIDTH: i32 = 82;
+        const BTN_HEIGHT: i32 = 25;
+        const BTN_SPACING: i32 = 10;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(QueuePanel::WIDTH, QueuePanel::HEIGHT)
+            .with_label("Queue");
+
+        let btn_y = QueuePanel::HEIGHT - MARGIN - BTN_HEIGHT;
+
+        let mut browser = HoldBrowser::new(
+            MARGIN,
+            MARGIN,
+            QueuePanel::WIDTH - 2 * MARGIN,
+            btn_y - 2 * MARGIN,
+            None,
+        );
+        browser.set_column_char('\t');
+        browser.set_column_widths(&[24, 160, 126]);
+        browser.set_tooltip("Drag a track to change when it plays");
+
+        let btn_x = |i: i32| MARGIN + i * (BTN_WIDTH + BTN_SPACING);
+        let mut play_next_btn = Button::new(btn_x(0), btn_y, BTN_WIDTH, BTN_HEIGHT, "Play next");
+        let mut move_to_end_btn =
+            Button::new(btn_x(1), btn_y, BTN_WIDTH, BTN_HEIGHT, "Move to end");
+        let mut remove_btn = Button::new(btn_x(2), btn_y, BTN_WIDTH, BTN_HEIGHT, "Remove");
+        let mut clear_btn = Button::new(btn_x(3), btn_y, BTN_WIDTH, BTN_HEIGHT, "Clear played");
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let queue_panel = QueuePanel {
+            window,
+            browser,
+            audio_sender,
+            queue,
+            shown: Rc::new(RefCell::new(None)),
+            select_next: Rc::new(Cell::new(None)),
+            drag_from: Rc::new(Cell::new(None)),
+            tags: Rc::new(RefCell::new(HashMap::new())),
+        };
+        queue_panel.update();
+
+        let panel = queue_panel.clone();
+        play_next_btn.set_callback(move |_| {
+            let next = panel.queue.borrow().current_index() + 1;
+            panel.move_selected(next);
+        });
+
+        let panel = queue_panel.clone();
+        move_to_end_btn.set_callback(move |_| {
+            let last = panel.queue.borrow().tracks().len() - 1;
+            panel.move_selected(last);
+        });
+
+        let panel = queue_panel.clone();
+        remove_btn.set_callback(move |_| panel.remove_selected());
+
+        let panel = queue_panel.clone();
+        clear_btn.set_callback(move |_| {
+            // Every index moves up, so there is no line to keep selected
+            let mut browser = panel.browser.clone();
+            browser.deselect(browser.value());
+            panel.send(Message::ClearPlayed);
+        });
+
+        let panel = queue_panel.clone();
+        queue_panel
+            .browser
+            .clone()
+            .handle(move |_, event| panel.handle(event));
+
+        queue_panel
+    }
+
+    /// Return a handle to the panel's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// List the queue again if it changed since it was last listed.
+    /// This function is intended to be called continuously in the main loop.
+    pub fn update(&self) {
+        let queue = self.queue.borrow();
+        if self.shown.borrow().as_ref() == Some(&*queue) {
+            return;
+        }
+
+        let mut browser = self.browser.clone();
+        let selected = self.select_next.take().or_else(|| self.selected());
+        let scroll = browser.position();
+        browser.clear();
+
+        for (index, track) in queue.tracks().iter().enumerate() {
+            let (title, artist) = self.describe(track);
+
+            // The track that is playing stands out, and the ones that were played fade into the background
+            let (icon, format) = match index.cmp(&queue.current_index()) {
+                Ordering::Less => ("", format!("@C{}", Color::Inactive.bits())),
+                Ordering::Equal => (QueuePanel::CURRENT_ICON, "@b".to_string()),
+                Ordering::Greater => ("", String::new()),
+            };
+            let length = track
+                .length
+                .map(ProgressBar::format_duration)
+                .unwrap_or_default();
+
+            // "@." stops the browser from treating the title and artist as formatting
+            browser.add(&format!(
+                "{format}{icon}\t{format}@.{}\t{format}@.{}\t{format}{length}",
+                title.replace('\t', " "),
+                artist.replace('\t', " "),
+            ));
+        }
+
+        browser.set_position(scroll);
+        if let Some(line) = selected.and_then(|index| i32::try_from(index + 1).ok())
+            && line <= browser.size()
+        {
+            browser.select(line);
+        }
+
+        *self.shown.borrow_mut() = Some(queue.clone());
+    }
+
+    /// Handle an event sent to the browser. Returns `true` if the event was used.
+    fn handle(&self, event: Event) -> bool {
+        match event {
+            // The browser selects the line under the mouse as it is dragged, so the line that was pressed is still
+            // selected when the drag starts, and the line it is dropped on is selected when it is released
+            Event::Push => {
+                self.drag_from.set(None);
+                false
+            }
+            Event::Drag => {
+                if self.drag_from.get().is_none() {
+                    self.drag_from.set(self.selected());
+                }
+                false
+            }
+            Event::Released => {
+                if let (Some(from), Some(to)) = (self.drag_from.take(), self.selected())
+                    && from != to
+                {
+                    self.move_track(from, to);
+                }
+                false
+            }
+            Event::KeyDown if app::event_key() == Key::Delete => {
+                self.remove_selected();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the selected track to `to`, if it hasn't been played yet.
+    fn move_selected(&self, to: usize) {
+        if let Some(from) = self.selected()
+            && from != to
+        {
+            self.move_track(from, to);
+        }
+    }
+
+    fn move_track(&self, from: usize, to: usize) {
+        // Only tracks that haven't been played can be moved, and only to places that haven't been played
+        let mut queue = self.queue.borrow().clone();
+        if !queue.move_track(from, to) {
+            self.shown.take();
+            self.update();
+            return;
+        }
+
+        self.select_next.set(Some(to));
+        self.send(Message::MoveTrack { from, to });
+    }
+
+    fn remove_selected(&self) {
+        if let Some(index) = self.selected() {
+            self.send(Message::RemoveTrack(index));
+        }
+    }
+
+    /// Return the index in the queue of the selected track, if a track is selected.
+    fn selected(&self) -> Option<usize> {
+        // Browser lines start at 1, and 0 means that nothing is selected
+        usize::try_from(self.browser.value() - 1).ok()
+    }
+
+    /// Return the title and artist to list for `track`.
+    fn describe(&self, track: &QueuedTrack) -> (String, String) {
+        // What a station is playing changes, so it is never remembered
+        if let Some(stream_info) = &track.stream {
+            let details = stream_info.details();
+            let station = details
+                .station
+                .clone()
+                .unwrap_or_else(|| track.path.to_string_lossy().into_owned());
+
+            return (NowPlaying::stream_title(&details), station);
+        }
+
+        self.tags
+            .borrow_mut()
+            .entry(track.path.clone())
+            .or_insert_with(|| QueuePanel::read_tags(&track.path))
+            .clone()
+    }
+
+    /// Read the title and artist of the file at `path`. Files without a title are listed by their name.
+    fn read_tags(path: &Path) -> (String, String) {
+        // The length comes from the queue, so the audio properties don't need to be read
+        let tag = Probe::open(path)
+            .and_then(|probe| {
+                probe
+                    .options(ParseOptions::new().read_properties(false))
+                    .read()
+            })
+            .ok()
+            .and_then(|file| file.primary_tag().or_else(|| file.first_tag()).cloned());
+
+        let title = tag
+            .as_ref()
+            .and_then(|tag| tag.title().map(|title| title.to_string()))
+            .unwrap_or_else(|| {
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        let artist = tag
+            .as_ref()
+            .and_then(|tag| tag.artist().map(|artist| artist.to_string()))
+            .unwrap_or_default();
+
+        (title, artist)
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) = self.audio_sender.send(message) {
+            eprintln!("Unable to change the queue: {:?}", e);
+        }
+    }
+}
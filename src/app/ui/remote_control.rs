@@ -0,0 +1,206 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::app::Message;
+#[cfg(unix)]
+use crate::app::control::ControlServer;
+use crate::app::control::{ControlCall, ControlRequest, ControlResponse};
+use crate::app::http_api::{HttpServer, HttpSettings};
+use crate::app::queue::{self, Queue};
+use crate::app::stream;
+use crate::app::ui::now_playing::NowPlaying;
+use crate::app::ui::shortcuts::Shortcuts;
+
+/// Answers the requests that other programs send to the control socket, such as `audio_player ctl`, and to the HTTP server if it is turned on.
+pub struct RemoteControl {
+    /// Kept so that the socket stays open for as long as the player runs, if it could be opened
+    #[cfg(unix)]
+    _server: Option<ControlServer>,
+
+    /// Kept so that the HTTP server keeps running for as long as the player runs
+    _http_server: Option<HttpServer>,
+
+    /// The receiver for the requests that arrive on the socket and over HTTP
+    call_receiver: mpsc::Receiver<ControlCall>,
+
+    /// What is shown for the `now_playing` query
+    now_playing: ControlResponse,
+
+    audio_sender: mpsc::Sender<Message>,
+
+    /// Playback, seeking and the volume are shared with the keyboard shortcuts
+    shortcuts: Shortcuts,
+
+    /// The queue, as last reported by the audio thread
+    queue: Rc<RefCell<Queue>>,
+}
+
+impl RemoteControl {
+    /// Start listening on the control socket, and start the HTTP server if the config file has `http_settings`.
+    /// Either one runs without the other, such as when another instance is already listening on the socket.
+    /// Returns `None` if neither could be started.
+    pub fn new(
+        now_playing: &NowPlaying,
+        track_path: &Path,
+        audio_sender: mpsc::Sender<Message>,
+        shortcuts: Shortcuts,
+        queue: Rc<RefCell<Queue>>,
+        http_settings: Option<HttpSettings>,
+    ) -> Option<RemoteControl> {
+        let (call_sender, call_receiver) = mpsc::channel();
+
+        // There are no Unix sockets to listen on elsewhere, but the HTTP server still works
+        #[cfg(unix)]
+        let server = ControlServer::start(call_sender.clone())
+            .inspect_err(|e| eprintln!("Unable to open the control socket: {}", e))
+            .ok();
+
+        // The HTTP server is opt-in, since it lets other machines control the player
+        let http_server = http_settings.and_then(|settings| {
+            HttpServer::start(settings, call_sender)
+                .inspect(|http_server| {
+                    if let Some(address) = http_server.address() {
+                        println!("Serving the remote control on http://{}", address);
+                    }
+                })
+                .inspect_err(|e| eprintln!("{}", e))
+                .ok()
+        });
+
+        #[cfg(unix)]
+        let listening = server.is_some() || http_server.is_some();
+        #[cfg(not(unix))]
+        let listening = http_server.is_some();
+        if !listening {
+            return None;
+        }
+
+        Some(RemoteControl {
+            #[cfg(unix)]
+            _server: server,
+            _http_server: http_server,
+            call_receiver,
+            now_playing: RemoteControl::now_playing(now_playing, track_path),
+            audio_sender,
+            shortcuts,
+            queue,
+        })
+    }
+
+    /// Answer for a different track, once the queue has moved on to it.
+    pub fn set_track(&mut self, now_playing: &NowPlaying, track_path: &Path, shortcuts: Shortcuts) {
+        self.set_now_playing(now_playing, track_path);
+        self.shortcuts = shortcuts;
+    }
+
+    /// Answer with a different title or artist, such as when a stream moves on to the next song.
+    pub fn set_now_playing(&mut self, now_playing: &NowPlaying, track_path: &Path) {
+        self.now_playing = RemoteControl::now_playing(now_playing, track_path);
+    }
+
+    /// Answer every request that has arrived since the last update.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) {
+        for call in self.call_receiver.try_iter().collect::<Vec<_>>() {
+            let response = self.answer(&call.request);
+            call.reply(response);
+        }
+    }
+
+    fn answer(&self, request: &ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Play => self.shortcuts.set_playing(true),
+            ControlRequest::Pause => self.shortcuts.set_playing(false),
+            ControlRequest::Seek { position } => {
+                let Ok(position) = Duration::try_from_secs_f64(*position) else {
+                    return RemoteControl::error(format!("Invalid position: {}", position));
+                };
+                self.shortcuts.seek(position);
+            }
+            ControlRequest::Volume { volume } => {
+                if !(0.0..=1.0).contains(volume) {
+                    return RemoteControl::error(format!(
+                        "The volume has to be between 0.0 and 1.0, not {}",
+                        volume
+                    ));
+                }
+                self.shortcuts.set_volume_level(*volume);
+            }
+            ControlRequest::Enqueue { paths } => {
+                if let Some(missing) = paths
+                    .iter()
+                    .find(|path| !path.exists() && !stream::is_stream(path))
+                {
+                    return RemoteControl::error(format!("No such file: {}", missing.display()));
+                }
+
+                // Folders and playlists are queued as the tracks in them, the same as when they're opened
+                let tracks: Vec<PathBuf> = paths
+                    .iter()
+                    .flat_map(|path| queue::tracks_to_open(path))
+                    .collect();
+                if tracks.is_empty() {
+                    return RemoteControl::error("There is nothing to play in those".to_string());
+                }
+                for track in tracks {
+                    self.send(Message::Enqueue(track));
+                }
+            }
+            ControlRequest::Next => {
+                if self.queue.borrow().upcoming().is_empty() {
+                    return RemoteControl::error("Nothing is queued after this track".to_string());
+                }
+                self.send(Message::Next);
+            }
+            ControlRequest::Status => {
+                return ControlResponse::Status {
+                    playing: self.shortcuts.is_playing(),
+                    position: self.shortcuts.position().as_secs_f64(),
+                    length: self
+                        .shortcuts
+                        .audio_length()
+                        .map(|length| length.as_secs_f64()),
+                    volume: self.shortcuts.volume(),
+                };
+            }
+            ControlRequest::NowPlaying => return self.now_playing.clone(),
+            ControlRequest::Queue => {
+                let queue = self.queue.borrow();
+                return ControlResponse::Queue {
+                    tracks: queue
+                        .tracks()
+                        .iter()
+                        .map(|track| track.path.clone())
+                        .collect(),
+                    current: queue.current_index(),
+                };
+            }
+        }
+
+        ControlResponse::Ok
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) = self.audio_sender.send(message) {
+            eprintln!("Unable to send remote command to the audio thread: {:?}", e);
+        }
+    }
+
+    fn error(message: String) -> ControlResponse {
+        ControlResponse::Error { message }
+    }
+
+    fn now_playing(now_playing: &NowPlaying, track_path: &Path) -> ControlResponse {
+        ControlResponse::NowPlaying {
+            title: now_playing.title().to_string(),
+            artist: now_playing.artist().to_string(),
+            album: now_playing.album().map(str::to_string),
+            path: PathBuf::from(track_path),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{cell::RefCell, rc::Rc};
+
+use fltk::{
+    draw,
+    enums::{Align, Color, Font},
+    group::Group,
+    prelude::*,
+    window::Window,
+};
+
+use crate::app::waveform::Peak;
+
+/// What the preview shows.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PreviewContent {
+    /// Where a click would seek to
+    timestamp: String,
+
+    /// The bookmark or chapter at that position
+    label: Option<String>,
+
+    /// The waveform around that position, with the position in the middle
+    peaks: Vec<Peak>,
+}
+
+/// A small window that floats above the progress bar while the mouse is over it, showing where a click would land.
+#[derive(Clone)]
+pub struct SeekPreview {
+    window: Window,
+    content: Rc<RefCell<PreviewContent>>,
+}
+
+impl SeekPreview {
+    const PADDING: i32 = 4;
+    const TIMESTAMP_HEIGHT: i32 = 16;
+    const LABEL_HEIGHT: i32 = 14;
+    const WAVEFORM_HEIGHT: i32 = 20;
+    const MAX_LABEL_WIDTH: i32 = 200;
+
+    /// How far above the top of the progress bar the preview floats
+    const GAP: i32 = 6;
+
+    /// How many columns of the waveform are shown on either side of the mouse, each of which is drawn 2 pixels wide
+    pub const WAVEFORM_RADIUS: usize = 20;
+
+    /// The colors of the theme, so that the preview follows it
+    const BACKGROUND_COLOR: Color = Color::Background2;
+    const TEXT_COLOR: Color = Color::Foreground;
+    const WAVEFORM_COLOR: Color = Color::Selection;
+
+    /// Create the preview, hidden.
+    pub fn new() -> SeekPreview {
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let mut window = Window::default().with_size(1, 1);
+        window.set_border(false);
+        // Float above the main window without taking the focus, like a tooltip
+        window.set_override();
+        window.end();
+
+        Group::set_current(parent.as_ref());
+
+        let content = Rc::new(RefCell::new(PreviewContent::default()));
+
+        {
+            let content = Rc::clone(&content);
+            window.draw(move |window| SeekPreview::draw_content(window, &content.borrow()));
+        }
+
+        SeekPreview { window, content }
+    }
+
+    /// Return a handle to the preview's window, so that it can be deleted along with the track.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Show the preview centered on the screen coordinate `x_root`, with its bottom `GAP` pixels above `y_root`.
+    pub fn show_at(
+        &self,
+        x_root: i32,
+        y_root: i32,
+        timestamp: String,
+        label: Option<String>,
+        peaks: Vec<Peak>,
+    ) {
+        draw::set_font(Font::HelveticaBold, 12);
+        let (timestamp_width, _) = draw::measure(&timestamp, false);
+        draw::set_font(Font::Helvetica, 11);
+        let label_width = label
+            .as_deref()
+            .map_or(0, |label| draw::measure(label, false).0)
+            .min(SeekPreview::MAX_LABEL_WIDTH);
+        let waveform_width = if peaks.is_empty() {
+            0
+        } else {
+            (2 * SeekPreview::WAVEFORM_RADIUS + 1) as i32 * 2
+        };
+
+        let width = timestamp_width.max(label_width).max(waveform_width) + 2 * SeekPreview::PADDING;
+        let mut height = SeekPreview::TIMESTAMP_HEIGHT + 2 * SeekPreview::PADDING;
+        if label.is_some() {
+            height += SeekPreview::LABEL_HEIGHT;
+        }
+        if !peaks.is_empty() {
+            height += SeekPreview::WAVEFORM_HEIGHT;
+        }
+
+        *self.content.borrow_mut() = PreviewContent {
+            timestamp,
+            label,
+            peaks,
+        };
+
+        let mut window = self.window.clone();
+        window.resize(
+            x_root - width / 2,
+            y_root - SeekPreview::GAP - height,
+            width,
+            height,
+        );
+        window.show();
+        window.redraw();
+    }
+
+    pub fn hide(&self) {
+        self.window.clone().hide();
+    }
+
+    fn draw_content(window: &Window, content: &PreviewContent) {
+        draw::draw_rect_fill(0, 0, window.w(), window.h(), SeekPreview::BACKGROUND_COLOR);
+        draw::draw_rect_with_color(0, 0, window.w(), window.h(), Color::Dark3);
+
+        let inner_width = window.w() - 2 * SeekPreview::PADDING;
+        let mut y = SeekPreview::PADDING;
+
+        draw::set_draw_color(SeekPreview::TEXT_COLOR);
+        draw::set_font(Font::HelveticaBold, 12);
+        draw::draw_text2(
+            &content.timestamp,
+            SeekPreview::PADDING,
+            y,
+            inner_width,
+            SeekPreview::TIMESTAMP_HEIGHT,
+            Align::Center,
+        );
+        y += SeekPreview::TIMESTAMP_HEIGHT;
+
+        if let Some(label) = &content.label {
+            draw::set_draw_color(Color::Dark3);
+            draw::set_font(Font::Helvetica, 11);
+            draw::push_clip(
+                SeekPreview::PADDING,
+                y,
+                inner_width,
+                SeekPreview::LABEL_HEIGHT,
+            );
+            draw::draw_text2(
+                label,
+                SeekPreview::PADDING,
+                y,
+                inner_width,
+                SeekPreview::LABEL_HEIGHT,
+                Align::Center,
+            );
+            draw::pop_clip();
+            y += SeekPreview::LABEL_HEIGHT;
+        }
+
+        if !content.peaks.is_empty() {
+            SeekPreview::draw_peaks(window, &content.peaks, y);
+        }
+    }
+
+    /// Draw the waveform around the mouse, with a line marking where the mouse is.
+    fn draw_peaks(window: &Window, peaks: &[Peak], y: i32) {
+        const COLUMN_WIDTH: i32 = 2;
+
+        let center_y = y + SeekPreview::WAVEFORM_HEIGHT / 2;
+        let half_height = SeekPreview::WAVEFORM_HEIGHT as f32 / 2.0;
+        let left = (window.w() - peaks.len() as i32 * COLUMN_WIDTH) / 2;
+
+        for (i, peak) in peaks.iter().enumerate() {
+            let top = center_y - (peak.max * half_height) as i32;
+            let bottom = center_y - (peak.min * half_height) as i32;
+            draw::draw_rect_fill(
+                left + i as i32 * COLUMN_WIDTH,
+                top,
+                COLUMN_WIDTH,
+                (bottom - top).max(1),
+                SeekPreview::WAVEFORM_COLOR,
+            );
+        }
+
+        draw::draw_rect_fill(
+            window.w() / 2,
+            y,
+            1,
+            SeekPreview::WAVEFORM_HEIGHT,
+            SeekPreview::TEXT_COLOR,
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+use fltk::{
+    app,
+    enums::{Event, Key, Shortcut},
+    prelude::*,
+    window::DoubleWindow,
+};
+
+use crate::app::Message;
+use crate::app::config::Config;
+use crate::app::keybindings::{self, Action, KeyCombo};
+use crate::app::ui::help_overlay::HelpOverlay;
+use crate::app::ui::markers::Markers;
+use crate::app::ui::playback_buttons::PlaybackButtons;
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// Handles the keyboard shortcuts of the main window, so that the player can be used without the mouse.
+#[derive(Clone)]
+pub struct Shortcuts {
+    /// The config file, which has the keybindings and how far the seek shortcuts jump
+    config: Rc<Config>,
+    audio_sender: mpsc::Sender<Message>,
+
+    /// Play/pause goes through the button, so that its label stays in sync
+    playback_buttons: PlaybackButtons,
+
+    markers: Markers,
+    current_audio_pos: Rc<RefCell<Duration>>,
+
+    /// The length of the track, which isn't known for live streams, and for some files until it has been worked out
+    audio_length: Rc<Cell<Option<Duration>>>,
+
+    volume: Rc<Cell<Volume>>,
+
+    /// The list of shortcuts, which is shown and hidden with the help shortcut
+    help_overlay: HelpOverlay,
+}
+
+/// The volume that the user picked, which is kept while muted so that unmuting goes back to it.
+/// This outlives the shortcuts of each track, so that the volume carries over to the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volume {
+    level: f32,
+    muted: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Volume {
+        Volume {
+            level: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl Volume {
+    /// Return the volume changed by `delta`, between silent and full volume. Changing the volume also unmutes it.
+    fn changed_by(self, delta: f32) -> Volume {
+        // Round to whole percents, so that stepping up and down always lands on the same volumes
+        let level = ((self.level + delta) * 100.0).round() / 100.0;
+
+        Volume {
+            level: level.clamp(0.0, 1.0),
+            muted: false,
+        }
+    }
+
+    /// Return how loud the audio should actually be.
+    fn effective(self) -> f32 {
+        if self.muted { 0.0 } else { self.level }
+    }
+}
+
+impl Shortcuts {
+    /// Create the shortcuts of the main window, along with the overlay that lists them. The window's events have to be
+    /// passed on to [`Shortcuts::handle`].
+    /// This has to be called after every other widget is added to the window, so that the overlay is drawn on top.
+    pub fn new(
+        window: &DoubleWindow,
+        config: Rc<Config>,
+        volume: Rc<Cell<Volume>>,
+        audio_sender: mpsc::Sender<Message>,
+        playback_buttons: PlaybackButtons,
+        markers: Markers,
+        progress_bar: &ProgressBar,
+    ) -> Shortcuts {
+        let help_overlay = HelpOverlay::new(
+            window.w(),
+            window.h(),
+            &config.keybindings,
+            config.playback.seek_steps(),
+        );
+
+        Shortcuts {
+            config,
+            audio_sender,
+            playback_buttons,
+            markers,
+            current_audio_pos: progress_bar.current_audio_pos(),
+            audio_length: progress_bar.audio_length_handle(),
+            volume,
+            help_overlay,
+        }
+    }
+
+    /// Handle an event sent to the main window. Returns `true` if the event was used.
+    pub fn handle(&self, event: Event) -> bool {
+        match event {
+            Event::KeyDown | Event::Shortcut => {
+                let action = Shortcuts::pressed_key_combo()
+                    .and_then(|combo| self.config.keybindings.action_for(combo));
+
+                // While the overlay is shown, any key closes it
+                if self.help_overlay.is_visible() {
+                    self.help_overlay.hide();
+                    return true;
+                }
+
+                match action {
+                    Some(action) => {
+                        self.perform(action);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Event::Push if self.help_overlay.is_visible() => {
+                self.help_overlay.hide();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Do what a shortcut is bound to. Other ways of controlling the player, such as media keys, use this too.
+    pub fn perform(&self, action: Action) {
+        let current_pos = *self.current_audio_pos.borrow();
+        let seek_steps = self.config.playback.seek_steps();
+
+        match action {
+            Action::PlayPause => self.playback_buttons.toggle_play_pause(),
+            Action::SeekForward => self.send(Message::FastForward(seek_steps.step)),
+            Action::SeekBackward => self.send(Message::Rewind(seek_steps.step)),
+            Action::SeekForwardLarge => self.send(Message::FastForward(seek_steps.large_step)),
+            Action::SeekBackwardLarge => self.send(Message::Rewind(seek_steps.large_step)),
+            Action::VolumeUp => self.set_volume(self.volume.get().changed_by(Action::VOLUME_STEP)),
+            Action::VolumeDown => {
+                self.set_volume(self.volume.get().changed_by(-Action::VOLUME_STEP))
+            }
+            Action::Mute => {
+                let volume = self.volume.get();
+                self.set_volume(Volume {
+                    muted: !volume.muted,
+                    ..volume
+                });
+            }
+            Action::Next => self.markers.next(current_pos),
+            Action::Previous => self.markers.previous(current_pos),
+            Action::JumpTo(tenths) => {
+                // There is nothing to jump to without knowing how long the track is
+                if let Some(audio_length) = self.audio_length.get() {
+                    self.seek(audio_length.mul_f64(f64::from(tenths) / 10.0))
+                }
+            }
+            Action::Help => self.help_overlay.toggle(),
+        }
+    }
+
+    /// Return whether the audio is playing.
+    pub fn is_playing(&self) -> bool {
+        self.playback_buttons.is_playing()
+    }
+
+    /// Play or pause the audio. Nothing happens if it is already doing so.
+    pub fn set_playing(&self, playing: bool) {
+        if self.is_playing() != playing {
+            self.playback_buttons.toggle_play_pause();
+        }
+    }
+
+    /// Return the position in the track, as last reported by the audio thread.
+    pub fn position(&self) -> Duration {
+        *self.current_audio_pos.borrow()
+    }
+
+    pub fn audio_length(&self) -> Option<Duration> {
+        self.audio_length.get()
+    }
+
+    /// Jump to a position in the track, without going past its end.
+    pub fn seek(&self, position: Duration) {
+        let position = self
+            .audio_length
+            .get()
+            .map_or(position, |audio_length| position.min(audio_length));
+
+        self.send(Message::Seek(position));
+    }
+
+    /// Return how loud the audio is, from 0.0 to 1.0, which is 0.0 while muted.
+    pub fn volume(&self) -> f32 {
+        self.volume.get().effective()
+    }
+
+    /// Change the volume to `level`, from 0.0 to 1.0. This also unmutes the audio.
+    pub fn set_volume_level(&self, level: f32) {
+        self.set_volume(Volume {
+            level: level.clamp(0.0, 1.0),
+            muted: false,
+        });
+    }
+
+    fn set_volume(&self, volume: Volume) {
+        self.volume.set(volume);
+        self.send(Message::SetVolume(volume.effective()));
+    }
+
+    fn send(&self, message: Message) {
+        if let Err(e) = self.audio_sender.send(message) {
+            eprintln!("Unable to send shortcut to the audio thread: {:?}", e);
+        }
+    }
+
+    /// Return the key combo of the key event that is being handled.
+    fn pressed_key_combo() -> Option<KeyCombo> {
+        let key = app::event_key();
+        let state = app::event_state();
+
+        let mut combo = KeyCombo {
+            key: Shortcuts::bound_key(key)?,
+            ctrl: state.contains(Shortcut::Ctrl),
+            shift: state.contains(Shortcut::Shift),
+            alt: state.contains(Shortcut::Alt),
+        };
+
+        // Use the character that the key typed, so that "?" works no matter where it is on the keyboard
+        let text = app::event_text();
+        let mut text = text.chars();
+        if let (Some(c), None) = (text.next(), text.next())
+            && !c.is_control()
+            && c != ' '
+        {
+            combo.key = keybindings::Key::Char(c.to_ascii_lowercase());
+
+            // Shift is part of typing symbols such as "?", so it only counts as a modifier for letters
+            if !c.is_alphabetic() {
+                combo.shift = false;
+            }
+        }
+
+        Some(combo)
+    }
+
+    /// Turn an FLTK key into a key that can be bound to an action.
+    fn bound_key(key: Key) -> Option<keybindings::Key> {
+        let bound_key = match key {
+            Key::Left => keybindings::Key::Left,
+            Key::Right => keybindings::Key::Right,
+            Key::Up => keybindings::Key::Up,
+            Key::Down => keybindings::Key::Down,
+            Key::Home => keybindings::Key::Home,
+            Key::End => keybindings::Key::End,
+            Key::PageUp => keybindings::Key::PageUp,
+            Key::PageDown => keybindings::Key::PageDown,
+            Key::Enter | Key::KPEnter => keybindings::Key::Enter,
+            Key::Escape => keybindings::Key::Escape,
+            Key::Tab => keybindings::Key::Tab,
+            Key::BackSpace => keybindings::Key::Backspace,
+            Key::Delete => keybindings::Key::Delete,
+            key if key == Key::from_char(' ') => keybindings::Key::Space,
+            key if Key::is_fn_key(key) => {
+                keybindings::Key::F(u8::try_from(key.bits() - Key::F1.bits() + 1).ok()?)
+            }
+            key => {
+                // Ignore keys such as Shift on their own
+                let c = char::from_u32(u32::try_from(key.bits()).ok()?)
+                    .filter(|c| c.is_ascii_graphic())?;
+                keybindings::Key::Char(c.to_ascii_lowercase())
+            }
+        };
+
+        Some(bound_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod volume {
+        use super::*;
+
+        #[test]
+        fn test_change() {
+            let volume = Volume {
+                level: 0.5,
+                muted: false,
+            };
+
+            assert_eq!(volume.changed_by(0.05).level, 0.55);
+        }
+
+        #[test]
+        fn test_clamped() {
+            let volume = Volume {
+                level: 0.98,
+                muted: false,
+            };
+
+            assert_eq!(volume.changed_by(0.05).level, 1.0);
+            assert_eq!(volume.changed_by(-2.0).level, 0.0);
+        }
+
+        #[test]
+        fn test_change_unmutes() {
+            let volume = Volume {
+                level: 0.5,
+                muted: true,
+            };
+
+            assert_eq!(volume.effective(), 0.0);
+            assert!(!volume.changed_by(-0.05).muted);
+        }
+    }
+}
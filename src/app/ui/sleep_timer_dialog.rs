@@ -0,0 +1,273 @@
+use std::{rc::Rc, sync::mpsc, time::Duration};
+
+use fltk::{
+    button::Button, frame::Frame, group::Group, menu::Choice, misc::Spinner, prelude::*,
+    window::Window,
+};
+
+use crate::app::Message;
+use crate::app::sleep_timer::{SleepCountdown, SleepTimer, SleepTimerMode, SleepTimerStatus};
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// A dialog to start, extend and cancel the sleep timer, along with a countdown in the main window while it runs.
+pub struct SleepTimerDialog {
+    window: Window,
+
+    /// Shows how long is left before playback stops, in the main window
+    countdown: Frame,
+
+    /// Shows the same countdown inside of the dialog
+    status: Frame,
+
+    /// The receiver for the updates that the audio thread sends about the timer, which outlives the dialog of each track
+    status_receiver: Rc<mpsc::Receiver<SleepTimerStatus>>,
+}
+
+/// The widgets that make up the sleep timer settings.
+#[derive(Clone)]
+struct SleepTimerForm {
+    mode: Choice,
+    amount: Spinner,
+}
+
+impl SleepTimerDialog {
+    const WIDTH: i32 = 300;
+    const HEIGHT: i32 = 165;
+
+    /// The indices of the modes in the mode choice
+    const MODE_MINUTES: i32 = 0;
+    const MODE_END_OF_TRACK: i32 = 1;
+    const MODE_TRACKS: i32 = 2;
+
+    const DEFAULT_MINUTES: f64 = 30.0;
+
+    /// How many minutes extending a timed sleep timer adds, as shown to the user
+    const EXTEND_MINUTES: u64 = SleepTimer::EXTEND_DURATION.as_secs() / 60;
+
+    /// Create the countdown in the main window, and the dialog to control the timer. The dialog stays hidden until its window is shown.
+    pub fn new(
+        win_width: i32,
+        audio_sender: mpsc::Sender<Message>,
+        status_receiver: Rc<mpsc::Receiver<SleepTimerStatus>>,
+    ) -> SleepTimerDialog {
+        const COUNTDOWN_WIDTH: i32 = 200;
+        const COUNTDOWN_Y: i32 = 35;
+        const COUNTDOWN_HEIGHT: i32 = 25;
+
+        const LABEL_WIDTH: i32 = 110;
+        const FIELD_WIDTH: i32 = 170;
+        const ROW_HEIGHT: i32 = 25;
+        const ROW_SPACING: i32 = 30;
+        const BTN_WIDTH: i32 = 80;
+        const BTN_SPACING: i32 = 10;
+
+        // The countdown goes between the buttons at the top of the main window, and only shows while the timer runs
+        let mut countdown = Frame::new(
+            (win_width - COUNTDOWN_WIDTH) / 2,
+            COUNTDOWN_Y,
+            COUNTDOWN_WIDTH,
+            COUNTDOWN_HEIGHT,
+            "",
+        );
+        countdown.hide();
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(SleepTimerDialog::WIDTH, SleepTimerDialog::HEIGHT)
+            .with_label("Sleep timer");
+
+        let row_y = |row: i32| 15 + row * ROW_SPACING;
+
+        let mut mode = Choice::new(LABEL_WIDTH, row_y(0), FIELD_WIDTH, ROW_HEIGHT, "Stop");
+        mode.add_choice("After minutes");
+        mode.add_choice("At end of track");
+        mode.add_choice("After tracks");
+        mode.set_value(SleepTimerDialog::MODE_MINUTES);
+
+        let mut amount = Spinner::new(LABEL_WIDTH, row_y(1), FIELD_WIDTH, ROW_HEIGHT, "Minutes");
+        amount.set_step(1.0);
+        SleepTimerDialog::set_amount_range(&mut amount, SleepTimerDialog::MODE_MINUTES);
+        amount.set_value(SleepTimerDialog::DEFAULT_MINUTES);
+
+        let status = Frame::new(
+            15,
+            row_y(2),
+            SleepTimerDialog::WIDTH - 30,
+            ROW_HEIGHT,
+            "Off",
+        );
+
+        let btn_x = |i: i32| 15 + i * (BTN_WIDTH + BTN_SPACING);
+        let mut start_btn = Button::new(btn_x(0), row_y(3) + 15, BTN_WIDTH, ROW_HEIGHT, "Start");
+        let mut extend_btn = Button::new(btn_x(1), row_y(3) + 15, BTN_WIDTH, ROW_HEIGHT, "Extend");
+        extend_btn.set_tooltip(&format!(
+            "Add {} minutes, or one more track",
+            SleepTimerDialog::EXTEND_MINUTES
+        ));
+        let mut cancel_btn = Button::new(btn_x(2), row_y(3) + 15, BTN_WIDTH, ROW_HEIGHT, "Cancel");
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let form = SleepTimerForm { mode, amount };
+
+        // Relabel the amount to match the chosen mode
+        {
+            let mut amount = form.amount.clone();
+            form.mode.clone().set_callback(move |mode| {
+                SleepTimerDialog::set_amount_range(&mut amount, mode.value());
+            });
+        }
+
+        {
+            let audio_sender = audio_sender.clone();
+            start_btn.set_callback(move |_| {
+                let mode = SleepTimerDialog::read_mode(&form);
+                SleepTimerDialog::send(&audio_sender, Message::SetSleepTimer(Some(mode)));
+            });
+        }
+
+        {
+            let audio_sender = audio_sender.clone();
+            extend_btn.set_callback(move |_| {
+                SleepTimerDialog::send(&audio_sender, Message::ExtendSleepTimer);
+            });
+        }
+
+        cancel_btn.set_callback(move |_| {
+            SleepTimerDialog::send(&audio_sender, Message::SetSleepTimer(None));
+        });
+
+        SleepTimerDialog {
+            window,
+            countdown,
+            status,
+            status_receiver,
+        }
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Show the latest state of the timer.
+    /// Returns `true` if the timer ran out and stopped playback since the last update.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn update(&mut self) -> bool {
+        let mut expired = false;
+        let mut changed = false;
+
+        for status in self.status_receiver.try_iter() {
+            changed = true;
+
+            match status {
+                SleepTimerStatus::Running(countdown) => {
+                    let label = SleepTimerDialog::format_countdown(countdown);
+                    self.countdown.set_label(&format!("\u{f04b2} {}", label));
+                    self.countdown.show();
+                    self.status.set_label(&label);
+                }
+                SleepTimerStatus::Off => {
+                    self.countdown.hide();
+                    self.status.set_label("Off");
+                }
+                SleepTimerStatus::Expired => {
+                    self.countdown.hide();
+                    self.status.set_label("Off");
+                    expired = true;
+                }
+            }
+        }
+
+        // The countdown has no background of its own, so redraw the window behind it when its text changes
+        if changed && let Some(mut parent) = self.countdown.parent() {
+            parent.redraw();
+        }
+
+        expired
+    }
+
+    /// Turn the values of the form into the mode of the timer.
+    fn read_mode(form: &SleepTimerForm) -> SleepTimerMode {
+        let amount = form.amount.value().max(1.0) as u32;
+
+        match form.mode.value() {
+            SleepTimerDialog::MODE_END_OF_TRACK => SleepTimerMode::AfterTracks(1),
+            SleepTimerDialog::MODE_TRACKS => SleepTimerMode::AfterTracks(amount),
+            _ => SleepTimerMode::After(Duration::from_secs(u64::from(amount) * 60)),
+        }
+    }
+
+    /// Change what the amount spinner counts, depending on the mode.
+    fn set_amount_range(amount: &mut Spinner, mode: i32) {
+        match mode {
+            SleepTimerDialog::MODE_END_OF_TRACK => amount.deactivate(),
+            SleepTimerDialog::MODE_TRACKS => {
+                amount.activate();
+                amount.set_label("Tracks");
+                amount.set_range(1.0, 100.0);
+            }
+            _ => {
+                amount.activate();
+                amount.set_label("Minutes");
+                amount.set_range(1.0, 600.0);
+            }
+        }
+
+        amount.set_value(amount.value().clamp(amount.minimum(), amount.maximum()));
+    }
+
+    /// Return the text of the countdown, such as "Sleep in 14:32" or "Sleep after 2 tracks".
+    fn format_countdown(countdown: SleepCountdown) -> String {
+        match countdown {
+            SleepCountdown::Time(remaining) => {
+                format!("Sleep in {}", ProgressBar::format_duration(remaining))
+            }
+            SleepCountdown::Tracks(1) => "Sleep after this track".to_string(),
+            SleepCountdown::Tracks(tracks) => format!("Sleep after {} tracks", tracks),
+        }
+    }
+
+    fn send(audio_sender: &mpsc::Sender<Message>, message: Message) {
+        if let Err(e) = audio_sender.send(message) {
+            eprintln!("Unable to change the sleep timer: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod format_countdown {
+        use super::*;
+
+        #[test]
+        fn test_time() {
+            assert_eq!(
+                SleepTimerDialog::format_countdown(SleepCountdown::Time(Duration::from_secs(872))),
+                "Sleep in 14:32"
+            );
+        }
+
+        #[test]
+        fn test_end_of_track() {
+            assert_eq!(
+                SleepTimerDialog::format_countdown(SleepCountdown::Tracks(1)),
+                "Sleep after this track"
+            );
+        }
+
+        #[test]
+        fn test_tracks() {
+            assert_eq!(
+                SleepTimerDialog::format_countdown(SleepCountdown::Tracks(3)),
+                "Sleep after 3 tracks"
+            );
+        }
+    }
+}
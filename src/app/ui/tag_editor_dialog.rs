@@ -0,0 +1,349 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use fltk::{
+    browser::MultiBrowser,
+    button::Button,
+    dialog::{self, NativeFileChooser, NativeFileChooserType},
+    enums::FrameType,
+    frame::Frame,
+    group::Group,
+    input::Input,
+    prelude::*,
+    window::Window,
+};
+use lofty::picture::PictureType;
+use lofty::tag::Tag;
+
+use crate::app::tag_editor::{self, CoverEdit, TagEdit, TagField};
+use crate::app::ui::now_playing::NowPlaying;
+
+/// A dialog that edits the title, artist, album and cover of the current track.
+/// More tracks can be added to the list, so that the fields they should share can be edited all at once.
+#[derive(Clone)]
+pub struct TagEditorDialog {
+    window: Window,
+
+    /// The tracks that can be edited, where the selected ones are the ones being edited
+    browser: MultiBrowser,
+    paths: Rc<RefCell<Vec<PathBuf>>>,
+
+    /// An input for each field, in the same order as `TagField::ALL`
+    inputs: Vec<Input>,
+
+    /// What the inputs were filled with, so that only the fields the user changes are written
+    shown_values: Rc<RefCell<Vec<String>>>,
+
+    cover: Frame,
+    cover_edit: Rc<RefCell<CoverEdit>>,
+
+    /// The tracks whose tags were saved since the app last checked
+    saved: Rc<RefCell<Vec<PathBuf>>>,
+}
+
+impl TagEditorDialog {
+    const WIDTH: i32 = 420;
+    const HEIGHT: i32 = 400;
+
+    const AUDIO_FILTER: &str = "*.{mp3,flac,ogg,opus,m4a,mp4,wav,aiff,ape,wv}";
+    const IMAGE_FILTER: &str = "*.{jpg,jpeg,png,gif,bmp,tiff}";
+
+    const DIFFERENT_VALUES_TOOLTIP: &str =
+        "The selected tracks have different values, which are kept unless something is typed here";
+
+    /// Create the tag editor for the track at `track_path`. The dialog stays hidden until its window is shown.
+    pub fn new(track_path: &Path) -> TagEditorDialog {
+        const MARGIN: i32 = 15;
+        const LABEL_WIDTH: i32 = 85;
+        const FIELD_WIDTH: i32 = 180;
+        const ROW_HEIGHT: i32 = 25;
+        const ROW_SPACING: i32 = 30;
+        const BROWSER_HEIGHT: i32 = 75;
+        const COVER_SIZE: i32 = 110;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(TagEditorDialog::WIDTH, TagEditorDialog::HEIGHT)
+            .with_label("Edit tags");
+
+        let browser = MultiBrowser::new(
+            MARGIN,
+            MARGIN,
+            TagEditorDialog::WIDTH - 2 * MARGIN,
+            BROWSER_HEIGHT,
+            None,
+        );
+
+        let add_y = MARGIN + BROWSER_HEIGHT + 5;
+        let mut add_btn = Button::new(MARGIN, add_y, 100, ROW_HEIGHT, "Add tracks…");
+        add_btn.set_tooltip("Add more tracks, to edit several of them at once");
+
+        let row_y = |row: i32| add_y + ROW_HEIGHT + MARGIN + row * ROW_SPACING;
+
+        let inputs: Vec<Input> = TagField::ALL
+            .iter()
+            .enumerate()
+            .map(|(row, field)| {
+                Input::new(
+                    MARGIN + LABEL_WIDTH,
+                    row_y(row as i32),
+                    FIELD_WIDTH,
+                    ROW_HEIGHT,
+                    field.label(),
+                )
+            })
+            .collect();
+
+        let cover_x = TagEditorDialog::WIDTH - MARGIN - COVER_SIZE;
+        let mut cover = Frame::new(cover_x, row_y(0), COVER_SIZE, COVER_SIZE, "");
+        cover.set_frame(FrameType::BorderBox);
+
+        let cover_btn_y = row_y(0) + COVER_SIZE + 5;
+        let mut choose_cover_btn = Button::new(
+            cover_x,
+            cover_btn_y,
+            COVER_SIZE,
+            ROW_HEIGHT,
+            "Choose cover…",
+        );
+        let mut remove_cover_btn = Button::new(
+            cover_x,
+            cover_btn_y + ROW_SPACING,
+            COVER_SIZE,
+            ROW_HEIGHT,
+            "Remove cover",
+        );
+
+        let btn_y = TagEditorDialog::HEIGHT - MARGIN - ROW_HEIGHT;
+        let mut save_btn = Button::new(
+            TagEditorDialog::WIDTH - MARGIN - 80,
+            btn_y,
+            80,
+            ROW_HEIGHT,
+            "Save",
+        );
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        let tag_editor_dialog = TagEditorDialog {
+            window,
+            browser,
+            paths: Rc::new(RefCell::new(Vec::new())),
+            inputs,
+            shown_values: Rc::new(RefCell::new(Vec::new())),
+            cover,
+            cover_edit: Rc::new(RefCell::new(CoverEdit::Keep)),
+            saved: Rc::new(RefCell::new(Vec::new())),
+        };
+        tag_editor_dialog.add_tracks(vec![track_path.to_path_buf()]);
+
+        let dialog = tag_editor_dialog.clone();
+        tag_editor_dialog
+            .browser
+            .clone()
+            .set_callback(move |_| dialog.load_selected());
+
+        let dialog = tag_editor_dialog.clone();
+        add_btn.set_callback(move |_| {
+            let mut chooser = NativeFileChooser::new(NativeFileChooserType::BrowseMultiFile);
+            chooser.set_filter(TagEditorDialog::AUDIO_FILTER);
+            chooser.show();
+
+            dialog.add_tracks(chooser.filenames());
+        });
+
+        let dialog = tag_editor_dialog.clone();
+        choose_cover_btn.set_callback(move |_| {
+            let mut chooser = NativeFileChooser::new(NativeFileChooserType::BrowseFile);
+            chooser.set_filter(TagEditorDialog::IMAGE_FILTER);
+            chooser.show();
+
+            let path = chooser.filename();
+            if path.as_os_str().is_empty() {
+                return;
+            }
+
+            match CoverEdit::from_image_file(&path) {
+                Ok(cover_edit) => dialog.set_cover_edit(cover_edit),
+                Err(e) => dialog::alert_default(&e),
+            }
+        });
+
+        let dialog = tag_editor_dialog.clone();
+        remove_cover_btn.set_callback(move |_| dialog.set_cover_edit(CoverEdit::Remove));
+
+        let dialog = tag_editor_dialog.clone();
+        save_btn.set_callback(move |_| dialog.save());
+
+        tag_editor_dialog
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Return the tracks whose tags were saved since this was last called, so that they can be shown again.
+    /// This function is intended to be called continuously in the app's main loop.
+    pub fn take_saved(&self) -> Vec<PathBuf> {
+        self.saved.borrow_mut().drain(..).collect()
+    }
+
+    /// Add tracks to the list and select them, along with the tracks that were already selected.
+    fn add_tracks(&self, new_paths: Vec<PathBuf>) {
+        let mut browser = self.browser.clone();
+
+        for path in new_paths {
+            let existing = self.paths.borrow().iter().position(|p| *p == path);
+            let line = match existing {
+                Some(index) => index as i32 + 1,
+                None => {
+                    let name = path.file_name().unwrap_or(path.as_os_str());
+                    browser.add(&format!("@.{}", name.to_string_lossy()));
+                    self.paths.borrow_mut().push(path);
+                    browser.size()
+                }
+            };
+            browser.select(line);
+        }
+
+        self.load_selected();
+    }
+
+    /// Return the tracks that are selected.
+    fn selected_paths(&self) -> Vec<PathBuf> {
+        self.paths
+            .borrow()
+            .iter()
+            .enumerate()
+            // Browser lines start at 1
+            .filter(|(i, _)| self.browser.selected(*i as i32 + 1))
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+
+    /// Fill the form with the tags of the selected tracks, throwing away any changes that weren't saved.
+    fn load_selected(&self) {
+        let paths = self.selected_paths();
+        let tags: Vec<Tag> = paths
+            .iter()
+            .filter_map(|path| match tag_editor::read_tag(path) {
+                Ok(tag) => Some(tag),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    None
+                }
+            })
+            .collect();
+
+        let mut shown_values = self.shown_values.borrow_mut();
+        shown_values.clear();
+
+        for (field, input) in TagField::ALL.iter().zip(self.inputs.iter()) {
+            let mut input = input.clone();
+            let value = field.common_value(&tags);
+
+            match &value {
+                Some(_) => input.set_tooltip(""),
+                None => input.set_tooltip(TagEditorDialog::DIFFERENT_VALUES_TOOLTIP),
+            }
+            let value = value.unwrap_or_default();
+            input.set_value(&value);
+            shown_values.push(value);
+
+            if paths.is_empty() {
+                input.deactivate();
+            } else {
+                input.activate();
+            }
+        }
+        drop(shown_values);
+
+        *self.cover_edit.borrow_mut() = CoverEdit::Keep;
+        TagEditorDialog::show_cover(&mut self.cover.clone(), tags.first());
+    }
+
+    fn set_cover_edit(&self, cover_edit: CoverEdit) {
+        let mut cover = self.cover.clone();
+        match &cover_edit {
+            CoverEdit::Replace(picture) => {
+                cover.set_image_scaled(Some(NowPlaying::cover_image(Some(picture))));
+            }
+            _ => cover.set_image_scaled(Some(NowPlaying::cover_image(None))),
+        }
+        cover.redraw();
+
+        *self.cover_edit.borrow_mut() = cover_edit;
+    }
+
+    /// Show the front cover in `tag`, or the default cover if it has none.
+    fn show_cover(cover: &mut Frame, tag: Option<&Tag>) {
+        let picture = tag.and_then(|tag| {
+            tag.pictures()
+                .iter()
+                .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        });
+
+        cover.set_image_scaled(Some(NowPlaying::cover_image(picture)));
+        cover.redraw();
+    }
+
+    /// Turn the form into an edit, which only has the fields that the user changed.
+    fn read_edit(&self) -> TagEdit {
+        let shown_values = self.shown_values.borrow();
+
+        let fields = TagField::ALL
+            .iter()
+            .zip(self.inputs.iter().zip(shown_values.iter()))
+            .filter(|(_, (input, shown))| input.value() != **shown)
+            .map(|(field, (input, _))| (*field, input.value()))
+            .collect();
+
+        TagEdit {
+            fields,
+            cover: self.cover_edit.borrow().clone(),
+        }
+    }
+
+    /// Write the changes to every selected track, and close the dialog once they are all saved.
+    fn save(&self) {
+        let paths = self.selected_paths();
+        if paths.is_empty() {
+            dialog::alert_default("Select the tracks to edit");
+            return;
+        }
+
+        let edit = self.read_edit();
+        if edit.is_empty() {
+            self.window.clone().hide();
+            return;
+        }
+        if let Err(e) = edit.validate() {
+            dialog::alert_default(&e);
+            return;
+        }
+
+        let mut errors = Vec::new();
+        for path in paths {
+            match edit.apply(&path) {
+                Ok(()) => self.saved.borrow_mut().push(path),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        self.load_selected();
+
+        if errors.is_empty() {
+            self.window.clone().hide();
+        } else {
+            dialog::alert_default(&errors.join("\n"));
+        }
+    }
+}
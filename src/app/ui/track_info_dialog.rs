@@ -0,0 +1,299 @@
+use std::{cell::Cell, fs, path::Path, rc::Rc};
+
+use fltk::{
+    browser::Browser,
+    button::{Button, CheckButton},
+    group::Group,
+    output::Output,
+    prelude::*,
+    window::Window,
+};
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::{Picture, PictureInformation};
+use lofty::read_from_path;
+use lofty::tag::{ItemKey, ItemValue, Tag};
+
+use crate::app::ui::now_playing::NowPlaying;
+use crate::app::ui::progress_bar::ProgressBar;
+
+/// A dialog that lists everything that is known about the current track: its format, every field of each of its tags,
+/// and the pictures embedded in them.
+pub struct TrackInfoDialog {
+    window: Window,
+}
+
+impl TrackInfoDialog {
+    const WIDTH: i32 = 420;
+    const HEIGHT: i32 = 400;
+
+    /// Create the track info dialog for the track at `path`. The dialog stays hidden until its window is shown.
+    /// `show_technical` is whether the technical line of `technical_widget` is shown, which the dialog can change.
+    /// `tag_editor` is the window of the dialog that edits the track's tags.
+    pub fn new(
+        path: &Path,
+        show_technical: Rc<Cell<bool>>,
+        technical_widget: Option<Output>,
+        mut tag_editor: Window,
+    ) -> TrackInfoDialog {
+        const MARGIN: i32 = 15;
+        const CHECK_HEIGHT: i32 = 25;
+        const EDIT_BTN_WIDTH: i32 = 90;
+        const FIELD_COLUMN_WIDTH: i32 = 140;
+
+        // Create the window on its own, rather than inside of the window that is currently being built
+        let parent = Group::try_current();
+        Group::set_current(None::<&Group>);
+
+        let window = Window::default()
+            .with_size(TrackInfoDialog::WIDTH, TrackInfoDialog::HEIGHT)
+            .with_label("Track info");
+
+        let check_y = TrackInfoDialog::HEIGHT - MARGIN - CHECK_HEIGHT;
+
+        let mut browser = Browser::new(
+            MARGIN,
+            MARGIN,
+            TrackInfoDialog::WIDTH - 2 * MARGIN,
+            check_y - 2 * MARGIN,
+            None,
+        );
+        browser.set_column_char('\t');
+        browser.set_column_widths(&[FIELD_COLUMN_WIDTH]);
+
+        let mut technical_check = CheckButton::new(
+            MARGIN,
+            check_y,
+            TrackInfoDialog::WIDTH - 3 * MARGIN - EDIT_BTN_WIDTH,
+            CHECK_HEIGHT,
+            "Show the format under the artist",
+        );
+        technical_check.set_checked(show_technical.get());
+
+        let mut edit_btn = Button::new(
+            TrackInfoDialog::WIDTH - MARGIN - EDIT_BTN_WIDTH,
+            check_y,
+            EDIT_BTN_WIDTH,
+            CHECK_HEIGHT,
+            "Edit tags…",
+        );
+
+        window.end();
+        Group::set_current(parent.as_ref());
+
+        edit_btn.set_callback(move |_| tag_editor.show());
+
+        for line in TrackInfoDialog::lines(path) {
+            browser.add(&line);
+        }
+
+        // Streams have no technical line to show
+        match technical_widget {
+            Some(mut technical_widget) => technical_check.set_callback(move |check| {
+                show_technical.set(check.is_checked());
+
+                if check.is_checked() {
+                    technical_widget.show();
+                } else {
+                    technical_widget.hide();
+                }
+                if let Some(mut window) = technical_widget.window() {
+                    window.redraw();
+                }
+            }),
+            None => technical_check.deactivate(),
+        }
+
+        TrackInfoDialog { window }
+    }
+
+    /// Return a handle to the dialog's window, so that it can be shown from a callback.
+    pub fn window(&self) -> Window {
+        self.window.clone()
+    }
+
+    /// Return the lines of the browser: a section about the file, followed by one for each of its tags.
+    fn lines(path: &Path) -> Vec<String> {
+        let mut lines = vec![TrackInfoDialog::heading("File")];
+        lines.push(TrackInfoDialog::row("Path", &path.to_string_lossy()));
+
+        let tagged_file = match read_from_path(path) {
+            Ok(tagged_file) => tagged_file,
+            Err(e) => {
+                lines.push(TrackInfoDialog::row("Error", &e.to_string()));
+                return lines;
+            }
+        };
+
+        let properties = tagged_file.properties();
+        lines.push(TrackInfoDialog::row(
+            "Format",
+            &NowPlaying::technical_details(tagged_file.file_type(), properties),
+        ));
+        lines.push(TrackInfoDialog::row(
+            "Length",
+            &ProgressBar::format_duration(properties.duration()),
+        ));
+        if let Ok(metadata) = fs::metadata(path) {
+            lines.push(TrackInfoDialog::row(
+                "Size",
+                &TrackInfoDialog::format_size(metadata.len() as usize),
+            ));
+        }
+
+        for tag in tagged_file.tags() {
+            lines.extend(TrackInfoDialog::tag_lines(tag));
+        }
+        if tagged_file.tags().is_empty() {
+            lines.push(TrackInfoDialog::row("Tags", "None"));
+        }
+
+        lines
+    }
+
+    /// Return a heading for the tag, followed by every field and picture in it.
+    fn tag_lines(tag: &Tag) -> Vec<String> {
+        let mut lines = vec![
+            String::new(),
+            TrackInfoDialog::heading(&format!("{:?} tag", tag.tag_type())),
+        ];
+
+        for item in tag.items() {
+            let value = match item.value() {
+                ItemValue::Text(text) | ItemValue::Locator(text) => text.clone(),
+                ItemValue::Binary(data) => TrackInfoDialog::format_size(data.len()),
+            };
+
+            // A field can span multiple lines, such as lyrics, but the browser can only show one per line
+            for (i, value_line) in value.lines().enumerate() {
+                let field = if i == 0 {
+                    TrackInfoDialog::field_name(item.key())
+                } else {
+                    String::new()
+                };
+                lines.push(TrackInfoDialog::row(&field, value_line));
+            }
+        }
+
+        for picture in tag.pictures() {
+            lines.push(TrackInfoDialog::row(
+                &TrackInfoDialog::spaced(&format!("{:?}", picture.pic_type())),
+                &TrackInfoDialog::picture_details(picture),
+            ));
+        }
+
+        lines
+    }
+
+    fn heading(text: &str) -> String {
+        // "@b" makes the line bold, and "@." stops the rest of it from being treated as formatting
+        format!("@b@.{}", text)
+    }
+
+    fn row(field: &str, value: &str) -> String {
+        format!("@.{}\t@.{}", field, value)
+    }
+
+    /// Describe a picture, such as "image/jpeg · 500×500 · 34 KB". The size of the image is only known for PNGs and JPEGs.
+    fn picture_details(picture: &Picture) -> String {
+        let mut details = Vec::new();
+
+        if let Some(mime_type) = picture.mime_type() {
+            details.push(mime_type.as_str().to_string());
+        }
+        if let Ok(information) = PictureInformation::from_picture(picture)
+            && information.width > 0
+        {
+            details.push(format!("{}×{}", information.width, information.height));
+        }
+        details.push(TrackInfoDialog::format_size(picture.data().len()));
+        if let Some(description) = picture.description().filter(|d| !d.is_empty()) {
+            details.push(format!("\"{}\"", description));
+        }
+
+        details.join(" · ")
+    }
+
+    /// Return the name of a field, such as "Album artist" for `ItemKey::AlbumArtist`.
+    /// Fields that lofty doesn't know are shown with the name they have in the tag.
+    fn field_name(key: &ItemKey) -> String {
+        match key {
+            ItemKey::Unknown(name) => name.clone(),
+            key => TrackInfoDialog::spaced(&format!("{:?}", key)),
+        }
+    }
+
+    /// Turn a name like "TrackTitle" into "Track title".
+    fn spaced(name: &str) -> String {
+        let mut spaced = String::new();
+
+        for (i, c) in name.chars().enumerate() {
+            if i > 0 && c.is_uppercase() {
+                spaced.push(' ');
+                spaced.extend(c.to_lowercase());
+            } else {
+                spaced.push(c);
+            }
+        }
+
+        spaced
+    }
+
+    /// Return a size in bytes in the unit that suits it, such as "34 KB".
+    fn format_size(bytes: usize) -> String {
+        const KB: f64 = 1024.0;
+        const MB: f64 = KB * 1024.0;
+
+        let size = bytes as f64;
+        if size >= MB {
+            format!("{:.1} MB", size / MB)
+        } else if size >= KB {
+            format!("{:.0} KB", size / KB)
+        } else {
+            format!("{} bytes", bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod field_name {
+        use super::*;
+
+        #[test]
+        fn test_known_field() {
+            assert_eq!(
+                TrackInfoDialog::field_name(&ItemKey::AlbumArtist),
+                "Album artist"
+            );
+        }
+
+        #[test]
+        fn test_unknown_field() {
+            assert_eq!(
+                TrackInfoDialog::field_name(&ItemKey::Unknown("MOOD".to_string())),
+                "MOOD"
+            );
+        }
+    }
+
+    mod format_size {
+        use super::*;
+
+        #[test]
+        fn test_bytes() {
+            assert_eq!(TrackInfoDialog::format_size(512), "512 bytes");
+        }
+
+        #[test]
+        fn test_kilobytes() {
+            assert_eq!(TrackInfoDialog::format_size(34 * 1024 + 100), "34 KB");
+        }
+
+        #[test]
+        fn test_megabytes() {
+            assert_eq!(TrackInfoDialog::format_size(2_621_440), "2.5 MB");
+        }
+    }
+}
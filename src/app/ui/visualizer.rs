@@ -0,0 +1,284 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use fltk::{
+    app::{self, MouseButton},
+    draw,
+    enums::{Align, Color, Event, Font},
+    frame::Frame,
+    prelude::{WidgetBase, WidgetExt},
+};
+
+use crate::app::visualizer::{
+    self, Level, Meter, SampleTap, Spectrum, TapSnapshot, VisualizerMode,
+};
+
+/// A panel under the playback controls that shows what is playing as a spectrum, an oscilloscope or a level meter.
+/// Clicking it switches between them.
+pub struct Visualizer {
+    widget: Frame,
+    tap: Arc<SampleTap>,
+
+    /// What was worked out from the samples at the last frame, which the widget draws
+    state: Rc<RefCell<VisualizerState>>,
+
+    /// How many samples the tap had been given at the last frame, to tell when nothing is playing
+    last_written: usize,
+    last_frame: Instant,
+}
+
+/// What the visualizer draws.
+struct VisualizerState {
+    spectrum: Spectrum,
+    meter: Meter,
+
+    /// The latest samples, for the oscilloscope
+    waveform: TapSnapshot,
+}
+
+impl Visualizer {
+    /// How often the visualizer is redrawn, which is 40 times a second
+    pub const FRAME_INTERVAL: Duration = Duration::from_millis(25);
+
+    const Y: i32 = 409;
+    const WIDTH: i32 = 360;
+    const HEIGHT: i32 = 70;
+
+    const BAR_COUNT: usize = 32;
+    const BAR_GAP: i32 = 2;
+
+    /// The colors of the theme, so that the visualizer follows it
+    const BACKGROUND_COLOR: Color = Color::Background;
+    const LEVEL_COLOR: Color = Color::Selection;
+    const PEAK_COLOR: (u8, u8, u8) = (0xff, 0x99, 0x00);
+
+    const LABEL_FONTSIZE: i32 = 10;
+
+    /// Create the visualizer. `mode` is what it shows, which carries over from one track to the next.
+    pub fn new(win_width: i32, tap: Arc<SampleTap>, mode: Rc<Cell<VisualizerMode>>) -> Visualizer {
+        let x = (win_width - Visualizer::WIDTH) / 2;
+        let mut widget = Frame::new(x, Visualizer::Y, Visualizer::WIDTH, Visualizer::HEIGHT, "");
+        widget.set_tooltip("Click to switch between the spectrum, oscilloscope and level meter");
+
+        let state = Rc::new(RefCell::new(VisualizerState {
+            spectrum: Spectrum::new(Visualizer::BAR_COUNT),
+            meter: Meter::default(),
+            waveform: TapSnapshot::default(),
+        }));
+
+        {
+            let state = Rc::clone(&state);
+            let mode = Rc::clone(&mode);
+
+            widget.draw(move |widget| {
+                let state = state.borrow();
+                let mode = mode.get();
+
+                draw::push_clip(widget.x(), widget.y(), widget.w(), widget.h());
+
+                draw::draw_rect_fill(
+                    widget.x(),
+                    widget.y(),
+                    widget.w(),
+                    widget.h(),
+                    Visualizer::BACKGROUND_COLOR,
+                );
+
+                match mode {
+                    VisualizerMode::Spectrum => {
+                        Visualizer::draw_spectrum(widget, state.spectrum.bands())
+                    }
+                    VisualizerMode::Oscilloscope => {
+                        Visualizer::draw_oscilloscope(widget, &state.waveform)
+                    }
+                    VisualizerMode::Meter => Visualizer::draw_meter(widget, &state.meter),
+                }
+
+                draw::set_font(Font::Helvetica, Visualizer::LABEL_FONTSIZE);
+                draw::set_draw_color(Color::gray_ramp(12));
+                draw::draw_text2(
+                    mode.label(),
+                    widget.x() + 4,
+                    widget.y() + 2,
+                    widget.w() - 8,
+                    Visualizer::LABEL_FONTSIZE,
+                    Align::TopRight | Align::Inside,
+                );
+
+                draw::pop_clip();
+            });
+        }
+
+        widget.handle(move |widget, event| match event {
+            Event::Push if app::event_mouse_button() == MouseButton::Left => {
+                mode.set(mode.get().next());
+                widget.redraw();
+                true
+            }
+            _ => false,
+        });
+
+        Visualizer {
+            widget,
+            last_written: tap.written(),
+            tap,
+            state,
+            last_frame: Instant::now(),
+        }
+    }
+
+    /// Analyze the samples that were played since the last frame, and redraw the visualizer.
+    /// This function is intended to be called continuously in the app's main loop. It only does anything once a
+    /// frame is due, so it can be called more often than that.
+    pub fn update(&mut self) {
+        let elapsed = self.last_frame.elapsed();
+        if elapsed < Visualizer::FRAME_INTERVAL {
+            return;
+        }
+        self.last_frame = Instant::now();
+
+        // Nothing has been played since the last frame, such as while paused, so let everything fall back to silence
+        let written = self.tap.written();
+        let snapshot = if written == self.last_written {
+            TapSnapshot::default()
+        } else {
+            self.tap.snapshot(Spectrum::FFT_SIZE)
+        };
+        self.last_written = written;
+
+        let mono: Vec<f32> = snapshot
+            .left
+            .iter()
+            .zip(&snapshot.right)
+            .map(|(left, right)| (left + right) / 2.0)
+            .collect();
+
+        let mut state = self.state.borrow_mut();
+        state.spectrum.update(&mono, snapshot.sample_rate, elapsed);
+        state.meter.update(&snapshot, elapsed);
+        state.waveform = snapshot;
+
+        self.widget.redraw();
+    }
+
+    /// Draw a bar for each band, with a line above it where its peak is held.
+    fn draw_spectrum(widget: &Frame, bands: &[Level]) {
+        let (peak_r, peak_g, peak_b) = Visualizer::PEAK_COLOR;
+        let bottom = widget.y() + widget.h();
+
+        for (i, band) in bands.iter().enumerate() {
+            // Work out both edges from the index, so that the bars fill the whole width despite rounding
+            let left = widget.x() + i as i32 * widget.w() / bands.len() as i32;
+            let right = widget.x() + (i as i32 + 1) * widget.w() / bands.len() as i32;
+            let width = (right - left - Visualizer::BAR_GAP).max(1);
+
+            let height = (band.value * widget.h() as f32) as i32;
+            draw::draw_rect_fill(
+                left,
+                bottom - height,
+                width,
+                height,
+                Visualizer::LEVEL_COLOR,
+            );
+
+            if band.peak > 0.0 {
+                let peak_y = bottom - (band.peak * widget.h() as f32) as i32;
+                draw::draw_rect_fill(
+                    left,
+                    peak_y.min(bottom - 2),
+                    width,
+                    2,
+                    Color::from_rgb(peak_r, peak_g, peak_b),
+                );
+            }
+        }
+    }
+
+    /// Draw the waveform of the left channel in the top half, and the right channel in the bottom half.
+    fn draw_oscilloscope(widget: &Frame, waveform: &TapSnapshot) {
+        let half_height = widget.h() / 2;
+
+        // Show the same stretch of both channels, starting where the left one rises through zero
+        let width = widget.w() as usize;
+        let start = visualizer::trigger_point(&waveform.left, width);
+
+        for (channel, samples) in [&waveform.left, &waveform.right].into_iter().enumerate() {
+            let center_y = widget.y() + half_height * channel as i32 + half_height / 2;
+
+            draw::set_draw_color(Color::gray_ramp(6));
+            draw::draw_line(widget.x(), center_y, widget.x() + widget.w(), center_y);
+
+            let Some(samples) = samples.get(start..) else {
+                continue;
+            };
+
+            draw::set_draw_color(Visualizer::LEVEL_COLOR);
+            draw::begin_line();
+            for (i, sample) in samples.iter().take(width).enumerate() {
+                let y =
+                    center_y as f64 - (sample.clamp(-1.0, 1.0) * half_height as f32 / 2.0) as f64;
+                draw::vertex((widget.x() + i as i32) as f64, y);
+            }
+            draw::end_line();
+        }
+    }
+
+    /// Draw a bar for each channel with its average level in front of its peaks, and a line where its peak is held.
+    fn draw_meter(widget: &Frame, meter: &Meter) {
+        const LABEL_WIDTH: i32 = 14;
+        const BAR_HEIGHT: i32 = 14;
+
+        // The peaks are behind the average level, in a darker shade of it
+        let peak_color = Visualizer::LEVEL_COLOR.darker();
+        let (hold_r, hold_g, hold_b) = Visualizer::PEAK_COLOR;
+
+        let bar_x = widget.x() + LABEL_WIDTH;
+        let bar_width = widget.w() - LABEL_WIDTH - 8;
+        let spacing = widget.h() / 2;
+
+        for (channel, label) in ["L", "R"].into_iter().enumerate() {
+            let bar_y = widget.y() + spacing * channel as i32 + (spacing - BAR_HEIGHT) / 2;
+            let level_width = |level: f32| (level * bar_width as f32) as i32;
+
+            draw::set_font(Font::Helvetica, Visualizer::LABEL_FONTSIZE);
+            draw::set_draw_color(Color::gray_ramp(12));
+            draw::draw_text2(
+                label,
+                widget.x(),
+                bar_y,
+                LABEL_WIDTH,
+                BAR_HEIGHT,
+                Align::Center,
+            );
+
+            draw::draw_rect_fill(
+                bar_x,
+                bar_y,
+                level_width(meter.peak[channel].value),
+                BAR_HEIGHT,
+                peak_color,
+            );
+            draw::draw_rect_fill(
+                bar_x,
+                bar_y,
+                level_width(meter.rms[channel].value),
+                BAR_HEIGHT,
+                Visualizer::LEVEL_COLOR,
+            );
+
+            if meter.peak[channel].peak > 0.0 {
+                draw::draw_rect_fill(
+                    bar_x + level_width(meter.peak[channel].peak).min(bar_width - 2),
+                    bar_y,
+                    2,
+                    BAR_HEIGHT,
+                    Color::from_rgb(hold_r, hold_g, hold_b),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::f32::consts::PI;
+
+/// Work out the amplitude of each frequency in `samples`, where the amplitude at index `i` is for the frequency
+/// `i * sample_rate / samples.len()`. Only the first half of the frequencies are returned, since the rest mirror them.
+///
+/// A Hann window is applied first so that frequencies between two bins don't smear across the whole spectrum.
+/// A full-scale sine wave has an amplitude of roughly 1.0.
+///
+/// # Panics
+/// If the number of samples isn't a power of two.
+pub(crate) fn amplitudes(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    assert!(n.is_power_of_two(), "The FFT needs a power of two samples");
+
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * hann(i, n))
+        .collect();
+    let mut im = vec![0.0; n];
+
+    fft(&mut re, &mut im);
+
+    // The Hann window halves the amplitude, and the energy of a real signal is split between both halves of the spectrum
+    let scale = 4.0 / n as f32;
+    re.iter()
+        .zip(&im)
+        .take(n / 2)
+        .map(|(re, im)| (re * re + im * im).sqrt() * scale)
+        .collect()
+}
+
+fn hann(i: usize, n: usize) -> f32 {
+    0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()
+}
+
+/// An in-place radix-2 FFT, where `re` and `im` are the real and imaginary parts of the signal.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // Put the samples in bit-reversed order, so that each pass can combine neighbouring halves
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    mod amplitudes {
+        use super::*;
+
+        fn sine(bin: usize, n: usize, amplitude: f32) -> Vec<f32> {
+            (0..n)
+                .map(|i| amplitude * (2.0 * PI * bin as f32 * i as f32 / n as f32).sin())
+                .collect()
+        }
+
+        #[test]
+        fn test_sine_peaks_at_its_frequency() {
+            let amplitudes = amplitudes(&sine(64, 1024, 1.0));
+
+            let loudest = amplitudes
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(bin, _)| bin);
+            assert_eq!(loudest, Some(64));
+            assert!((amplitudes[64] - 1.0).abs() < 0.01);
+        }
+
+        #[test]
+        fn test_amplitude_scales() {
+            let amplitudes = amplitudes(&sine(10, 256, 0.25));
+            assert!((amplitudes[10] - 0.25).abs() < 0.01);
+        }
+
+        #[test]
+        fn test_silence() {
+            assert!(amplitudes(&[0.0; 128]).iter().all(|a| *a == 0.0));
+        }
+    }
+}
@@ -0,0 +1,361 @@
+mod fft;
+mod tap;
+
+use std::time::Duration;
+
+pub(crate) use tap::{SampleTap, TapSnapshot, Tapped};
+
+/// What the visualizer shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum VisualizerMode {
+    /// Bars for how loud each range of frequencies is, from bass on the left to treble on the right
+    #[default]
+    Spectrum,
+
+    /// The waveforms of the left and right channels
+    Oscilloscope,
+
+    /// How loud each channel is, along with its peaks
+    Meter,
+}
+
+impl VisualizerMode {
+    /// Every mode, in the order that clicking the visualizer goes through them.
+    pub(crate) const ALL: [VisualizerMode; 3] = [
+        VisualizerMode::Spectrum,
+        VisualizerMode::Oscilloscope,
+        VisualizerMode::Meter,
+    ];
+
+    /// Return the mode that comes after this one.
+    pub(crate) fn next(&self) -> VisualizerMode {
+        let index = VisualizerMode::ALL
+            .iter()
+            .position(|mode| mode == self)
+            .unwrap_or(0);
+
+        VisualizerMode::ALL[(index + 1) % VisualizerMode::ALL.len()]
+    }
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            VisualizerMode::Spectrum => "Spectrum",
+            VisualizerMode::Oscilloscope => "Oscilloscope",
+            VisualizerMode::Meter => "Level meter",
+        }
+    }
+}
+
+/// How loud the audio is for the visualizer, from 0.0 for silence to 1.0 for full scale.
+/// Levels fall back gradually instead of dropping straight to a quieter value, and the highest recent level is held
+/// for a moment, like the peak lights of a mixing desk.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Level {
+    pub(crate) value: f32,
+
+    /// The highest value that was reached recently
+    pub(crate) peak: f32,
+
+    /// How long ago the peak was reached
+    peak_age: Duration,
+}
+
+impl Level {
+    /// How much of the full scale the value can fall per second
+    const FALL_RATE: f32 = 1.5;
+
+    /// How long the peak stays put before it starts to fall
+    const PEAK_HOLD: Duration = Duration::from_secs(1);
+    const PEAK_FALL_RATE: f32 = 0.5;
+
+    /// Move the level towards `target`, where `elapsed` is how long it has been since the last update.
+    pub(crate) fn update(&mut self, target: f32, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32();
+        self.value = target.max(self.value - Level::FALL_RATE * seconds).max(0.0);
+
+        self.peak_age += elapsed;
+        if self.value >= self.peak {
+            self.peak = self.value;
+            self.peak_age = Duration::ZERO;
+        } else if self.peak_age > Level::PEAK_HOLD {
+            self.peak = (self.peak - Level::PEAK_FALL_RATE * seconds).max(self.value);
+        }
+    }
+}
+
+/// The levels of a range of frequencies, spaced out on a logarithmic scale so that each octave gets the same room.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Spectrum {
+    bands: Vec<Level>,
+}
+
+impl Spectrum {
+    /// How many samples each update looks at. More samples can tell the low frequencies apart, but react slower
+    pub(crate) const FFT_SIZE: usize = 2048;
+
+    const MIN_FREQUENCY: f32 = 30.0;
+    const MAX_FREQUENCY: f32 = 16000.0;
+
+    pub(crate) fn new(band_count: usize) -> Spectrum {
+        Spectrum {
+            bands: vec![Level::default(); band_count],
+        }
+    }
+
+    pub(crate) fn bands(&self) -> &[Level] {
+        &self.bands
+    }
+
+    /// Analyze the latest `samples` of a channel. Pass no samples while the audio is paused, so that the bars fall.
+    pub(crate) fn update(&mut self, samples: &[f32], sample_rate: u32, elapsed: Duration) {
+        let targets = Spectrum::band_levels(samples, sample_rate, self.bands.len());
+
+        for (band, target) in self.bands.iter_mut().zip(targets) {
+            band.update(target, elapsed);
+        }
+    }
+
+    /// Return how loud each band is in the last `FFT_SIZE` samples, which are padded with silence if there are fewer.
+    fn band_levels(samples: &[f32], sample_rate: u32, band_count: usize) -> Vec<f32> {
+        if samples.is_empty() || sample_rate == 0 {
+            return vec![0.0; band_count];
+        }
+
+        let mut window = vec![0.0; Spectrum::FFT_SIZE];
+        let latest = &samples[samples.len().saturating_sub(Spectrum::FFT_SIZE)..];
+        window[Spectrum::FFT_SIZE - latest.len()..].copy_from_slice(latest);
+
+        let amplitudes = fft::amplitudes(&window);
+        let bin_width = sample_rate as f32 / Spectrum::FFT_SIZE as f32;
+        let max_frequency = Spectrum::MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+
+        (0..band_count)
+            .map(|band| {
+                let (low, high) = Spectrum::band_range(band, band_count, max_frequency);
+                let low_bin = (low / bin_width).round() as usize;
+                let high_bin = ((high / bin_width).round() as usize).max(low_bin + 1);
+
+                // The lowest bands can be narrower than a single bin, in which case they share it
+                let amplitude = amplitudes
+                    .get(low_bin..high_bin.min(amplitudes.len()))
+                    .and_then(|bins| bins.iter().copied().reduce(f32::max))
+                    .unwrap_or(0.0);
+
+                loudness(amplitude)
+            })
+            .collect()
+    }
+
+    /// Return the lowest and highest frequency of a band.
+    fn band_range(band: usize, band_count: usize, max_frequency: f32) -> (f32, f32) {
+        let ratio = max_frequency / Spectrum::MIN_FREQUENCY;
+        let edge = |i: usize| Spectrum::MIN_FREQUENCY * ratio.powf(i as f32 / band_count as f32);
+
+        (edge(band), edge(band + 1))
+    }
+}
+
+/// The levels of the left and right channels, for the level meter.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Meter {
+    /// The average loudness of each channel, which is close to how loud it sounds
+    pub(crate) rms: [Level; 2],
+
+    /// The loudest sample of each channel
+    pub(crate) peak: [Level; 2],
+}
+
+impl Meter {
+    /// How many samples of each channel the meter looks at, which is about 20 ms of audio at 48 kHz
+    pub(crate) const WINDOW: usize = 1024;
+
+    /// Measure the latest samples. Pass an empty snapshot while the audio is paused, so that the levels fall.
+    pub(crate) fn update(&mut self, snapshot: &TapSnapshot, elapsed: Duration) {
+        for (channel, samples) in [&snapshot.left, &snapshot.right].into_iter().enumerate() {
+            let samples = &samples[samples.len().saturating_sub(Meter::WINDOW)..];
+
+            let peak = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let rms = match samples.len() {
+                0 => 0.0,
+                len => (samples.iter().map(|s| s * s).sum::<f32>() / len as f32).sqrt(),
+            };
+
+            self.rms[channel].update(loudness(rms), elapsed);
+            self.peak[channel].update(loudness(peak), elapsed);
+        }
+    }
+}
+
+/// The quietest level that is shown, in decibels below full scale
+const FLOOR_DB: f32 = -60.0;
+
+/// Turn an amplitude into a loudness from 0.0 to 1.0, on a decibel scale from `FLOOR_DB` to 0 dB.
+pub(crate) fn loudness(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+
+    let db = 20.0 * amplitude.log10();
+    ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// Return where to start drawing a waveform of `width` samples, so that it stays still between frames.
+/// This is where the waveform first rises through zero, or the start of the last `width` samples if it never does.
+pub(crate) fn trigger_point(samples: &[f32], width: usize) -> usize {
+    let latest_start = samples.len().saturating_sub(width);
+
+    (1..=latest_start)
+        .find(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+        .unwrap_or(latest_start)
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    mod next {
+        use super::*;
+
+        #[test]
+        fn test_wraps_around() {
+            assert_eq!(
+                VisualizerMode::Spectrum.next(),
+                VisualizerMode::Oscilloscope
+            );
+            assert_eq!(VisualizerMode::Meter.next(), VisualizerMode::Spectrum);
+        }
+    }
+
+    mod level {
+        use super::*;
+
+        #[test]
+        fn test_rises_straight_away() {
+            let mut level = Level::default();
+            level.update(0.8, Duration::from_millis(20));
+
+            assert_eq!(level.value, 0.8);
+            assert_eq!(level.peak, 0.8);
+        }
+
+        #[test]
+        fn test_falls_gradually() {
+            let mut level = Level::default();
+            level.update(1.0, Duration::from_millis(20));
+            level.update(0.0, Duration::from_millis(100));
+
+            assert!((level.value - 0.85).abs() < 1e-6);
+        }
+
+        #[test]
+        fn test_peak_is_held() {
+            let mut level = Level::default();
+            level.update(1.0, Duration::from_millis(20));
+            level.update(0.0, Duration::from_millis(500));
+            assert_eq!(level.peak, 1.0);
+
+            level.update(0.0, Duration::from_millis(600));
+            assert!(level.peak < 1.0);
+            assert!(level.peak >= level.value);
+        }
+    }
+
+    mod spectrum {
+        use super::*;
+
+        #[test]
+        fn test_tone_lights_up_its_band() {
+            let mut spectrum = Spectrum::new(16);
+            spectrum.update(
+                &sine(1000.0, 44100, Spectrum::FFT_SIZE),
+                44100,
+                Duration::from_millis(20),
+            );
+
+            let loudest = spectrum
+                .bands()
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.value.total_cmp(&b.1.value))
+                .map(|(band, _)| band)
+                .unwrap();
+            let (low, high) = Spectrum::band_range(loudest, 16, Spectrum::MAX_FREQUENCY);
+
+            assert!((low..high).contains(&1000.0), "{}..{}", low, high);
+            assert!(spectrum.bands()[loudest].value > 0.9);
+        }
+
+        #[test]
+        fn test_silence() {
+            let mut spectrum = Spectrum::new(8);
+            spectrum.update(&[], 44100, Duration::from_millis(20));
+
+            assert!(spectrum.bands().iter().all(|band| band.value == 0.0));
+        }
+
+        #[test]
+        fn test_bands_cover_the_range() {
+            let (low, _) = Spectrum::band_range(0, 10, 16000.0);
+            let (_, high) = Spectrum::band_range(9, 10, 16000.0);
+
+            assert!((low - Spectrum::MIN_FREQUENCY).abs() < 1e-3);
+            assert!((high - 16000.0).abs() < 1.0);
+        }
+    }
+
+    mod meter {
+        use super::*;
+
+        #[test]
+        fn test_channels_are_measured_apart() {
+            let snapshot = TapSnapshot {
+                left: sine(440.0, 48000, Meter::WINDOW),
+                right: vec![0.0; Meter::WINDOW],
+                sample_rate: 48000,
+            };
+
+            let mut meter = Meter::default();
+            meter.update(&snapshot, Duration::from_millis(20));
+
+            // A full-scale sine wave peaks at 0 dB, and its RMS is 3 dB lower
+            assert!(meter.peak[0].value > 0.99);
+            assert!((meter.rms[0].value - (1.0 - 3.0 / 60.0)).abs() < 0.01);
+            assert_eq!(meter.peak[1].value, 0.0);
+        }
+    }
+
+    mod loudness {
+        use super::*;
+
+        #[test]
+        fn test_scale() {
+            assert_eq!(loudness(1.0), 1.0);
+            assert_eq!(loudness(0.0), 0.0);
+            assert!((loudness(0.001) - 0.0).abs() < 1e-6);
+            assert!((loudness(0.1) - 2.0 / 3.0).abs() < 1e-3);
+        }
+    }
+
+    mod trigger_point {
+        use super::*;
+
+        #[test]
+        fn test_rising_zero_crossing() {
+            let samples = [0.5, -0.5, -0.2, 0.1, 0.4, -0.1];
+            assert_eq!(trigger_point(&samples, 2), 3);
+        }
+
+        #[test]
+        fn test_no_crossing() {
+            assert_eq!(trigger_point(&[0.1; 10], 4), 6);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{ChannelCount, SampleRate, Source};
+
+/// The latest samples that went to the speakers, kept in a ring buffer that the audio thread writes to without ever
+/// waiting on a lock. The UI reads it whenever it draws a frame, which can see a sample or two from the next write,
+/// but never holds up the audio.
+pub(crate) struct SampleTap {
+    /// The bits of each `f32` sample, since there is no atomic float
+    samples: Box<[AtomicU32]>,
+
+    /// How many samples have ever been written. The next sample goes to `written % CAPACITY`
+    written: AtomicUsize,
+
+    channels: AtomicU16,
+    sample_rate: AtomicU32,
+
+    /// The value of `written` when the current channel layout started, so that the reader knows where each frame begins
+    format_start: AtomicUsize,
+}
+
+/// The latest audio of the tap, split into its left and right channels. Both channels are the same for mono audio.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TapSnapshot {
+    pub(crate) left: Vec<f32>,
+    pub(crate) right: Vec<f32>,
+    pub(crate) sample_rate: u32,
+}
+
+impl SampleTap {
+    /// How many samples are kept, which is enough for the spectrum of stereo audio at 192 kHz
+    pub(crate) const CAPACITY: usize = 16384;
+
+    pub(crate) fn new() -> SampleTap {
+        SampleTap {
+            samples: (0..SampleTap::CAPACITY)
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+            written: AtomicUsize::new(0),
+            channels: AtomicU16::new(2),
+            sample_rate: AtomicU32::new(44100),
+            format_start: AtomicUsize::new(0),
+        }
+    }
+
+    /// Return how many samples have ever been written, which stops changing while the audio is paused.
+    pub(crate) fn written(&self) -> usize {
+        self.written.load(Ordering::Acquire)
+    }
+
+    /// Copy the latest `frames` frames. Returns fewer frames if that many haven't been written yet.
+    pub(crate) fn snapshot(&self, frames: usize) -> TapSnapshot {
+        let written = self.written();
+        let channels = self.channels.load(Ordering::Relaxed).max(1) as usize;
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        let format_start = self.format_start.load(Ordering::Relaxed).min(written);
+
+        // Only read whole frames of the current layout, and leave some of the buffer alone for the writer
+        let available = (written - format_start).min(SampleTap::CAPACITY / 2) / channels;
+        let frames = frames.min(available);
+        let first = written - (written - format_start) % channels - frames * channels;
+
+        let sample = |i: usize| {
+            f32::from_bits(self.samples[i % SampleTap::CAPACITY].load(Ordering::Relaxed))
+        };
+
+        let left: Vec<f32> = (0..frames)
+            .map(|frame| sample(first + frame * channels))
+            .collect();
+        let right = match channels {
+            1 => left.clone(),
+            _ => (0..frames)
+                .map(|frame| sample(first + frame * channels + 1))
+                .collect(),
+        };
+
+        TapSnapshot {
+            left,
+            right,
+            sample_rate,
+        }
+    }
+
+    /// Add a sample. Only one thread can write to the tap at a time.
+    fn push(&self, sample: f32) {
+        let written = self.written.load(Ordering::Relaxed);
+
+        self.samples[written % SampleTap::CAPACITY].store(sample.to_bits(), Ordering::Relaxed);
+        self.written.store(written + 1, Ordering::Release);
+    }
+
+    /// Start a new channel layout with the next sample, if the layout is different.
+    /// Decoders start a new span with every packet, which mostly keeps the same layout, so the frames before it are kept.
+    fn set_format(&self, channels: ChannelCount, sample_rate: SampleRate) {
+        if channels == self.channels.load(Ordering::Relaxed)
+            && sample_rate == self.sample_rate.load(Ordering::Relaxed)
+        {
+            return;
+        }
+
+        self.start_format(channels, sample_rate);
+    }
+
+    /// Start a new channel layout with the next sample, even if it is the same as the current one.
+    fn start_format(&self, channels: ChannelCount, sample_rate: SampleRate) {
+        self.channels.store(channels, Ordering::Relaxed);
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.format_start
+            .store(self.written.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+impl Default for SampleTap {
+    fn default() -> SampleTap {
+        SampleTap::new()
+    }
+}
+
+/// A source that copies every sample it plays into a `SampleTap`.
+pub(crate) struct Tapped<S> {
+    input: S,
+    tap: Arc<SampleTap>,
+
+    /// How many samples are left in the current span, after which the channel layout can change
+    span_left: usize,
+
+    /// Whether the next span starts a new layout even if it is the same as the current one, because a seek can leave
+    /// the samples before it out of step with the frames
+    realign: bool,
+}
+
+impl<S: Source> Tapped<S> {
+    pub(crate) fn new(input: S, tap: Arc<SampleTap>) -> Tapped<S> {
+        Tapped {
+            input,
+            tap,
+            span_left: 0,
+            realign: false,
+        }
+    }
+}
+
+impl<S: Source> Iterator for Tapped<S> {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.span_left == 0 {
+            let (channels, sample_rate) = (self.input.channels(), self.input.sample_rate());
+            if std::mem::take(&mut self.realign) {
+                self.tap.start_format(channels, sample_rate);
+            } else {
+                self.tap.set_format(channels, sample_rate);
+            }
+            self.span_left = self.input.current_span_len().unwrap_or(usize::MAX);
+        }
+
+        let sample = self.input.next()?;
+        self.span_left = self.span_left.saturating_sub(1);
+        self.tap.push(sample);
+
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Tapped<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // Seeking can land in the middle of a frame's worth of spans, so start a new layout from the next sample
+        self.span_left = 0;
+        self.realign = true;
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    /// A source that starts a new span every `span_len` samples, like a decoder does with every packet.
+    struct Packets {
+        samples: std::vec::IntoIter<f32>,
+        span_len: usize,
+        channels: ChannelCount,
+    }
+
+    impl Iterator for Packets {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            self.samples.next()
+        }
+    }
+
+    impl Source for Packets {
+        fn current_span_len(&self) -> Option<usize> {
+            Some(self.span_len.min(self.samples.len()))
+        }
+
+        fn channels(&self) -> ChannelCount {
+            self.channels
+        }
+
+        fn sample_rate(&self) -> SampleRate {
+            8000
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    /// Play `samples` through a tap and return it.
+    fn tapped(channels: u16, samples: Vec<f32>) -> Arc<SampleTap> {
+        let tap = Arc::new(SampleTap::new());
+        let source = Tapped::new(
+            SamplesBuffer::new(channels, 8000, samples),
+            Arc::clone(&tap),
+        );
+
+        // The samples come out of the tap as they were
+        let played: Vec<f32> = source.collect();
+        assert_eq!(played.len(), tap.written());
+
+        tap
+    }
+
+    mod snapshot {
+        use super::*;
+
+        #[test]
+        fn test_stereo() {
+            let tap = tapped(2, vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+            let snapshot = tap.snapshot(2);
+
+            assert_eq!(snapshot.left, [0.2, 0.3]);
+            assert_eq!(snapshot.right, [-0.2, -0.3]);
+            assert_eq!(snapshot.sample_rate, 8000);
+        }
+
+        #[test]
+        fn test_mono() {
+            let tap = tapped(1, vec![0.1, 0.2, 0.3]);
+            let snapshot = tap.snapshot(10);
+
+            assert_eq!(snapshot.left, [0.1, 0.2, 0.3]);
+            assert_eq!(snapshot.right, snapshot.left);
+        }
+
+        #[test]
+        fn test_wraps_around() {
+            let samples: Vec<f32> = (0..SampleTap::CAPACITY * 3).map(|i| i as f32).collect();
+            let tap = tapped(1, samples);
+            let snapshot = tap.snapshot(3);
+
+            let last = (SampleTap::CAPACITY * 3) as f32;
+            assert_eq!(snapshot.left, [last - 3.0, last - 2.0, last - 1.0]);
+        }
+
+        #[test]
+        fn test_spans_of_the_same_layout() {
+            let tap = Arc::new(SampleTap::new());
+            let packets = Packets {
+                samples: vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3, 0.4, -0.4].into_iter(),
+                span_len: 2,
+                channels: 2,
+            };
+            Tapped::new(packets, Arc::clone(&tap)).for_each(drop);
+
+            // Every span is kept, not just the last one
+            let snapshot = tap.snapshot(10);
+            assert_eq!(snapshot.left, [0.1, 0.2, 0.3, 0.4]);
+            assert_eq!(snapshot.right, [-0.1, -0.2, -0.3, -0.4]);
+        }
+
+        #[test]
+        fn test_frames_follow_the_new_track() {
+            let tap = Arc::new(SampleTap::new());
+
+            // A mono track with an odd number of samples, followed by a stereo one
+            Tapped::new(SamplesBuffer::new(1, 8000, vec![0.5; 3]), Arc::clone(&tap)).for_each(drop);
+            Tapped::new(
+                SamplesBuffer::new(2, 8000, vec![0.1, -0.1, 0.2, -0.2]),
+                Arc::clone(&tap),
+            )
+            .for_each(drop);
+
+            let snapshot = tap.snapshot(10);
+            assert_eq!(snapshot.left, [0.1, 0.2]);
+            assert_eq!(snapshot.right, [-0.1, -0.2]);
+        }
+    }
+}
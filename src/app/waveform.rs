@@ -0,0 +1,457 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::app::audio_handler::AudioHandler;
+use crate::app::stable_hash::stable_hash;
+
+/// How loud one column of the waveform is, where samples range from -1.0 to 1.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Peak {
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+
+    /// The average loudness of the column, which is closer to how loud it sounds than its peaks
+    pub(crate) rms: f32,
+}
+
+/// The peaks of a whole track, with one peak for each pixel of the seek bar.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Waveform {
+    pub(crate) peaks: Vec<Peak>,
+}
+
+impl Waveform {
+    /// How many frames are measured together before they are split into columns.
+    /// The columns can't be worked out straight away, since the length of the track isn't always known until it has been decoded
+    const BLOCK_FRAMES: usize = 256;
+
+    /// Measure every sample of `source`, and split the track into `columns` peaks.
+    /// Returns no peaks if the source has no samples.
+    pub(crate) fn compute(source: impl Source, columns: usize) -> Waveform {
+        let channels = usize::from(source.channels().max(1));
+        let block_len = Waveform::BLOCK_FRAMES * channels;
+
+        let mut blocks = Vec::new();
+        let mut block = Block::default();
+
+        for sample in source {
+            block.add(sample);
+            if block.count == block_len {
+                blocks.push(block);
+                block = Block::default();
+            }
+        }
+        if block.count > 0 {
+            blocks.push(block);
+        }
+
+        Waveform {
+            peaks: Waveform::columns(&blocks, columns),
+        }
+    }
+
+    /// Merge the blocks into `columns` columns. When there are fewer blocks than columns, neighbouring columns share a block.
+    fn columns(blocks: &[Block], columns: usize) -> Vec<Peak> {
+        if blocks.is_empty() {
+            return Vec::new();
+        }
+
+        (0..columns)
+            .map(|column| {
+                let start = column * blocks.len() / columns;
+                let end = ((column + 1) * blocks.len() / columns).max(start + 1);
+
+                blocks[start..end]
+                    .iter()
+                    .fold(Block::default(), |merged, block| merged.merge(block))
+                    .peak()
+            })
+            .collect()
+    }
+}
+
+/// The samples of part of a track, measured as they are decoded.
+#[derive(Debug, Clone, Copy, Default)]
+struct Block {
+    min: f32,
+    max: f32,
+    sum_of_squares: f64,
+    count: usize,
+}
+
+impl Block {
+    fn add(&mut self, sample: f32) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.sum_of_squares += f64::from(sample * sample);
+        self.count += 1;
+    }
+
+    fn merge(self, other: &Block) -> Block {
+        Block {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            sum_of_squares: self.sum_of_squares + other.sum_of_squares,
+            count: self.count + other.count,
+        }
+    }
+
+    fn peak(&self) -> Peak {
+        let rms = match self.count {
+            0 => 0.0,
+            count => (self.sum_of_squares / count as f64).sqrt() as f32,
+        };
+
+        Peak {
+            min: self.min.max(-1.0),
+            max: self.max.min(1.0),
+            rms: rms.min(1.0),
+        }
+    }
+}
+
+/// A waveform that was saved to disk, along with what the file looked like when it was measured.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedWaveform {
+    /// The track that was measured, to tell apart two tracks whose cache files have the same name
+    path: PathBuf,
+
+    /// When the track was last modified, in seconds since the Unix epoch, so that edited tracks are measured again
+    modified: u64,
+    len: u64,
+
+    waveform: Waveform,
+}
+
+/// Saves the waveform of each track to disk, since decoding a whole track takes a while.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeakCache {
+    /// Where the waveforms are saved. If this is `None`, nothing is cached.
+    dir: Option<PathBuf>,
+}
+
+impl PeakCache {
+    /// How many bytes of waveforms are kept. Once there are more, the ones that were saved first are deleted.
+    const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+    /// Use the waveforms in the user's cache directory.
+    pub(crate) fn new() -> PeakCache {
+        PeakCache {
+            dir: dirs::cache_dir().map(|dir| dir.join("audio_player").join("waveforms")),
+        }
+    }
+
+    /// Return the saved waveform of the track at `path` with `columns` peaks, unless the track changed since it was saved.
+    pub(crate) fn load(&self, path: &Path, columns: usize) -> Option<Waveform> {
+        let cache_path = self.cache_path(path, columns)?;
+        let contents = match fs::read_to_string(&cache_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", cache_path.display(), e);
+                return None;
+            }
+        };
+
+        let cached: CachedWaveform = match serde_json::from_str(&contents) {
+            Ok(cached) => cached,
+            Err(e) => {
+                eprintln!("Unable to parse {}: {}", cache_path.display(), e);
+                return None;
+            }
+        };
+
+        let (modified, len) = PeakCache::file_version(path)?;
+        (cached.path == path
+            && cached.modified == modified
+            && cached.len == len
+            && cached.waveform.peaks.len() == columns)
+            .then_some(cached.waveform)
+    }
+
+    /// Save the waveform of the track at `path`.
+    pub(crate) fn save(&self, path: &Path, waveform: &Waveform) {
+        let (Some(cache_path), Some((modified, len))) = (
+            self.cache_path(path, waveform.peaks.len()),
+            PeakCache::file_version(path),
+        ) else {
+            return;
+        };
+
+        let cached = CachedWaveform {
+            path: path.to_path_buf(),
+            modified,
+            len,
+            waveform: waveform.clone(),
+        };
+
+        let written = serde_json::to_string(&cached)
+            .map_err(io::Error::other)
+            .and_then(|contents| {
+                cache_path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&cache_path, contents))
+            });
+
+        if let Err(e) = written {
+            eprintln!("Unable to save {}: {}", cache_path.display(), e);
+        }
+
+        self.trim(PeakCache::MAX_SIZE);
+    }
+
+    /// Delete the waveforms that were saved first, until the rest take up at most `max_size` bytes.
+    fn trim(&self, max_size: u64) {
+        let Some(entries) = self.dir.as_ref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return;
+        };
+
+        let mut files: Vec<(PathBuf, SystemTime, u64)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+        files.sort_by_key(|(_, modified, _)| *modified);
+
+        let mut size: u64 = files.iter().map(|(_, _, len)| len).sum();
+        for (path, _, len) in files {
+            if size <= max_size {
+                break;
+            }
+
+            match fs::remove_file(&path) {
+                Ok(()) => size -= len,
+                Err(e) => eprintln!("Unable to delete {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Return where the waveform of the track at `path` with `columns` peaks is saved.
+    fn cache_path(&self, path: &Path, columns: usize) -> Option<PathBuf> {
+        // The name has to stay the same between runs, so that the waveform is found again
+        let hash = stable_hash(path.as_os_str().as_encoded_bytes());
+
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{:016x}-{}.json", hash, columns)),
+        )
+    }
+
+    /// Return when the file at `path` was last modified and how long it is, to tell whether it changed.
+    fn file_version(path: &Path) -> Option<(u64, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+
+        Some((modified, metadata.len()))
+    }
+}
+
+/// Works out the waveform of a track in the background, or loads it from the cache.
+pub(crate) struct WaveformLoader {
+    receiver: mpsc::Receiver<Waveform>,
+}
+
+impl WaveformLoader {
+    /// Start working out the waveform of the track at `path`, with `columns` peaks.
+    pub(crate) fn start(path: PathBuf, columns: usize, cache: PeakCache) -> WaveformLoader {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let waveform = match cache.load(&path, columns) {
+                Some(waveform) => waveform,
+                None => {
+                    let decoder =
+                        match File::open(&path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|file| {
+                                AudioHandler::build_decoder(file).map_err(|e| e.to_string())
+                            }) {
+                            Ok(decoder) => decoder,
+                            Err(e) => {
+                                eprintln!(
+                                    "Unable to draw the waveform of {}: {}",
+                                    path.display(),
+                                    e
+                                );
+                                return;
+                            }
+                        };
+
+                    let waveform = Waveform::compute(decoder, columns);
+                    cache.save(&path, &waveform);
+                    waveform
+                }
+            };
+
+            // The track may have been switched in the meantime, in which case nobody needs the waveform
+            let _ = sender.send(waveform);
+        });
+
+        WaveformLoader { receiver }
+    }
+
+    /// Return the waveform once it is ready.
+    pub(crate) fn try_recv(&self) -> Option<Waveform> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::time::Duration;
+
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("audio_player_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    mod compute {
+        use super::*;
+
+        #[test]
+        fn test_columns() {
+            // One block of silence, then one block of a square wave
+            let mut samples = vec![0.0; Waveform::BLOCK_FRAMES];
+            samples
+                .extend((0..Waveform::BLOCK_FRAMES).map(|i| if i % 2 == 0 { 0.5 } else { -0.5 }));
+
+            let waveform = Waveform::compute(SamplesBuffer::new(1, 8000, samples), 2);
+
+            assert_eq!(waveform.peaks[0], Peak::default());
+            assert_eq!(
+                waveform.peaks[1],
+                Peak {
+                    min: -0.5,
+                    max: 0.5,
+                    rms: 0.5,
+                }
+            );
+        }
+
+        #[test]
+        fn test_more_columns_than_blocks() {
+            let samples = vec![0.25; Waveform::BLOCK_FRAMES * 2];
+            let waveform = Waveform::compute(SamplesBuffer::new(2, 8000, samples), 4);
+
+            assert_eq!(waveform.peaks.len(), 4);
+            assert!(waveform.peaks.iter().all(|peak| peak.max == 0.25));
+        }
+
+        #[test]
+        fn test_empty() {
+            let waveform = Waveform::compute(SamplesBuffer::new(1, 8000, Vec::<f32>::new()), 10);
+            assert!(waveform.peaks.is_empty());
+        }
+    }
+
+    mod peak_cache {
+        use super::*;
+
+        fn waveform(columns: usize) -> Waveform {
+            Waveform {
+                peaks: vec![
+                    Peak {
+                        min: -0.5,
+                        max: 0.5,
+                        rms: 0.25,
+                    };
+                    columns
+                ],
+            }
+        }
+
+        #[test]
+        fn test_round_trip() {
+            let dir = temp_dir("peak_cache_round_trip");
+            let track = dir.join("track.mp3");
+            fs::write(&track, b"audio").unwrap();
+
+            let cache = PeakCache {
+                dir: Some(dir.join("cache")),
+            };
+            cache.save(&track, &waveform(10));
+
+            assert_eq!(cache.load(&track, 10), Some(waveform(10)));
+            // The seek bar changed width
+            assert_eq!(cache.load(&track, 20), None);
+        }
+
+        #[test]
+        fn test_changed_track() {
+            let dir = temp_dir("peak_cache_changed_track");
+            let track = dir.join("track.mp3");
+            fs::write(&track, b"audio").unwrap();
+
+            let cache = PeakCache {
+                dir: Some(dir.join("cache")),
+            };
+            cache.save(&track, &waveform(10));
+            fs::write(&track, b"different audio").unwrap();
+
+            assert_eq!(cache.load(&track, 10), None);
+        }
+
+        #[test]
+        fn test_trim() {
+            let dir = temp_dir("peak_cache_trim");
+            let cache = PeakCache {
+                dir: Some(dir.join("cache")),
+            };
+
+            let mut tracks = Vec::new();
+            for name in ["1.mp3", "2.mp3", "3.mp3"] {
+                let track = dir.join(name);
+                fs::write(&track, b"audio").unwrap();
+                cache.save(&track, &waveform(10));
+                tracks.push(track);
+
+                // Make sure that the waveforms are saved at different times
+                thread::sleep(Duration::from_millis(20));
+            }
+
+            let saved_len = fs::metadata(cache.cache_path(&tracks[0], 10).unwrap())
+                .unwrap()
+                .len();
+            cache.trim(saved_len * 2);
+
+            assert_eq!(cache.load(&tracks[0], 10), None);
+            assert_eq!(cache.load(&tracks[1], 10), Some(waveform(10)));
+            assert_eq!(cache.load(&tracks[2], 10), Some(waveform(10)));
+        }
+
+        #[test]
+        fn test_no_cache_dir() {
+            let dir = temp_dir("peak_cache_no_dir");
+            let track = dir.join("track.mp3");
+            fs::write(&track, b"audio").unwrap();
+
+            let cache = PeakCache::default();
+            cache.save(&track, &waveform(10));
+
+            assert_eq!(cache.load(&track, 10), None);
+        }
+    }
+}
@@ -0,0 +1,568 @@
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+
+#[cfg(unix)]
+use crate::app::control::{self, ControlRequest, ControlResponse};
+use crate::app::dsp::DspSettings;
+use crate::app::export::{ExportFormat, ExportOptions, Exporter};
+use crate::app::stream;
+
+pub(crate) const USAGE: &str = "\
+Usage:
+    audio_player [FILES...]                  Open the player and play FILES one after the other.
+                                             FILES can also be folders, playlists, or http(s) URLs of
+                                             internet radio streams.
+                                             If the player is already open, FILES are added to its queue instead
+    audio_player export <INPUT> <OUTPUT> [OPTIONS]
+                                             Convert INPUT without opening the player
+    audio_player ctl <COMMAND>               Control the player that is already open
+
+Control commands:
+    play, pause                 Resume or pause playback
+    seek <TIME>                 Jump to TIME in the current track, e.g. 1:30 or 90.5
+    volume <PERCENT>            Change the volume, from 0 to 100
+    enqueue <FILES...>          Add FILES to the end of the queue
+    next                        Skip to the next track in the queue
+    status                      Print whether the player is playing, its position, and its volume as JSON
+    now-playing                 Print the title, artist and album of the current track as JSON
+    queue                       Print the tracks in the queue as JSON
+
+Export options:
+    --format <wav|flac|opus>    Output format (default: guessed from OUTPUT's extension)
+    --sample-rate <HZ>          Output sample rate (default: the sample rate of INPUT)
+    --bit-depth <BITS>          Output bit depth (default: 16)
+    --start <TIME>              Only export from TIME onwards, e.g. 1:30 or 90.5
+    --end <TIME>                Only export up to TIME
+    --speed <RATIO>             Change the playback speed (and pitch) by RATIO
+    --normalize                 Scale the audio so that its peak is just below 0 dBFS
+    --no-tags                   Don't copy the tags and cover art of INPUT";
+
+/// What the program has been asked to do from the command line.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    /// Open the player, and play `files` one after the other.
+    Play { files: Vec<PathBuf> },
+
+    /// Export a file without opening the player.
+    Export {
+        input: PathBuf,
+        output: PathBuf,
+        options: ExportOptions,
+    },
+
+    /// Send a request to the player that is already open.
+    #[cfg(unix)]
+    Ctl(ControlRequest),
+
+    /// Print the usage.
+    Help,
+}
+
+/// Parse the command line arguments, not including the program name.
+/// # Errors
+/// - If an unknown subcommand or option is passed
+/// - If an option is missing its value, or its value is invalid
+/// - If a required argument is missing
+/// - If a file to play does not exist
+pub(crate) fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+
+    match args.peek().map(String::as_str) {
+        None => Ok(Command::Play { files: Vec::new() }),
+        Some("-h" | "--help" | "help") => Ok(Command::Help),
+        Some("export") => parse_export_args(args.skip(1)),
+        #[cfg(unix)]
+        Some("ctl") => parse_ctl_args(args.skip(1)),
+        Some(_) => parse_files(args),
+    }
+}
+
+/// Parse the files, folders, playlists and streams to play. Anything that doesn't exist and isn't a URL is taken to be
+/// a mistyped command.
+fn parse_files(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let files = args
+        .map(|arg| {
+            let path = PathBuf::from(&arg);
+            if path.exists() || stream::is_stream(&path) {
+                Ok(path)
+            } else {
+                Err(format!("Unknown command or file: {}", arg))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Command::Play { files })
+}
+
+#[cfg(unix)]
+fn parse_ctl_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let command = args
+        .next()
+        .ok_or_else(|| "ctl needs a COMMAND".to_string())?;
+
+    let request = match command.as_str() {
+        "play" => ControlRequest::Play,
+        "pause" => ControlRequest::Pause,
+        "next" => ControlRequest::Next,
+        "status" => ControlRequest::Status,
+        "now-playing" => ControlRequest::NowPlaying,
+        "queue" => ControlRequest::Queue,
+        "seek" => {
+            let time = args
+                .next()
+                .ok_or_else(|| "ctl seek needs a TIME".to_string())?;
+            ControlRequest::Seek {
+                position: parse_timestamp(&time)?.as_secs_f64(),
+            }
+        }
+        "volume" => {
+            let percent = args
+                .next()
+                .ok_or_else(|| "ctl volume needs a PERCENT".to_string())?;
+            let percent: f32 = parse_number(&percent)?;
+
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!(
+                    "The volume has to be from 0 to 100, not {}",
+                    percent
+                ));
+            }
+
+            ControlRequest::Volume {
+                volume: percent / 100.0,
+            }
+        }
+        "enqueue" => {
+            let paths: Vec<PathBuf> = args.by_ref().map(PathBuf::from).collect();
+            if paths.is_empty() {
+                return Err("ctl enqueue needs at least one file".to_string());
+            }
+
+            ControlRequest::Enqueue { paths }
+        }
+        other => return Err(format!("Unknown control command: {}", other)),
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(format!("Unexpected argument: {}", extra));
+    }
+
+    Ok(Command::Ctl(request))
+}
+
+fn parse_export_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut paths = Vec::new();
+    let mut format = None;
+    let mut sample_rate = None;
+    let mut bit_depth = None;
+    let mut start = None;
+    let mut end = None;
+    let mut dsp = DspSettings::default();
+    let mut apply_dsp = false;
+    let mut copy_tags = true;
+
+    while let Some(arg) = args.next() {
+        // Take the value that comes after an option
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", option))
+        };
+
+        match arg.as_str() {
+            "--format" => {
+                let name = value("--format")?;
+                format = Some(
+                    ExportFormat::from_name(&name)
+                        .ok_or_else(|| format!("Unknown format: {}", name))?,
+                );
+            }
+            "--sample-rate" => sample_rate = Some(parse_number(&value("--sample-rate")?)?),
+            "--bit-depth" => bit_depth = Some(parse_number(&value("--bit-depth")?)?),
+            "--start" => start = Some(parse_timestamp(&value("--start")?)?),
+            "--end" => end = Some(parse_timestamp(&value("--end")?)?),
+            "--speed" => {
+                dsp.speed = parse_number(&value("--speed")?)?;
+                apply_dsp = true;
+            }
+            "--normalize" => {
+                dsp.normalize = true;
+                apply_dsp = true;
+            }
+            "--no-tags" => copy_tags = false,
+            option if option.starts_with("--") => {
+                return Err(format!("Unknown option: {}", option));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [input, output]: [PathBuf; 2] = paths
+        .try_into()
+        .map_err(|_| "export needs exactly one INPUT and one OUTPUT".to_string())?;
+
+    let format = match format {
+        Some(format) => format,
+        None => ExportFormat::from_path(&output).ok_or_else(|| {
+            format!(
+                "Unable to guess the format of {}, please pass --format",
+                output.display()
+            )
+        })?,
+    };
+
+    let mut options = ExportOptions::new(format);
+    options.sample_rate = sample_rate;
+    options.bit_depth = bit_depth.unwrap_or(options.bit_depth);
+    options.start = start;
+    options.end = end;
+    options.dsp = apply_dsp.then_some(dsp);
+    options.copy_tags = copy_tags;
+
+    options.validate().map_err(|e| e.to_string())?;
+
+    Ok(Command::Export {
+        input,
+        output,
+        options,
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number: {}", value))
+}
+
+/// Parse a timestamp like "1:02:03", "2:03" or "123.5" into a Duration.
+/// # Errors
+/// - If any part of the timestamp is not a number
+/// - If the minutes or seconds of a timestamp with colons are 60 or more
+pub(crate) fn parse_timestamp(timestamp: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid timestamp: {}", timestamp);
+
+    let parts: Vec<&str> = timestamp.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    // The seconds are the only part that can have a fraction
+    let (whole_parts, seconds) = parts.split_at(parts.len() - 1);
+    let seconds: f64 = seconds[0]
+        .parse()
+        .ok()
+        .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+        .ok_or_else(invalid)?;
+
+    if !whole_parts.is_empty() && seconds >= 60.0 {
+        return Err(invalid());
+    }
+
+    // Add up the hours and minutes, where every part except the first has to stay below 60
+    let mut total_minutes = 0;
+    for (i, part) in whole_parts.iter().enumerate() {
+        let value: u64 = part.parse().map_err(|_| invalid())?;
+
+        if i > 0 && value >= 60 {
+            return Err(invalid());
+        }
+
+        total_minutes = total_minutes * 60 + value;
+    }
+
+    Ok(Duration::from_secs(total_minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Send a request to the player that is already open, printing its answer to any query as JSON.
+#[cfg(unix)]
+pub(crate) fn run_ctl(request: ControlRequest) {
+    // The player runs from a different directory, so it needs to know exactly where the files are
+    let request = match request {
+        ControlRequest::Enqueue { paths } => ControlRequest::Enqueue {
+            paths: absolute_paths(&paths),
+        },
+        request => request,
+    };
+
+    match control::send(&request) {
+        Ok(ControlResponse::Ok) => {}
+        Ok(ControlResponse::Error { message }) => {
+            eprintln!("{}", message);
+            exit(1);
+        }
+        Ok(response) => match serde_json::to_string(&response) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Unable to print the answer: {}", e),
+        },
+        Err(e) => {
+            eprintln!("Unable to reach the player, is it open? ({})", e);
+            exit(1);
+        }
+    }
+}
+
+/// Add `files` to the queue of the player that is already open. Returns `false` if no player is open.
+#[cfg(unix)]
+pub(crate) fn hand_off(files: &[PathBuf]) -> bool {
+    let request = ControlRequest::Enqueue {
+        paths: absolute_paths(files),
+    };
+
+    match control::send(&request) {
+        Ok(ControlResponse::Error { message }) => {
+            eprintln!("{}", message);
+            exit(1);
+        }
+        Ok(_) => true,
+        Err(_) => false,
+    }
+}
+
+/// Make the paths of files absolute, leaving the URLs of streams as they are.
+#[cfg(unix)]
+fn absolute_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| {
+            if stream::is_stream(path) {
+                return path.clone();
+            }
+            path::absolute(path).unwrap_or_else(|_| path.clone())
+        })
+        .collect()
+}
+
+/// Run an export from the command line, printing the progress as it goes.
+pub(crate) fn run_export(input: &Path, output: &Path, options: &ExportOptions) {
+    let mut last_percentage = None;
+
+    let result = Exporter::export(input, output, options, |progress| {
+        let percentage = (progress * 100.0) as u32;
+
+        // Only redraw the line when the percentage actually changes
+        if last_percentage != Some(percentage) {
+            last_percentage = Some(percentage);
+            eprint!("\rExporting {}... {}%", output.display(), percentage);
+            let _ = io::stderr().flush();
+        }
+    });
+
+    eprintln!();
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    mod parse_args {
+        use super::*;
+
+        #[test]
+        fn test_no_args() {
+            assert_eq!(
+                parse_args(args(&[])),
+                Ok(Command::Play { files: Vec::new() })
+            );
+        }
+
+        #[test]
+        fn test_unknown_command() {
+            assert!(parse_args(args(&["dance"])).is_err());
+        }
+
+        #[test]
+        fn test_files() {
+            let path = std::env::temp_dir().join("audio_player_cli_test.mp3");
+            std::fs::write(&path, b"").unwrap();
+
+            let command = parse_args(args(&[path.to_str().unwrap()]));
+
+            assert_eq!(command, Ok(Command::Play { files: vec![path] }));
+        }
+
+        #[test]
+        fn test_folder() {
+            let folder = std::env::temp_dir();
+
+            let command = parse_args(args(&[folder.to_str().unwrap()]));
+
+            assert_eq!(
+                command,
+                Ok(Command::Play {
+                    files: vec![folder]
+                })
+            );
+        }
+
+        #[test]
+        fn test_stream() {
+            let command = parse_args(args(&["https://radio.example/stream.mp3"]));
+
+            assert_eq!(
+                command,
+                Ok(Command::Play {
+                    files: vec![PathBuf::from("https://radio.example/stream.mp3")]
+                })
+            );
+        }
+
+        #[test]
+        fn test_ctl_seek() {
+            assert_eq!(
+                parse_args(args(&["ctl", "seek", "1:30"])),
+                Ok(Command::Ctl(ControlRequest::Seek { position: 90.0 }))
+            );
+        }
+
+        #[test]
+        fn test_ctl_volume() {
+            assert_eq!(
+                parse_args(args(&["ctl", "volume", "50"])),
+                Ok(Command::Ctl(ControlRequest::Volume { volume: 0.5 }))
+            );
+            assert!(parse_args(args(&["ctl", "volume", "150"])).is_err());
+        }
+
+        #[test]
+        fn test_ctl_enqueue() {
+            assert_eq!(
+                parse_args(args(&["ctl", "enqueue", "a.mp3", "b.mp3"])),
+                Ok(Command::Ctl(ControlRequest::Enqueue {
+                    paths: vec![PathBuf::from("a.mp3"), PathBuf::from("b.mp3")],
+                }))
+            );
+            assert!(parse_args(args(&["ctl", "enqueue"])).is_err());
+        }
+
+        #[test]
+        fn test_ctl_unknown_command() {
+            assert!(parse_args(args(&["ctl", "dance"])).is_err());
+            assert!(parse_args(args(&["ctl", "play", "now"])).is_err());
+        }
+
+        #[test]
+        fn test_export_guesses_format() {
+            let command = parse_args(args(&["export", "in.mp3", "out.flac"])).unwrap();
+
+            assert_eq!(
+                command,
+                Command::Export {
+                    input: PathBuf::from("in.mp3"),
+                    output: PathBuf::from("out.flac"),
+                    options: ExportOptions::new(ExportFormat::Flac),
+                }
+            );
+        }
+
+        #[test]
+        fn test_export_options() {
+            let command = parse_args(args(&[
+                "export",
+                "in.mp3",
+                "out",
+                "--format",
+                "wav",
+                "--sample-rate",
+                "48000",
+                "--bit-depth",
+                "24",
+                "--start",
+                "0:30",
+                "--normalize",
+                "--no-tags",
+            ]))
+            .unwrap();
+
+            let mut options = ExportOptions::new(ExportFormat::Wav);
+            options.sample_rate = Some(48000);
+            options.bit_depth = 24;
+            options.start = Some(Duration::from_secs(30));
+            options.dsp = Some(DspSettings {
+                speed: 1.0,
+                normalize: true,
+            });
+            options.copy_tags = false;
+
+            assert_eq!(
+                command,
+                Command::Export {
+                    input: PathBuf::from("in.mp3"),
+                    output: PathBuf::from("out"),
+                    options,
+                }
+            );
+        }
+
+        #[test]
+        fn test_export_missing_output() {
+            assert!(parse_args(args(&["export", "in.mp3"])).is_err());
+        }
+
+        #[test]
+        fn test_export_unknown_extension() {
+            assert!(parse_args(args(&["export", "in.mp3", "out.xyz"])).is_err());
+        }
+
+        #[test]
+        fn test_export_missing_value() {
+            assert!(parse_args(args(&["export", "in.mp3", "out.wav", "--bit-depth"])).is_err());
+        }
+    }
+
+    mod parse_timestamp {
+        use super::*;
+
+        #[test]
+        fn test_seconds() {
+            assert_eq!(parse_timestamp("90.5"), Ok(Duration::from_millis(90500)));
+        }
+
+        #[test]
+        fn test_minutes() {
+            assert_eq!(parse_timestamp("2:38"), Ok(Duration::from_secs(158)));
+        }
+
+        #[test]
+        fn test_hours() {
+            assert_eq!(parse_timestamp("1:00:01"), Ok(Duration::from_secs(3601)));
+        }
+
+        #[test]
+        fn test_invalid() {
+            assert!(parse_timestamp("").is_err());
+            assert!(parse_timestamp("abc").is_err());
+            assert!(parse_timestamp("1:75").is_err());
+            assert!(parse_timestamp("1:60:00").is_err());
+            assert!(parse_timestamp("-5").is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    mod absolute_paths {
+        use super::*;
+
+        #[test]
+        fn test_files_and_streams() {
+            let paths = absolute_paths(&[
+                PathBuf::from("track.mp3"),
+                PathBuf::from("http://radio.example/stream"),
+            ]);
+
+            assert!(paths[0].is_absolute());
+            assert_eq!(paths[1], PathBuf::from("http://radio.example/stream"));
+        }
+    }
+}
@@ -1,6 +1,35 @@
 mod app;
+mod cli;
+
+use std::process::exit;
+
 use app::AudioApp;
+use cli::Command;
+
 fn main() {
-    let mut app = AudioApp::new();
-    app.run();
+    let command = cli::parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        exit(1);
+    });
+
+    match command {
+        Command::Play { files } => {
+            // Hand the files to the player that is already open, rather than opening another window
+            #[cfg(unix)]
+            if !files.is_empty() && cli::hand_off(&files) {
+                return;
+            }
+
+            let mut app = AudioApp::new();
+            app.run(files);
+        }
+        Command::Export {
+            input,
+            output,
+            options,
+        } => cli::run_export(&input, &output, &options),
+        #[cfg(unix)]
+        Command::Ctl(request) => cli::run_ctl(request),
+        Command::Help => println!("{}", cli::USAGE),
+    }
 }